@@ -1,5 +1,5 @@
 import { BrowserRouter, Routes, Route, Navigate } from 'react-router-dom'
-import { QueryClient, QueryClientProvider } from '@tanstack/react-query'
+import { QueryClient, QueryClientProvider, useQuery } from '@tanstack/react-query'
 import { Layout } from './components/Layout'
 import { LoginPage } from './pages/LoginPage'
 import { DashboardPage } from './pages/DashboardPage'
@@ -9,6 +9,7 @@ import { CreateMachinePage } from './pages/CreateMachinePage'
 import { InstancesPage } from './pages/InstancesPage'
 import { InstanceDetailPage } from './pages/InstanceDetailPage'
 import { WalPage } from './pages/WalPage'
+import { server } from './lib/api'
 
 const queryClient = new QueryClient({
   defaultOptions: {
@@ -19,6 +20,33 @@ const queryClient = new QueryClient({
   },
 })
 
+// Only the first visit to "/" follows the configured landing page, so the
+// dashboard stays reachable from the sidebar
+let landingHandled = false
+
+function LandingPage() {
+  const { data: serverInfo, isLoading } = useQuery({
+    queryKey: ['server-info'],
+    queryFn: () => server.info(),
+    enabled: !landingHandled,
+  })
+
+  if (!landingHandled) {
+    if (isLoading) {
+      return null
+    }
+    landingHandled = true
+    const ui = serverInfo?.ui
+    if (ui?.default_machine) {
+      return <Navigate to={`/machines/${encodeURIComponent(ui.default_machine)}`} replace />
+    }
+    if (ui?.default_view) {
+      return <Navigate to={`/${ui.default_view}`} replace />
+    }
+  }
+  return <DashboardPage />
+}
+
 function App() {
   return (
     <QueryClientProvider client={queryClient}>
@@ -26,7 +54,7 @@ function App() {
         <Routes>
           <Route path="/login" element={<LoginPage />} />
           <Route path="/" element={<Layout />}>
-            <Route index element={<DashboardPage />} />
+            <Route index element={<LandingPage />} />
             <Route path="machines" element={<MachinesPage />} />
             <Route path="machines/:name" element={<MachineDetailPage />} />
             <Route path="create-machine" element={<CreateMachinePage />} />
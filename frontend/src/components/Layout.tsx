@@ -6,6 +6,7 @@ import {
   Workflow,
   Box,
   ScrollText,
+  LogIn,
   LogOut,
   Server,
   ChevronLeft,
@@ -43,19 +44,22 @@ export function Layout() {
     retry: false,
   })
 
+  // Browsing without a session when the server allows anonymous reads
+  const anonymous = !authData?.logged_in && authData?.anonymous_read === true
+
   const { data: serverInfo } = useQuery({
     queryKey: ['server-info'],
     queryFn: () => server.info(),
     refetchInterval: 30000,
-    enabled: authData?.logged_in === true,
+    enabled: authData?.logged_in === true || anonymous,
   })
 
   // Redirect to login if not authenticated
   useEffect(() => {
-    if (!authLoading && (!authData?.logged_in || authError)) {
+    if (!authLoading && ((!authData?.logged_in && !anonymous) || authError)) {
       navigate('/login')
     }
-  }, [authData, authLoading, authError, navigate])
+  }, [authData, authLoading, authError, anonymous, navigate])
 
   const handleLogout = async () => {
     try {
@@ -78,7 +82,7 @@ export function Layout() {
   }
 
   // Don't render if not authenticated
-  if (!authData?.logged_in) {
+  if (!authData?.logged_in && !anonymous) {
     return null
   }
 
@@ -171,7 +175,18 @@ export function Layout() {
 
         {/* User */}
         <div className="p-2 border-t border-border">
-          {collapsed ? (
+          {anonymous ? (
+            <Link
+              to="/login"
+              title={collapsed ? 'Sign in to make changes' : undefined}
+              className={`flex items-center gap-2 text-sm text-muted hover:text-foreground w-full ${
+                collapsed ? 'justify-center p-2' : 'px-2'
+              }`}
+            >
+              <LogIn className={collapsed ? 'w-5 h-5' : 'w-4 h-4'} />
+              {!collapsed && <span>Sign in to make changes</span>}
+            </Link>
+          ) : collapsed ? (
             <button
               onClick={handleLogout}
               title="Logout"
@@ -181,7 +196,7 @@ export function Layout() {
             </button>
           ) : (
             <div className="px-2">
-              <div className="text-sm text-muted mb-2">{authData.username}</div>
+              <div className="text-sm text-muted mb-2">{authData?.username}</div>
               <button
                 onClick={handleLogout}
                 className="flex items-center gap-2 text-sm text-muted hover:text-foreground w-full"
@@ -24,6 +24,11 @@ async function handleResponse<T>(response: Response): Promise<T> {
       error.details
     )
   }
+  // Servers with server.response_envelope on wrap bodies as { data }
+  if (response.headers.get('X-Response-Envelope') === 'data') {
+    const body = await response.json()
+    return body.data
+  }
   return response.json()
 }
 
@@ -46,7 +51,40 @@ async function post<T>(path: string, body?: unknown): Promise<T> {
   return handleResponse<T>(res)
 }
 
+// Helper for PUT requests with credentials
+async function put<T>(path: string, body: unknown): Promise<T> {
+  const res = await fetch(`${API_BASE}${path}`, {
+    method: 'PUT',
+    headers: { 'Content-Type': 'application/json' },
+    credentials: 'include',
+    body: JSON.stringify(body),
+  })
+  return handleResponse<T>(res)
+}
+
+// Helper for DELETE requests; resolves to null on 204 No Content
+async function del<T>(path: string): Promise<T | null> {
+  const res = await fetch(`${API_BASE}${path}`, {
+    method: 'DELETE',
+    credentials: 'include',
+  })
+  if (res.status === 204) {
+    return null
+  }
+  return handleResponse<T>(res)
+}
+
 // Auth
+export interface SessionInfo {
+  id: string
+  username: string
+  created_at: number
+  last_activity: number
+  ip?: string
+  user_agent?: string
+  current: boolean
+}
+
 export const auth = {
   async login(username: string, password: string) {
     return post<{ username: string }>('/auth/login', { username, password })
@@ -57,7 +95,50 @@ export const auth = {
   },
 
   async me() {
-    return get<{ username: string; logged_in: boolean }>('/auth/me')
+    return get<{
+      username: string
+      logged_in: boolean
+      password_reset_required: boolean
+      anonymous_read: boolean
+    }>('/auth/me')
+  },
+
+  async breakGlass(token: string, username: string) {
+    return post<{ username: string; password_reset_required: boolean; expires_at: number }>(
+      '/auth/break-glass',
+      { token, username }
+    )
+  },
+
+  async resetPassword(newPassword: string) {
+    return post<{ username: string }>('/auth/reset-password', { new_password: newPassword })
+  },
+
+  async banner() {
+    return get<{ banner: string }>('/auth/banner')
+  },
+
+  async status() {
+    return get<{ initialized: boolean; user_count: number }>('/auth/status')
+  },
+
+  // Create the first admin account; only allowed while no users exist
+  async setup(username: string, password: string) {
+    return post<{ username: string }>('/auth/setup', { username, password })
+  },
+
+  // Active sessions; admins may pass another user's name
+  async sessions(username?: string) {
+    const query = username ? `?username=${encodeURIComponent(username)}` : ''
+    return get<{ username: string; items: SessionInfo[] }>(`/auth/sessions${query}`)
+  },
+
+  async revokeSession(id: string) {
+    return del<{ id: string; revoked: boolean }>(`/auth/sessions/${id}`)
+  },
+
+  async revokeOtherSessions() {
+    return post<{ revoked: number }>('/auth/sessions/revoke-others')
   },
 }
 
@@ -78,8 +159,10 @@ export interface MachineDefinition {
     event: string
     to: string
     guard?: string
+    payloadDefaults?: Record<string, unknown>
+    description?: string
   }>
-  meta?: Record<string, unknown>
+  meta?: Record<string, unknown> & { stateDescriptions?: Record<string, string> }
 }
 
 export interface ValidationResult {
@@ -89,15 +172,22 @@ export interface ValidationResult {
 }
 
 export const machines = {
-  async list() {
-    return get<{ items: Machine[] }>('/machines')
+  async list(params?: { search?: string; limit?: number; offset?: number }) {
+    const query = new URLSearchParams()
+    if (params?.search) query.set('search', params.search)
+    if (params?.limit) query.set('limit', String(params.limit))
+    if (params?.offset) query.set('offset', String(params.offset))
+    const qs = query.toString()
+    return get<{ items: Machine[]; total: number; has_more: boolean }>(
+      qs ? `/machines?${qs}` : '/machines'
+    )
   },
 
   async get(name: string) {
     return get<{ machine: string; versions: number[] }>(`/machines/${name}`)
   },
 
-  async getVersion(name: string, version: number) {
+  async getVersion(name: string, version: number | 'latest') {
     return get<{
       machine: string
       version: number
@@ -109,26 +199,183 @@ export const machines = {
   async createVersion(
     name: string,
     definition: MachineDefinition,
-    options?: { version?: number; baseVersion?: number }
+    options?: { version?: number; baseVersion?: number; checkInstances?: boolean }
   ) {
+    const query = options?.checkInstances ? '?check_instances=true' : ''
     return post<{
       machine: string
       version: number
       checksum: string
       created: boolean
-    }>(`/machines/${name}/versions`, {
+      warnings?: ValidationResult['warnings']
+    }>(`/machines/${name}/versions${query}`, {
       version: options?.version,
       definition,
       base_version: options?.baseVersion,
     })
   },
 
+  async importFromUrl(
+    name: string,
+    url: string,
+    options?: {
+      version?: number
+      baseVersion?: number
+      strategy?: 'skip' | 'overwrite' | 'version' | 'rename'
+    },
+  ) {
+    return post<{
+      action: 'created' | 'skipped' | 'versioned' | 'renamed'
+      machine: string
+      version: number
+      checksum: string
+      created: boolean
+    }>(`/machines/${name}/import/url`, {
+      url,
+      version: options?.version,
+      base_version: options?.baseVersion,
+      strategy: options?.strategy,
+    })
+  },
+
+  async events(name: string, version: number) {
+    return get<{
+      machine: string
+      version: number
+      states: Array<{
+        state: string
+        events: Array<{
+          event: string
+          to: string
+          guard?: string
+          payload_defaults?: Record<string, unknown>
+          transition_index: number
+        }>
+      }>
+    }>(`/machines/${name}/versions/${version}/events`)
+  },
+
+  async reachable(name: string, version: number, from: string) {
+    return get<{
+      machine: string
+      version: number
+      from: string
+      states: string[]
+      transitions: Array<{ index: number; from: string; event: string; to: string; guard?: string }>
+    }>(`/machines/${name}/versions/${version}/reachable?from=${encodeURIComponent(from)}`)
+  },
+
+  async path(name: string, version: number, from: string, to: string, all = false) {
+    const params = new URLSearchParams({ from, to })
+    if (all) params.set('all', 'true')
+    return get<{
+      machine: string
+      version: number
+      from: string
+      to: string
+      result: 'FOUND' | 'NO_PATH'
+      paths: Array<Array<{ event: string; guard?: string; to: string }>>
+      truncated: boolean
+    }>(`/machines/${name}/versions/${version}/path?${params}`)
+  },
+
+  async diff(name: string, from: number, to?: number) {
+    const params = new URLSearchParams({ from: String(from) })
+    if (to !== undefined) params.set('to', String(to))
+    type Transition = { from: string; event: string; to: string; guard?: string }
+    return get<{
+      machine: string
+      from: number
+      to: number
+      identical: boolean
+      added_states: string[]
+      removed_states: string[]
+      added_transitions: Transition[]
+      removed_transitions: Transition[]
+    }>(`/machines/${name}/diff?${params}`)
+  },
+
+  async getDraft(name: string) {
+    return get<{ machine: string; definition: unknown; saved_at: number }>(`/machines/${name}/draft`)
+  },
+
+  async saveDraft(name: string, definition: unknown) {
+    return put<{ machine: string; definition: unknown; saved_at: number }>(`/machines/${name}/draft`, {
+      definition,
+    })
+  },
+
+  async discardDraft(name: string) {
+    return del<null>(`/machines/${name}/draft`)
+  },
+
+  async publish(machines: { name: string; definition: unknown }[]) {
+    return post<{
+      published: { machine: string; version: number; checksum: string; created: boolean }[]
+    }>('/machines/publish', machines)
+  },
+
   async validate(definition: unknown) {
     return post<ValidationResult>('/machines/validate', { definition })
   },
+
+  async normalize(definition: unknown, stripBuilderPositions = false) {
+    const query = stripBuilderPositions ? '?strip_builder_positions=true' : ''
+    return post<{
+      definition: unknown
+      canonical: string
+      checksum: string
+      warnings?: ValidationResult['warnings']
+    }>(`/machines/normalize${query}`, { definition })
+  },
+
+  async validateAgainstInstances(name: string, definition: unknown) {
+    return post<{
+      machine: string
+      valid: boolean
+      errors: ValidationResult['errors']
+      states_in_use: { state: string; instances: number }[]
+      scanned: number
+      truncated: boolean
+    }>(`/machines/${name}/validate-against-instances`, { definition })
+  },
+
+  async duplicates() {
+    return get<{
+      clusters: Array<{ checksum: string; machines: Array<{ machine: string; version: number }> }>
+      machines_checked: number
+      failed: string[]
+    }>('/machines/duplicates')
+  },
 }
 
 // Instances
+export interface PathStep {
+  event: string | null
+  state: string
+}
+
+export interface ComparedInstance {
+  instance_id: string
+  version: number
+  state: string
+  last_wal_offset: number
+}
+
+export interface InstanceComparison {
+  machine: string
+  a: ComparedInstance
+  b: ComparedInstance
+  same_state: boolean
+  context_diff: Array<{ path: string; a?: unknown; b?: unknown }>
+  history: {
+    common: PathStep[]
+    diverged_at: number | null
+    a_only: PathStep[]
+    b_only: PathStep[]
+  }
+}
+
 export interface Instance {
   id: string
   machine: string
@@ -136,7 +383,14 @@ export interface Instance {
   state: string
   created_at: number
   updated_at: number
+  /** Present with `?time_format=iso` or `?tz=` */
+  created_at_formatted?: string
+  updated_at_formatted?: string
   last_wal_offset: number
+  is_archived: boolean
+  paused: boolean
+  /** Epoch ms the instance TTL sweep expires it, for machines with a TTL */
+  expires_at?: number
 }
 
 export interface InstanceDetail {
@@ -146,6 +400,8 @@ export interface InstanceDetail {
   state: string
   ctx: Record<string, unknown>
   last_wal_offset: number
+  is_archived: boolean
+  paused: boolean
 }
 
 export interface HistoryEvent {
@@ -155,26 +411,157 @@ export interface HistoryEvent {
   from_state?: string
   to_state: string
   timestamp: number
+  timestamp_formatted?: string
   ctx?: Record<string, unknown>
 }
 
+export type BatchGetItem =
+  | InstanceDetail
+  | { error: { code: string; message: string; details?: unknown } }
+
+export interface DiffTransition {
+  from: string
+  event: string
+  to: string
+  guard?: string
+}
+
+export interface VersionStatus {
+  instance_id: string
+  machine: string
+  state: string
+  version: number
+  latest_version: number
+  upgrade_available: boolean
+  state_removed: boolean
+  diff: {
+    added_states: string[]
+    removed_states: string[]
+    added_transitions: DiffTransition[]
+    removed_transitions: DiffTransition[]
+  }
+}
+
 export const instances = {
-  async list(machine: string, params?: { state?: string; limit?: number; offset?: number }) {
+  async wait(
+    id: string,
+    expectedState: string,
+    options?: { until?: 'leave' | 'enter'; timeout?: string }
+  ) {
+    const params = new URLSearchParams({ expected_state: expectedState })
+    if (options?.until) params.set('until', options.until)
+    if (options?.timeout) params.set('timeout', options.timeout)
+    return get<{
+      instance_id: string
+      state: string
+      last_wal_offset: number
+      condition_met: boolean
+      waited_ms: number
+    }>(`/instances/${id}/wait?${params}`)
+  },
+
+  async compare(a: string, b: string) {
+    return get<InstanceComparison>(
+      `/instances/compare?a=${encodeURIComponent(a)}&b=${encodeURIComponent(b)}`
+    )
+  },
+
+  async list(
+    machine: string,
+    params?: {
+      state?: string
+      limit?: number
+      offset?: number
+      includeArchived?: boolean
+      paused?: boolean
+    }
+  ) {
     const query = new URLSearchParams()
     query.set('machine', machine)
     if (params?.state) query.set('state', params.state)
+    if (params?.includeArchived) query.set('include_archived', 'true')
+    if (params?.paused !== undefined) query.set('paused', String(params.paused))
     if (params?.limit) query.set('limit', String(params.limit))
     if (params?.offset) query.set('offset', String(params.offset))
     return get<{ items: Instance[]; total: number; has_more: boolean }>(`/instances?${query}`)
   },
 
+  async count(machine: string, params?: { state?: string; includeArchived?: boolean }) {
+    const query = new URLSearchParams()
+    query.set('machine', machine)
+    if (params?.state) query.set('state', params.state)
+    if (params?.includeArchived) query.set('include_archived', 'true')
+    return get<{ total: number }>(`/instances/count?${query}`)
+  },
+
   async get(id: string) {
     return get<InstanceDetail>(`/instances/${id}`)
   },
 
+  async batchGet(ids: string[]) {
+    return post<{ instances: Record<string, BatchGetItem> }>('/instances/batch-get', { ids })
+  },
+
   async getHistory(id: string) {
     return get<{ instance_id: string; events: HistoryEvent[] }>(`/instances/${id}/history`)
   },
+
+  async getVersionStatus(id: string) {
+    return get<VersionStatus>(`/instances/${id}/version-status`)
+  },
+
+  async archive(id: string) {
+    return post<{ instance_id: string; is_archived: boolean }>(`/instances/${id}/archive`)
+  },
+
+  async restore(id: string) {
+    return post<{ instance_id: string; is_archived: boolean }>(`/instances/${id}/restore`)
+  },
+
+  // Permanent delete; retries of a delete that already succeeded resolve to null
+  async delete(id: string) {
+    return del<{ instance_id: string; deleted: boolean; wal_offset: number }>(
+      `/instances/${id}?idempotent=true`
+    )
+  },
+
+  async pause(id: string, reason?: string) {
+    return post<{
+      instance_id: string
+      paused: boolean
+      pause?: { paused_by: string; paused_at: number; reason?: string }
+    }>(`/instances/${id}/pause`, reason ? { reason } : {})
+  },
+
+  async resume(id: string) {
+    return post<{ instance_id: string; paused: boolean }>(`/instances/${id}/resume`)
+  },
+
+  async fork(id: string, instanceId?: string, fromOffset?: number) {
+    const query = fromOffset !== undefined ? `?from_offset=${fromOffset}` : ''
+    return post<{
+      source_id: string
+      instance_id: string
+      machine: string
+      version: number
+      state: string
+      replayed: number
+      total_events: number
+      skipped: number
+      completed: boolean
+      failed?: { offset: number; event: string; error: string }
+    }>(`/instances/${id}/fork${query}`, instanceId ? { instance_id: instanceId } : {})
+  },
+
+  // Server-Sent Events: `state`, then `transition`s, then `deleted`
+  eventStreamUrl(id: string) {
+    return `${API_BASE}/instances/${encodeURIComponent(id)}/events/stream`
+  },
+
+  // Audit package download (instance, definition, WAL history and timeline)
+  packageUrl(id: string) {
+    return `${API_BASE}/instances/${encodeURIComponent(id)}/package`
+  },
 }
 
 // WAL
@@ -185,7 +572,29 @@ export interface WalEntry {
   instance_id?: string
   machine?: string
   version?: number
-  details: Record<string, unknown>
+  timestamp?: number
+  payload:
+    | { type: 'create_instance'; initial_state: string; initial_ctx?: Record<string, unknown> }
+    | {
+        type: 'apply_event'
+        event: string
+        from_state: string
+        to_state: string
+        payload?: unknown
+        ctx?: Record<string, unknown>
+        event_id?: string
+      }
+    | { type: 'delete_instance'; reason?: string }
+    | null
+  raw?: Record<string, unknown>
+  /** Present with `resolve: true` */
+  resolution?: WalResolution
+}
+
+export interface WalResolution {
+  resolved: boolean
+  machine_latest_version?: number
+  instance_state?: string
 }
 
 export interface WalStats {
@@ -202,24 +611,116 @@ export interface WalStats {
   }
 }
 
+export interface WalSample {
+  timestamp: number
+  entry_count: number
+  total_size_bytes: number
+}
+
+export interface WalStatsHistory {
+  enabled: boolean
+  interval_ms: number
+  retention_ms: number
+  samples: WalSample[]
+}
+
 export const wal = {
-  async list(params?: { from?: number; limit?: number }) {
+  async list(params?: { from?: number; limit?: number; resolve?: boolean }) {
     const query = new URLSearchParams()
     if (params?.from !== undefined) query.set('from', String(params.from))
     if (params?.limit !== undefined) query.set('limit', String(params.limit))
+    if (params?.resolve) query.set('resolve', 'true')
     return get<{ records: WalEntry[]; next_offset?: number }>(`/wal?${query}`)
   },
 
-  async get(offset: number) {
-    return get<{ sequence: number; offset: number; entry: unknown }>(`/wal/${offset}`)
+  async get(offset: number, params?: { resolve?: boolean }) {
+    const query = params?.resolve ? '?resolve=true' : ''
+    return get<{ sequence: number; offset: number; entry: unknown; resolution?: WalResolution }>(
+      `/wal/${offset}${query}`
+    )
   },
 
   async stats() {
     return get<WalStats>('/wal/stats')
   },
+
+  async statsHistory() {
+    return get<WalStatsHistory>('/wal/stats/history')
+  },
+
+  async rangeDigest(from: number, to: number) {
+    return get<{
+      from: number
+      to: number
+      algorithm: string
+      digest: string
+      entry_count: number
+      first_offset: number | null
+      last_offset: number | null
+      complete: boolean
+    }>(`/wal/range-digest?from=${from}&to=${to}`)
+  },
+}
+
+// Search
+export interface SearchHit {
+  type: 'machine' | 'instance'
+  id: string
+  machine: string
+  state?: string
+  matched: string
+  score: number
+}
+
+export const search = {
+  async query(q: string, options: { limit?: number; cursor?: string } = {}) {
+    const params = new URLSearchParams({ q })
+    if (options.limit) params.set('limit', String(options.limit))
+    if (options.cursor) params.set('cursor', options.cursor)
+    return get<{
+      items: SearchHit[]
+      total: number
+      next_cursor: string | null
+      instances_scanned: number
+      truncated: boolean
+    }>(`/search?${params}`)
+  },
 }
 
 // Server
+export interface ServerInternals {
+  generated_at: number
+  http: { in_flight: number; max_in_flight: number }
+  instance_cache: {
+    size: number
+    capacity: number
+    hits: number
+    misses: number
+    hit_rate: number | null
+  } | null
+  transition_usage_cache_entries: number
+  applied_event_ids: number
+  rstmdb: {
+    address: string
+    active_endpoint: string
+    queue: { interactive_waiting: number; bulk_waiting: number } | null
+  }
+  background: {
+    keepalive: { enabled: boolean; last_success_at: number | null; consecutive_failures: number }
+    wal_sampler: { enabled: boolean; last_sample_at: number | null; samples: number }
+    ttl_sweeper: { enabled: boolean; last_sweep_at: number | null; expired: number }
+    tasks: Array<{
+      name: string
+      critical: boolean
+      running: boolean
+      last_started_at: number | null
+      restarts: number
+      last_error?: string
+      down_since?: number
+    }>
+  }
+}
+
 export const server = {
   async info() {
     return get<{
@@ -231,6 +732,11 @@ export const server = {
         protocol_version: number
         features: string[]
       }
+      anonymous_read: boolean
+      ui: {
+        default_machine: string | null
+        default_view: 'machines' | 'instances' | 'wal' | null
+      }
     }>('/server/info')
   },
 
@@ -239,6 +745,21 @@ export const server = {
       status: string
       rstmdb_connected: boolean
       latency_ms: number
+      last_ping_at: number | null
+      consecutive_ping_failures: number
+      rstmdb_endpoint: string
     }>('/server/health')
   },
+
+  async readiness() {
+    return get<{
+      ready: boolean
+      dependencies: Array<{ name: string; ready: boolean; latency_ms: number; error?: string }>
+    }>('/server/readiness')
+  },
+
+  /** Admin only */
+  async internals() {
+    return get<ServerInternals>('/server/internals')
+  },
 }
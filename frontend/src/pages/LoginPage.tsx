@@ -1,6 +1,6 @@
 import { useState } from 'react'
 import { useNavigate } from 'react-router-dom'
-import { useQueryClient } from '@tanstack/react-query'
+import { useQuery, useQueryClient } from '@tanstack/react-query'
 import { Server } from 'lucide-react'
 import { auth } from '@/lib/api'
 
@@ -12,18 +12,36 @@ export function LoginPage() {
   const [error, setError] = useState('')
   const [loading, setLoading] = useState(false)
 
+  const { data: bannerData } = useQuery({
+    queryKey: ['auth-banner'],
+    queryFn: auth.banner,
+    staleTime: Infinity,
+  })
+  const banner = bannerData?.banner.trim()
+
+  // With no users yet, the form creates the first admin instead
+  const { data: statusData } = useQuery({
+    queryKey: ['auth-status'],
+    queryFn: auth.status,
+  })
+  const setup = statusData?.initialized === false
+
   const handleSubmit = async (e: React.FormEvent) => {
     e.preventDefault()
     setError('')
     setLoading(true)
 
     try {
-      await auth.login(username, password)
+      if (setup) {
+        await auth.setup(username, password)
+      } else {
+        await auth.login(username, password)
+      }
       // Invalidate auth query so Layout re-checks auth status
       await queryClient.invalidateQueries({ queryKey: ['auth-me'] })
       navigate('/')
     } catch (err) {
-      setError(err instanceof Error ? err.message : 'Login failed')
+      setError(err instanceof Error ? err.message : setup ? 'Setup failed' : 'Login failed')
     } finally {
       setLoading(false)
     }
@@ -37,9 +55,19 @@ export function LoginPage() {
             <Server className="w-8 h-8 text-primary" />
             <h1 className="text-2xl font-bold">rstmdb Studio</h1>
           </div>
-          <p className="text-muted">Sign in to manage your rstmdb instance</p>
+          <p className="text-muted">
+            {setup
+              ? 'Create the first admin account to get started'
+              : 'Sign in to manage your rstmdb instance'}
+          </p>
         </div>
 
+        {banner && (
+          <div className="mb-6 p-3 text-sm whitespace-pre-wrap bg-surface border border-border rounded-lg">
+            {banner}
+          </div>
+        )}
+
         <form onSubmit={handleSubmit} className="space-y-4">
           {error && (
             <div className="bg-destructive/10 text-destructive text-sm p-3 rounded-lg">
@@ -80,7 +108,13 @@ export function LoginPage() {
             disabled={loading}
             className="w-full py-2 px-4 bg-primary text-white rounded-lg hover:bg-primary/90 disabled:opacity-50 transition-colors"
           >
-            {loading ? 'Signing in...' : 'Sign in'}
+            {setup
+              ? loading
+                ? 'Creating account...'
+                : 'Create admin account'
+              : loading
+                ? 'Signing in...'
+                : 'Sign in'}
           </button>
         </form>
       </div>
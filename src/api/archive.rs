@@ -0,0 +1,218 @@
+//! Soft-deleted (archived) instances
+//!
+//! rstmdb only supports irreversible deletes, so Studio keeps a tombstone per
+//! archived instance in `<data_dir>/archived.json`. The instance and its WAL
+//! history stay in rstmdb; archived instances are hidden from listings and
+//! refuse events until restored.
+
+use crate::api::auth::{require_admin, SessionUser};
+use crate::error::{ApiError, ApiResult};
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use chrono::Utc;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub machine: String,
+    /// State when archived; archived instances accept no events
+    pub state: String,
+    pub archived_by: String,
+    /// Epoch milliseconds
+    pub archived_at: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ArchiveData {
+    instances: HashMap<String, Tombstone>,
+}
+
+/// Tombstones backed by a JSON file
+pub struct ArchiveStore {
+    path: PathBuf,
+    data: RwLock<ArchiveData>,
+}
+
+impl ArchiveStore {
+    pub fn new(path: &PathBuf) -> Self {
+        let data = if path.exists() {
+            let content = std::fs::read_to_string(path).unwrap_or_default();
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            ArchiveData::default()
+        };
+
+        Self {
+            path: path.clone(),
+            data: RwLock::new(data),
+        }
+    }
+
+    pub fn is_archived(&self, id: &str) -> bool {
+        self.data.read().instances.contains_key(id)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Tombstone> {
+        self.data.read().instances.get(id).cloned()
+    }
+
+    /// Number of archived instances of `machine`, optionally in `state`
+    pub fn count(&self, machine: &str, state: Option<&str>) -> u64 {
+        self.data
+            .read()
+            .instances
+            .values()
+            .filter(|t| t.machine == machine && state.is_none_or(|s| t.state == s))
+            .count() as u64
+    }
+
+    /// Record a tombstone; returns false if the instance was already archived
+    pub fn archive(&self, id: &str, tombstone: Tombstone) -> anyhow::Result<bool> {
+        {
+            let mut data = self.data.write();
+            if data.instances.contains_key(id) {
+                return Ok(false);
+            }
+            data.instances.insert(id.to_string(), tombstone);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Remove a tombstone; returns false if the instance was not archived
+    pub fn restore(&self, id: &str) -> anyhow::Result<bool> {
+        if self.data.write().instances.remove(id).is_none() {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let data = self.data.read();
+        let content = serde_json::to_string_pretty(&*data)?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(&self.path, content)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveResponse {
+    pub instance_id: String,
+    pub is_archived: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tombstone: Option<Tombstone>,
+}
+
+/// POST /api/v1/instances/:id/archive
+pub async fn archive_instance(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<SessionUser>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<ArchiveResponse>> {
+    require_admin(&user)?;
+
+    let instance = state.rstmdb.get_instance(&id).await?;
+    let tombstone = Tombstone {
+        machine: instance.machine,
+        state: instance.state,
+        archived_by: user.username.clone(),
+        archived_at: Utc::now().timestamp_millis(),
+    };
+    let archived = state
+        .archive
+        .archive(&id, tombstone)
+        .map_err(|e| ApiError::internal(format!("Failed to save archive: {}", e)))?;
+    if !archived {
+        return Err(ApiError::conflict(format!(
+            "Instance '{}' is already archived",
+            id
+        )));
+    }
+    tracing::info!(instance_id = %id, username = %user.username, "Instance archived");
+
+    Ok(Json(ArchiveResponse {
+        tombstone: state.archive.get(&id),
+        instance_id: id,
+        is_archived: true,
+    }))
+}
+
+/// POST /api/v1/instances/:id/restore
+pub async fn restore_instance(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<SessionUser>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<ArchiveResponse>> {
+    require_admin(&user)?;
+
+    let restored = state
+        .archive
+        .restore(&id)
+        .map_err(|e| ApiError::internal(format!("Failed to save archive: {}", e)))?;
+    if !restored {
+        return Err(ApiError::conflict(format!(
+            "Instance '{}' is not archived",
+            id
+        )));
+    }
+    tracing::info!(instance_id = %id, username = %user.username, "Instance restored");
+
+    Ok(Json(ArchiveResponse {
+        instance_id: id,
+        is_archived: false,
+        tombstone: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_archive_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("rstmdb-studio-test-{}", uuid::Uuid::new_v4()))
+            .join("archived.json")
+    }
+
+    fn tombstone(machine: &str, state: &str) -> Tombstone {
+        Tombstone {
+            machine: machine.to_string(),
+            state: state.to_string(),
+            archived_by: "admin".to_string(),
+            archived_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_archive_and_restore_persist() {
+        let path = temp_archive_path();
+        let store = ArchiveStore::new(&path);
+        assert!(store.archive("i-1", tombstone("order", "paid")).unwrap());
+        assert!(!store.archive("i-1", tombstone("order", "paid")).unwrap());
+        assert!(store.archive("i-2", tombstone("order", "new")).unwrap());
+
+        let reloaded = ArchiveStore::new(&path);
+        assert!(reloaded.is_archived("i-1"));
+        assert_eq!(reloaded.count("order", None), 2);
+        assert_eq!(reloaded.count("order", Some("paid")), 1);
+        assert_eq!(reloaded.count("invoice", None), 0);
+
+        assert!(reloaded.restore("i-1").unwrap());
+        assert!(!reloaded.restore("i-1").unwrap());
+        assert!(!ArchiveStore::new(&path).is_archived("i-1"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
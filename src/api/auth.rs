@@ -1,8 +1,19 @@
 //! Authentication API handlers
 
+use crate::auth::{ClientInfo, Credentials, LoginMethod, Role, SessionInfo};
+use crate::config::AuthBackendKind;
 use crate::error::{ApiError, ApiResult};
+use crate::static_files::static_handler;
+use crate::tls::ClientCert;
 use crate::AppState;
-use axum::{extract::State, Json};
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderName, HeaderValue, Method},
+    middleware::Next,
+    response::Response,
+    Extension, Json,
+};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tower_sessions::Session;
@@ -10,15 +21,65 @@ use tower_sessions::Session;
 // Session keys
 const SESSION_USER_KEY: &str = "user";
 
+/// Set on SPA page loads that will need a login, for proxies and the SPA
+pub const X_AUTH_REQUIRED: HeaderName = HeaderName::from_static("x-auth-required");
+
+/// SPA route that renders the login form itself
+const LOGIN_PAGE_PATH: &str = "/login";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionUser {
     pub username: String,
+    #[serde(default)]
+    pub role: Role,
+    /// Unix timestamp of login, used for the absolute session lifetime
+    #[serde(default)]
+    pub logged_in_at: i64,
+    /// Unix timestamp of the last authenticated request, used for the idle timeout
+    #[serde(default)]
+    pub last_activity: i64,
+    /// Break-glass session: only the password reset endpoint is allowed
+    #[serde(default)]
+    pub password_reset_required: bool,
+    /// Unix timestamp after which the session ends, regardless of activity
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// Id in the session registry; revoked sessions are no longer listed there
+    #[serde(default)]
+    pub session_id: String,
+}
+
+impl SessionUser {
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
 }
 
+/// The logged-in user of a handler that anonymous reads can reach.
+///
+/// With `server.allow_anonymous_read`, GET requests pass `require_auth`
+/// without a session, so such handlers take `Option<Extension<SessionUser>>`
+/// and answer `401` here rather than failing on the missing extension.
+pub fn session_user(user: Option<Extension<SessionUser>>) -> ApiResult<SessionUser> {
+    user.map(|Extension(user)| user)
+        .ok_or_else(ApiError::unauthorized)
+}
+
+/// Reject users without the admin role
+pub fn require_admin(user: &SessionUser) -> ApiResult<()> {
+    if user.is_admin() {
+        Ok(())
+    } else {
+        Err(ApiError::forbidden("Admin role required"))
+    }
+}
+
+/// Login body; which variant is accepted depends on the configured backend
 #[derive(Debug, Deserialize)]
-pub struct LoginRequest {
-    pub username: String,
-    pub password: String,
+#[serde(untagged)]
+pub enum LoginRequest {
+    Password { username: String, password: String },
+    Token { token: String },
 }
 
 #[derive(Debug, Serialize)]
@@ -26,50 +87,250 @@ pub struct LoginResponse {
     pub username: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct BreakGlassRequest {
+    pub token: String,
+    /// Account whose password will be reset
+    pub username: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BreakGlassResponse {
+    pub username: String,
+    pub password_reset_required: bool,
+    /// Unix timestamp when the session ends
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthStatusResponse {
+    /// False until a first admin exists; the UI then offers first-run setup
+    pub initialized: bool,
+    pub user_count: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetupRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BannerResponse {
+    /// Markdown text; empty when no banner is configured
+    pub banner: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct MeResponse {
     pub username: String,
     pub logged_in: bool,
+    pub password_reset_required: bool,
+    /// Reads are allowed without logging in
+    pub anonymous_read: bool,
 }
 
 /// POST /api/v1/auth/login
 pub async fn login(
     State(state): State<Arc<AppState>>,
+    client: ClientInfo,
     session: Session,
     Json(req): Json<LoginRequest>,
 ) -> ApiResult<Json<LoginResponse>> {
+    let backend = &state.auth_backend;
+    let credentials = match (backend.login_method(), req) {
+        (LoginMethod::Password, LoginRequest::Password { username, password }) => {
+            Credentials::Password { username, password }
+        }
+        (LoginMethod::Token, LoginRequest::Token { token }) => Credentials::Token(token),
+        (method, _) => {
+            return Err(ApiError::bad_request(format!(
+                "This server expects {} login",
+                method.as_str()
+            )))
+        }
+    };
+
     // Verify credentials
-    if !state.auth_store.verify(&req.username, &req.password) {
-        return Err(ApiError::unauthorized());
-    }
+    let username = backend
+        .verify(&credentials)
+        .await?
+        .ok_or_else(ApiError::unauthorized)?;
+
+    // Sessions carry the stored casing, not whatever was typed at login
+    let (username, role) = match backend.get_user(&username).await {
+        Some(user) => (user.username, user.role),
+        None => (username, Default::default()),
+    };
 
     // Store user in session
+    let now = Utc::now().timestamp();
     let session_user = SessionUser {
-        username: req.username.clone(),
+        username: username.clone(),
+        role,
+        logged_in_at: now,
+        last_activity: now,
+        password_reset_required: false,
+        expires_at: None,
+        session_id: String::new(),
     };
-    session
-        .insert(SESSION_USER_KEY, session_user)
-        .await
-        .map_err(|e| {
-            tracing::error!(error = %e, "Failed to store session");
-            ApiError::internal("Failed to create session")
-        })?;
+    store_session(&state, &session, session_user, client).await?;
+
+    tracing::info!(username = %username, "User logged in");
+
+    Ok(Json(LoginResponse { username }))
+}
+
+/// Register the session, store the user in it and save it so the cookie is
+/// set before the response
+async fn store_session(
+    state: &AppState,
+    session: &Session,
+    mut user: SessionUser,
+    client: ClientInfo,
+) -> ApiResult<()> {
+    let timeouts = &state.session_timeouts;
+    state
+        .sessions
+        .prune(user.logged_in_at, timeouts.idle, timeouts.max_lifetime);
+    user.session_id = state
+        .sessions
+        .register(&user.username, client, user.logged_in_at);
+
+    session.insert(SESSION_USER_KEY, user).await.map_err(|e| {
+        tracing::error!(error = %e, "Failed to store session");
+        ApiError::internal("Failed to create session")
+    })?;
 
-    // Explicitly save session to ensure cookie is set before response
     session.save().await.map_err(|e| {
         tracing::error!(error = %e, "Failed to save session");
         ApiError::internal("Failed to save session")
-    })?;
+    })
+}
+
+/// POST /api/v1/auth/break-glass
+///
+/// Redeems the single-use startup token for a short-lived admin session that
+/// can only reset the given account's password.
+pub async fn break_glass(
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    session: Session,
+    Json(req): Json<BreakGlassRequest>,
+) -> ApiResult<Json<BreakGlassResponse>> {
+    if req.username.trim().is_empty() {
+        return Err(ApiError::bad_request("username is required"));
+    }
+    if !state.break_glass.redeem(&req.token) {
+        tracing::warn!(username = %req.username, "Rejected break-glass token");
+        return Err(ApiError::unauthorized());
+    }
 
-    tracing::info!(username = %req.username, "User logged in");
+    let now = Utc::now().timestamp();
+    let expires_at = now + state.break_glass.ttl().as_secs() as i64;
+    store_session(
+        &state,
+        &session,
+        SessionUser {
+            username: req.username.clone(),
+            role: Role::Admin,
+            logged_in_at: now,
+            last_activity: now,
+            password_reset_required: true,
+            expires_at: Some(expires_at),
+            session_id: String::new(),
+        },
+        client,
+    )
+    .await?;
 
-    Ok(Json(LoginResponse {
+    tracing::warn!(
+        username = %req.username,
+        "BREAK-GLASS TOKEN USED: admin recovery session granted, password reset required"
+    );
+
+    Ok(Json(BreakGlassResponse {
         username: req.username,
+        password_reset_required: true,
+        expires_at,
+    }))
+}
+
+/// POST /api/v1/auth/reset-password
+///
+/// Completes break-glass recovery: sets the account's password and turns the
+/// session into a regular one.
+pub async fn reset_password(
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    session: Session,
+    Json(req): Json<ResetPasswordRequest>,
+) -> ApiResult<Json<LoginResponse>> {
+    let user = current_user(&state, &session)
+        .await?
+        .ok_or_else(ApiError::unauthorized)?;
+    if !user.password_reset_required {
+        return Err(ApiError::forbidden(
+            "Password reset is only available to break-glass sessions",
+        ));
+    }
+    if req.new_password.is_empty() {
+        return Err(ApiError::bad_request("new_password must not be empty"));
+    }
+
+    state
+        .auth_store
+        .set_password(&user.username, &req.new_password)
+        .map_err(|e| {
+            tracing::error!(error = %e, "Failed to reset password");
+            ApiError::internal("Failed to reset password")
+        })?;
+
+    let role = state
+        .auth_store
+        .get_user(&user.username)
+        .map(|u| u.role)
+        .unwrap_or_default();
+    // The recovery session is replaced by a regular one
+    state.sessions.revoke(&user.session_id);
+    let now = Utc::now().timestamp();
+    store_session(
+        &state,
+        &session,
+        SessionUser {
+            username: user.username.clone(),
+            role,
+            logged_in_at: now,
+            last_activity: now,
+            password_reset_required: false,
+            expires_at: None,
+            session_id: String::new(),
+        },
+        client,
+    )
+    .await?;
+
+    tracing::warn!(username = %user.username, "Password reset via break-glass session");
+
+    Ok(Json(LoginResponse {
+        username: user.username,
     }))
 }
 
 /// POST /api/v1/auth/logout
-pub async fn logout(session: Session) -> ApiResult<Json<serde_json::Value>> {
+pub async fn logout(
+    State(state): State<Arc<AppState>>,
+    session: Session,
+) -> ApiResult<Json<serde_json::Value>> {
+    if let Ok(Some(user)) = session.get::<SessionUser>(SESSION_USER_KEY).await {
+        state.sessions.revoke(&user.session_id);
+    }
+
     // Clear session
     session.flush().await.map_err(|e| {
         tracing::error!(error = %e, "Failed to flush session");
@@ -79,22 +340,320 @@ pub async fn logout(session: Session) -> ApiResult<Json<serde_json::Value>> {
     Ok(Json(serde_json::json!({ "logged_out": true })))
 }
 
-/// GET /api/v1/auth/me
-pub async fn me(session: Session) -> ApiResult<Json<MeResponse>> {
-    // Get user from session
-    let user: Option<SessionUser> = session.get(SESSION_USER_KEY).await.map_err(|e| {
-        tracing::error!(error = %e, "Failed to read session");
-        ApiError::internal("Session error")
-    })?;
+#[derive(Debug, Default, Deserialize)]
+pub struct SessionsQuery {
+    /// List another user's sessions (admins only)
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionItem {
+    #[serde(flatten)]
+    pub info: SessionInfo,
+    /// The session making this request
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionsResponse {
+    pub username: String,
+    /// Most recently active first
+    pub items: Vec<SessionItem>,
+}
+
+/// GET /api/v1/auth/sessions
+pub async fn list_sessions(
+    State(state): State<Arc<AppState>>,
+    user: Option<Extension<SessionUser>>,
+    Query(query): Query<SessionsQuery>,
+) -> ApiResult<Json<SessionsResponse>> {
+    let user = session_user(user)?;
+    let username = match query.username {
+        Some(name) if name != user.username => {
+            require_admin(&user)?;
+            name
+        }
+        _ => user.username.clone(),
+    };
+    let items = state
+        .sessions
+        .list(&username)
+        .into_iter()
+        .map(|info| SessionItem {
+            current: info.id == user.session_id,
+            info,
+        })
+        .collect();
+    Ok(Json(SessionsResponse { username, items }))
+}
+
+/// DELETE /api/v1/auth/sessions/:id
+///
+/// Users revoke their own sessions; admins anyone's. The revoked session is
+/// logged out on its next request.
+pub async fn revoke_session(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<SessionUser>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let owner = state
+        .sessions
+        .get(&id)
+        .map(|info| info.username)
+        // Other users' session ids are not confirmed to non-admins
+        .filter(|owner| *owner == user.username || user.is_admin())
+        .ok_or_else(|| ApiError::not_found("Session"))?;
+    state.sessions.revoke(&id);
+    tracing::info!(username = %owner, revoked_by = %user.username, "Session revoked");
+
+    Ok(Json(serde_json::json!({ "id": id, "revoked": true })))
+}
+
+/// POST /api/v1/auth/sessions/revoke-others
+///
+/// Revokes every session of the current user except the one making the request.
+pub async fn revoke_other_sessions(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<SessionUser>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let revoked = state
+        .sessions
+        .revoke_others(&user.username, &user.session_id);
+    tracing::info!(username = %user.username, revoked, "Other sessions revoked");
+
+    Ok(Json(serde_json::json!({ "revoked": revoked })))
+}
 
-    match user {
+/// GET /api/v1/auth/me
+pub async fn me(
+    State(state): State<Arc<AppState>>,
+    session: Session,
+) -> ApiResult<Json<MeResponse>> {
+    let anonymous_read = state.config.server.allow_anonymous_read;
+    match current_user(&state, &session).await? {
         Some(u) => Ok(Json(MeResponse {
             username: u.username,
             logged_in: true,
+            password_reset_required: u.password_reset_required,
+            anonymous_read,
         })),
         None => Ok(Json(MeResponse {
             username: String::new(),
             logged_in: false,
+            password_reset_required: false,
+            anonymous_read,
         })),
     }
 }
+
+/// GET /api/v1/auth/status
+///
+/// External auth backends manage their own users, so they count as initialized.
+pub async fn status(State(state): State<Arc<AppState>>) -> Json<AuthStatusResponse> {
+    let user_count = state.auth_store.user_count();
+    Json(AuthStatusResponse {
+        initialized: state.config.auth.backend != AuthBackendKind::Local || user_count > 0,
+        user_count,
+    })
+}
+
+/// POST /api/v1/auth/setup
+///
+/// Creates the first admin, replacing `rstmdb-studio init` for new
+/// deployments, and logs them in. Refused once any user exists.
+pub async fn setup(
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    session: Session,
+    Json(req): Json<SetupRequest>,
+) -> ApiResult<Json<LoginResponse>> {
+    if state.config.auth.backend != AuthBackendKind::Local {
+        return Err(ApiError::bad_request(
+            "First-run setup is only available with the local auth backend",
+        ));
+    }
+    let username = req.username.trim();
+    if username.is_empty() {
+        return Err(ApiError::bad_request("username is required"));
+    }
+    if req.password.is_empty() {
+        return Err(ApiError::bad_request("password must not be empty"));
+    }
+
+    let created = state
+        .auth_store
+        .create_first_user(username, &req.password)
+        .map_err(|e| {
+            tracing::error!(error = %e, "Failed to create first admin user");
+            ApiError::internal("Failed to create user")
+        })?;
+    if !created {
+        return Err(ApiError::conflict("Studio is already initialized"));
+    }
+
+    let now = Utc::now().timestamp();
+    store_session(
+        &state,
+        &session,
+        SessionUser {
+            username: username.to_string(),
+            role: Role::Admin,
+            logged_in_at: now,
+            last_activity: now,
+            password_reset_required: false,
+            expires_at: None,
+            session_id: String::new(),
+        },
+        client,
+    )
+    .await?;
+
+    tracing::warn!(username = %username, "First admin user created via setup");
+
+    Ok(Json(LoginResponse {
+        username: username.to_string(),
+    }))
+}
+
+/// GET /api/v1/auth/banner
+pub async fn banner(State(state): State<Arc<AppState>>) -> Json<BannerResponse> {
+    Json(BannerResponse {
+        banner: state.live.read().login_banner.clone().unwrap_or_default(),
+    })
+}
+
+/// Load the session user, enforcing the idle and absolute session timeouts.
+///
+/// Expired sessions are flushed and treated as logged out; live sessions get
+/// their `last_activity` refreshed.
+pub async fn current_user(state: &AppState, session: &Session) -> ApiResult<Option<SessionUser>> {
+    let user: Option<SessionUser> = session.get(SESSION_USER_KEY).await.map_err(|e| {
+        tracing::error!(error = %e, "Failed to read session");
+        ApiError::internal("Session error")
+    })?;
+
+    let Some(mut user) = user else {
+        return Ok(None);
+    };
+
+    let now = Utc::now().timestamp();
+    let timeouts = &state.session_timeouts;
+    let expired = if now - user.last_activity > timeouts.idle.as_secs() as i64 {
+        Some("idle_timeout")
+    } else if now - user.logged_in_at > timeouts.max_lifetime.as_secs() as i64 {
+        Some("max_lifetime")
+    } else if user.expires_at.is_some_and(|at| now >= at) {
+        Some("expired")
+    } else if !state.sessions.touch(&user.session_id, now) {
+        Some("revoked")
+    } else {
+        None
+    };
+
+    if let Some(reason) = expired {
+        tracing::info!(username = %user.username, reason, "Session expired");
+        state.sessions.revoke(&user.session_id);
+        session.flush().await.map_err(|e| {
+            tracing::error!(error = %e, "Failed to flush session");
+            ApiError::internal("Session error")
+        })?;
+        return Ok(None);
+    }
+
+    user.last_activity = now;
+    session.insert(SESSION_USER_KEY, &user).await.map_err(|e| {
+        tracing::error!(error = %e, "Failed to update session");
+        ApiError::internal("Session error")
+    })?;
+
+    Ok(Some(user))
+}
+
+/// Methods that never change state
+fn is_read(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD)
+}
+
+/// Browser navigation to an SPA page, as opposed to an asset or XHR
+fn is_page_load(req: &Request) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+        && req.uri().path() != LOGIN_PAGE_PATH
+}
+
+/// Frontend fallback: serves the SPA unchanged, but marks page loads without
+/// a session with `X-Auth-Required: true` when anonymous reads are off. The
+/// SPA still renders (and routes to its login form); API paths never get
+/// here and keep answering JSON 401.
+pub async fn spa_fallback(
+    State(state): State<Arc<AppState>>,
+    session: Session,
+    req: Request,
+) -> Response {
+    let login_required = !state.config.server.allow_anonymous_read
+        && is_page_load(&req)
+        && matches!(current_user(&state, &session).await, Ok(None));
+    let mut response = static_handler(req).await;
+    if login_required {
+        response
+            .headers_mut()
+            .insert(X_AUTH_REQUIRED, HeaderValue::from_static("true"));
+    }
+    response
+}
+
+/// The user a verified client certificate on the request's connection
+/// authenticates as, per `server.tls.client_cert_roles`
+fn certificate_user(state: &AppState, req: &Request) -> Option<SessionUser> {
+    let cert = req.extensions().get::<ClientCert>()?;
+    let role = *state
+        .config
+        .server
+        .tls
+        .client_cert_roles
+        .get(&cert.common_name)?;
+    let now = Utc::now().timestamp();
+    Some(SessionUser {
+        username: cert.common_name.clone(),
+        role,
+        logged_in_at: now,
+        last_activity: now,
+        password_reset_required: false,
+        expires_at: None,
+        session_id: String::new(),
+    })
+}
+
+/// Middleware rejecting requests without a live session
+///
+/// A client certificate listed in `server.tls.client_cert_roles` stands in
+/// for a session. The authenticated `SessionUser` is inserted into request
+/// extensions. With
+/// `server.allow_anonymous_read`, reads without a session pass through with no
+/// `SessionUser`; writes still require one.
+pub async fn require_auth(
+    State(state): State<Arc<AppState>>,
+    session: Session,
+    mut req: Request,
+    next: Next,
+) -> ApiResult<Response> {
+    let user = match current_user(&state, &session).await? {
+        Some(user) => Some(user),
+        None => certificate_user(&state, &req),
+    };
+    let Some(user) = user else {
+        if state.config.server.allow_anonymous_read && is_read(req.method()) {
+            return Ok(next.run(req).await);
+        }
+        return Err(ApiError::unauthorized());
+    };
+    if user.password_reset_required {
+        return Err(ApiError::forbidden(
+            "Password reset required before this session can be used",
+        ));
+    }
+    req.extensions_mut().insert(user);
+    Ok(next.run(req).await)
+}
@@ -0,0 +1,101 @@
+//! Request body size limits
+//!
+//! `tower_http`'s `RequestBodyLimitLayer` rejects oversized bodies up front
+//! when `Content-Length` is sent and caps streamed bodies while they are
+//! read. Either way the client gets a plain-text `413`, which is rewritten
+//! into a `BODY_TOO_LARGE` API error.
+
+use crate::error::ApiError;
+use axum::{
+    extract::DefaultBodyLimit,
+    http::{header, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    Router,
+};
+use tower_http::limit::RequestBodyLimitLayer;
+
+/// Reject request bodies to `router`'s routes larger than `max_bytes`
+pub fn with_body_limit<S>(router: Router<S>, max_bytes: usize) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router
+        // Replaces axum's 2 MB default for extractors such as `Json`
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(max_bytes))
+        .layer(middleware::map_response(
+            move |response: Response| async move { into_api_error(response, max_bytes) },
+        ))
+}
+
+/// Replace a non-JSON `413` from the limit layer or an extractor
+fn into_api_error(response: Response, max_bytes: usize) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || is_json {
+        return response;
+    }
+    ApiError::body_too_large(format!(
+        "Request body exceeds the limit of {} bytes",
+        max_bytes
+    ))
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Json};
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn spawn(max_bytes: usize) -> SocketAddr {
+        let echo = |Json(v): Json<serde_json::Value>| async move { Json(v) };
+        let router = with_body_limit(Router::new().route("/", post(echo)), max_bytes);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body_rejected() {
+        let addr = spawn(16).await;
+        let url = format!("http://{}/", addr);
+        let client = reqwest::Client::new();
+        let post = |body: String| {
+            client
+                .post(&url)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body)
+                .send()
+        };
+
+        let small = post(r#"{"a":1}"#.to_string()).await.unwrap();
+        assert_eq!(small.status(), StatusCode::OK);
+
+        let large = format!(r#"{{"a":"{}"}}"#, "x".repeat(64));
+        let rejected = post(large.clone()).await.unwrap();
+        assert_eq!(rejected.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body: serde_json::Value = rejected.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "BODY_TOO_LARGE");
+
+        // Without Content-Length the limit applies while the body is read
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "POST / HTTP/1.1\r\nHost: test\r\nContent-Type: application/json\r\n\
+             Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            large.len(),
+            large
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+        assert!(response.contains("BODY_TOO_LARGE"));
+    }
+}
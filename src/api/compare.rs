@@ -0,0 +1,273 @@
+//! Side-by-side comparison of two instances
+//!
+//! For "why did this one fail but that one succeed" investigations: the
+//! states, a context diff and the paths both instances took through the
+//! machine, split where they diverged. Contexts are redacted before they are
+//! compared, so a diff never reveals a redacted value.
+
+use crate::api::instances::instance_wal_entries;
+use crate::constants::wal_entry_types;
+use crate::error::{ApiError, ApiResult};
+use crate::json_ext::ValueExt;
+use crate::AppState;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct CompareQuery {
+    pub a: String,
+    pub b: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComparedInstance {
+    pub instance_id: String,
+    pub version: u32,
+    pub state: String,
+    pub last_wal_offset: u64,
+}
+
+/// A context value that differs; omitted on the side missing the key
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ContextChange {
+    /// JSON pointer into the context
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub a: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub b: Option<Value>,
+}
+
+/// One state entered along an instance's path
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PathStep {
+    /// Event that entered the state, `None` for the initial state
+    pub event: Option<String>,
+    pub state: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PathComparison {
+    /// Steps both instances took, in order
+    pub common: Vec<PathStep>,
+    /// Index into the paths of the first step that differs, `None` if one
+    /// path is a prefix of the other
+    pub diverged_at: Option<usize>,
+    /// Steps after the common prefix
+    pub a_only: Vec<PathStep>,
+    pub b_only: Vec<PathStep>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompareResponse {
+    pub machine: String,
+    pub a: ComparedInstance,
+    pub b: ComparedInstance,
+    pub same_state: bool,
+    pub context_diff: Vec<ContextChange>,
+    pub history: PathComparison,
+}
+
+/// Differences between two contexts, by JSON pointer. Objects are compared
+/// key by key; anything else, arrays included, is compared whole.
+fn context_diff(a: &Value, b: &Value, path: &str, changes: &mut Vec<ContextChange>) {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => context_diff(a, b, &child, changes),
+                    (a, b) => changes.push(ContextChange {
+                        path: child,
+                        a: a.cloned(),
+                        b: b.cloned(),
+                    }),
+                }
+            }
+        }
+        (a, b) if a != b => changes.push(ContextChange {
+            path: path.to_string(),
+            a: Some(a.clone()),
+            b: Some(b.clone()),
+        }),
+        _ => {}
+    }
+}
+
+/// States an instance entered, in order, from its WAL entries
+fn path_steps(entries: &[(u64, Value)]) -> Vec<PathStep> {
+    entries
+        .iter()
+        .filter_map(|(_, entry)| match entry["type"].as_str() {
+            Some(wal_entry_types::CREATE_INSTANCE) => Some(PathStep {
+                event: None,
+                state: entry.str_or_empty("initial_state"),
+            }),
+            Some(wal_entry_types::APPLY_EVENT) => Some(PathStep {
+                event: Some(entry.str_or_empty("event")),
+                state: entry.str_or_empty("to_state"),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn compare_paths(a: Vec<PathStep>, b: Vec<PathStep>) -> PathComparison {
+    let shared = a.iter().zip(&b).take_while(|(a, b)| a == b).count();
+    let diverged_at = (shared < a.len() && shared < b.len()).then_some(shared);
+    let mut a = a;
+    let a_only = a.split_off(shared);
+    PathComparison {
+        common: a,
+        diverged_at,
+        a_only,
+        b_only: b.into_iter().skip(shared).collect(),
+    }
+}
+
+/// GET /api/v1/instances/compare?a=ID1&b=ID2
+///
+/// Both instances must belong to the same machine. Histories cover the same
+/// bounded WAL scan as `GET .../history`.
+pub async fn compare_instances(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CompareQuery>,
+) -> ApiResult<Json<CompareResponse>> {
+    let (a, b) = tokio::try_join!(
+        state.rstmdb.get_instance(&query.a),
+        state.rstmdb.get_instance(&query.b)
+    )?;
+    if a.machine != b.machine {
+        return Err(ApiError::bad_request(format!(
+            "Instances belong to different machines ('{}' and '{}')",
+            a.machine, b.machine
+        )));
+    }
+
+    let a_entries = instance_wal_entries(&state, &a.instance_id, a.last_wal_offset).await?;
+    let b_entries = instance_wal_entries(&state, &b.instance_id, b.last_wal_offset).await?;
+
+    let (mut a_ctx, mut b_ctx) = (a.ctx, b.ctx);
+    state.redactor.redact(&mut a_ctx);
+    state.redactor.redact(&mut b_ctx);
+    let mut changes = Vec::new();
+    context_diff(&a_ctx, &b_ctx, "", &mut changes);
+
+    Ok(Json(CompareResponse {
+        machine: a.machine,
+        same_state: a.state == b.state,
+        a: ComparedInstance {
+            instance_id: a.instance_id,
+            version: a.version,
+            state: a.state,
+            last_wal_offset: a.last_wal_offset,
+        },
+        b: ComparedInstance {
+            instance_id: b.instance_id,
+            version: b.version,
+            state: b.state,
+            last_wal_offset: b.last_wal_offset,
+        },
+        context_diff: changes,
+        history: compare_paths(path_steps(&a_entries), path_steps(&b_entries)),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_context_diff_by_pointer() {
+        let a = json!({
+            "amount": 100,
+            "customer": { "tier": "gold", "a/b": 1 },
+            "items": [1, 2],
+            "only_a": true
+        });
+        let b = json!({
+            "amount": 100,
+            "customer": { "tier": "silver", "a/b": 1 },
+            "items": [1, 2, 3],
+            "only_b": null
+        });
+        let mut changes = Vec::new();
+        context_diff(&a, &b, "", &mut changes);
+        assert_eq!(
+            changes,
+            vec![
+                ContextChange {
+                    path: "/customer/tier".to_string(),
+                    a: Some(json!("gold")),
+                    b: Some(json!("silver")),
+                },
+                ContextChange {
+                    path: "/items".to_string(),
+                    a: Some(json!([1, 2])),
+                    b: Some(json!([1, 2, 3])),
+                },
+                ContextChange {
+                    path: "/only_a".to_string(),
+                    a: Some(json!(true)),
+                    b: None,
+                },
+                ContextChange {
+                    path: "/only_b".to_string(),
+                    a: None,
+                    b: Some(json!(null)),
+                },
+            ]
+        );
+
+        let mut changes = Vec::new();
+        context_diff(&a, &a, "", &mut changes);
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn test_compare_paths_finds_divergence() {
+        let entry = |event: Option<&str>, state: &str| match event {
+            None => (
+                0,
+                json!({"type": "create_instance", "initial_state": state}),
+            ),
+            Some(event) => (
+                0,
+                json!({"type": "apply_event", "event": event, "to_state": state}),
+            ),
+        };
+        let a = path_steps(&[
+            entry(None, "pending"),
+            entry(Some("PAY"), "paid"),
+            (0, json!({"type": "put_machine"})),
+            entry(Some("SHIP"), "shipped"),
+        ]);
+        let b = path_steps(&[
+            entry(None, "pending"),
+            entry(Some("PAY"), "paid"),
+            entry(Some("REFUND"), "refunded"),
+        ]);
+
+        let comparison = compare_paths(a.clone(), b);
+        assert_eq!(comparison.common.len(), 2);
+        assert_eq!(comparison.diverged_at, Some(2));
+        assert_eq!(comparison.a_only[0].state, "shipped");
+        assert_eq!(comparison.b_only[0].event.as_deref(), Some("REFUND"));
+
+        // One path still on the way along the other's
+        let behind = a[..2].to_vec();
+        let comparison = compare_paths(behind, a);
+        assert_eq!(comparison.diverged_at, None);
+        assert!(comparison.a_only.is_empty());
+        assert_eq!(comparison.b_only.len(), 1);
+    }
+}
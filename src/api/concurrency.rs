@@ -0,0 +1,98 @@
+//! Limit on requests handled at once
+//!
+//! Requests beyond `server.max_in_flight_requests` are shed immediately with
+//! `RATE_LIMITED` (`503`) rather than queued, so an overloaded Studio fails
+//! fast instead of accumulating unbounded work. Health probes are not
+//! limited.
+
+use crate::error::ApiError;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+#[derive(Debug)]
+pub struct InFlightLimit {
+    permits: Arc<Semaphore>,
+    max: usize,
+}
+
+impl InFlightLimit {
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(max)),
+            max,
+        }
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Requests currently being handled
+    pub fn in_flight(&self) -> usize {
+        self.max - self.permits.available_permits()
+    }
+}
+
+/// Run the request if a slot is free, otherwise reject it
+pub async fn enforce(
+    State(limit): State<Arc<InFlightLimit>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Ok(_permit) = limit.permits.clone().try_acquire_owned() else {
+        tracing::warn!(max = limit.max, "Shedding request: too many in flight");
+        return ApiError::rate_limited(format!(
+            "Server is handling the maximum of {} requests; retry shortly",
+            limit.max
+        ))
+        .into_response();
+    };
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, middleware, routing::get, Router};
+    use tokio::sync::Notify;
+
+    #[tokio::test]
+    async fn test_excess_requests_are_shed() {
+        let limit = Arc::new(InFlightLimit::new(1));
+        let release = Arc::new(Notify::new());
+        let waiting = release.clone();
+        let router = Router::new()
+            .route(
+                "/slow",
+                get(move || async move {
+                    waiting.notified().await;
+                    "done"
+                }),
+            )
+            .layer(middleware::from_fn_with_state(limit.clone(), enforce));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/slow", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let first = tokio::spawn(reqwest::get(url.clone()));
+        while limit.in_flight() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let shed = reqwest::get(&url).await.unwrap();
+        assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = shed.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "RATE_LIMITED");
+
+        release.notify_one();
+        let first = first.await.unwrap().unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(limit.in_flight(), 0);
+    }
+}
@@ -0,0 +1,110 @@
+//! Content-Type enforcement for JSON request bodies
+
+use crate::error::{ApiError, ApiResult};
+use axum::{
+    extract::Request,
+    http::{header, HeaderMap, Method},
+    middleware::Next,
+    response::Response,
+};
+
+/// Whether a request with this method and headers carries a body
+fn has_body(method: &Method, headers: &HeaderMap) -> bool {
+    if !matches!(*method, Method::POST | Method::PUT | Method::PATCH) {
+        return false;
+    }
+    if headers.contains_key(header::TRANSFER_ENCODING) {
+        return true;
+    }
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .is_some_and(|len| len > 0)
+}
+
+/// Whether a Content-Type value is JSON (`application/json` or `application/*+json`)
+fn is_json(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    essence == "application/json"
+        || (essence.starts_with("application/") && essence.ends_with("+json"))
+}
+
+fn check_content_type(method: &Method, headers: &HeaderMap) -> ApiResult<()> {
+    if !has_body(method, headers) {
+        return Ok(());
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    match content_type {
+        Some(ct) if is_json(ct) => Ok(()),
+        Some(ct) => Err(ApiError::bad_request(format!(
+            "Expected application/json, got '{}'",
+            ct
+        ))),
+        None => Err(ApiError::bad_request(
+            "Expected application/json, Content-Type header is missing",
+        )),
+    }
+}
+
+/// Middleware rejecting POST/PUT/PATCH bodies that are not declared as JSON.
+///
+/// Bodiless requests (e.g. logout) pass through untouched.
+pub async fn require_json(req: Request, next: Next) -> ApiResult<Response> {
+    check_content_type(req.method(), req.headers())?;
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(content_type: Option<&str>, length: usize) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(ct) = content_type {
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(ct).unwrap());
+        }
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+        headers
+    }
+
+    #[test]
+    fn test_form_encoded_body_rejected() {
+        let headers = headers(Some("application/x-www-form-urlencoded"), 12);
+        let err = check_content_type(&Method::POST, &headers).unwrap_err();
+        assert_eq!(err.code, "BAD_REQUEST");
+        assert!(err.message.starts_with("Expected application/json"));
+    }
+
+    #[test]
+    fn test_missing_content_type_rejected() {
+        let err = check_content_type(&Method::PATCH, &headers(None, 2)).unwrap_err();
+        assert_eq!(err.code, "BAD_REQUEST");
+    }
+
+    #[test]
+    fn test_json_variants_accepted() {
+        for ct in [
+            "application/json",
+            "application/json; charset=utf-8",
+            "Application/JSON",
+            "application/merge-patch+json",
+        ] {
+            assert!(check_content_type(&Method::POST, &headers(Some(ct), 2)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_bodiless_and_get_requests_pass() {
+        assert!(check_content_type(&Method::POST, &headers(None, 0)).is_ok());
+        assert!(check_content_type(&Method::GET, &headers(Some("text/plain"), 5)).is_ok());
+    }
+}
@@ -0,0 +1,253 @@
+//! Machine definition drafts
+//!
+//! Server-side autosave for the builder, so work in progress survives a
+//! browser crash. Each user has at most one draft per machine name, kept in
+//! `<data_dir>/drafts.json`. Drafts are only checked to be JSON objects: they
+//! are often invalid mid-edit, and never reach rstmdb until the builder
+//! publishes through `POST .../versions` and discards the draft.
+
+use crate::api::auth::{session_user, SessionUser};
+use crate::constants::drafts::MAX_DRAFTS_PER_USER;
+use crate::error::{ApiError, ApiResult};
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use chrono::Utc;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Draft {
+    pub definition: Value,
+    /// Epoch milliseconds
+    pub saved_at: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DraftData {
+    /// Username to machine name to draft
+    users: HashMap<String, HashMap<String, Draft>>,
+}
+
+/// Drafts backed by a JSON file
+pub struct DraftStore {
+    path: PathBuf,
+    data: RwLock<DraftData>,
+}
+
+impl DraftStore {
+    pub fn new(path: &PathBuf) -> Self {
+        let data = if path.exists() {
+            let content = std::fs::read_to_string(path).unwrap_or_default();
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            DraftData::default()
+        };
+
+        Self {
+            path: path.clone(),
+            data: RwLock::new(data),
+        }
+    }
+
+    pub fn get(&self, username: &str, machine: &str) -> Option<Draft> {
+        self.data.read().users.get(username)?.get(machine).cloned()
+    }
+
+    /// Save or replace a draft; returns false if the user already has
+    /// `MAX_DRAFTS_PER_USER` drafts of other machines
+    pub fn save(&self, username: &str, machine: &str, draft: Draft) -> anyhow::Result<bool> {
+        {
+            let mut data = self.data.write();
+            let drafts = data.users.entry(username.to_string()).or_default();
+            if !drafts.contains_key(machine) && drafts.len() >= MAX_DRAFTS_PER_USER {
+                return Ok(false);
+            }
+            drafts.insert(machine.to_string(), draft);
+        }
+        self.save_file()?;
+        Ok(true)
+    }
+
+    /// Drop a draft; returns false if there was none
+    pub fn discard(&self, username: &str, machine: &str) -> anyhow::Result<bool> {
+        {
+            let mut data = self.data.write();
+            let Some(drafts) = data.users.get_mut(username) else {
+                return Ok(false);
+            };
+            if drafts.remove(machine).is_none() {
+                return Ok(false);
+            }
+            if drafts.is_empty() {
+                data.users.remove(username);
+            }
+        }
+        self.save_file()?;
+        Ok(true)
+    }
+
+    fn save_file(&self) -> anyhow::Result<()> {
+        let data = self.data.read();
+        let content = serde_json::to_string_pretty(&*data)?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(&self.path, content)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveDraftRequest {
+    pub definition: Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DraftResponse {
+    pub machine: String,
+    #[serde(flatten)]
+    pub draft: Draft,
+}
+
+/// GET /api/v1/machines/:name/draft
+pub async fn get_draft(
+    State(state): State<Arc<AppState>>,
+    user: Option<Extension<SessionUser>>,
+    Path(name): Path<String>,
+) -> ApiResult<Json<DraftResponse>> {
+    let user = session_user(user)?;
+    let draft = state
+        .drafts
+        .get(&user.username, &name)
+        .ok_or_else(|| ApiError::not_found("Draft"))?;
+    Ok(Json(DraftResponse {
+        machine: name,
+        draft,
+    }))
+}
+
+/// PUT /api/v1/machines/:name/draft
+pub async fn save_draft(
+    State(state): State<Arc<AppState>>,
+    user: Option<Extension<SessionUser>>,
+    Path(name): Path<String>,
+    Json(req): Json<SaveDraftRequest>,
+) -> ApiResult<Json<DraftResponse>> {
+    let user = session_user(user)?;
+    if !req.definition.is_object() {
+        return Err(ApiError::bad_request(
+            "Draft definition must be a JSON object",
+        ));
+    }
+
+    let draft = Draft {
+        definition: req.definition,
+        saved_at: Utc::now().timestamp_millis(),
+    };
+    let saved = state
+        .drafts
+        .save(&user.username, &name, draft.clone())
+        .map_err(|e| ApiError::internal(format!("Failed to save drafts: {}", e)))?;
+    if !saved {
+        return Err(ApiError::conflict(format!(
+            "At most {} drafts can be kept; discard one first",
+            MAX_DRAFTS_PER_USER
+        )));
+    }
+    tracing::debug!(machine = %name, username = %user.username, "Draft saved");
+
+    Ok(Json(DraftResponse {
+        machine: name,
+        draft,
+    }))
+}
+
+/// DELETE /api/v1/machines/:name/draft
+///
+/// `204` whether or not a draft existed, so publishing can always discard.
+pub async fn discard_draft(
+    State(state): State<Arc<AppState>>,
+    user: Option<Extension<SessionUser>>,
+    Path(name): Path<String>,
+) -> ApiResult<StatusCode> {
+    let user = session_user(user)?;
+    state
+        .drafts
+        .discard(&user.username, &name)
+        .map_err(|e| ApiError::internal(format!("Failed to save drafts: {}", e)))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_drafts_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("rstmdb-studio-test-{}", uuid::Uuid::new_v4()))
+            .join("drafts.json")
+    }
+
+    fn draft(states: Value) -> Draft {
+        Draft {
+            definition: json!({ "states": states }),
+            saved_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_drafts_are_per_user_and_persist() {
+        let path = temp_drafts_path();
+        let store = DraftStore::new(&path);
+        assert!(store.save("ada", "orders", draft(json!(["a"]))).unwrap());
+        assert!(store
+            .save("ada", "orders", draft(json!(["a", "b"])))
+            .unwrap());
+        assert!(store.save("bob", "orders", draft(json!(["x"]))).unwrap());
+
+        let reloaded = DraftStore::new(&path);
+        assert_eq!(
+            reloaded.get("ada", "orders").unwrap().definition,
+            json!({ "states": ["a", "b"] })
+        );
+        assert_eq!(
+            reloaded.get("bob", "orders").unwrap().definition,
+            json!({ "states": ["x"] })
+        );
+        assert!(reloaded.get("ada", "refunds").is_none());
+
+        assert!(reloaded.discard("ada", "orders").unwrap());
+        assert!(!reloaded.discard("ada", "orders").unwrap());
+        let reloaded = DraftStore::new(&path);
+        assert!(reloaded.get("ada", "orders").is_none());
+        assert!(reloaded.get("bob", "orders").is_some());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_draft_count_is_capped_per_user() {
+        let path = temp_drafts_path();
+        let store = DraftStore::new(&path);
+        for i in 0..MAX_DRAFTS_PER_USER {
+            assert!(store
+                .save("ada", &format!("m{}", i), draft(json!([])))
+                .unwrap());
+        }
+        assert!(!store.save("ada", "one-more", draft(json!([]))).unwrap());
+        // Replacing an existing draft and other users are unaffected
+        assert!(store.save("ada", "m0", draft(json!(["a"]))).unwrap());
+        assert!(store.save("bob", "one-more", draft(json!([]))).unwrap());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,188 @@
+//! Duplicate machine detection
+//!
+//! Teams sometimes register the same definition under different names.
+//! Machines whose latest versions share a definition checksum (see
+//! [`crate::checksum`]) are reported together so they can be consolidated.
+//! Versions are immutable, so checksums are cached per `(machine, version)`.
+
+use crate::api::machines::latest_versions;
+use crate::checksum::definition_checksum;
+use crate::constants::machines::{CHECKSUM_CACHE_MAX_ENTRIES, DUPLICATES_CONCURRENCY};
+use crate::error::{ApiError, ApiResult};
+use crate::rstmdb;
+use crate::AppState;
+use axum::{extract::State, Json};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Definition checksums of machine versions
+#[derive(Default)]
+pub struct ChecksumCache {
+    entries: Mutex<HashMap<(String, u32), String>>,
+}
+
+impl ChecksumCache {
+    fn get(&self, machine: &str, version: u32) -> Option<String> {
+        self.entries
+            .lock()
+            .get(&(machine.to_string(), version))
+            .cloned()
+    }
+
+    fn insert(&self, machine: String, version: u32, checksum: String) {
+        let mut entries = self.entries.lock();
+        if entries.len() >= CHECKSUM_CACHE_MAX_ENTRIES {
+            entries.clear();
+        }
+        entries.insert((machine, version), checksum);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DuplicateMachine {
+    pub machine: String,
+    pub version: u32,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DuplicateCluster {
+    pub checksum: String,
+    /// Sorted by machine name
+    pub machines: Vec<DuplicateMachine>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicatesResponse {
+    pub clusters: Vec<DuplicateCluster>,
+    pub machines_checked: usize,
+    /// Machines whose latest definition could not be fetched
+    pub failed: Vec<String>,
+}
+
+/// Groups of two or more machines with the same checksum, largest first
+fn cluster(checksums: Vec<(DuplicateMachine, String)>) -> Vec<DuplicateCluster> {
+    let mut groups: BTreeMap<String, Vec<DuplicateMachine>> = BTreeMap::new();
+    for (machine, checksum) in checksums {
+        groups.entry(checksum).or_default().push(machine);
+    }
+    let mut clusters: Vec<DuplicateCluster> = groups
+        .into_iter()
+        .filter(|(_, machines)| machines.len() > 1)
+        .map(|(checksum, mut machines)| {
+            machines.sort_by(|a, b| a.machine.cmp(&b.machine));
+            DuplicateCluster { checksum, machines }
+        })
+        .collect();
+    clusters.sort_by(|a, b| {
+        b.machines
+            .len()
+            .cmp(&a.machines.len())
+            .then_with(|| a.machines[0].machine.cmp(&b.machines[0].machine))
+    });
+    clusters
+}
+
+/// Checksum of a machine version's definition, from the cache or rstmdb
+async fn version_checksum(state: &AppState, machine: &str, version: u32) -> ApiResult<String> {
+    if let Some(checksum) = state.definition_checksums.get(machine, version) {
+        return Ok(checksum);
+    }
+    let result = state.rstmdb.get_machine(machine, version).await?;
+    let checksum = definition_checksum(&result["definition"]);
+    state
+        .definition_checksums
+        .insert(machine.to_string(), version, checksum.clone());
+    Ok(checksum)
+}
+
+/// GET /api/v1/machines/duplicates
+///
+/// Compares the latest version of every machine; builder layout is ignored.
+pub async fn find_duplicates(
+    State(state): State<Arc<AppState>>,
+) -> ApiResult<Json<DuplicatesResponse>> {
+    let latest = latest_versions(&state).await?;
+    let machines_checked = latest.len();
+
+    let permits = Arc::new(Semaphore::new(DUPLICATES_CONCURRENCY));
+    let priority = rstmdb::current_priority();
+    let budget = rstmdb::current_budget();
+    let mut fetches = JoinSet::new();
+    for (machine, version) in latest {
+        let state = state.clone();
+        let permits = permits.clone();
+        let fetch = rstmdb::with_priority(priority, async move {
+            let _permit = permits.acquire_owned().await;
+            let result = version_checksum(&state, &machine, version).await;
+            (DuplicateMachine { machine, version }, result)
+        });
+        fetches.spawn(rstmdb::with_budget(budget.clone(), fetch));
+    }
+
+    let mut checksums = Vec::with_capacity(machines_checked);
+    let mut failed = Vec::new();
+    while let Some(joined) = fetches.join_next().await {
+        let (machine, result) =
+            joined.map_err(|e| ApiError::internal(format!("Checksum fetch failed: {}", e)))?;
+        match result {
+            Ok(checksum) => checksums.push((machine, checksum)),
+            Err(e) => {
+                tracing::warn!(machine = %machine.machine, error = %e.message, "Failed to checksum machine");
+                failed.push(machine.machine);
+            }
+        }
+    }
+    failed.sort();
+
+    Ok(Json(DuplicatesResponse {
+        clusters: cluster(checksums),
+        machines_checked,
+        failed,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(name: &str, version: u32) -> DuplicateMachine {
+        DuplicateMachine {
+            machine: name.to_string(),
+            version,
+        }
+    }
+
+    #[test]
+    fn test_cluster_groups_shared_checksums() {
+        let clusters = cluster(vec![
+            (machine("orders", 3), "aa".to_string()),
+            (machine("payments", 1), "bb".to_string()),
+            (machine("order-flow", 1), "aa".to_string()),
+            (machine("refunds", 2), "cc".to_string()),
+            (machine("checkout", 4), "aa".to_string()),
+            (machine("refund", 1), "cc".to_string()),
+        ]);
+        assert_eq!(
+            clusters,
+            vec![
+                DuplicateCluster {
+                    checksum: "aa".to_string(),
+                    machines: vec![
+                        machine("checkout", 4),
+                        machine("order-flow", 1),
+                        machine("orders", 3)
+                    ],
+                },
+                DuplicateCluster {
+                    checksum: "cc".to_string(),
+                    machines: vec![machine("refund", 1), machine("refunds", 2)],
+                },
+            ]
+        );
+        assert!(cluster(vec![(machine("solo", 1), "aa".to_string())]).is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+//! Response envelope
+//!
+//! With `server.response_envelope` on, successful JSON responses are wrapped
+//! as `{ "data": ... }` so every response is either `{ data }` or
+//! `{ error }`. Errors already have that shape, and non-JSON bodies (diagrams,
+//! text diffs), downloads (packages) and empty responses are left alone. Wrapped
+//! responses carry `X-Response-Envelope: data` so a client can tell an
+//! envelope from a bare body that happens to have a `data` key.
+
+use crate::error::ApiError;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+pub const X_RESPONSE_ENVELOPE: HeaderName = HeaderName::from_static("x-response-envelope");
+
+/// Wrap successful JSON responses when `enabled`
+pub async fn wrap(State(enabled): State<bool>, req: Request, next: Next) -> Response {
+    apply(enabled, next.run(req).await).await
+}
+
+async fn apply(enabled: bool, response: Response) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/json"));
+    // Downloads are streamed and saved as they are
+    let is_attachment = response.headers().contains_key(header::CONTENT_DISPOSITION);
+    if !enabled || !response.status().is_success() || !is_json || is_attachment {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return ApiError::internal(format!("Failed to read response: {}", e)).into_response()
+        }
+    };
+    let data: Value = match serde_json::from_slice(&bytes) {
+        Ok(data) => data,
+        // Not actually JSON; pass it on as it was
+        Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+    };
+    let wrapped = serde_json::json!({ "data": data }).to_string();
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(X_RESPONSE_ENVELOPE, HeaderValue::from_static("data"));
+    Response::from_parts(parts, Body::from(wrapped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, Json};
+    use serde_json::json;
+
+    async fn body_json(response: Response) -> Value {
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_enveloped_mode_wraps_successful_json() {
+        let wrapped = apply(true, Json(json!({"id": "a"})).into_response()).await;
+        assert_eq!(wrapped.status(), StatusCode::OK);
+        assert_eq!(wrapped.headers()[X_RESPONSE_ENVELOPE], "data");
+        assert_eq!(body_json(wrapped).await, json!({"data": {"id": "a"}}));
+
+        let list = apply(true, Json(json!([1, 2])).into_response()).await;
+        assert_eq!(body_json(list).await, json!({"data": [1, 2]}));
+
+        // Errors keep their `{ error }` body
+        let error = apply(true, ApiError::not_found("Machine").into_response()).await;
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
+        assert!(error.headers().get(X_RESPONSE_ENVELOPE).is_none());
+        assert_eq!(body_json(error).await["error"]["code"], "NOT_FOUND");
+
+        let text = apply(true, "--- v1\n+++ v2\n".into_response()).await;
+        assert!(text.headers().get(X_RESPONSE_ENVELOPE).is_none());
+        let bytes = to_bytes(text.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&bytes[..], b"--- v1\n+++ v2\n");
+
+        let download = apply(
+            true,
+            (
+                [(header::CONTENT_DISPOSITION, "attachment")],
+                Json(json!({"id": "a"})),
+            )
+                .into_response(),
+        )
+        .await;
+        assert_eq!(body_json(download).await, json!({"id": "a"}));
+
+        let empty = apply(true, StatusCode::NO_CONTENT.into_response()).await;
+        assert_eq!(empty.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_bare_mode_leaves_responses_unchanged() {
+        let bare = apply(false, Json(json!({"id": "a"})).into_response()).await;
+        assert!(bare.headers().get(X_RESPONSE_ENVELOPE).is_none());
+        assert_eq!(body_json(bare).await, json!({"id": "a"}));
+
+        let error = apply(false, ApiError::not_found("Machine").into_response()).await;
+        assert_eq!(body_json(error).await["error"]["code"], "NOT_FOUND");
+    }
+}
@@ -0,0 +1,109 @@
+//! Apply-event deduplication
+//!
+//! A client may send an `event_id` (UUID) with an event so retries are safe.
+//! The id is passed to rstmdb as the idempotency key, and Studio remembers
+//! the result of each applied id for `server.event_dedup_window`: a retry
+//! inside the window gets the original result back with `applied: false`,
+//! before Studio's own checks (which would reject a retry once the instance
+//! has moved on) run again.
+
+use crate::constants::instances::EVENT_DEDUP_MAX_ENTRIES;
+use crate::rstmdb::ApplyEventResult;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+type Key = (String, String);
+
+/// Results of recently applied events by `(instance_id, event_id)`
+pub struct AppliedEvents {
+    window: Duration,
+    entries: Mutex<HashMap<Key, (Instant, ApplyEventResult)>>,
+}
+
+impl AppliedEvents {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The original result of `event_id`, marked as not applied again
+    pub fn replay(&self, instance_id: &str, event_id: &str) -> Option<ApplyEventResult> {
+        let key = (instance_id.to_string(), event_id.to_string());
+        let entries = self.entries.lock();
+        entries
+            .get(&key)
+            .filter(|(applied_at, _)| applied_at.elapsed() < self.window)
+            .map(|(_, result)| ApplyEventResult {
+                applied: false,
+                ..result.clone()
+            })
+    }
+
+    pub fn record(&self, instance_id: &str, event_id: &str, result: &ApplyEventResult) {
+        if self.window.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock();
+        entries.retain(|_, (applied_at, _)| applied_at.elapsed() < self.window);
+        if entries.len() >= EVENT_DEDUP_MAX_ENTRIES {
+            // Oldest first, so the ids most likely to be retried survive
+            if let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, (applied_at, _))| *applied_at)
+                .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            (instance_id.to_string(), event_id.to_string()),
+            (Instant::now(), result.clone()),
+        );
+    }
+
+    /// Remembered results, including expired ones not yet evicted
+    pub fn entry_count(&self) -> usize {
+        self.entries.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result() -> ApplyEventResult {
+        ApplyEventResult {
+            from_state: "pending".to_string(),
+            to_state: "paid".to_string(),
+            ctx: None,
+            wal_offset: 42,
+            applied: true,
+            event_id: Some("e-1".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_replay_within_window() {
+        let applied = AppliedEvents::new(Duration::from_secs(60));
+        assert!(applied.replay("i-1", "e-1").is_none());
+
+        applied.record("i-1", "e-1", &result());
+        let replayed = applied.replay("i-1", "e-1").unwrap();
+        assert!(!replayed.applied);
+        assert_eq!(replayed.to_state, "paid");
+        assert_eq!(replayed.wal_offset, 42);
+        // Ids are scoped to their instance
+        assert!(applied.replay("i-2", "e-1").is_none());
+    }
+
+    #[test]
+    fn test_zero_window_disables_dedup() {
+        let applied = AppliedEvents::new(Duration::ZERO);
+        applied.record("i-1", "e-1", &result());
+        assert!(applied.replay("i-1", "e-1").is_none());
+        assert_eq!(applied.entry_count(), 0);
+    }
+}
@@ -0,0 +1,219 @@
+//! Instance event streams
+//!
+//! `GET /instances/:id/events/stream` follows one instance over Server-Sent
+//! Events, for curl and other simple clients. The first event (`state`) is
+//! the instance as it is now; after that the WAL is tailed from the
+//! instance's last offset and each of its entries is sent as it appears:
+//! `transition` for applied events, then `deleted` when the instance is
+//! deleted, after which the stream ends. Transition and deletion events
+//! carry their WAL offset as the SSE id, so a client reconnecting with
+//! `Last-Event-ID` picks up after the last one it saw.
+
+use crate::api::instances::InstanceResponse;
+use crate::api::wal::{ApplyEventPayload, DeleteInstancePayload};
+use crate::constants::instances::WAIT_POLL_INTERVAL_MS;
+use crate::constants::wal::MAX_PAGE_SIZE;
+use crate::constants::wal_entry_types;
+use crate::error::ApiResult;
+use crate::json_ext::ValueExt;
+use crate::redact::Redactor;
+use crate::rstmdb::{with_priority, Priority};
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::stream::{self, Stream};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// One entry of the followed instance, as sent to the client
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(untagged)]
+enum InstanceEvent {
+    Transition {
+        offset: u64,
+        /// Epoch milliseconds
+        timestamp: i64,
+        #[serde(flatten)]
+        transition: ApplyEventPayload,
+    },
+    Deleted {
+        offset: u64,
+        /// Epoch milliseconds
+        timestamp: i64,
+        #[serde(flatten)]
+        deletion: DeleteInstancePayload,
+    },
+}
+
+impl InstanceEvent {
+    fn into_sse(self) -> Event {
+        let (name, offset) = match &self {
+            InstanceEvent::Transition { offset, .. } => ("transition", *offset),
+            InstanceEvent::Deleted { offset, .. } => ("deleted", *offset),
+        };
+        Event::default()
+            .event(name)
+            .id(offset.to_string())
+            .json_data(&self)
+            .unwrap_or_default()
+    }
+}
+
+/// Events for instance `id` among WAL `records`, redacted; nothing after a
+/// deletion is returned
+fn instance_events(id: &str, records: &mut [Value], redactor: &Redactor) -> Vec<InstanceEvent> {
+    let mut events = Vec::new();
+    for record in records {
+        let offset = record.u64_or("offset", 0);
+        let entry = &mut record["entry"];
+        if entry["instance_id"].as_str() != Some(id) {
+            continue;
+        }
+        redactor.redact_wal_entry(entry);
+        let timestamp = entry.i64_or("timestamp", 0);
+        match entry["type"].as_str() {
+            Some(wal_entry_types::APPLY_EVENT) => events.push(InstanceEvent::Transition {
+                offset,
+                timestamp,
+                transition: serde_json::from_value(entry.take()).unwrap_or_default(),
+            }),
+            Some(wal_entry_types::DELETE_INSTANCE) => {
+                events.push(InstanceEvent::Deleted {
+                    offset,
+                    timestamp,
+                    deletion: serde_json::from_value(entry.take()).unwrap_or_default(),
+                });
+                break;
+            }
+            _ => {}
+        }
+    }
+    events
+}
+
+/// Where the stream is in the WAL
+struct Tail {
+    state: Arc<AppState>,
+    id: String,
+    from: u64,
+    pending: VecDeque<Event>,
+    done: bool,
+}
+
+impl Tail {
+    /// Wait a poll interval, then queue the instance's new entries
+    async fn poll(&mut self) {
+        tokio::time::sleep(Duration::from_millis(WAIT_POLL_INTERVAL_MS)).await;
+        let read = self.state.rstmdb.wal_read(self.from, Some(MAX_PAGE_SIZE));
+        let mut page = match with_priority(Priority::Bulk, read).await {
+            Ok(page) => page,
+            Err(e) => {
+                // The client can reconnect with Last-Event-ID to carry on
+                tracing::warn!(instance_id = %self.id, error = %e, "Instance event stream stopped");
+                self.pending.push_back(
+                    Event::default()
+                        .event("error")
+                        .json_data(&e)
+                        .unwrap_or_default(),
+                );
+                self.done = true;
+                return;
+            }
+        };
+        let Some(records) = page["records"].as_array_mut() else {
+            return;
+        };
+        if let Some(last) = records.last() {
+            self.from = self.from.max(last.u64_or("offset", 0) + 1);
+        }
+        for event in instance_events(&self.id, records, &self.state.redactor) {
+            self.done |= matches!(event, InstanceEvent::Deleted { .. });
+            self.pending.push_back(event.into_sse());
+        }
+    }
+}
+
+/// GET /api/v1/instances/:id/events/stream
+pub async fn stream_instance_events(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> ApiResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let result = state.rstmdb.get_instance_fresh(&id).await?;
+    let resume_after = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    let from = resume_after.unwrap_or(result.last_wal_offset) + 1;
+
+    let mut instance = InstanceResponse::new(result, &state);
+    state.redactor.redact(&mut instance.ctx);
+    let initial = Event::default()
+        .event("state")
+        .json_data(&instance)
+        .unwrap_or_default();
+
+    let tail = Tail {
+        state,
+        id,
+        from,
+        pending: VecDeque::from([initial]),
+        done: false,
+    };
+    let events = stream::unfold(tail, |mut tail| async move {
+        loop {
+            if let Some(event) = tail.pending.pop_front() {
+                return Some((Ok(event), tail));
+            }
+            if tail.done {
+                return None;
+            }
+            tail.poll().await;
+        }
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redact::REDACTED;
+    use serde_json::json;
+
+    #[test]
+    fn test_instance_events_follow_one_instance_until_deleted() {
+        let redactor = Redactor::new(&["/card".to_string()]).unwrap();
+        let mut records = vec![
+            json!({"offset": 10, "entry": {"type": "apply_event", "instance_id": "i-2",
+                "event": "PAY", "from_state": "new", "to_state": "paid", "timestamp": 1}}),
+            json!({"offset": 11, "entry": {"type": "apply_event", "instance_id": "i-1",
+                "event": "PAY", "from_state": "new", "to_state": "paid", "timestamp": 2,
+                "payload": {"card": "4111", "amount": 5}, "ctx": {"card": "4111"}}}),
+            json!({"offset": 12, "entry": {"type": "delete_instance", "instance_id": "i-1",
+                "timestamp": 3, "reason": "cleanup"}}),
+            json!({"offset": 13, "entry": {"type": "create_instance", "instance_id": "i-1",
+                "initial_state": "new", "timestamp": 4}}),
+        ];
+
+        let events = instance_events("i-1", &mut records, &redactor);
+        assert_eq!(events.len(), 2);
+        let transition = serde_json::to_value(&events[0]).unwrap();
+        assert_eq!(transition["offset"], 11);
+        assert_eq!(transition["event"], "PAY");
+        assert_eq!(transition["to_state"], "paid");
+        assert_eq!(transition["payload"]["amount"], 5);
+        assert_eq!(transition["payload"]["card"], REDACTED);
+        assert_eq!(transition["ctx"]["card"], REDACTED);
+        assert_eq!(
+            serde_json::to_value(&events[1]).unwrap(),
+            json!({"offset": 12, "timestamp": 3, "reason": "cleanup"})
+        );
+    }
+}
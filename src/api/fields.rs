@@ -0,0 +1,142 @@
+//! Sparse fieldsets
+//!
+//! `?fields=id,state` trims a response to the named top-level keys after it
+//! has been serialized, for clients on slow links. List responses apply the
+//! selection to each item and keep their paging fields.
+
+use crate::error::{ApiError, ApiResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FieldsQuery {
+    /// Comma-separated top-level keys to keep
+    pub fields: Option<String>,
+}
+
+/// Keys to keep; `None` keeps the whole response
+#[derive(Debug, Clone, Default)]
+pub struct FieldSelection(Option<Vec<String>>);
+
+impl FieldsQuery {
+    /// Parse the requested keys, rejecting any not in `known`
+    pub fn selection(&self, known: &[&str]) -> ApiResult<FieldSelection> {
+        let Some(fields) = self.fields.as_deref() else {
+            return Ok(FieldSelection(None));
+        };
+        let mut selected: Vec<String> = Vec::new();
+        for field in fields.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            if !known.contains(&field) {
+                return Err(ApiError::bad_request(format!(
+                    "Unknown field '{}', expected one of: {}",
+                    field,
+                    known.join(", ")
+                )));
+            }
+            if !selected.iter().any(|s| s == field) {
+                selected.push(field.to_string());
+            }
+        }
+        if selected.is_empty() {
+            return Err(ApiError::bad_request("fields must name at least one field"));
+        }
+        Ok(FieldSelection(Some(selected)))
+    }
+}
+
+impl FieldSelection {
+    /// Whether `field` will be in the response
+    pub fn includes(&self, field: &str) -> bool {
+        self.0
+            .as_ref()
+            .is_none_or(|selected| selected.iter().any(|s| s == field))
+    }
+
+    /// Serialize `body`, keeping only the selected keys
+    pub fn apply<T: Serialize>(&self, body: &T) -> ApiResult<Value> {
+        let mut value = to_value(body)?;
+        self.prune(&mut value);
+        Ok(value)
+    }
+
+    /// Serialize a list response, keeping only the selected keys of each of
+    /// its `items`
+    pub fn apply_to_items<T: Serialize>(&self, body: &T) -> ApiResult<Value> {
+        let mut value = to_value(body)?;
+        if let Some(items) = value.get_mut("items").and_then(Value::as_array_mut) {
+            for item in items {
+                self.prune(item);
+            }
+        }
+        Ok(value)
+    }
+
+    fn prune(&self, value: &mut Value) {
+        if let (Some(selected), Some(obj)) = (&self.0, value.as_object_mut()) {
+            obj.retain(|key, _| selected.iter().any(|s| s == key));
+        }
+    }
+}
+
+fn to_value<T: Serialize>(body: &T) -> ApiResult<Value> {
+    serde_json::to_value(body)
+        .map_err(|e| ApiError::internal(format!("Failed to serialize response: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const KNOWN: &[&str] = &["id", "state", "ctx"];
+
+    fn query(fields: Option<&str>) -> FieldsQuery {
+        FieldsQuery {
+            fields: fields.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_selection_validates_fields() {
+        let all = query(None).selection(KNOWN).unwrap();
+        assert!(all.includes("ctx"));
+        assert_eq!(
+            all.apply(&json!({"id": "a", "ctx": {}})).unwrap(),
+            json!({"id": "a", "ctx": {}})
+        );
+
+        let some = query(Some(" id, state,id")).selection(KNOWN).unwrap();
+        assert!(some.includes("state"));
+        assert!(!some.includes("ctx"));
+
+        for bad in ["id,secret", "", " , "] {
+            let err = query(Some(bad)).selection(KNOWN).unwrap_err();
+            assert_eq!(err.code, "BAD_REQUEST", "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_apply_prunes_objects_and_list_items() {
+        let selection = query(Some("id,state")).selection(KNOWN).unwrap();
+        assert_eq!(
+            selection
+                .apply(&json!({"id": "a", "state": "s", "ctx": {"k": 1}}))
+                .unwrap(),
+            json!({"id": "a", "state": "s"})
+        );
+
+        let list = json!({
+            "items": [{"id": "a", "state": "s", "ctx": {}}, {"id": "b", "ctx": {}}],
+            "total": 2,
+            "has_more": false
+        });
+        assert_eq!(
+            selection.apply_to_items(&list).unwrap(),
+            json!({
+                "items": [{"id": "a", "state": "s"}, {"id": "b"}],
+                "total": 2,
+                "has_more": false
+            })
+        );
+    }
+}
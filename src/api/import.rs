@@ -0,0 +1,486 @@
+//! Machine definition import from URLs
+//!
+//! Lets CI register a definition by reference (e.g. a raw file in a git
+//! repository) instead of uploading it. The URL is fetched server-side, so
+//! only allowlisted schemes and hosts are contacted and redirects are not
+//! followed.
+//!
+//! When a machine of the same name already exists, `strategy` decides what
+//! happens: keep it (`skip`), add a version (`version`, the default) or
+//! import under a new name (`rename`). rstmdb never changes a stored
+//! version, so `overwrite` is refused with `CONFLICT` and only imports
+//! a machine that doesn't exist yet.
+
+use crate::api::machines::{
+    create_machine_version, latest_versions, CreateMachineVersionQuery,
+    CreateMachineVersionRequest, CreateMachineVersionResponse,
+};
+use crate::config::ImportConfig;
+use crate::constants::import::RENAME_SUFFIX;
+use crate::error::{ApiError, ApiResult};
+use crate::json_ext::ValueExt;
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Deserialize)]
+pub struct ImportUrlRequest {
+    /// Location of a JSON or YAML definition
+    pub url: String,
+    pub version: Option<u32>,
+    /// Version to compare against to detect changes
+    pub base_version: Option<u32>,
+    /// What to do if the machine already exists
+    #[serde(default)]
+    pub strategy: ImportStrategy,
+}
+
+/// Handling of an import whose machine name is already taken
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStrategy {
+    /// Leave the existing machine untouched
+    Skip,
+    /// Replace the existing machine. Refused: rstmdb versions are
+    /// immutable.
+    Overwrite,
+    /// Add a new version, as `POST .../versions` does
+    #[default]
+    Version,
+    /// Import as a new machine named `<name>-imported`
+    Rename,
+}
+
+/// What an import did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportAction {
+    /// No machine of that name existed
+    Created,
+    Skipped,
+    Versioned,
+    Renamed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    pub action: ImportAction,
+    /// Machine and version the definition ended up in; for `skipped`, the
+    /// existing latest version
+    #[serde(flatten)]
+    pub result: CreateMachineVersionResponse,
+}
+
+/// Where an import goes, given the latest version of every machine
+#[derive(Debug, PartialEq, Eq)]
+struct ImportPlan {
+    action: ImportAction,
+    machine: String,
+    /// `None` resolves the version as `POST .../versions` does
+    version: Option<u32>,
+}
+
+fn plan_import(
+    strategy: ImportStrategy,
+    name: &str,
+    requested_version: Option<u32>,
+    latest: &HashMap<String, u32>,
+) -> ApiResult<ImportPlan> {
+    let plan = |action, machine: &str, version| ImportPlan {
+        action,
+        machine: machine.to_string(),
+        version,
+    };
+    let Some(&current) = latest.get(name) else {
+        return Ok(plan(ImportAction::Created, name, requested_version));
+    };
+    Ok(match strategy {
+        ImportStrategy::Skip => plan(ImportAction::Skipped, name, Some(current)),
+        ImportStrategy::Overwrite => {
+            return Err(ApiError::conflict(format!(
+                "Machine '{}' already exists and rstmdb cannot replace a stored version; \
+                     import with strategy 'version' or 'rename' instead",
+                name
+            ))
+            .with_details(serde_json::json!({ "strategy": "overwrite" })))
+        }
+        ImportStrategy::Version => plan(ImportAction::Versioned, name, requested_version),
+        ImportStrategy::Rename => {
+            let base = format!("{}{}", name, RENAME_SUFFIX);
+            let renamed = std::iter::once(base.clone())
+                .chain((2..).map(|n| format!("{}-{}", base, n)))
+                .find(|candidate| !latest.contains_key(candidate))
+                .unwrap_or(base);
+            plan(ImportAction::Renamed, &renamed, None)
+        }
+    })
+}
+
+fn host_allowed(allowed: &[String], host: &str) -> bool {
+    allowed.iter().any(|entry| {
+        let entry = entry.to_ascii_lowercase();
+        match entry.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .is_some_and(|sub| sub.ends_with('.')),
+            None => host == entry,
+        }
+    })
+}
+
+/// Parse `url` and check it against the import allowlist
+fn check_url(config: &ImportConfig, url: &str) -> ApiResult<Url> {
+    let parsed =
+        Url::parse(url).map_err(|e| ApiError::bad_request(format!("Invalid URL: {}", e)))?;
+    if !config
+        .allowed_schemes
+        .iter()
+        .any(|s| s.eq_ignore_ascii_case(parsed.scheme()))
+    {
+        return Err(ApiError::forbidden(format!(
+            "URL scheme '{}' is not allowed for import",
+            parsed.scheme()
+        )));
+    }
+    let host = parsed.host_str().unwrap_or("");
+    if !host_allowed(&config.allowed_hosts, host) {
+        return Err(ApiError::forbidden(format!(
+            "Host '{}' is not allowed for import",
+            host
+        )));
+    }
+    Ok(parsed)
+}
+
+/// Fetch at most `config.max_bytes` from `url`, giving up after `timeout`
+async fn fetch(config: &ImportConfig, timeout: Duration, url: Url) -> ApiResult<Vec<u8>> {
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|e| ApiError::internal(format!("Failed to build HTTP client: {}", e)))?;
+
+    let fetch_error = |e: reqwest::Error| {
+        if e.is_timeout() {
+            ApiError::timeout(format!(
+                "Fetching the definition did not complete within {}",
+                humantime::format_duration(timeout)
+            ))
+        } else {
+            ApiError::bad_request(format!("Failed to fetch definition: {}", e))
+        }
+    };
+    let too_large = || {
+        ApiError::bad_request(format!(
+            "Definition exceeds the import size limit of {} bytes",
+            config.max_bytes
+        ))
+    };
+
+    let mut response = client.get(url).send().await.map_err(fetch_error)?;
+    if !response.status().is_success() {
+        return Err(ApiError::bad_request(format!(
+            "Fetching definition returned HTTP {}",
+            response.status()
+        )));
+    }
+    if response
+        .content_length()
+        .is_some_and(|len| len > config.max_bytes as u64)
+    {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(fetch_error)? {
+        if body.len() + chunk.len() > config.max_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Parse a definition as JSON, falling back to YAML
+fn parse_definition(body: &[u8]) -> ApiResult<Value> {
+    if let Ok(definition) = serde_json::from_slice(body) {
+        return Ok(definition);
+    }
+    serde_yaml::from_slice(body)
+        .map_err(|e| ApiError::bad_request(format!("Definition is not valid JSON or YAML: {}", e)))
+}
+
+/// POST /api/v1/machines/:name/import/url
+///
+/// Validates and stores the fetched definition like `POST .../versions`,
+/// including `?check_instances=true`, according to `strategy`.
+pub async fn import_machine_from_url(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<CreateMachineVersionQuery>,
+    Json(req): Json<ImportUrlRequest>,
+) -> ApiResult<Json<ImportResponse>> {
+    let config = &state.config.import;
+    let url = check_url(config, &req.url)?;
+    let body = fetch(config, state.import_timeout, url).await?;
+    let definition = parse_definition(&body)?;
+    import_definition(state, name, query, &req, definition)
+        .await
+        .map(Json)
+}
+
+/// Store a fetched `definition` according to `req.strategy`
+async fn import_definition(
+    state: Arc<AppState>,
+    name: String,
+    query: CreateMachineVersionQuery,
+    req: &ImportUrlRequest,
+    definition: Value,
+) -> ApiResult<ImportResponse> {
+    let latest = latest_versions(&state).await?;
+    let plan = plan_import(req.strategy, &name, req.version, &latest)?;
+    tracing::info!(
+        machine = %name,
+        url = %req.url,
+        action = ?plan.action,
+        target = %plan.machine,
+        "Importing machine definition"
+    );
+
+    if let (ImportAction::Skipped, Some(version)) = (plan.action, plan.version) {
+        let existing = state.rstmdb.get_machine(&name, version).await?;
+        return Ok(ImportResponse {
+            action: plan.action,
+            result: CreateMachineVersionResponse {
+                machine: name,
+                version,
+                checksum: existing.str_or_empty("checksum"),
+                created: false,
+                warnings: Vec::new(),
+            },
+        });
+    }
+    // The unchanged-definition check only applies when adding to the
+    // requested machine
+    let base_version = match plan.action {
+        ImportAction::Created | ImportAction::Versioned => req.base_version,
+        _ => None,
+    };
+    let Json(result) = create_machine_version(
+        State(state),
+        Path(plan.machine),
+        Query(query),
+        Json(CreateMachineVersionRequest {
+            version: plan.version,
+            definition,
+            base_version,
+        }),
+    )
+    .await?;
+    Ok(ImportResponse {
+        action: plan.action,
+        result,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::rstmdb::FakeRstmdb;
+
+    fn config(hosts: &[&str]) -> ImportConfig {
+        ImportConfig {
+            allowed_hosts: hosts.iter().map(|h| h.to_string()).collect(),
+            ..ImportConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_check_url_allowlist() {
+        let config = config(&["raw.githubusercontent.com", "*.example.com"]);
+        assert!(check_url(&config, "https://raw.githubusercontent.com/o/r/main/m.json").is_ok());
+        assert!(check_url(&config, "https://artifacts.example.com/m.yaml").is_ok());
+        assert!(check_url(&config, "https://a.b.EXAMPLE.com/m.yaml").is_ok());
+
+        for denied in [
+            "https://example.com/m.json",
+            "https://evilexample.com/m.json",
+            "https://169.254.169.254/latest/meta-data",
+            "http://raw.githubusercontent.com/o/r/main/m.json",
+            "file:///etc/passwd",
+        ] {
+            let err = check_url(&config, denied).unwrap_err();
+            assert_eq!(err.code, "FORBIDDEN", "{}", denied);
+        }
+        assert_eq!(
+            check_url(&config, "not a url").unwrap_err().code,
+            "BAD_REQUEST"
+        );
+
+        // No allowed hosts: import disabled
+        let disabled = ImportConfig::default();
+        assert!(check_url(&disabled, "https://raw.githubusercontent.com/m.json").is_err());
+    }
+
+    #[test]
+    fn test_plan_import_strategies_for_existing_machine() {
+        let latest = HashMap::from([
+            ("orders".to_string(), 3),
+            ("orders-imported".to_string(), 1),
+        ]);
+        let plan = |strategy, version| plan_import(strategy, "orders", version, &latest).unwrap();
+        let expect = |action, machine: &str, version| ImportPlan {
+            action,
+            machine: machine.to_string(),
+            version,
+        };
+
+        assert_eq!(
+            plan(ImportStrategy::Skip, None),
+            expect(ImportAction::Skipped, "orders", Some(3))
+        );
+        for version in [None, Some(2)] {
+            let err = plan_import(ImportStrategy::Overwrite, "orders", version, &latest);
+            assert_eq!(err.unwrap_err().code, "CONFLICT");
+        }
+        assert_eq!(
+            plan(ImportStrategy::Version, None),
+            expect(ImportAction::Versioned, "orders", None)
+        );
+        assert_eq!(
+            plan(ImportStrategy::Rename, Some(5)),
+            expect(ImportAction::Renamed, "orders-imported-2", None)
+        );
+        assert_eq!(ImportStrategy::default(), ImportStrategy::Version);
+
+        // Without a collision every strategy simply creates the machine
+        for strategy in [
+            ImportStrategy::Skip,
+            ImportStrategy::Overwrite,
+            ImportStrategy::Version,
+            ImportStrategy::Rename,
+        ] {
+            assert_eq!(
+                plan_import(strategy, "refunds", None, &latest).unwrap(),
+                expect(ImportAction::Created, "refunds", None)
+            );
+        }
+    }
+
+    fn definition(states: &[&str]) -> Value {
+        serde_json::json!({
+            "states": states,
+            "initial": states[0],
+            "transitions": [{ "from": states[0], "event": "GO", "to": states[1] }],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_import_strategies_store_the_expected_versions() {
+        let existing = definition(&["new", "done"]);
+        let fake = FakeRstmdb::new().with_machine("orders", 3, existing.clone());
+        let state = AppState::for_test(Config::default(), fake.clone());
+        let imported = definition(&["new", "paid"]);
+        let import = |strategy, version| {
+            let req = ImportUrlRequest {
+                url: "https://example.com/orders.json".to_string(),
+                version,
+                base_version: None,
+                strategy,
+            };
+            let (state, definition) = (state.clone(), imported.clone());
+            async move {
+                let query = CreateMachineVersionQuery {
+                    check_instances: false,
+                };
+                import_definition(state, "orders".to_string(), query, &req, definition).await
+            }
+        };
+
+        let skipped = import(ImportStrategy::Skip, None).await.unwrap();
+        assert_eq!(skipped.action, ImportAction::Skipped);
+        assert_eq!((skipped.result.version, skipped.result.created), (3, false));
+        assert_eq!(fake.machine("orders", 3), Some(existing.clone()));
+        assert_eq!(fake.machine("orders", 4), None);
+
+        for version in [None, Some(3)] {
+            let err = import(ImportStrategy::Overwrite, version)
+                .await
+                .unwrap_err();
+            assert_eq!(err.code, "CONFLICT");
+        }
+        assert_eq!(fake.machine("orders", 3), Some(existing.clone()));
+        assert_eq!(fake.calls("put_machine"), 0);
+
+        let versioned = import(ImportStrategy::Version, None).await.unwrap();
+        assert_eq!(versioned.action, ImportAction::Versioned);
+        assert_eq!(
+            (versioned.result.machine.as_str(), versioned.result.version),
+            ("orders", 4)
+        );
+        assert!(versioned.result.created);
+        assert_eq!(fake.machine("orders", 4), Some(imported.clone()));
+
+        let renamed = import(ImportStrategy::Rename, Some(7)).await.unwrap();
+        assert_eq!(renamed.action, ImportAction::Renamed);
+        assert_eq!(
+            (renamed.result.machine.as_str(), renamed.result.version),
+            ("orders-imported", 1)
+        );
+        assert_eq!(fake.machine("orders-imported", 1), Some(imported.clone()));
+        assert_eq!(fake.machine("orders", 3), Some(existing));
+    }
+
+    #[tokio::test]
+    async fn test_import_of_a_new_machine_creates_it_with_any_strategy() {
+        let fake = FakeRstmdb::new();
+        let state = AppState::for_test(Config::default(), fake.clone());
+        for (n, strategy) in [
+            ImportStrategy::Skip,
+            ImportStrategy::Overwrite,
+            ImportStrategy::Version,
+            ImportStrategy::Rename,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let name = format!("machine-{}", n);
+            let req = ImportUrlRequest {
+                url: "https://example.com/m.json".to_string(),
+                version: None,
+                base_version: None,
+                strategy,
+            };
+            let query = CreateMachineVersionQuery {
+                check_instances: false,
+            };
+            let definition = definition(&["a", "b"]);
+            let result = import_definition(state.clone(), name.clone(), query, &req, definition)
+                .await
+                .unwrap();
+            assert_eq!(result.action, ImportAction::Created, "{:?}", strategy);
+            assert_eq!((result.result.version, result.result.created), (1, true));
+            assert!(fake.machine(&name, 1).is_some());
+        }
+    }
+
+    #[test]
+    fn test_parse_definition_json_or_yaml() {
+        let json = br#"{"states": ["a"], "initial": "a", "transitions": []}"#;
+        let yaml = b"states: [a]\ninitial: a\ntransitions: []\n";
+        assert_eq!(
+            parse_definition(json).unwrap(),
+            parse_definition(yaml).unwrap()
+        );
+        assert!(parse_definition(b"states: [a\n").is_err());
+    }
+}
@@ -0,0 +1,348 @@
+//! Instance expiry sweep
+//!
+//! rstmdb cannot expire instances itself, so when `instance_ttl.enabled` is
+//! set a background task periodically lists the instances of every machine
+//! with a configured TTL and expires those created longer ago than the TTL
+//! that are still in a non-terminal state of their machine version. Paused
+//! and archived instances are left alone. Expiring applies the machine's
+//! timeout event (guarded by the state the sweep saw) or deletes the
+//! instance; each one is logged. Nothing is expired in maintenance mode.
+//!
+//! A sweep examines at most `MAX_SCAN_PER_MACHINE` instances of a machine.
+//! The next sweep resumes listing where it stopped, wrapping around to the
+//! start, so every instance is reached within a few sweeps. Deletes shift
+//! later instances back, so a few may wait for the following pass.
+
+use crate::config::{InstanceTtlAction, InstanceTtlRule};
+use crate::constants::instance_ttl::MAX_SCAN_PER_MACHINE;
+use crate::constants::instances::SCAN_PAGE_SIZE;
+use crate::error::ApiResult;
+use crate::rstmdb::{with_priority, InstanceSummary, Priority};
+use crate::validation::TransitionGraph;
+use crate::AppState;
+use parking_lot::Mutex;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Configured TTLs and sweep progress
+pub struct InstanceTtl {
+    enabled: bool,
+    rules: HashMap<String, InstanceTtlRule>,
+    /// Epoch milliseconds of the last completed sweep
+    last_sweep: Mutex<Option<i64>>,
+    expired: AtomicU64,
+    /// Listing offset each machine's next sweep starts from
+    resume_at: Mutex<HashMap<String, u32>>,
+}
+
+impl InstanceTtl {
+    pub fn new(enabled: bool, rules: HashMap<String, InstanceTtlRule>) -> Self {
+        Self {
+            enabled,
+            rules,
+            last_sweep: Mutex::new(None),
+            expired: AtomicU64::new(0),
+            resume_at: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// When an instance created at `created_at` (epoch ms) expires, if its
+    /// machine has a TTL. Instances in terminal states never do, whatever
+    /// this says.
+    pub fn expires_at(&self, machine: &str, created_at: i64) -> Option<i64> {
+        if !self.enabled {
+            return None;
+        }
+        let rule = self.rules.get(machine)?;
+        Some(created_at.saturating_add(rule.ttl.as_millis() as i64))
+    }
+
+    pub fn last_sweep(&self) -> Option<i64> {
+        *self.last_sweep.lock()
+    }
+
+    /// Instances expired since startup
+    pub fn expired_total(&self) -> u64 {
+        self.expired.load(Ordering::Relaxed)
+    }
+}
+
+/// Instances of `machine` past their TTL at `now`, before terminal states
+/// are considered
+async fn expired_candidates(
+    state: &AppState,
+    machine: &str,
+    rule: &InstanceTtlRule,
+    now: i64,
+) -> ApiResult<Vec<InstanceSummary>> {
+    let ttl_ms = rule.ttl.as_millis() as i64;
+    let start = state
+        .instance_ttl
+        .resume_at
+        .lock()
+        .get(machine)
+        .copied()
+        .unwrap_or(0);
+    let mut candidates = Vec::new();
+    let mut scanned = 0u64;
+    let mut offset = start;
+    let mut wrapped = false;
+    let mut resume_at = 0;
+    'pages: loop {
+        let page = state
+            .rstmdb
+            .list_instances(machine, None, Some(SCAN_PAGE_SIZE), Some(offset))
+            .await?;
+        for (position, instance) in (offset..).zip(page.instances) {
+            // Back where this sweep started: every instance was examined
+            if wrapped && position >= start {
+                break 'pages;
+            }
+            scanned += 1;
+            if instance.created_at.saturating_add(ttl_ms) <= now
+                && !state.archive.is_archived(&instance.id)
+                && !state.paused.is_paused(&instance.id)
+            {
+                candidates.push(instance);
+            }
+        }
+        offset += SCAN_PAGE_SIZE;
+        if !page.has_more {
+            if start == 0 || wrapped {
+                break;
+            }
+            wrapped = true;
+            offset = 0;
+            continue;
+        }
+        if scanned >= MAX_SCAN_PER_MACHINE {
+            tracing::warn!(
+                machine,
+                scanned,
+                "Instance TTL sweep stopped at its scan limit; the rest are checked next sweep"
+            );
+            resume_at = offset;
+            break;
+        }
+    }
+    state
+        .instance_ttl
+        .resume_at
+        .lock()
+        .insert(machine.to_string(), resume_at);
+    Ok(candidates)
+}
+
+/// Expire the unfinished instances of one machine; returns how many were
+async fn sweep_machine(
+    state: &AppState,
+    machine: &str,
+    rule: &InstanceTtlRule,
+    now: i64,
+) -> ApiResult<u64> {
+    // Listing is done before acting, since deletes shift later pages
+    let candidates = expired_candidates(state, machine, rule, now).await?;
+
+    let mut terminal_by_version: HashMap<u32, HashSet<String>> = HashMap::new();
+    let mut expired = 0;
+    for instance in candidates {
+        if let Entry::Vacant(entry) = terminal_by_version.entry(instance.version) {
+            let def = state.rstmdb.get_machine(machine, instance.version).await?;
+            let terminal = TransitionGraph::from_definition(&def["definition"])
+                .terminal_states()
+                .into_iter()
+                .map(String::from)
+                .collect();
+            entry.insert(terminal);
+        }
+        if terminal_by_version[&instance.version].contains(&instance.state) {
+            continue;
+        }
+
+        let result = match (rule.action, rule.event.as_deref()) {
+            (InstanceTtlAction::Event, Some(event)) => state
+                .rstmdb
+                .apply_event(&instance.id, event, None, Some(&instance.state), None)
+                .await
+                .map(|r| format!("applied {} ({} -> {})", event, r.from_state, r.to_state)),
+            _ => state
+                .rstmdb
+                .delete_instance(&instance.id)
+                .await
+                .map(|_| "deleted".to_string()),
+        };
+        match result {
+            Ok(outcome) => {
+                expired += 1;
+                tracing::info!(
+                    machine,
+                    instance_id = %instance.id,
+                    state = %instance.state,
+                    created_at = instance.created_at,
+                    outcome = %outcome,
+                    "Instance expired"
+                );
+            }
+            // e.g. moved on since it was listed; retried next sweep if still due
+            Err(e) => tracing::warn!(
+                machine,
+                instance_id = %instance.id,
+                error = %e.message,
+                "Failed to expire instance"
+            ),
+        }
+    }
+    Ok(expired)
+}
+
+async fn sweep(state: &Arc<AppState>) {
+    // Maintenance mode holds back writes, including this sweep's
+    if state.maintenance.is_enabled() {
+        return;
+    }
+    let now = chrono::Utc::now().timestamp_millis();
+    for (machine, rule) in &state.instance_ttl.rules {
+        match with_priority(Priority::Bulk, sweep_machine(state, machine, rule, now)).await {
+            Ok(expired) => {
+                state
+                    .instance_ttl
+                    .expired
+                    .fetch_add(expired, Ordering::Relaxed);
+            }
+            Err(e) => {
+                tracing::warn!(machine = %machine, error = %e.message, "Instance TTL sweep failed")
+            }
+        }
+    }
+    *state.instance_ttl.last_sweep.lock() = Some(chrono::Utc::now().timestamp_millis());
+}
+
+/// Sweep every `interval`. Runs until the process exits.
+pub async fn run_sweeper(state: Arc<AppState>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+        sweep(&state).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::rstmdb::FakeRstmdb;
+    use serde_json::json;
+
+    #[test]
+    fn test_expires_at() {
+        let rules = HashMap::from([(
+            "orders".to_string(),
+            InstanceTtlRule {
+                ttl: Duration::from_secs(60),
+                action: InstanceTtlAction::Delete,
+                event: None,
+            },
+        )]);
+        let ttl = InstanceTtl::new(true, rules.clone());
+        assert_eq!(ttl.expires_at("orders", 1_000), Some(61_000));
+        assert_eq!(ttl.expires_at("carts", 1_000), None);
+
+        let disabled = InstanceTtl::new(false, rules);
+        assert_eq!(disabled.expires_at("orders", 1_000), None);
+    }
+
+    #[tokio::test]
+    async fn test_sweep_leaves_declared_final_states_alone() {
+        // `closed` is final even though it can still be archived
+        let fake = FakeRstmdb::new().with_machine(
+            "tickets",
+            1,
+            json!({
+                "states": ["open", "closed", "archived"],
+                "initial": "open",
+                "final": ["closed"],
+                "transitions": [
+                    { "from": "open", "event": "CLOSE", "to": "closed" },
+                    { "from": "closed", "event": "ARCHIVE", "to": "archived" }
+                ]
+            }),
+        );
+        let state = AppState::for_test(Config::default(), fake.clone());
+        for id in ["t-open", "t-closed"] {
+            state
+                .rstmdb
+                .create_instance("tickets", 1, Some(id), None)
+                .await
+                .unwrap();
+        }
+        state
+            .rstmdb
+            .apply_event("t-closed", "CLOSE", None, None, None)
+            .await
+            .unwrap();
+
+        let rule = InstanceTtlRule {
+            ttl: Duration::from_secs(60),
+            action: InstanceTtlAction::Delete,
+            event: None,
+        };
+        let now = chrono::Utc::now().timestamp_millis();
+        let expired = sweep_machine(&state, "tickets", &rule, now).await.unwrap();
+        assert_eq!(expired, 1);
+        assert!(fake.instance("t-open").is_none());
+        assert_eq!(fake.instance("t-closed").unwrap().state, "closed");
+    }
+
+    #[tokio::test]
+    async fn test_sweep_resumes_past_the_scan_limit() {
+        // v1 instances start in a final state, v2 ones can expire
+        let fake = FakeRstmdb::new()
+            .with_machine(
+                "tickets",
+                1,
+                json!({"states": ["closed"], "initial": "closed", "final": ["closed"]}),
+            )
+            .with_machine(
+                "tickets",
+                2,
+                json!({
+                    "states": ["open", "closed"],
+                    "initial": "open",
+                    "transitions": [{ "from": "open", "event": "CLOSE", "to": "closed" }]
+                }),
+            );
+        let state = AppState::for_test(Config::default(), fake.clone());
+        // Listed in id order, so every expirable instance is past the limit
+        for n in 0..MAX_SCAN_PER_MACHINE + 20 {
+            let version = if n < MAX_SCAN_PER_MACHINE { 1 } else { 2 };
+            state
+                .rstmdb
+                .create_instance("tickets", version, Some(&format!("t-{:05}", n)), None)
+                .await
+                .unwrap();
+        }
+
+        let rule = InstanceTtlRule {
+            ttl: Duration::from_secs(60),
+            action: InstanceTtlAction::Delete,
+            event: None,
+        };
+        let now = chrono::Utc::now().timestamp_millis();
+        assert_eq!(
+            sweep_machine(&state, "tickets", &rule, now).await.unwrap(),
+            0
+        );
+        assert_eq!(
+            sweep_machine(&state, "tickets", &rule, now).await.unwrap(),
+            20
+        );
+        assert!(fake
+            .instance(&format!("t-{:05}", MAX_SCAN_PER_MACHINE))
+            .is_none());
+        assert!(fake.instance("t-00000").is_some());
+    }
+}
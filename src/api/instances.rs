@@ -1,16 +1,38 @@
 //! Instance API handlers
 
-use crate::constants::{history_event_types, instances::HISTORY_MAX_WAL_SCAN, wal_entry_types};
-use crate::error::ApiResult;
+use crate::api::auth::{require_admin, SessionUser};
+use crate::api::fields::FieldsQuery;
+use crate::api::machines::latest_version;
+use crate::api::pagination::{offset_links, with_page_headers};
+use crate::api::timefmt::{format_opt, TimeFormatQuery};
+use crate::config::EventPolicy;
+use crate::constants::instances::{
+    BATCH_GET_CONCURRENCY, DEFAULT_LIST_LIMIT, HISTORY_WAL_PAGE_SIZE,
+};
+use crate::constants::{history_event_types, wal_entry_types};
+use crate::error::{ApiError, ApiResult};
+use crate::guard;
 use crate::json_ext::ValueExt;
+use crate::rstmdb::{
+    self, ApplyEventResult, CreateInstanceResult, DeleteInstanceResult, InstanceResult,
+};
+use crate::validation::context_schema::validate_context;
+use crate::validation::{
+    diff_definitions, transition_from_states, DefinitionDiff, TransitionGraph, CONTEXT_SCHEMA_FIELD,
+};
 use crate::AppState;
 use axum::{
-    extract::{Path, Query, State},
-    Json,
+    extract::{OriginalUri, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
 #[derive(Debug, Deserialize)]
 pub struct ListInstancesQuery {
@@ -22,6 +44,117 @@ pub struct ListInstancesQuery {
     pub limit: Option<u32>,
     /// Offset for pagination
     pub offset: Option<u32>,
+    /// Comma-separated sort fields (`created_at`, `updated_at`, `state`, `id`)
+    pub sort: Option<String>,
+    /// Comma-separated directions (`asc`/`desc`) matching `sort` by position
+    pub order: Option<String>,
+    /// Include archived (soft-deleted) instances
+    #[serde(default)]
+    pub include_archived: bool,
+    /// Only paused (`true`) or only unpaused (`false`) instances
+    pub paused: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CountInstancesQuery {
+    pub machine: String,
+    pub state: Option<String>,
+    /// Include archived (soft-deleted) instances
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteInstanceQuery {
+    /// Answer `204` instead of `404` when the instance is already gone
+    #[serde(default)]
+    pub idempotent: bool,
+}
+
+/// Header marking a request as safe to retry; a delete carrying it is idempotent
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+#[derive(Debug, Serialize)]
+pub struct CountInstancesResponse {
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortField {
+    CreatedAt,
+    UpdatedAt,
+    State,
+    Id,
+}
+
+impl SortField {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "created_at" => Some(SortField::CreatedAt),
+            "updated_at" => Some(SortField::UpdatedAt),
+            "state" => Some(SortField::State),
+            "id" => Some(SortField::Id),
+            _ => None,
+        }
+    }
+
+    fn compare(&self, a: &InstanceListItem, b: &InstanceListItem) -> std::cmp::Ordering {
+        match self {
+            SortField::CreatedAt => a.created_at.cmp(&b.created_at),
+            SortField::UpdatedAt => a.updated_at.cmp(&b.updated_at),
+            SortField::State => a.state.cmp(&b.state),
+            SortField::Id => a.id.cmp(&b.id),
+        }
+    }
+}
+
+/// Parse `sort`/`order` query params into sort keys.
+///
+/// Directions pair with fields by position; fields without one sort ascending.
+fn parse_sort(sort: &str, order: Option<&str>) -> ApiResult<Vec<(SortField, bool)>> {
+    let orders: Vec<&str> = order
+        .map(|o| o.split(',').map(str::trim).collect())
+        .unwrap_or_default();
+
+    sort.split(',')
+        .map(str::trim)
+        .enumerate()
+        .map(|(i, name)| {
+            let field = SortField::parse(name).ok_or_else(|| {
+                ApiError::bad_request(format!(
+                    "Unknown sort field '{}', expected one of: created_at, updated_at, state, id",
+                    name
+                ))
+            })?;
+            let descending = match orders.get(i).copied() {
+                None | Some("asc") => false,
+                Some("desc") => true,
+                Some(other) => {
+                    return Err(ApiError::bad_request(format!(
+                        "Unknown sort order '{}', expected 'asc' or 'desc'",
+                        other
+                    )))
+                }
+            };
+            Ok((field, descending))
+        })
+        .collect()
+}
+
+fn sort_items(items: &mut [InstanceListItem], keys: &[(SortField, bool)]) {
+    items.sort_by(|a, b| {
+        keys.iter()
+            .map(|(field, descending)| {
+                let ord = field.compare(a, b);
+                if *descending {
+                    ord.reverse()
+                } else {
+                    ord
+                }
+            })
+            .find(|ord| ord.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
 }
 
 #[derive(Debug, Serialize)]
@@ -32,7 +165,19 @@ pub struct InstanceListItem {
     pub state: String,
     pub created_at: i64,
     pub updated_at: i64,
+    /// `created_at` formatted per `?time_format`/`?tz`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at_formatted: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at_formatted: Option<String>,
     pub last_wal_offset: u64,
+    pub is_archived: bool,
+    /// Events are refused until the instance is resumed
+    pub paused: bool,
+    /// When the `instance_ttl` sweep expires the instance (epoch ms), for
+    /// machines with a TTL; terminal instances never expire
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -50,6 +195,73 @@ pub struct InstanceResponse {
     pub state: String,
     pub ctx: Value,
     pub last_wal_offset: u64,
+    pub is_archived: bool,
+    /// Events are refused until the instance is resumed
+    pub paused: bool,
+}
+
+impl InstanceListItem {
+    /// Keys selectable with `?fields=`
+    pub const FIELDS: &'static [&'static str] = &[
+        "id",
+        "machine",
+        "version",
+        "state",
+        "created_at",
+        "updated_at",
+        "created_at_formatted",
+        "updated_at_formatted",
+        "last_wal_offset",
+        "is_archived",
+        "paused",
+        "expires_at",
+    ];
+}
+
+impl InstanceResponse {
+    /// Keys selectable with `?fields=`
+    pub const FIELDS: &'static [&'static str] = &[
+        "instance_id",
+        "machine",
+        "version",
+        "state",
+        "ctx",
+        "last_wal_offset",
+        "is_archived",
+        "paused",
+    ];
+
+    /// Combine rstmdb's view of the instance with Studio's archive and pause flags
+    pub fn new(result: InstanceResult, state: &AppState) -> Self {
+        Self {
+            is_archived: state.archive.is_archived(&result.instance_id),
+            paused: state.paused.is_paused(&result.instance_id),
+            instance_id: result.instance_id,
+            machine: result.machine,
+            version: result.version,
+            state: result.state,
+            ctx: result.ctx,
+            last_wal_offset: result.last_wal_offset,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchGetRequest {
+    pub ids: Vec<String>,
+}
+
+/// One id's outcome in a batch-get
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum BatchGetItem {
+    Found(InstanceResponse),
+    Failed { error: ApiError },
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchGetResponse {
+    pub instances: BTreeMap<String, BatchGetItem>,
 }
 
 #[derive(Debug, Serialize)]
@@ -60,20 +272,374 @@ pub struct HistoryEvent {
     pub from_state: Option<String>,
     pub to_state: String,
     pub timestamp: i64,
+    /// `timestamp` formatted per `?time_format`/`?tz`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_formatted: Option<String>,
     pub ctx: Option<Value>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Replace the configured `redaction.paths` in contexts
+    #[serde(default)]
+    pub redact: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct InstanceHistoryResponse {
     pub instance_id: String,
     pub events: Vec<HistoryEvent>,
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct ForkRequest {
+    /// Id for the new instance; generated by rstmdb when omitted
+    pub instance_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ForkQuery {
+    /// Only replay events at or after this WAL offset
+    pub from_offset: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForkFailure {
+    /// WAL offset of the source event that could not be replayed
+    pub offset: u64,
+    pub event: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForkResponse {
+    pub source_id: String,
+    pub instance_id: String,
+    pub machine: String,
+    pub version: u32,
+    /// State of the new instance after the replay
+    pub state: String,
+    /// Events applied to the new instance
+    pub replayed: usize,
+    /// Events in the source instance's history
+    pub total_events: usize,
+    /// Events before `from_offset` that were not replayed
+    pub skipped: usize,
+    /// False when the replay stopped at `failed`
+    pub completed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed: Option<ForkFailure>,
+}
+
+/// One recorded event of a source instance, to be re-applied
+#[derive(Debug, PartialEq)]
+struct ReplayEvent {
+    offset: u64,
+    event: String,
+    payload: Option<Value>,
+    from_state: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CheckEventRequest {
+    pub event: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckEventFailure {
+    pub code: String,
+    pub message: String,
+    /// For `GUARD_FAILED`, the evaluation trace of the last guard tried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<guard::GuardTrace>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckEventResponse {
+    pub instance_id: String,
+    pub event: String,
+    pub from_state: String,
+    pub would_transition: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to_state: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<CheckEventFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionStatusResponse {
+    pub instance_id: String,
+    pub machine: String,
+    pub state: String,
+    pub version: u32,
+    pub latest_version: u32,
+    pub upgrade_available: bool,
+    /// The instance's current state is not declared in the latest version
+    pub state_removed: bool,
+    /// Changes from the instance's version to the latest
+    pub diff: DefinitionDiff,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInstanceRequest {
+    pub machine: String,
+    pub version: u32,
+    /// Instance id; generated by rstmdb when omitted
+    pub instance_id: Option<String>,
+    /// Initial context (defaults to `{}`)
+    pub ctx: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApplyEventRequest {
+    pub event: String,
+    pub payload: Option<Value>,
+    /// Reject the event unless the instance is in this state
+    pub expected_state: Option<String>,
+    /// Reject the event unless this is still the instance's `last_wal_offset`.
+    /// Checked by Studio before the write, not by rstmdb, so it is not a
+    /// compare-and-swap; see [`apply_event`].
+    pub expected_wal_offset: Option<u64>,
+    /// Client-chosen UUID making retries safe: an id already applied to the
+    /// instance returns the original result with `applied: false`
+    pub event_id: Option<String>,
+}
+
+/// Dry-run failure codes
+mod check_failure {
+    pub const NO_TRANSITION: &str = "NO_TRANSITION";
+    pub const GUARD_FAILED: &str = "GUARD_FAILED";
+    pub const INVALID_GUARD: &str = "INVALID_GUARD";
+}
+
+/// Check a context against the machine's `contextSchema`, if it declares one
+fn enforce_context_schema(definition: &Value, ctx: &Value) -> ApiResult<()> {
+    let Some(schema) = definition.get(CONTEXT_SCHEMA_FIELD) else {
+        return Ok(());
+    };
+    let violations = validate_context(schema, ctx);
+    match violations.first() {
+        None => Ok(()),
+        Some(first) => Err(ApiError::context_schema_violation(format!(
+            "Context does not match the machine's contextSchema at {}: {}",
+            first.path, first.message
+        ))
+        .with_details(json!({ "violations": violations }))),
+    }
+}
+
+/// Context after applying an event payload, as rstmdb computes it: a shallow
+/// merge where each top-level payload key replaces the context's, so a
+/// nested object is replaced whole rather than merged and a `null` value is
+/// stored as `null`. A missing or non-object payload leaves the context
+/// unchanged.
+fn merged_context(ctx: &Value, payload: Option<&Value>) -> Value {
+    let mut merged = ctx.clone();
+    if let (Some(target), Some(Value::Object(fields))) = (merged.as_object_mut(), payload) {
+        for (key, value) in fields {
+            target.insert(key.clone(), value.clone());
+        }
+    }
+    merged
+}
+
+/// POST /api/v1/instances
+pub async fn create_instance(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<CreateInstanceRequest>,
+) -> ApiResult<Json<CreateInstanceResult>> {
+    let machine = state.rstmdb.get_machine(&req.machine, req.version).await?;
+    let ctx = req.ctx.unwrap_or_else(|| json!({}));
+    enforce_context_schema(&machine["definition"], &ctx)?;
+
+    let result = state
+        .rstmdb
+        .create_instance(
+            &req.machine,
+            req.version,
+            req.instance_id.as_deref(),
+            Some(ctx),
+        )
+        .await?;
+
+    tracing::info!(
+        instance_id = %result.instance_id,
+        machine = %req.machine,
+        version = req.version,
+        "Instance created"
+    );
+    Ok(Json(result))
+}
+
+/// Enforce the machine's `event_policies` entry, if any
+fn check_event_policy(
+    policy: Option<&EventPolicy>,
+    machine: &str,
+    event: &str,
+    user: &SessionUser,
+) -> ApiResult<()> {
+    let Some(policy) = policy else {
+        return Ok(());
+    };
+    let listed = |events: &[String]| events.iter().any(|e| e == event);
+    let allowed = policy.allow.as_deref().is_none_or(listed) && !listed(&policy.deny);
+    if !allowed {
+        return Err(ApiError::forbidden(format!(
+            "Event '{}' is not allowed for machine '{}'",
+            event, machine
+        )));
+    }
+    if listed(&policy.admin_only) && !user.is_admin() {
+        return Err(ApiError::forbidden(format!(
+            "Event '{}' on machine '{}' requires the admin role",
+            event, machine
+        )));
+    }
+    Ok(())
+}
+
+/// POST /api/v1/instances/:id/events
+///
+/// The machine's `event_policies` entry is enforced first. When the machine
+/// declares a `contextSchema`, the context that would result from the payload
+/// is validated before the event is sent to rstmdb. The event is sent with
+/// `expected_state` pinned to the state that was validated, so rstmdb
+/// refuses it if the instance transitioned in between. A client-supplied
+/// `expected_state` that doesn't match is refused with `STATE_MISMATCH`, as
+/// is an `expected_wal_offset` the instance has moved past.
+///
+/// rstmdb's apply takes an expected state but no expected offset, so only
+/// the state is checked atomically. `expected_wal_offset` is compared with
+/// a fresh read just before the write: a concurrent write that lands in
+/// between and leaves the instance in the same state (a self-transition,
+/// or a context-only change) is not detected.
+///
+/// An `event_id` seen for this instance within `server.event_dedup_window`
+/// short-circuits all of the above and returns the first result again; past
+/// the window, rstmdb's own idempotency check applies.
+pub async fn apply_event(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<SessionUser>,
+    Path(id): Path<String>,
+    Json(req): Json<ApplyEventRequest>,
+) -> ApiResult<Json<ApplyEventResult>> {
+    if let Some(event_id) = &req.event_id {
+        if uuid::Uuid::parse_str(event_id).is_err() {
+            return Err(ApiError::bad_request(format!(
+                "event_id '{}' is not a valid UUID",
+                event_id
+            )));
+        }
+        if let Some(original) = state.applied_events.replay(&id, event_id) {
+            tracing::debug!(instance_id = %id, event_id = %event_id, "Duplicate event_id; returning original result");
+            return Ok(Json(original));
+        }
+    }
+    if state.archive.is_archived(&id) {
+        return Err(ApiError::conflict(format!(
+            "Instance '{}' is archived; restore it before applying events",
+            id
+        )));
+    }
+    if state.paused.is_paused(&id) {
+        return Err(ApiError::instance_paused(format!(
+            "Instance '{}' is paused; resume it before applying events",
+            id
+        )));
+    }
+    // A cached copy could hide a write made since, and the checks below
+    // must see the state the event is pinned to
+    let instance = state.rstmdb.get_instance_fresh(&id).await?;
+    if let Some(expected) = req.expected_wal_offset {
+        check_wal_offset(&instance, expected)?;
+    }
+    if let Some(expected) = req.expected_state.as_deref() {
+        check_state(&instance, expected)?;
+    }
+    check_event_policy(
+        state.config.event_policies.get(&instance.machine),
+        &instance.machine,
+        &req.event,
+        &user,
+    )?;
+    let machine = state
+        .rstmdb
+        .get_machine(&instance.machine, instance.version)
+        .await?;
+    let resulting = merged_context(&instance.ctx, req.payload.as_ref());
+    enforce_context_schema(&machine["definition"], &resulting)?;
+
+    let mut result = state
+        .rstmdb
+        .apply_event(
+            &id,
+            &req.event,
+            req.payload,
+            // rstmdb checks the state atomically, so a transition between
+            // the checks above and this apply is refused
+            Some(&instance.state),
+            req.event_id.as_deref(),
+        )
+        .await?;
+    if let Some(event_id) = req.event_id {
+        result.event_id.get_or_insert_with(|| event_id.clone());
+        if result.applied {
+            state.applied_events.record(&id, &event_id, &result);
+        }
+    }
+    Ok(Json(result))
+}
+
+/// `STATE_MISMATCH` unless the instance is in the state the client expects
+fn check_state(instance: &InstanceResult, expected: &str) -> ApiResult<()> {
+    if instance.state == expected {
+        return Ok(());
+    }
+    Err(ApiError::state_mismatch(format!(
+        "Instance '{}' is in state '{}', not '{}'",
+        instance.instance_id, instance.state, expected
+    ))
+    .with_details(json!({
+        "expected_state": expected,
+        "state": instance.state,
+    })))
+}
+
+/// `STATE_MISMATCH` unless the instance is still at the WAL offset the
+/// client last read
+fn check_wal_offset(instance: &InstanceResult, expected: u64) -> ApiResult<()> {
+    if instance.last_wal_offset == expected {
+        return Ok(());
+    }
+    Err(ApiError::state_mismatch(format!(
+        "Instance '{}' has changed since it was read (expected WAL offset {}, now {})",
+        instance.instance_id, expected, instance.last_wal_offset
+    ))
+    .with_details(json!({
+        "expected_wal_offset": expected,
+        "last_wal_offset": instance.last_wal_offset,
+        "state": instance.state,
+    })))
+}
+
 /// GET /api/v1/instances?machine=xxx
+///
+/// rstmdb has no server-side ordering, so `sort` applies within the returned
+/// page only.
 pub async fn list_instances(
     State(state): State<Arc<AppState>>,
+    OriginalUri(uri): OriginalUri,
     Query(query): Query<ListInstancesQuery>,
-) -> ApiResult<Json<InstanceListResponse>> {
+    Query(time): Query<TimeFormatQuery>,
+    Query(fields): Query<FieldsQuery>,
+) -> ApiResult<Response> {
+    let formatter = time.formatter()?;
+    let selection = fields.selection(InstanceListItem::FIELDS)?;
+    let sort_keys = match query.sort.as_deref() {
+        Some(sort) => parse_sort(sort, query.order.as_deref())?,
+        None => Vec::new(),
+    };
+
     let result = state
         .rstmdb
         .list_instances(
@@ -84,41 +650,223 @@ pub async fn list_instances(
         )
         .await?;
 
-    let items: Vec<InstanceListItem> = result
+    let mut total = result.total;
+    let mut items: Vec<InstanceListItem> = result
         .instances
         .into_iter()
         .map(|i| InstanceListItem {
+            is_archived: state.archive.is_archived(&i.id),
+            paused: state.paused.is_paused(&i.id),
+            expires_at: state.instance_ttl.expires_at(&i.machine, i.created_at),
             id: i.id,
             machine: i.machine,
             version: i.version,
             state: i.state,
             created_at: i.created_at,
             updated_at: i.updated_at,
+            created_at_formatted: format_opt(formatter, i.created_at),
+            updated_at_formatted: format_opt(formatter, i.updated_at),
             last_wal_offset: i.last_wal_offset,
         })
         .collect();
+    if !query.include_archived {
+        // Pages may come back short; the total excludes archived instances
+        items.retain(|i| !i.is_archived);
+        total = total.saturating_sub(state.archive.count(&query.machine, query.state.as_deref()));
+    }
+    if let Some(paused) = query.paused {
+        // Filtered within the page; the total still counts every instance
+        items.retain(|i| i.paused == paused);
+    }
 
-    Ok(Json(InstanceListResponse {
+    sort_items(&mut items, &sort_keys);
+
+    let links = offset_links(
+        total,
+        query.offset.unwrap_or(0) as u64,
+        query.limit.unwrap_or(DEFAULT_LIST_LIMIT) as u64,
+        result.has_more,
+    );
+    let body = selection.apply_to_items(&InstanceListResponse {
         items,
-        total: result.total,
+        total,
         has_more: result.has_more,
-    }))
+    })?;
+    Ok(with_page_headers(&uri, &links, Json(body)))
+}
+
+/// GET /api/v1/instances/count?machine=xxx
+///
+/// Just the total from `list_instances`, without transferring items.
+pub async fn count_instances(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CountInstancesQuery>,
+) -> ApiResult<Json<CountInstancesResponse>> {
+    let mut total = state
+        .rstmdb
+        .count_instances(&query.machine, query.state.as_deref())
+        .await?;
+    if !query.include_archived {
+        total = total.saturating_sub(state.archive.count(&query.machine, query.state.as_deref()));
+    }
+    Ok(Json(CountInstancesResponse { total }))
 }
 
 /// GET /api/v1/instances/:id
 pub async fn get_instance(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-) -> ApiResult<Json<InstanceResponse>> {
+    Query(fields): Query<FieldsQuery>,
+) -> ApiResult<Json<Value>> {
+    let selection = fields.selection(InstanceResponse::FIELDS)?;
     let result = state.rstmdb.get_instance(&id).await?;
+    Ok(Json(
+        selection.apply(&InstanceResponse::new(result, &state))?,
+    ))
+}
 
-    Ok(Json(InstanceResponse {
-        instance_id: result.instance_id,
-        machine: result.machine,
-        version: result.version,
-        state: result.state,
-        ctx: result.ctx,
-        last_wal_offset: result.last_wal_offset,
+/// DELETE /api/v1/instances/:id
+///
+/// Permanently deletes the instance in rstmdb and drops Studio's archive and
+/// pause records for it. A retried delete (`?idempotent=true` or an
+/// `Idempotency-Key` header) of an instance that no longer exists succeeds
+/// with `204` rather than failing with `404`.
+pub async fn delete_instance(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<SessionUser>,
+    Path(id): Path<String>,
+    Query(query): Query<DeleteInstanceQuery>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    require_admin(&user)?;
+    let idempotent = query.idempotent || headers.contains_key(IDEMPOTENCY_KEY_HEADER);
+
+    let result = match state.rstmdb.delete_instance(&id).await {
+        Ok(result) => Some(result),
+        Err(e) if e.code == "NOT_FOUND" && idempotent => None,
+        Err(e) => return Err(e),
+    };
+    // Sidecar records are dropped on retries too, in case the first attempt
+    // failed after rstmdb deleted the instance
+    state
+        .archive
+        .restore(&id)
+        .and_then(|_| state.paused.resume(&id))
+        .map_err(|e| ApiError::internal(format!("Failed to drop instance records: {}", e)))?;
+
+    match result {
+        Some(result) => {
+            tracing::info!(instance_id = %id, username = %user.username, "Instance deleted");
+            Ok(Json::<DeleteInstanceResult>(result).into_response())
+        }
+        None => Ok(StatusCode::NO_CONTENT.into_response()),
+    }
+}
+
+/// Deduplicated ids of a batch-get, rejecting requests over the cap
+fn batch_ids(ids: Vec<String>, max: usize) -> ApiResult<BTreeSet<String>> {
+    let ids: BTreeSet<String> = ids.into_iter().collect();
+    if ids.len() > max {
+        return Err(ApiError::bad_request(format!(
+            "At most {} instance ids may be requested at once (got {})",
+            max,
+            ids.len()
+        )));
+    }
+    if ids.iter().any(|id| id.is_empty()) {
+        return Err(ApiError::bad_request("Instance ids must not be empty"));
+    }
+    Ok(ids)
+}
+
+/// Fetch instances concurrently, at most `BATCH_GET_CONCURRENCY` at a time.
+///
+/// Results are returned per id, in completion order. Fetches keep the
+/// caller's rstmdb queue priority and operation budget.
+pub async fn fetch_instances(
+    state: &Arc<AppState>,
+    ids: impl IntoIterator<Item = String>,
+) -> ApiResult<Vec<(String, ApiResult<InstanceResult>)>> {
+    let permits = Arc::new(Semaphore::new(BATCH_GET_CONCURRENCY));
+    let priority = rstmdb::current_priority();
+    let budget = rstmdb::current_budget();
+    let mut fetches = JoinSet::new();
+    for id in ids {
+        let state = state.clone();
+        let permits = permits.clone();
+        let fetch = rstmdb::with_priority(priority, async move {
+            let _permit = permits.acquire_owned().await;
+            let result = state.rstmdb.get_instance(&id).await;
+            (id, result)
+        });
+        fetches.spawn(rstmdb::with_budget(budget.clone(), fetch));
+    }
+
+    let mut results = Vec::with_capacity(fetches.len());
+    while let Some(joined) = fetches.join_next().await {
+        results.push(joined.map_err(|e| ApiError::internal(format!("Batch fetch failed: {}", e)))?);
+    }
+    Ok(results)
+}
+
+/// POST /api/v1/instances/batch-get
+///
+/// Fetches several instances concurrently. A missing or failing id is
+/// reported in its own entry rather than failing the whole request.
+pub async fn batch_get_instances(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<BatchGetRequest>,
+) -> ApiResult<Json<BatchGetResponse>> {
+    let ids = batch_ids(req.ids, state.config.server.batch_get_max_ids)?;
+
+    let mut instances = BTreeMap::new();
+    for (id, result) in fetch_instances(&state, ids).await? {
+        let item = match result {
+            Ok(instance) => BatchGetItem::Found(InstanceResponse::new(instance, &state)),
+            Err(error) => BatchGetItem::Failed { error },
+        };
+        instances.insert(id, item);
+    }
+
+    Ok(Json(BatchGetResponse { instances }))
+}
+
+/// GET /api/v1/instances/:id/version-status
+///
+/// Compares the version an instance runs on with the machine's latest version.
+pub async fn get_version_status(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<VersionStatusResponse>> {
+    let instance = state.rstmdb.get_instance(&id).await?;
+    let latest = latest_version(&state, &instance.machine).await?;
+
+    let (diff, state_removed) = if latest > instance.version {
+        let current = state
+            .rstmdb
+            .get_machine(&instance.machine, instance.version)
+            .await?;
+        let newest = state.rstmdb.get_machine(&instance.machine, latest).await?;
+        let removed = !TransitionGraph::from_definition(&newest["definition"])
+            .states
+            .contains(&instance.state.as_str());
+        (
+            diff_definitions(&current["definition"], &newest["definition"]),
+            removed,
+        )
+    } else {
+        (DefinitionDiff::default(), false)
+    };
+
+    Ok(Json(VersionStatusResponse {
+        instance_id: instance.instance_id,
+        machine: instance.machine,
+        state: instance.state,
+        version: instance.version,
+        latest_version: latest,
+        upgrade_available: latest > instance.version,
+        state_removed,
+        diff,
     }))
 }
 
@@ -126,66 +874,795 @@ pub async fn get_instance(
 pub async fn get_instance_history(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+    Query(time): Query<TimeFormatQuery>,
 ) -> ApiResult<Json<InstanceHistoryResponse>> {
+    let formatter = time.formatter()?;
     // Get instance info first to know the WAL range
     let instance = state.rstmdb.get_instance(&id).await?;
 
-    // Read WAL entries - start from 0 and scan (TODO: optimize with index)
-    let wal_result = state.rstmdb.wal_read(0, Some(HISTORY_MAX_WAL_SCAN)).await?;
-
     let mut events = Vec::new();
+    for (offset, entry) in instance_wal_entries(&state, &id, instance.last_wal_offset).await? {
+        let entry_type = entry.str_or_empty("type");
+        let timestamp = entry.i64_or("timestamp", 0);
+
+        let event = match entry_type.as_str() {
+            wal_entry_types::CREATE_INSTANCE => Some(HistoryEvent {
+                offset,
+                event_type: history_event_types::CREATED.to_string(),
+                event: None,
+                from_state: None,
+                to_state: entry.str_or_empty("initial_state"),
+                timestamp,
+                timestamp_formatted: format_opt(formatter, timestamp),
+                ctx: entry.get("initial_ctx").cloned(),
+            }),
+            wal_entry_types::APPLY_EVENT => Some(HistoryEvent {
+                offset,
+                event_type: history_event_types::TRANSITION.to_string(),
+                event: Some(entry.str_or_empty("event")),
+                from_state: Some(entry.str_or_empty("from_state")),
+                to_state: entry.str_or_empty("to_state"),
+                timestamp,
+                timestamp_formatted: format_opt(formatter, timestamp),
+                ctx: entry.get("ctx").cloned(),
+            }),
+            _ => None,
+        };
+
+        if let Some(e) = event {
+            events.push(e);
+        }
+    }
+
+    if query.redact {
+        for ctx in events.iter_mut().filter_map(|e| e.ctx.as_mut()) {
+            state.redactor.redact(ctx);
+        }
+    }
+
+    // Reverse to show newest first
+    events.reverse();
 
-    if let Some(records) = wal_result["records"].as_array() {
+    Ok(Json(InstanceHistoryResponse {
+        instance_id: id,
+        events,
+    }))
+}
+
+/// WAL entries of one instance, oldest first, with their offsets.
+///
+/// rstmdb has no per-instance WAL index, so this pages through the WAL from
+/// the start up to the instance's last known offset. Each page counts
+/// against the request's rstmdb operation budget, so a WAL too long to scan
+/// fails with `RESOURCE_EXHAUSTED` rather than yielding a partial history.
+pub async fn instance_wal_entries(
+    state: &AppState,
+    id: &str,
+    last_wal_offset: u64,
+) -> ApiResult<Vec<(u64, Value)>> {
+    let mut pages = InstanceWalPages::new(id, last_wal_offset);
+    let mut entries = Vec::new();
+    while let Some(page) = pages.next_page(state).await? {
+        entries.extend(page);
+    }
+    Ok(entries)
+}
+
+/// Page-at-a-time form of [`instance_wal_entries`], for callers that stream
+/// the history instead of holding all of it
+pub struct InstanceWalPages {
+    id: String,
+    last_wal_offset: u64,
+    cursor: Option<u64>,
+    reached_end: bool,
+}
+
+impl InstanceWalPages {
+    pub fn new(id: &str, last_wal_offset: u64) -> Self {
+        Self {
+            id: id.to_string(),
+            last_wal_offset,
+            cursor: Some(0),
+            reached_end: false,
+        }
+    }
+
+    /// The instance's entries from the next WAL page, `None` once done
+    pub async fn next_page(&mut self, state: &AppState) -> ApiResult<Option<Vec<(u64, Value)>>> {
+        let Some(cursor) = self.cursor.take() else {
+            return Ok(None);
+        };
+        let mut page = state
+            .rstmdb
+            .wal_read(cursor, Some(HISTORY_WAL_PAGE_SIZE))
+            .await?;
+        let next_offset = page.u64_opt("next_offset");
+        let Some(records) = page["records"].as_array_mut().filter(|r| !r.is_empty()) else {
+            return Ok(None);
+        };
+        let mut entries = Vec::new();
+        let mut last = cursor;
         for record in records {
-            let entry = &record["entry"];
-            let entry_instance = entry.str_or_empty("instance_id");
+            last = record.u64_or("offset", cursor);
+            if record["entry"]["instance_id"].as_str() == Some(self.id.as_str()) {
+                entries.push((last, record["entry"].take()));
+            }
+            if last >= self.last_wal_offset {
+                self.reached_end = true;
+                return Ok(Some(entries));
+            }
+        }
+        self.cursor = next_offset.filter(|&next| next > last);
+        Ok(Some(entries))
+    }
+
+    /// Whether paging got as far as the instance's last known offset, rather
+    /// than running out of WAL first
+    pub fn reached_end(&self) -> bool {
+        self.reached_end
+    }
+}
+
+/// What a fork creates and re-applies, from a source instance's WAL entries
+#[derive(Debug, Default)]
+struct ReplayPlan {
+    /// Context of the new instance: the initial one, or the one the source
+    /// had just before `from_offset`
+    initial_ctx: Option<Value>,
+    events: Vec<ReplayEvent>,
+    /// Events before `from_offset`
+    skipped: usize,
+}
+
+/// Plan a fork; fails if the source's `create_instance` entry was not found
+/// in the WAL (e.g. compacted away), since a replay without it would start
+/// from the wrong context and silently miss events
+fn replay_plan(id: &str, entries: &[(u64, Value)], from_offset: u64) -> ApiResult<ReplayPlan> {
+    let created = entries
+        .iter()
+        .any(|(_, entry)| entry["type"].as_str() == Some(wal_entry_types::CREATE_INSTANCE));
+    if !created {
+        return Err(ApiError::conflict(format!(
+            "The creation of instance '{}' was not found in the WAL, so its history can't be replayed",
+            id
+        )));
+    }
 
-            if entry_instance != id {
+    let mut plan = ReplayPlan::default();
+    for (offset, entry) in entries {
+        match entry["type"].as_str() {
+            Some(wal_entry_types::CREATE_INSTANCE) => {
+                plan.initial_ctx = entry.get("initial_ctx").cloned();
+            }
+            Some(wal_entry_types::APPLY_EVENT) if *offset < from_offset => {
+                if let Some(ctx) = entry.get("ctx").filter(|c| !c.is_null()) {
+                    plan.initial_ctx = Some(ctx.clone());
+                }
+                plan.skipped += 1;
+            }
+            Some(wal_entry_types::APPLY_EVENT) => plan.events.push(ReplayEvent {
+                offset: *offset,
+                event: entry.str_or_empty("event"),
+                payload: entry.get("payload").filter(|p| !p.is_null()).cloned(),
+                from_state: entry.str_or_empty("from_state"),
+            }),
+            _ => {}
+        }
+    }
+    Ok(plan)
+}
+
+/// Enforce the event policy on every event a fork would replay, so forking
+/// can't apply an event the user could not apply directly
+fn check_replay_policy(
+    policy: Option<&EventPolicy>,
+    machine: &str,
+    events: &[ReplayEvent],
+    user: &SessionUser,
+) -> ApiResult<()> {
+    for event in events {
+        check_event_policy(policy, machine, &event.event, user)
+            .map_err(|e| e.with_details(json!({ "offset": event.offset, "event": event.event })))?;
+    }
+    Ok(())
+}
+
+/// Refuse replays longer than `server.max_replay_events`
+fn check_replay_length(events: usize, max: usize) -> ApiResult<()> {
+    if events <= max {
+        return Ok(());
+    }
+    Err(ApiError::history_too_long(format!(
+        "Instance history has {} events to replay, more than the limit of {}; use ?from_offset= to replay only recent events",
+        events, max
+    ))
+    .with_details(json!({ "events": events, "max_replay_events": max })))
+}
+
+/// POST /api/v1/instances/:id/fork
+///
+/// Creates a new instance on the source's machine version and re-applies the
+/// source's events in order. Each event must leave from the state it did
+/// originally; the replay stops at the first event that fails (e.g. a guard
+/// that evaluates differently) and reports it.
+///
+/// With `?from_offset=`, earlier events are skipped and the new instance
+/// starts with the context the source had at that point. It still starts in
+/// the machine's initial state, so the first replayed event must leave from
+/// there for the replay to get anywhere.
+///
+/// The same checks as `POST .../events` apply: the whole fork is refused if
+/// the machine's `event_policies` forbid the user any replayed event, and the
+/// initial and each resulting context must match the machine's
+/// `contextSchema`; a violating event stops the replay like any other failure.
+pub async fn fork_instance(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<SessionUser>,
+    Path(id): Path<String>,
+    Query(query): Query<ForkQuery>,
+    body: Option<Json<ForkRequest>>,
+) -> ApiResult<Json<ForkResponse>> {
+    let req = body.map(|Json(req)| req).unwrap_or_default();
+    let source = state.rstmdb.get_instance(&id).await?;
+    let entries = instance_wal_entries(&state, &id, source.last_wal_offset).await?;
+    let ReplayPlan {
+        initial_ctx,
+        events,
+        skipped,
+    } = replay_plan(&id, &entries, query.from_offset.unwrap_or(0))?;
+    check_replay_length(events.len(), state.config.server.max_replay_events)?;
+    check_replay_policy(
+        state.config.event_policies.get(&source.machine),
+        &source.machine,
+        &events,
+        &user,
+    )?;
+    let machine = state
+        .rstmdb
+        .get_machine(&source.machine, source.version)
+        .await?;
+    let definition = &machine["definition"];
+    let mut ctx = initial_ctx.unwrap_or_else(|| json!({}));
+    enforce_context_schema(definition, &ctx)?;
+
+    let created = state
+        .rstmdb
+        .create_instance(
+            &source.machine,
+            source.version,
+            req.instance_id.as_deref(),
+            Some(ctx.clone()),
+        )
+        .await?;
+
+    let mut current = created.state;
+    let mut replayed = 0;
+    let mut failed = None;
+    for event in &events {
+        let resulting = merged_context(&ctx, event.payload.as_ref());
+        let result = match enforce_context_schema(definition, &resulting) {
+            Ok(()) => {
+                state
+                    .rstmdb
+                    .apply_event(
+                        &created.instance_id,
+                        &event.event,
+                        event.payload.clone(),
+                        Some(&event.from_state),
+                        None,
+                    )
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        let error = match result {
+            Ok(applied) if applied.applied => {
+                current = applied.to_state;
+                ctx = applied.ctx.unwrap_or(resulting);
+                replayed += 1;
                 continue;
             }
+            Ok(_) => "Event was not applied".to_string(),
+            Err(e) => e.message,
+        };
+        failed = Some(ForkFailure {
+            offset: event.offset,
+            event: event.event.clone(),
+            error,
+        });
+        break;
+    }
 
-            let offset = record.u64_or("offset", 0);
-            let entry_type = entry.str_or_empty("type");
-            let timestamp = entry.i64_or("timestamp", 0);
-
-            let event = match entry_type.as_str() {
-                wal_entry_types::CREATE_INSTANCE => Some(HistoryEvent {
-                    offset,
-                    event_type: history_event_types::CREATED.to_string(),
-                    event: None,
-                    from_state: None,
-                    to_state: entry.str_or_empty("initial_state"),
-                    timestamp,
-                    ctx: entry.get("initial_ctx").cloned(),
-                }),
-                wal_entry_types::APPLY_EVENT => Some(HistoryEvent {
-                    offset,
-                    event_type: history_event_types::TRANSITION.to_string(),
-                    event: Some(entry.str_or_empty("event")),
-                    from_state: Some(entry.str_or_empty("from_state")),
-                    to_state: entry.str_or_empty("to_state"),
-                    timestamp,
-                    ctx: entry.get("ctx").cloned(),
-                }),
-                _ => None,
+    tracing::info!(
+        source_id = %id,
+        instance_id = %created.instance_id,
+        replayed,
+        completed = failed.is_none(),
+        "Instance forked"
+    );
+    Ok(Json(ForkResponse {
+        source_id: id,
+        instance_id: created.instance_id,
+        machine: source.machine,
+        version: source.version,
+        state: current,
+        replayed,
+        total_events: events.len() + skipped,
+        skipped,
+        completed: failed.is_none(),
+        failed,
+    }))
+}
+
+/// Render the context values a guard references, e.g. `ctx.amount = 120`.
+///
+/// Numbers are shown with their exact source text so large integers and long
+/// decimals are not rounded.
+fn observed_fields(expr: &guard::Expr, ctx: &Value) -> String {
+    expr.fields()
+        .into_iter()
+        .map(|path| {
+            let shown = match guard::lookup(ctx, path) {
+                None => "<missing>".to_string(),
+                Some(value) => path
+                    .split_last()
+                    .and_then(|(last, parents)| guard::lookup(ctx, parents)?.number_str(last))
+                    .unwrap_or_else(|| value.to_string()),
             };
+            format!("ctx.{} = {}", path.join("."), shown)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
-            if let Some(e) = event {
-                events.push(e);
-            }
+/// POST /api/v1/instances/:id/events/check
+///
+/// Dry-run an event: find the matching transition for the instance's current
+/// state and evaluate its guard against the current context, without writing
+/// to rstmdb.
+pub async fn check_event(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(req): Json<CheckEventRequest>,
+) -> ApiResult<Json<CheckEventResponse>> {
+    let instance = state.rstmdb.get_instance(&id).await?;
+    let machine = state
+        .rstmdb
+        .get_machine(&instance.machine, instance.version)
+        .await?;
+
+    let candidates: Vec<&Value> = machine["definition"]["transitions"]
+        .as_array()
+        .map(|arr| {
+            arr.iter()
+                .filter(|t| {
+                    t["event"].as_str() == Some(req.event.as_str())
+                        && transition_from_states(t).contains(&instance.state.as_str())
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut failure = CheckEventFailure {
+        code: check_failure::NO_TRANSITION.to_string(),
+        message: format!(
+            "No transition for event '{}' from state '{}'",
+            req.event, instance.state
+        ),
+        details: None,
+    };
+    let mut to_state = None;
 
-            // Stop if we've reached the instance's last known offset
-            if offset >= instance.last_wal_offset {
+    // Transitions are tried in definition order; the first passing guard wins
+    for transition in candidates {
+        match transition["guard"].as_str() {
+            None => {
+                to_state = transition.str_opt("to");
                 break;
             }
+            Some(expr) => match guard::evaluate(expr, &instance.ctx) {
+                Ok(true) => {
+                    to_state = transition.str_opt("to");
+                    break;
+                }
+                Ok(false) => {
+                    // Parsed once already; again for the fields and trace
+                    let Ok(parsed) = guard::parse(expr) else {
+                        continue;
+                    };
+                    failure = CheckEventFailure {
+                        code: check_failure::GUARD_FAILED.to_string(),
+                        message: format!(
+                            "Guard '{}' not satisfied ({})",
+                            expr,
+                            observed_fields(&parsed, &instance.ctx)
+                        ),
+                        details: Some(parsed.trace(expr, &instance.ctx)),
+                    };
+                }
+                Err(e) => {
+                    failure = CheckEventFailure {
+                        code: check_failure::INVALID_GUARD.to_string(),
+                        message: format!("Guard '{}' could not be parsed: {}", expr, e),
+                        details: None,
+                    };
+                }
+            },
         }
     }
 
-    // Reverse to show newest first
-    events.reverse();
-
-    Ok(Json(InstanceHistoryResponse {
+    Ok(Json(CheckEventResponse {
         instance_id: id,
-        events,
+        event: req.event,
+        from_state: instance.state,
+        would_transition: to_state.is_some(),
+        failure: if to_state.is_some() {
+            None
+        } else {
+            Some(failure)
+        },
+        to_state,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Role;
+    use crate::config::Config;
+    use crate::rstmdb::FakeRstmdb;
+
+    #[test]
+    fn test_replay_plan_from_wal_entries() {
+        let entries = vec![
+            (
+                3,
+                json!({ "type": "create_instance", "instance_id": "o1", "initial_ctx": { "n": 1 } }),
+            ),
+            (
+                7,
+                json!({ "type": "apply_event", "instance_id": "o1", "event": "PAY",
+                        "from_state": "new", "to_state": "paid", "payload": { "amount": 5 } }),
+            ),
+            (
+                9,
+                json!({ "type": "apply_event", "instance_id": "o1", "event": "SHIP",
+                        "from_state": "paid", "to_state": "shipped", "payload": null }),
+            ),
+        ];
+        let plan = replay_plan("o1", &entries, 0).unwrap();
+        assert_eq!(plan.initial_ctx, Some(json!({ "n": 1 })));
+        assert_eq!(plan.skipped, 0);
+        assert_eq!(
+            plan.events,
+            vec![
+                ReplayEvent {
+                    offset: 7,
+                    event: "PAY".to_string(),
+                    payload: Some(json!({ "amount": 5 })),
+                    from_state: "new".to_string(),
+                },
+                ReplayEvent {
+                    offset: 9,
+                    event: "SHIP".to_string(),
+                    payload: None,
+                    from_state: "paid".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replay_plan_suffix_and_limit() {
+        let entries = vec![
+            (
+                3,
+                json!({ "type": "create_instance", "initial_ctx": { "n": 1 } }),
+            ),
+            (
+                7,
+                json!({ "type": "apply_event", "event": "PAY", "from_state": "new",
+                        "to_state": "paid", "ctx": { "n": 2 } }),
+            ),
+            (
+                9,
+                json!({ "type": "apply_event", "event": "REOPEN", "from_state": "paid",
+                        "to_state": "new", "ctx": { "n": 3 } }),
+            ),
+            (
+                12,
+                json!({ "type": "apply_event", "event": "PAY", "from_state": "new",
+                        "to_state": "paid", "ctx": { "n": 4 } }),
+            ),
+        ];
+        let plan = replay_plan("o1", &entries, 10).unwrap();
+        assert_eq!(plan.skipped, 2);
+        assert_eq!(plan.initial_ctx, Some(json!({ "n": 3 })));
+        assert_eq!(plan.events.len(), 1);
+        assert_eq!(plan.events[0].offset, 12);
+
+        // Without its creation entry the history is incomplete
+        let err = replay_plan("o1", &entries[1..], 0).unwrap_err();
+        assert_eq!(err.code, "CONFLICT");
+
+        assert!(check_replay_length(3, 3).is_ok());
+        let err = check_replay_length(4, 3).unwrap_err();
+        assert_eq!(err.code, "HISTORY_TOO_LONG");
+        assert_eq!(err.details.unwrap()["events"], 4);
+    }
+
+    #[test]
+    fn test_batch_ids_dedupes_and_caps() {
+        let ids = batch_ids(vec!["b".into(), "a".into(), "b".into()], 2).unwrap();
+        assert_eq!(ids.into_iter().collect::<Vec<_>>(), vec!["a", "b"]);
+
+        let err = batch_ids(vec!["a".into(), "b".into(), "c".into()], 2).unwrap_err();
+        assert_eq!(err.code, "BAD_REQUEST");
+        assert!(batch_ids(vec![String::new()], 2).is_err());
+        assert!(batch_ids(Vec::new(), 2).unwrap().is_empty());
+    }
+
+    fn item(id: &str, state: &str, updated_at: i64) -> InstanceListItem {
+        InstanceListItem {
+            id: id.to_string(),
+            machine: "order".to_string(),
+            version: 1,
+            state: state.to_string(),
+            created_at: 0,
+            updated_at,
+            created_at_formatted: None,
+            updated_at_formatted: None,
+            is_archived: false,
+            paused: false,
+            expires_at: None,
+            last_wal_offset: 0,
+        }
+    }
+
+    #[test]
+    fn test_event_policy() {
+        let user = |role| SessionUser {
+            username: "u".to_string(),
+            role,
+            logged_in_at: 0,
+            last_activity: 0,
+            password_reset_required: false,
+            expires_at: None,
+            session_id: String::new(),
+        };
+        let (admin, viewer) = (user(Role::Admin), user(Role::Viewer));
+        let events = |list: &[&str]| list.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        let check = |policy: &EventPolicy, event: &str, user: &SessionUser| {
+            check_event_policy(Some(policy), "order", event, user).map_err(|e| e.code)
+        };
+
+        // No policy: everything goes
+        assert!(check_event_policy(None, "order", "FORCE_CLOSE", &viewer).is_ok());
+
+        let policy = EventPolicy {
+            allow: None,
+            deny: events(&["PURGE"]),
+            admin_only: events(&["FORCE_CLOSE"]),
+        };
+        assert!(check(&policy, "PAY", &viewer).is_ok());
+        assert_eq!(check(&policy, "PURGE", &admin).unwrap_err(), "FORBIDDEN");
+        assert_eq!(
+            check(&policy, "FORCE_CLOSE", &viewer).unwrap_err(),
+            "FORBIDDEN"
+        );
+        assert!(check(&policy, "FORCE_CLOSE", &admin).is_ok());
+
+        let allowlist = EventPolicy {
+            allow: Some(events(&["PAY", "SHIP"])),
+            ..EventPolicy::default()
+        };
+        assert!(check(&allowlist, "SHIP", &viewer).is_ok());
+        assert_eq!(
+            check(&allowlist, "REFUND", &admin).unwrap_err(),
+            "FORBIDDEN"
+        );
+    }
+
+    #[test]
+    fn test_fork_replay_respects_event_policy() {
+        let user = |role| SessionUser {
+            username: "u".to_string(),
+            role,
+            logged_in_at: 0,
+            last_activity: 0,
+            password_reset_required: false,
+            expires_at: None,
+            session_id: String::new(),
+        };
+        let event = |offset, name: &str| ReplayEvent {
+            offset,
+            event: name.to_string(),
+            payload: None,
+            from_state: "open".to_string(),
+        };
+        let policy = EventPolicy {
+            allow: None,
+            deny: vec!["PURGE".to_string()],
+            admin_only: vec!["FORCE_CLOSE".to_string()],
+        };
+        let history = [event(10, "PAY"), event(20, "FORCE_CLOSE")];
+
+        assert!(check_replay_policy(Some(&policy), "order", &history, &user(Role::Admin)).is_ok());
+        let err =
+            check_replay_policy(Some(&policy), "order", &history, &user(Role::Viewer)).unwrap_err();
+        assert_eq!(err.code, "FORBIDDEN");
+        assert_eq!(err.details.unwrap()["offset"], 20);
+
+        let purged = [event(10, "PURGE")];
+        assert!(check_replay_policy(Some(&policy), "order", &purged, &user(Role::Admin)).is_err());
+        assert!(check_replay_policy(None, "order", &purged, &user(Role::Viewer)).is_ok());
+    }
+
+    #[test]
+    fn test_merged_context_overlays_payload() {
+        let ctx = json!({"a": 1, "b": {"x": 1}});
+        let merged = merged_context(&ctx, Some(&json!({"b": {"y": 2}, "c": 3})));
+        assert_eq!(merged, json!({"a": 1, "b": {"y": 2}, "c": 3}));
+        assert_eq!(merged_context(&ctx, None), ctx);
+        assert_eq!(
+            merged_context(&ctx, Some(&json!({"a": null}))),
+            json!({"a": null, "b": {"x": 1}})
+        );
+        assert_eq!(merged_context(&ctx, Some(&json!([1, 2]))), ctx);
+    }
+
+    fn schema_machine() -> FakeRstmdb {
+        FakeRstmdb::new().with_machine(
+            "order",
+            1,
+            json!({
+                "states": ["new", "paid"],
+                "initial": "new",
+                "transitions": [{ "from": "new", "event": "PAY", "to": "paid" }],
+                "contextSchema": {
+                    "type": "object",
+                    "properties": { "amount": { "type": "number" } },
+                    "required": ["amount"]
+                }
+            }),
+        )
+    }
+
+    fn viewer() -> SessionUser {
+        SessionUser {
+            username: "u".to_string(),
+            role: Role::Viewer,
+            logged_in_at: 0,
+            last_activity: 0,
+            password_reset_required: false,
+            expires_at: None,
+            session_id: String::new(),
+        }
+    }
+
+    fn apply_request(payload: Value, expected_state: Option<&str>) -> ApplyEventRequest {
+        ApplyEventRequest {
+            event: "PAY".to_string(),
+            payload: Some(payload),
+            expected_state: expected_state.map(String::from),
+            expected_wal_offset: None,
+            event_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_instance_enforces_context_schema() {
+        let fake = schema_machine();
+        let state = AppState::for_test(Config::default(), fake.clone());
+        let create = |ctx| CreateInstanceRequest {
+            machine: "order".to_string(),
+            version: 1,
+            instance_id: Some("o-1".to_string()),
+            ctx: Some(ctx),
+        };
+
+        let err = create_instance(State(state.clone()), Json(create(json!({"amount": "x"}))))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, "CONTEXT_SCHEMA_VIOLATION");
+        assert_eq!(fake.calls("create_instance"), 0);
+
+        let Json(created) = create_instance(State(state), Json(create(json!({"amount": 5}))))
+            .await
+            .unwrap();
+        assert_eq!(created.state, "new");
+        assert_eq!(fake.instance("o-1").unwrap().ctx, json!({"amount": 5}));
+    }
+
+    #[tokio::test]
+    async fn test_apply_event_validates_merged_context_and_pins_state() {
+        let fake = schema_machine();
+        let state = AppState::for_test(Config::default(), fake.clone());
+        state
+            .rstmdb
+            .create_instance("order", 1, Some("o-1"), Some(json!({"amount": 5})))
+            .await
+            .unwrap();
+        let apply = |req| {
+            apply_event(
+                State(state.clone()),
+                Extension(viewer()),
+                Path("o-1".to_string()),
+                Json(req),
+            )
+        };
+
+        let err = apply(apply_request(json!({"amount": null}), None))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, "CONTEXT_SCHEMA_VIOLATION");
+        let err = apply(apply_request(json!({"amount": 7}), Some("paid")))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, "STATE_MISMATCH");
+        assert_eq!(fake.calls("apply_event"), 0);
+
+        let Json(result) = apply(apply_request(json!({"amount": 7}), None))
+            .await
+            .unwrap();
+        assert_eq!(
+            (result.from_state.as_str(), result.to_state.as_str()),
+            ("new", "paid")
+        );
+        let instance = fake.instance("o-1").unwrap();
+        assert_eq!(instance.ctx, json!({"amount": 7}));
+    }
+
+    #[test]
+    fn test_context_schema_enforced_only_when_declared() {
+        let plain = json!({"states": ["a"], "initial": "a", "transitions": []});
+        assert!(enforce_context_schema(&plain, &json!({"anything": 1})).is_ok());
+
+        let with_schema = json!({
+            "states": ["a"], "initial": "a", "transitions": [],
+            "contextSchema": {"type": "object", "required": ["amount"]}
+        });
+        let err = enforce_context_schema(&with_schema, &json!({})).unwrap_err();
+        assert_eq!(err.code, "CONTEXT_SCHEMA_VIOLATION");
+        assert_eq!(err.details.unwrap()["violations"][0]["path"], "$.amount");
+    }
+
+    #[test]
+    fn test_parse_sort_rejects_unknown_field() {
+        let err = parse_sort("state,ctx", None).unwrap_err();
+        assert_eq!(err.code, "BAD_REQUEST");
+        assert!(parse_sort("id", Some("sideways")).is_err());
+    }
+
+    #[test]
+    fn test_multi_field_sort() {
+        let mut items = vec![
+            item("c", "pending", 10),
+            item("a", "done", 5),
+            item("b", "pending", 30),
+        ];
+        let keys = parse_sort("state, updated_at", Some("asc,desc")).unwrap();
+        sort_items(&mut items, &keys);
+
+        let ids: Vec<&str> = items.iter().map(|i| i.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_check_wal_offset() {
+        let instance = InstanceResult {
+            instance_id: "i-1".to_string(),
+            machine: "orders".to_string(),
+            version: 1,
+            state: "paid".to_string(),
+            ctx: json!({}),
+            last_wal_offset: 42,
+        };
+        assert!(check_wal_offset(&instance, 42).is_ok());
+
+        let err = check_wal_offset(&instance, 40).unwrap_err();
+        assert_eq!(err.code, "STATE_MISMATCH");
+        assert_eq!(err.details.unwrap()["last_wal_offset"], 42);
+    }
+}
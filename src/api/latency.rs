@@ -0,0 +1,29 @@
+//! HTTP request latency
+//!
+//! Durations of API requests by method and matched route (e.g.
+//! `/api/v1/instances/:id`), exported as a histogram on `/metrics`. Requests
+//! that match no route are not recorded, which keeps the label set bounded.
+
+use crate::metrics::Histogram;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Time the request, including the auth and limit middleware inside this layer
+pub async fn record(State(histogram): State<Arc<Histogram>>, req: Request, next: Next) -> Response {
+    let Some(route) = req.extensions().get::<MatchedPath>().cloned() else {
+        return next.run(req).await;
+    };
+    let method = req.method().clone();
+    let started = Instant::now();
+    let response = next.run(req).await;
+    histogram.observe(
+        &[("method", method.as_str()), ("route", route.as_str())],
+        started.elapsed(),
+    );
+    response
+}
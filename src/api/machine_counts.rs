@@ -0,0 +1,151 @@
+//! Cached machine list counts
+//!
+//! `GET /api/v1/machines` shows each machine's state and transition counts,
+//! which take a definition fetch per machine. Versions are immutable, so
+//! counts are cached per `(machine, version)`; a background task fills the
+//! cache for every machine's latest version and drops superseded versions.
+
+use crate::api::machines::{get_definition_counts, latest_versions};
+use crate::constants::machine_counts::REFRESH_CONCURRENCY;
+use crate::rstmdb::{with_priority, Priority};
+use crate::AppState;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// States and transitions in one machine version's definition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefinitionCounts {
+    pub states: usize,
+    pub transitions: usize,
+}
+
+#[derive(Default)]
+pub struct MachineCountsCache {
+    entries: Mutex<HashMap<(String, u32), DefinitionCounts>>,
+    /// Epoch milliseconds of the last completed refresh
+    last_refresh: Mutex<Option<i64>>,
+}
+
+impl MachineCountsCache {
+    pub fn get(&self, machine: &str, version: u32) -> Option<DefinitionCounts> {
+        self.entries
+            .lock()
+            .get(&(machine.to_string(), version))
+            .copied()
+    }
+
+    pub fn insert(&self, machine: &str, version: u32, counts: DefinitionCounts) {
+        self.entries
+            .lock()
+            .insert((machine.to_string(), version), counts);
+    }
+
+    /// Forget a machine's counts, e.g. after Studio stored a new version
+    pub fn invalidate(&self, machine: &str) {
+        self.entries.lock().retain(|(name, _), _| name != machine);
+    }
+
+    /// Keep only the counts of the given latest versions
+    fn retain_latest(&self, latest: &HashMap<String, u32>) {
+        self.entries
+            .lock()
+            .retain(|(name, version), _| latest.get(name) == Some(version));
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    pub fn last_refresh(&self) -> Option<i64> {
+        *self.last_refresh.lock()
+    }
+}
+
+/// Counts of a machine version, from the cache or its definition
+pub async fn definition_counts(
+    state: &AppState,
+    machine: &str,
+    version: u32,
+) -> Option<DefinitionCounts> {
+    if let Some(counts) = state.machine_counts.get(machine, version) {
+        return Some(counts);
+    }
+    let definition = state.rstmdb.get_machine(machine, version).await.ok()?;
+    let (states, transitions) = get_definition_counts(&definition);
+    let counts = DefinitionCounts {
+        states,
+        transitions,
+    };
+    state.machine_counts.insert(machine, version, counts);
+    Some(counts)
+}
+
+/// Fill the cache for every machine's latest version
+async fn refresh(state: &Arc<AppState>, min_machines: usize) {
+    let latest = match with_priority(Priority::Bulk, latest_versions(state)).await {
+        Ok(latest) => latest,
+        Err(e) => {
+            tracing::debug!(error = %e.message, "Machine counts refresh failed");
+            return;
+        }
+    };
+    if latest.len() < min_machines {
+        return;
+    }
+
+    state.machine_counts.retain_latest(&latest);
+    let permits = Arc::new(Semaphore::new(REFRESH_CONCURRENCY));
+    let mut fetches = JoinSet::new();
+    for (machine, version) in latest {
+        let state = state.clone();
+        let permits = permits.clone();
+        fetches.spawn(with_priority(Priority::Bulk, async move {
+            let _permit = permits.acquire_owned().await;
+            definition_counts(&state, &machine, version).await
+        }));
+    }
+    while fetches.join_next().await.is_some() {}
+    *state.machine_counts.last_refresh.lock() = Some(chrono::Utc::now().timestamp_millis());
+}
+
+/// Refresh counts every `interval`. Runs until the process exits.
+pub async fn run_refresher(state: Arc<AppState>, interval: Duration, min_machines: usize) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+        refresh(&state, min_machines).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_keeps_latest_versions() {
+        let cache = MachineCountsCache::default();
+        let counts = DefinitionCounts {
+            states: 3,
+            transitions: 2,
+        };
+        cache.insert("orders", 1, counts);
+        cache.insert("orders", 2, counts);
+        cache.insert("refunds", 1, counts);
+        assert_eq!(cache.get("orders", 2), Some(counts));
+
+        let latest = HashMap::from([("orders".to_string(), 2), ("refunds".to_string(), 1)]);
+        cache.retain_latest(&latest);
+        assert_eq!(cache.get("orders", 1), None);
+        assert_eq!(cache.entry_count(), 2);
+
+        cache.invalidate("orders");
+        assert_eq!(cache.get("orders", 2), None);
+        assert_eq!(cache.get("refunds", 1), Some(counts));
+    }
+}
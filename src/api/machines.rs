@@ -1,15 +1,38 @@
 //! State machine API handlers
 
+use crate::api::fields::FieldsQuery;
+use crate::api::machine_counts::definition_counts;
+use crate::api::pagination::{offset_links, with_page_headers, PageLinks};
+use crate::api::path::ApiPath;
+use crate::checksum::{
+    canonical_json, canonical_pretty_json, definition_checksum, strip_builder_positions,
+};
+use crate::config::ValidationConfig;
+use crate::constants::instances::{ORPHAN_MAX_SCAN, SCAN_PAGE_SIZE, STUCK_MAX_SCAN};
+use crate::constants::machines::{
+    DEFAULT_SHORTEST_PATHS, DIFF_CONTEXT_LINES, LATEST_CACHE_CONTROL, LATEST_VERSION_ALIAS,
+    MAX_SHORTEST_PATHS, VERSIONED_CACHE_CONTROL,
+};
+use crate::diagram;
 use crate::error::{ApiError, ApiResult};
 use crate::json_ext::ValueExt;
-use crate::validation::{validate_definition, ValidationResult};
+use crate::unified_diff::unified_diff;
+use crate::validation::{
+    definition_stats, diff_definitions, to_sarif, validate_definition, validate_guard_overlap,
+    validate_reserved_names, validate_unknown_fields, DefinitionDiff, DefinitionStats, Severity,
+    TransitionGraph, ValidationError, ValidationResult, ValidationWarning, DESCRIPTION_FIELD,
+    PAYLOAD_DEFAULTS_FIELD, STATE_DESCRIPTIONS_FIELD,
+};
 use crate::AppState;
 use axum::{
-    extract::{Path, State},
+    extract::{OriginalUri, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 
 #[derive(Debug, Serialize)]
@@ -21,9 +44,33 @@ pub struct MachineListItem {
     pub transitions_count: usize,
 }
 
+impl MachineListItem {
+    /// Keys selectable with `?fields=`
+    pub const FIELDS: &'static [&'static str] = &[
+        "machine",
+        "versions",
+        "latest_version",
+        "states_count",
+        "transitions_count",
+    ];
+}
+
 #[derive(Debug, Serialize)]
 pub struct MachineListResponse {
     pub items: Vec<MachineListItem>,
+    /// Machines matching `search`, across all pages
+    pub total: u64,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListMachinesQuery {
+    /// Case-insensitive substring of the machine name
+    pub search: Option<String>,
+    /// Maximum number of results (default: all)
+    pub limit: Option<u32>,
+    /// Offset for pagination
+    pub offset: Option<u32>,
 }
 
 #[derive(Debug, Serialize)]
@@ -32,6 +79,11 @@ pub struct MachineResponse {
     pub versions: Vec<u32>,
 }
 
+impl MachineResponse {
+    /// Keys selectable with `?fields=`
+    pub const FIELDS: &'static [&'static str] = &["machine", "versions"];
+}
+
 #[derive(Debug, Serialize)]
 pub struct MachineVersionResponse {
     pub machine: String,
@@ -54,6 +106,21 @@ pub struct CreateMachineVersionResponse {
     pub version: u32,
     pub checksum: String,
     pub created: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ValidationWarning>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateMachineVersionQuery {
+    /// Report removed states that instances still occupy
+    #[serde(default)]
+    pub check_instances: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StateInUse {
+    pub state: String,
+    pub instances: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -61,8 +128,117 @@ pub struct ValidateRequest {
     pub definition: Value,
 }
 
+#[derive(Debug, Serialize)]
+pub struct InstanceCoverageResponse {
+    pub machine: String,
+    /// False when live instances occupy states the candidate does not declare
+    pub valid: bool,
+    /// One `ORPHANS_EXISTING_INSTANCE` error per undeclared state
+    pub errors: Vec<ValidationError>,
+    /// Every state occupied by a scanned instance
+    pub states_in_use: Vec<StateInUse>,
+    pub scanned: u64,
+    /// True when instances beyond the scan cap were not checked
+    pub truncated: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct NormalizeQuery {
+    /// Drop `meta._builderPositions` from the returned definition
+    #[serde(default)]
+    pub strip_builder_positions: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NormalizeResponse {
+    /// The definition with object keys sorted
+    pub definition: Value,
+    /// Exact text the checksum is computed over (never includes builder
+    /// positions)
+    pub canonical: String,
+    pub checksum: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ValidationWarning>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    pub from: u32,
+    /// Defaults to the latest version
+    pub to: Option<u32>,
+    #[serde(default)]
+    pub format: DiffFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffFormat {
+    /// States and transitions added and removed
+    #[default]
+    Json,
+    /// Unified diff of the canonical definitions, as `text/plain`
+    Text,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MachineDiffResponse {
+    pub machine: String,
+    pub from: u32,
+    pub to: u32,
+    /// Same Studio checksum; builder layout changes don't count
+    pub identical: bool,
+    #[serde(flatten)]
+    pub diff: DefinitionDiff,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ValidateQuery {
+    /// Report unknown definition fields (always on when `validation.strict` is set)
+    #[serde(default)]
+    pub strict: bool,
+    /// Search for guarded transitions that can fire for the same context
+    /// (always on when `validation.check_guard_overlap` is set)
+    #[serde(default)]
+    pub check_guard_overlap: bool,
+    /// Output format: `json` (default) or `sarif`
+    pub format: Option<String>,
+    /// Artifact URI to attach to SARIF results (e.g. the definition's path in the repo)
+    pub artifact: Option<String>,
+}
+
+/// SARIF media type
+const SARIF_CONTENT_TYPE: &str = "application/sarif+json";
+
+#[derive(Debug, Deserialize)]
+pub struct StuckQuery {
+    /// Minimum time since last update, e.g. "1h" or "30m"
+    pub older_than: String,
+    /// Only consider instances in this state
+    pub state: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StuckInstance {
+    pub id: String,
+    pub version: u32,
+    pub state: String,
+    pub updated_at: i64,
+    /// Milliseconds since the instance was last updated
+    pub stale_for_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StuckInstancesResponse {
+    pub machine: String,
+    pub items: Vec<StuckInstance>,
+    /// Number of instances examined
+    pub scanned: u64,
+    /// True when the scan stopped at the limit before covering all instances
+    pub truncated: bool,
+}
+
 /// Extract states and transitions count from a machine definition
-fn get_definition_counts(def: &Value) -> (usize, usize) {
+pub fn get_definition_counts(def: &Value) -> (usize, usize) {
     let states = def["definition"]["states"]
         .as_array()
         .map(|a| a.len())
@@ -74,46 +250,129 @@ fn get_definition_counts(def: &Value) -> (usize, usize) {
     (states, transitions)
 }
 
+/// Names and versions of the machines matching `search`, in rstmdb order
+fn search_machines(items: &[Value], search: Option<&str>) -> Vec<(String, Vec<u32>)> {
+    let needle = search.map(str::to_lowercase).filter(|s| !s.is_empty());
+    items
+        .iter()
+        .map(|item| (item.str_or_empty("machine"), item.u32_array("versions")))
+        .filter(|(machine, _)| {
+            needle
+                .as_deref()
+                .is_none_or(|n| machine.to_lowercase().contains(n))
+        })
+        .collect()
+}
+
 /// GET /api/v1/machines
+///
+/// Supports `?search=` and `offset`/`limit`. Counts come from the machine
+/// counts cache; definitions are only fetched for uncached machines on the
+/// returned page.
 pub async fn list_machines(
     State(state): State<Arc<AppState>>,
-) -> ApiResult<Json<MachineListResponse>> {
+    OriginalUri(uri): OriginalUri,
+    Query(query): Query<ListMachinesQuery>,
+    Query(fields): Query<FieldsQuery>,
+) -> ApiResult<Response> {
+    let selection = fields.selection(MachineListItem::FIELDS)?;
     let result = state.rstmdb.list_machines().await?;
+    let matches = search_machines(
+        result["items"].as_array().map(Vec::as_slice).unwrap_or(&[]),
+        query.search.as_deref(),
+    );
+
+    let total = matches.len() as u64;
+    let offset = query.offset.unwrap_or(0) as usize;
+    let limit = query.limit.map_or(matches.len(), |l| l as usize);
+    let has_more = offset.saturating_add(limit) < matches.len();
 
     let mut items: Vec<MachineListItem> = Vec::new();
+    for (machine, versions) in matches.into_iter().skip(offset).take(limit) {
+        let latest_version = versions.iter().max().copied().unwrap_or(1);
 
-    if let Some(items_arr) = result["items"].as_array() {
-        for item in items_arr {
-            let machine = item.str_or_empty("machine");
-            let versions = item.u32_array("versions");
-            let latest_version = versions.iter().max().copied().unwrap_or(1);
-
-            // Fetch definition for latest version to get states/transitions count
-            let (states_count, transitions_count) =
-                if let Ok(def) = state.rstmdb.get_machine(&machine, latest_version).await {
-                    get_definition_counts(&def)
-                } else {
-                    (0, 0)
-                };
-
-            items.push(MachineListItem {
-                machine,
-                versions,
-                latest_version,
-                states_count,
-                transitions_count,
-            });
-        }
+        // Fetch definition for latest version to get states/transitions count,
+        // unless neither was asked for
+        let wants_counts =
+            selection.includes("states_count") || selection.includes("transitions_count");
+        let counts = if wants_counts {
+            definition_counts(&state, &machine, latest_version).await
+        } else {
+            None
+        };
+        let (states_count, transitions_count) =
+            counts.map_or((0, 0), |c| (c.states, c.transitions));
+
+        items.push(MachineListItem {
+            machine,
+            versions,
+            latest_version,
+            states_count,
+            transitions_count,
+        });
     }
 
-    Ok(Json(MachineListResponse { items }))
+    let links = match query.limit {
+        Some(limit) => offset_links(total, offset as u64, limit as u64, has_more),
+        None => PageLinks {
+            total: Some(total),
+            ..Default::default()
+        },
+    };
+    let body = selection.apply_to_items(&MachineListResponse {
+        items,
+        total,
+        has_more,
+    })?;
+    Ok(with_page_headers(&uri, &links, Json(body)))
+}
+
+/// Latest version of every machine
+pub async fn latest_versions(state: &AppState) -> ApiResult<HashMap<String, u32>> {
+    let result = state.rstmdb.list_machines().await?;
+    Ok(result["items"]
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| {
+                    let latest = item.u32_array("versions").into_iter().max()?;
+                    Some((item.str_opt("machine")?, latest))
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Highest version of `machine` in a `list_machines` result; `None` for a
+/// machine that doesn't exist yet (or lists no versions)
+fn latest_listed(machines: &Value, machine: &str) -> Option<u32> {
+    machines["items"]
+        .as_array()?
+        .iter()
+        .find(|item| item["machine"].as_str() == Some(machine))
+        .and_then(|info| info.u32_array("versions").into_iter().max())
+}
+
+/// Version an implicitly versioned put of `machine` creates: one past the
+/// latest, or 1 for a new machine
+fn next_version(machines: &Value, machine: &str) -> u32 {
+    latest_listed(machines, machine).map_or(1, |latest| latest.saturating_add(1))
+}
+
+/// Highest registered version of a machine
+pub async fn latest_version(state: &AppState, machine: &str) -> ApiResult<u32> {
+    let result = state.rstmdb.list_machines().await?;
+    latest_listed(&result, machine).ok_or_else(|| ApiError::not_found("Machine"))
 }
 
 /// GET /api/v1/machines/:name
 pub async fn get_machine(
     State(state): State<Arc<AppState>>,
     Path(name): Path<String>,
-) -> ApiResult<Json<MachineResponse>> {
+    Query(fields): Query<FieldsQuery>,
+) -> ApiResult<Json<Value>> {
+    let selection = fields.selection(MachineResponse::FIELDS)?;
     let result = state.rstmdb.list_machines().await?;
 
     // Find the machine in the list
@@ -124,61 +383,651 @@ pub async fn get_machine(
     });
 
     match machine_info {
-        Some(info) => Ok(Json(MachineResponse {
+        Some(info) => Ok(Json(selection.apply(&MachineResponse {
             machine: name,
             versions: info.u32_array("versions"),
-        })),
+        })?)),
         None => Err(ApiError::not_found("Machine")),
     }
 }
 
+/// Whether an `If-None-Match` header matches the given entity tag
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
 /// GET /api/v1/machines/:name/versions/:version
+///
+/// `:version` may be `latest`. The definition's checksum is sent as the ETag
+/// (computed by Studio if rstmdb reports none); numbered versions are
+/// immutable and cacheable indefinitely, while `latest` must be revalidated.
+/// Via `latest` the ETag also carries the resolved version, since the same
+/// definition may be published again as a newer version.
 pub async fn get_machine_version(
     State(state): State<Arc<AppState>>,
-    Path((name, version)): Path<(String, u32)>,
-) -> ApiResult<Json<MachineVersionResponse>> {
+    Path((name, version)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let latest = version == LATEST_VERSION_ALIAS;
+    let (version, cache_control) = if latest {
+        (latest_version(&state, &name).await?, LATEST_CACHE_CONTROL)
+    } else {
+        let version = version.parse::<u32>().map_err(|_| {
+            ApiError::bad_request(format!(
+                "Invalid version '{}': expected a number or '{}'",
+                version, LATEST_VERSION_ALIAS
+            ))
+        })?;
+        (version, VERSIONED_CACHE_CONTROL)
+    };
+
     let result = state.rstmdb.get_machine(&name, version).await?;
+    // Same algorithm as rstmdb's, so either way the ETag can be compared
+    // with a checksum from `POST /machines/normalize`
+    let checksum = match result.str_or_empty("checksum") {
+        checksum if checksum.is_empty() => definition_checksum(&result["definition"]),
+        checksum => checksum,
+    };
+    let etag = if latest {
+        format!("\"v{}-{}\"", version, checksum)
+    } else {
+        format!("\"{}\"", checksum)
+    };
+    if etag_matches(&headers, &etag) {
+        return Ok((
+            StatusCode::NOT_MODIFIED,
+            [
+                (header::ETAG, etag),
+                (header::CACHE_CONTROL, cache_control.to_string()),
+            ],
+        )
+            .into_response());
+    }
 
-    Ok(Json(MachineVersionResponse {
+    let mut response = Json(MachineVersionResponse {
         machine: name,
         version,
         definition: result["definition"].clone(),
-        checksum: result.str_or_empty("checksum"),
+        checksum,
+    })
+    .into_response();
+    let response_headers = response.headers_mut();
+    response_headers.insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static(cache_control),
+    );
+    if let Ok(etag) = header::HeaderValue::from_str(&etag) {
+        response_headers.insert(header::ETAG, etag);
+    }
+    Ok(response)
+}
+
+/// GET /api/v1/machines/:name/versions/:version/stats
+pub async fn get_machine_version_stats(
+    State(state): State<Arc<AppState>>,
+    ApiPath((name, version)): ApiPath<(String, u32)>,
+) -> ApiResult<Json<DefinitionStats>> {
+    let result = state.rstmdb.get_machine(&name, version).await?;
+    Ok(Json(definition_stats(&result["definition"])))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReachableQuery {
+    pub from: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReachableTransition {
+    /// Index of the transition in `$.transitions`
+    pub index: usize,
+    pub from: String,
+    pub event: String,
+    pub to: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guard: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReachableResponse {
+    pub machine: String,
+    pub version: u32,
+    pub from: String,
+    /// Reachable states including `from`, in definition order
+    pub states: Vec<String>,
+    /// Transitions between reachable states, one per source state
+    pub transitions: Vec<ReachableTransition>,
+}
+
+/// GET /api/v1/machines/:name/versions/:version/reachable?from=STATE
+pub async fn get_reachable_states(
+    State(state): State<Arc<AppState>>,
+    ApiPath((name, version)): ApiPath<(String, u32)>,
+    Query(query): Query<ReachableQuery>,
+) -> ApiResult<Json<ReachableResponse>> {
+    let result = state.rstmdb.get_machine(&name, version).await?;
+    let graph = TransitionGraph::from_definition(&result["definition"]);
+    if !graph.states.contains(&query.from.as_str()) {
+        return Err(ApiError::bad_request(format!(
+            "State '{}' is not declared in {} v{}",
+            query.from, name, version
+        )));
+    }
+
+    let states = graph.reachable_from(&query.from);
+    let transitions = graph
+        .edges
+        .iter()
+        .filter(|e| states.contains(&e.from))
+        .map(|e| ReachableTransition {
+            index: e.index,
+            from: e.from.to_string(),
+            event: e.event.to_string(),
+            to: e.to.to_string(),
+            guard: e.guard.map(String::from),
+        })
+        .collect();
+
+    Ok(Json(ReachableResponse {
+        machine: name,
+        version,
+        from: query.from,
+        states: states.into_iter().map(String::from).collect(),
+        transitions,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PathQuery {
+    pub from: String,
+    pub to: String,
+    /// Return every shortest path (up to `limit`) rather than the first
+    #[serde(default)]
+    pub all: bool,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransitionStep {
+    pub event: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guard: Option<String>,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PathResult {
+    Found,
+    NoPath,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathResponse {
+    pub machine: String,
+    pub version: u32,
+    pub from: String,
+    pub to: String,
+    pub result: PathResult,
+    /// Shortest event sequences, all of the same length
+    pub paths: Vec<Vec<TransitionStep>>,
+    /// True when more shortest paths exist than were returned
+    pub truncated: bool,
+}
+
+/// GET /api/v1/machines/:name/versions/:version/path?from=A&to=B[&all=true]
+///
+/// Guards are reported, not evaluated, so a path may need a particular
+/// context to be followed.
+pub async fn find_path(
+    State(state): State<Arc<AppState>>,
+    ApiPath((name, version)): ApiPath<(String, u32)>,
+    Query(query): Query<PathQuery>,
+) -> ApiResult<Json<PathResponse>> {
+    let result = state.rstmdb.get_machine(&name, version).await?;
+    let graph = TransitionGraph::from_definition(&result["definition"]);
+    for s in [&query.from, &query.to] {
+        if !graph.states.contains(&s.as_str()) {
+            return Err(ApiError::bad_request(format!(
+                "State '{}' is not declared in {} v{}",
+                s, name, version
+            )));
+        }
+    }
+
+    let limit = if query.all {
+        query
+            .limit
+            .unwrap_or(DEFAULT_SHORTEST_PATHS)
+            .clamp(1, MAX_SHORTEST_PATHS)
+    } else {
+        1
+    };
+    // One extra path tells whether the result was cut short
+    let mut paths = graph.shortest_paths(&query.from, &query.to, limit + 1);
+    let truncated = query.all && paths.len() > limit;
+    paths.truncate(limit);
+
+    Ok(Json(PathResponse {
+        machine: name,
+        version,
+        from: query.from,
+        to: query.to,
+        result: if paths.is_empty() {
+            PathResult::NoPath
+        } else {
+            PathResult::Found
+        },
+        paths: paths
+            .into_iter()
+            .map(|path| {
+                path.into_iter()
+                    .map(|edge| TransitionStep {
+                        event: edge.event.to_string(),
+                        guard: edge.guard.map(String::from),
+                        to: edge.to.to_string(),
+                    })
+                    .collect()
+            })
+            .collect(),
+        truncated,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventOption {
+    pub event: String,
+    pub to: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guard: Option<String>,
+    /// Payload to pre-fill, from the transition's `payloadDefaults`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload_defaults: Option<Value>,
+    /// Index of the transition in `$.transitions`
+    pub transition_index: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StateEvents {
+    pub state: String,
+    pub events: Vec<EventOption>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MachineEventsResponse {
+    pub machine: String,
+    pub version: u32,
+    /// States with outgoing transitions, in definition order
+    pub states: Vec<StateEvents>,
+}
+
+/// Events accepted in each state, grouped by from-state
+fn events_by_state(definition: &Value) -> Vec<StateEvents> {
+    let graph = TransitionGraph::from_definition(definition);
+    graph
+        .states
+        .iter()
+        .map(|state| StateEvents {
+            state: state.to_string(),
+            events: graph
+                .outgoing(state)
+                .map(|edge| EventOption {
+                    event: edge.event.to_string(),
+                    to: edge.to.to_string(),
+                    guard: edge.guard.map(String::from),
+                    payload_defaults: definition["transitions"][edge.index]
+                        .get(PAYLOAD_DEFAULTS_FIELD)
+                        .cloned(),
+                    transition_index: edge.index,
+                })
+                .collect(),
+        })
+        .filter(|s| !s.events.is_empty())
+        .collect()
+}
+
+/// GET /api/v1/machines/:name/versions/:version/events
+///
+/// Lists the events each state accepts, for building apply-event forms.
+pub async fn get_machine_version_events(
+    State(state): State<Arc<AppState>>,
+    ApiPath((name, version)): ApiPath<(String, u32)>,
+) -> ApiResult<Json<MachineEventsResponse>> {
+    let result = state.rstmdb.get_machine(&name, version).await?;
+    Ok(Json(MachineEventsResponse {
+        states: events_by_state(&result["definition"]),
+        machine: name,
+        version,
+    }))
+}
+
+/// GET /api/v1/machines/:name/versions/:version/diagram.svg
+pub async fn get_machine_version_diagram(
+    State(state): State<Arc<AppState>>,
+    ApiPath((name, version)): ApiPath<(String, u32)>,
+) -> ApiResult<Response> {
+    let result = state.rstmdb.get_machine(&name, version).await?;
+    let svg = diagram::render_svg(&result["definition"]);
+    Ok(([(header::CONTENT_TYPE, diagram::CONTENT_TYPE)], svg).into_response())
+}
+
+/// GET /api/v1/machines/:name/diff?from=1&to=2
+///
+/// States and transitions added and removed between two versions, or with
+/// `?format=text` a unified diff of their canonical pretty JSON for pasting
+/// into code review. Keys are sorted and builder layout is left out, so
+/// neither key order nor moved nodes show up as changes.
+pub async fn diff_machine_versions(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<DiffQuery>,
+) -> ApiResult<Response> {
+    let to = match query.to {
+        Some(to) => to,
+        None => latest_version(&state, &name).await?,
+    };
+    let old = state.rstmdb.get_machine(&name, query.from).await?;
+    let new = state.rstmdb.get_machine(&name, to).await?;
+    let (old, new) = (&old["definition"], &new["definition"]);
+
+    match query.format {
+        DiffFormat::Json => Ok(Json(MachineDiffResponse {
+            identical: definition_checksum(old) == definition_checksum(new),
+            diff: diff_definitions(old, new),
+            machine: name,
+            from: query.from,
+            to,
+        })
+        .into_response()),
+        DiffFormat::Text => {
+            let text = unified_diff(
+                &format!("{}/v{}", name, query.from),
+                &format!("{}/v{}", name, to),
+                &canonical_pretty_json(old),
+                &canonical_pretty_json(new),
+                DIFF_CONTEXT_LINES,
+            );
+            Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], text).into_response())
+        }
+    }
+}
+
+/// GET /api/v1/machines/:name/stuck?older_than=1h&state=S
+///
+/// Instances in a non-terminal state whose `updated_at` is older than the
+/// threshold, most stale first. Terminal states (declared `final` or
+/// without outgoing transitions) are taken from each instance's own machine
+/// version.
+pub async fn list_stuck_instances(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<StuckQuery>,
+) -> ApiResult<Json<StuckInstancesResponse>> {
+    let older_than = humantime::parse_duration(&query.older_than).map_err(|e| {
+        ApiError::bad_request(format!("Invalid older_than '{}': {}", query.older_than, e))
+    })?;
+    let now = chrono::Utc::now().timestamp_millis();
+    let cutoff = now - older_than.as_millis() as i64;
+
+    let mut terminal_by_version: HashMap<u32, HashSet<String>> = HashMap::new();
+    let mut items = Vec::new();
+    let mut scanned = 0u64;
+    let mut offset = 0u32;
+    let mut truncated = false;
+
+    loop {
+        let page = state
+            .rstmdb
+            .list_instances(
+                &name,
+                query.state.as_deref(),
+                Some(SCAN_PAGE_SIZE),
+                Some(offset),
+            )
+            .await?;
+
+        for instance in page.instances {
+            scanned += 1;
+            if instance.updated_at >= cutoff {
+                continue;
+            }
+
+            if let Entry::Vacant(entry) = terminal_by_version.entry(instance.version) {
+                let def = state.rstmdb.get_machine(&name, instance.version).await?;
+                let terminal = TransitionGraph::from_definition(&def["definition"])
+                    .terminal_states()
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+                entry.insert(terminal);
+            }
+            if terminal_by_version[&instance.version].contains(&instance.state) {
+                continue;
+            }
+
+            items.push(StuckInstance {
+                id: instance.id,
+                version: instance.version,
+                state: instance.state,
+                updated_at: instance.updated_at,
+                stale_for_ms: now - instance.updated_at,
+            });
+        }
+
+        if !page.has_more {
+            break;
+        }
+        if scanned >= STUCK_MAX_SCAN {
+            truncated = true;
+            break;
+        }
+        offset += SCAN_PAGE_SIZE;
+    }
+
+    items.sort_by_key(|i| i.updated_at);
+
+    Ok(Json(StuckInstancesResponse {
+        machine: name,
+        items,
+        scanned,
+        truncated,
     }))
 }
 
-/// Compare two machine definitions, ignoring meta._builderPositions
+/// Compare two whole machine definitions, ignoring meta._builderPositions
+/// and state and transition descriptions
 fn definitions_equal(a: &Value, b: &Value) -> bool {
-    if a["states"] != b["states"] {
-        return false;
+    without_descriptions(a) == without_descriptions(b)
+}
+
+/// `definition` with builder positions and state and transition
+/// descriptions removed
+fn without_descriptions(definition: &Value) -> Value {
+    let mut definition = definition.clone();
+    strip_builder_positions(&mut definition);
+    if let Some(meta) = definition.get_mut("meta").and_then(Value::as_object_mut) {
+        meta.remove(STATE_DESCRIPTIONS_FIELD);
     }
-    if a["initial"] != b["initial"] {
-        return false;
+    let transitions = definition
+        .get_mut("transitions")
+        .and_then(Value::as_array_mut);
+    for transition in transitions.into_iter().flatten() {
+        if let Some(obj) = transition.as_object_mut() {
+            obj.remove(DESCRIPTION_FIELD);
+        }
     }
-    if a["transitions"] != b["transitions"] {
+    definition
+}
+
+/// Whether `definition` needs no new version over `base`. Description-only
+/// edits count as unchanged unless `validation.description_changes_create_version`.
+fn definition_unchanged(definition: &Value, base: &Value, options: &ValidationConfig) -> bool {
+    if !definitions_equal(definition, base) {
         return false;
     }
+    if !options.description_changes_create_version {
+        return true;
+    }
+    let descriptions = |def: &Value| -> (Vec<Value>, Value) {
+        (
+            def["transitions"]
+                .as_array()
+                .map(|ts| ts.iter().map(|t| t[DESCRIPTION_FIELD].clone()).collect())
+                .unwrap_or_default(),
+            def["meta"][STATE_DESCRIPTIONS_FIELD].clone(),
+        )
+    };
+    descriptions(definition) == descriptions(base)
+}
 
-    // Compare meta (excluding _builderPositions)
-    let mut a_meta = a["meta"].clone();
-    let mut b_meta = b["meta"].clone();
-    if let Some(obj) = a_meta.as_object_mut() {
-        obj.remove("_builderPositions");
+/// Validation applied before a definition is stored
+fn validate_for_save(definition: &Value, options: &ValidationConfig) -> ValidationResult {
+    let mut validation = validate_definition(definition);
+    validate_reserved_names(definition, &options.reserved_names, &mut validation);
+    if options.strict {
+        validate_unknown_fields(definition, options.unknown_field_severity, &mut validation);
     }
-    if let Some(obj) = b_meta.as_object_mut() {
-        obj.remove("_builderPositions");
+    if options.check_guard_overlap {
+        validate_guard_overlap(definition, &mut validation);
     }
-    a_meta == b_meta
+    validation
+}
+
+/// States declared by `previous` but not by `definition`
+fn removed_states<'a>(previous: &'a Value, definition: &Value) -> Vec<&'a str> {
+    let kept: HashSet<&str> = TransitionGraph::from_definition(definition)
+        .states
+        .into_iter()
+        .collect();
+    TransitionGraph::from_definition(previous)
+        .states
+        .into_iter()
+        .filter(|s| !kept.contains(s))
+        .collect()
+}
+
+/// Removed states that instances of `name` currently occupy, compared with
+/// `base_version` or else the latest version
+async fn states_in_use(
+    state: &AppState,
+    name: &str,
+    base_version: Option<u32>,
+    definition: &Value,
+) -> ApiResult<Vec<StateInUse>> {
+    let previous_version = match base_version {
+        Some(v) => v,
+        None => match latest_versions(state).await?.get(name) {
+            Some(v) => *v,
+            // First version: nothing to strand
+            None => return Ok(Vec::new()),
+        },
+    };
+    let previous = state.rstmdb.get_machine(name, previous_version).await?;
+
+    let mut in_use = Vec::new();
+    for removed in removed_states(&previous["definition"], definition) {
+        let instances = state.rstmdb.count_instances(name, Some(removed)).await?;
+        if instances > 0 {
+            in_use.push(StateInUse {
+                state: removed.to_string(),
+                instances,
+            });
+        }
+    }
+    Ok(in_use)
+}
+
+/// `ORPHANS_EXISTING_INSTANCE` errors for occupied states that `definition`
+/// does not declare
+fn orphaned_states(counts: &BTreeMap<String, u64>, definition: &Value) -> Vec<ValidationError> {
+    let declared: HashSet<&str> = TransitionGraph::from_definition(definition)
+        .states
+        .into_iter()
+        .collect();
+    counts
+        .iter()
+        .filter(|(state, _)| !declared.contains(state.as_str()))
+        .map(|(state, instances)| ValidationError {
+            code: "ORPHANS_EXISTING_INSTANCE".to_string(),
+            message: format!(
+                "State '{}' is occupied by {} live instance(s) but is not declared",
+                state, instances
+            ),
+            path: None,
+        })
+        .collect()
+}
+
+/// POST /api/v1/machines/:name/validate-against-instances
+///
+/// Checks a candidate definition against the states live instances of `name`
+/// occupy, across all versions. Archived instances are ignored. Structural
+/// problems are left to `POST /machines/validate`.
+pub async fn validate_against_instances(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(req): Json<ValidateRequest>,
+) -> ApiResult<Json<InstanceCoverageResponse>> {
+    let mut counts: BTreeMap<String, u64> = BTreeMap::new();
+    let mut scanned = 0u64;
+    let mut offset = 0u32;
+    let mut truncated = false;
+
+    loop {
+        let page = state
+            .rstmdb
+            .list_instances(&name, None, Some(SCAN_PAGE_SIZE), Some(offset))
+            .await?;
+        for instance in page.instances {
+            scanned += 1;
+            if !state.archive.is_archived(&instance.id) {
+                *counts.entry(instance.state).or_default() += 1;
+            }
+        }
+        if !page.has_more {
+            break;
+        }
+        if scanned >= ORPHAN_MAX_SCAN {
+            truncated = true;
+            break;
+        }
+        offset += SCAN_PAGE_SIZE;
+    }
+
+    let errors = orphaned_states(&counts, &req.definition);
+    Ok(Json(InstanceCoverageResponse {
+        machine: name,
+        valid: errors.is_empty(),
+        errors,
+        states_in_use: counts
+            .into_iter()
+            .map(|(state, instances)| StateInUse { state, instances })
+            .collect(),
+        scanned,
+        truncated,
+    }))
 }
 
 /// POST /api/v1/machines/:name/versions
+///
+/// With `?check_instances=true`, states removed relative to the base (or
+/// latest) version that instances still occupy are reported as `STATE_IN_USE`
+/// warnings, or reject the version under `validation.state_in_use_severity:
+/// error`.
 pub async fn create_machine_version(
     State(state): State<Arc<AppState>>,
     Path(name): Path<String>,
+    Query(query): Query<CreateMachineVersionQuery>,
     Json(req): Json<CreateMachineVersionRequest>,
 ) -> ApiResult<Json<CreateMachineVersionResponse>> {
     // Validate definition first
-    let validation = validate_definition(&req.definition);
+    let options = state.live.read().validation.clone();
+    let validation = validate_for_save(&req.definition, &options);
     if !validation.valid {
         return Err(
             ApiError::validation_error("Invalid state machine definition")
@@ -191,7 +1040,7 @@ pub async fn create_machine_version(
         let base_data = state.rstmdb.get_machine(&name, base_ver).await?;
         let base_def = &base_data["definition"];
 
-        if definitions_equal(&req.definition, base_def) {
+        if definition_unchanged(&req.definition, base_def, &options) {
             tracing::info!(
                 machine = %name,
                 version = base_ver,
@@ -202,10 +1051,37 @@ pub async fn create_machine_version(
                 version: base_ver,
                 checksum: base_data.str_or_empty("checksum"),
                 created: false,
+                warnings: Vec::new(),
             }));
         }
     }
 
+    let mut warnings = Vec::new();
+    if query.check_instances {
+        let in_use = states_in_use(&state, &name, req.base_version, &req.definition).await?;
+        if !in_use.is_empty() {
+            let summary = in_use
+                .iter()
+                .map(|s| format!("'{}' ({} instances)", s.state, s.instances))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let message = format!(
+                "Removed states are still occupied by instances: {}",
+                summary
+            );
+            if options.state_in_use_severity == Severity::Error {
+                return Err(
+                    ApiError::state_in_use(message).with_details(json!({ "states": in_use }))
+                );
+            }
+            warnings.push(ValidationWarning {
+                code: "STATE_IN_USE".to_string(),
+                message,
+                path: None,
+            });
+        }
+    }
+
     // Determine version for new definition
     let version = if let Some(v) = req.version {
         v
@@ -213,19 +1089,8 @@ pub async fn create_machine_version(
         // Use base_version + 1
         base_ver + 1
     } else {
-        // Get from list
-        let machines = state.rstmdb.list_machines().await?;
-        let latest = machines["items"]
-            .as_array()
-            .and_then(|items| {
-                items
-                    .iter()
-                    .find(|item| item["machine"].as_str() == Some(&name))
-            })
-            .map(|info| info.u32_array("versions"))
-            .and_then(|versions| versions.into_iter().max())
-            .unwrap_or(0);
-        latest + 1
+        // A machine that doesn't exist yet is created at version 1
+        next_version(&state.rstmdb.list_machines().await?, &name)
     };
 
     // Create the machine version
@@ -233,6 +1098,7 @@ pub async fn create_machine_version(
         .rstmdb
         .put_machine(&name, version, req.definition)
         .await?;
+    state.machine_counts.invalidate(&name);
 
     tracing::info!(
         machine = %name,
@@ -246,20 +1112,410 @@ pub async fn create_machine_version(
         version: result.version,
         checksum: result.checksum,
         created: result.created,
+        warnings,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublishMachine {
+    pub name: String,
+    pub definition: Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublishValidation {
+    pub machine: String,
+    #[serde(flatten)]
+    pub result: ValidationResult,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublishResponse {
+    /// One entry per machine; `created: false` when the definition matched
+    /// the latest version
+    pub published: Vec<CreateMachineVersionResponse>,
+}
+
+/// Validate every machine of a publish, failing if any is invalid
+fn validate_publish(machines: &[PublishMachine], options: &ValidationConfig) -> ApiResult<()> {
+    if machines.is_empty() {
+        return Err(ApiError::bad_request("No machines to publish"));
+    }
+    let mut names = HashSet::new();
+    if let Some(dup) = machines.iter().find(|m| !names.insert(m.name.as_str())) {
+        return Err(ApiError::bad_request(format!(
+            "Machine '{}' appears more than once",
+            dup.name
+        )));
+    }
+
+    let results: Vec<PublishValidation> = machines
+        .iter()
+        .map(|m| PublishValidation {
+            machine: m.name.clone(),
+            result: validate_for_save(&m.definition, options),
+        })
+        .collect();
+    let invalid = results.iter().filter(|r| !r.result.valid).count();
+    if invalid > 0 {
+        return Err(ApiError::validation_error(format!(
+            "{} of {} machine definitions are invalid; nothing was published",
+            invalid,
+            results.len()
+        ))
+        .with_details(json!({ "results": results })));
+    }
+    Ok(())
+}
+
+/// POST /api/v1/machines/publish
+///
+/// Validates all definitions before storing any, so a GitOps deploy never
+/// leaves some machines updated and others not because of a bad definition.
+/// rstmdb has no transactions: if storing fails part-way, the error details
+/// list the versions already published.
+pub async fn publish_machines(
+    State(state): State<Arc<AppState>>,
+    Json(machines): Json<Vec<PublishMachine>>,
+) -> ApiResult<Json<PublishResponse>> {
+    let options = state.live.read().validation.clone();
+    validate_publish(&machines, &options)?;
+
+    let latest = latest_versions(&state).await?;
+    let mut published = Vec::with_capacity(machines.len());
+    for machine in machines {
+        let current = latest.get(&machine.name).copied();
+        let outcome = async {
+            if let Some(version) = current {
+                let base = state.rstmdb.get_machine(&machine.name, version).await?;
+                if definition_unchanged(&machine.definition, &base["definition"], &options) {
+                    return Ok(CreateMachineVersionResponse {
+                        machine: machine.name.clone(),
+                        version,
+                        checksum: base.str_or_empty("checksum"),
+                        created: false,
+                        warnings: Vec::new(),
+                    });
+                }
+            }
+            let version = current.unwrap_or(0) + 1;
+            let result = state
+                .rstmdb
+                .put_machine(&machine.name, version, machine.definition)
+                .await?;
+            state.machine_counts.invalidate(&result.machine);
+            Ok::<_, ApiError>(CreateMachineVersionResponse {
+                machine: result.machine,
+                version: result.version,
+                checksum: result.checksum,
+                created: result.created,
+                warnings: Vec::new(),
+            })
+        }
+        .await;
+        match outcome {
+            Ok(response) => published.push(response),
+            Err(e) => return Err(e.with_details(json!({ "published": published }))),
+        }
+    }
+
+    tracing::info!(
+        machines = published.len(),
+        created = published.iter().filter(|p| p.created).count(),
+        "Machines published"
+    );
+    Ok(Json(PublishResponse { published }))
+}
+
+/// POST /api/v1/machines/normalize
+///
+/// Validates a definition as a save would and returns its canonical form and
+/// Studio checksum, so tooling can diff and compare definitions
+/// deterministically. Invalid definitions fail with `VALIDATION_ERROR`.
+pub async fn normalize_machine(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<NormalizeQuery>,
+    Json(req): Json<ValidateRequest>,
+) -> ApiResult<Json<NormalizeResponse>> {
+    let options = state.live.read().validation.clone();
+    let validation = validate_for_save(&req.definition, &options);
+    if !validation.valid {
+        return Err(
+            ApiError::validation_error("Invalid state machine definition")
+                .with_details(serde_json::to_value(&validation).unwrap()),
+        );
+    }
+
+    let mut definition = req.definition;
+    if query.strip_builder_positions {
+        strip_builder_positions(&mut definition);
+    }
+    Ok(Json(NormalizeResponse {
+        canonical: canonical_json(&definition),
+        checksum: definition_checksum(&definition),
+        // serde_json maps keep keys sorted
+        definition,
+        warnings: validation.warnings,
     }))
 }
 
 /// POST /api/v1/machines/validate
 pub async fn validate_machine(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ValidateQuery>,
     Json(req): Json<ValidateRequest>,
-) -> ApiResult<Json<ValidationResult>> {
-    let result = validate_definition(&req.definition);
-    Ok(Json(result))
+) -> ApiResult<Response> {
+    let options = state.live.read().validation.clone();
+    let mut result = validate_definition(&req.definition);
+    validate_reserved_names(&req.definition, &options.reserved_names, &mut result);
+    if query.strict || options.strict {
+        validate_unknown_fields(&req.definition, options.unknown_field_severity, &mut result);
+    }
+    if query.check_guard_overlap || options.check_guard_overlap {
+        validate_guard_overlap(&req.definition, &mut result);
+    }
+
+    match query.format.as_deref() {
+        None | Some("json") => Ok(Json(result).into_response()),
+        Some("sarif") => {
+            let sarif = to_sarif(&result, query.artifact.as_deref());
+            Ok(([(header::CONTENT_TYPE, SARIF_CONTENT_TYPE)], Json(sarif)).into_response())
+        }
+        Some(other) => Err(ApiError::bad_request(format!(
+            "Unsupported format '{}', expected 'json' or 'sarif'",
+            other
+        ))),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Config;
+    use crate::rstmdb::FakeRstmdb;
+
+    #[tokio::test]
+    async fn test_create_machine_version_starts_a_new_machine_at_1() {
+        let fake = FakeRstmdb::new().with_machine(
+            "other",
+            4,
+            json!({ "states": ["a"], "initial": "a", "transitions": [] }),
+        );
+        let state = AppState::for_test(Config::default(), fake.clone());
+        let definition = json!({
+            "states": ["new", "done"],
+            "initial": "new",
+            "transitions": [{ "from": "new", "event": "FINISH", "to": "done" }]
+        });
+        let create = |name: &str| {
+            create_machine_version(
+                State(state.clone()),
+                Path(name.to_string()),
+                Query(CreateMachineVersionQuery {
+                    check_instances: false,
+                }),
+                Json(CreateMachineVersionRequest {
+                    version: None,
+                    definition: definition.clone(),
+                    base_version: None,
+                }),
+            )
+        };
+
+        let Json(created) = create("orders").await.unwrap();
+        assert_eq!(created.machine, "orders");
+        assert_eq!(created.version, 1);
+        assert!(created.created);
+        assert_eq!(fake.machine("orders", 1), Some(definition.clone()));
+
+        let Json(next) = create("other").await.unwrap();
+        assert_eq!((next.version, next.created), (5, true));
+    }
+
+    #[tokio::test]
+    async fn test_latest_etag_changes_when_a_version_republishes_a_definition() {
+        let definition = json!({ "states": ["a"], "initial": "a", "transitions": [] });
+        let fake = FakeRstmdb::new().with_machine("orders", 1, definition.clone());
+        let state = AppState::for_test(Config::default(), fake.clone());
+        let get = |version: &str, if_none_match: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(etag) = if_none_match {
+                headers.insert(header::IF_NONE_MATCH, etag.parse().unwrap());
+            }
+            get_machine_version(
+                State(state.clone()),
+                Path(("orders".to_string(), version.to_string())),
+                headers,
+            )
+        };
+        let etag = |response: &Response| {
+            response.headers()[header::ETAG]
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+
+        let first = get("latest", None).await.unwrap();
+        let latest_v1 = etag(&first);
+        assert_eq!(
+            get("latest", Some(&latest_v1)).await.unwrap().status(),
+            StatusCode::NOT_MODIFIED
+        );
+
+        let _ = fake.clone().with_machine("orders", 2, definition);
+        let second = get("latest", Some(&latest_v1)).await.unwrap();
+        assert_eq!(second.status(), StatusCode::OK);
+        assert_ne!(etag(&second), latest_v1);
+
+        // Numbered versions keep the bare checksum
+        let numbered = get("2", None).await.unwrap();
+        let checksum = etag(&numbered);
+        assert_eq!(etag(&get("1", None).await.unwrap()), checksum);
+        assert!(latest_v1.ends_with(&checksum[1..]));
+    }
+
+    #[test]
+    fn test_removed_states() {
+        let previous = json!({ "states": ["new", "paid", "legacy", "shipped"], "initial": "new" });
+        let next = json!({ "states": ["new", "paid", "shipped", "refunded"], "initial": "new" });
+        assert_eq!(removed_states(&previous, &next), vec!["legacy"]);
+        assert!(removed_states(&next, &next).is_empty());
+    }
+
+    #[test]
+    fn test_next_version_creates_missing_machine_at_one() {
+        let machines = json!({
+            "items": [
+                { "machine": "orders", "versions": [1, 3, 2] },
+                { "machine": "empty", "versions": [] }
+            ]
+        });
+        assert_eq!(next_version(&machines, "orders"), 4);
+        assert_eq!(next_version(&machines, "refunds"), 1);
+        assert_eq!(next_version(&machines, "empty"), 1);
+        assert_eq!(next_version(&json!({ "items": [] }), "refunds"), 1);
+        assert_eq!(next_version(&json!({}), "refunds"), 1);
+        assert_eq!(latest_listed(&machines, "refunds"), None);
+    }
+
+    #[test]
+    fn test_orphaned_states() {
+        let counts: BTreeMap<String, u64> = [("new", 3), ("legacy", 2), ("paid", 1)]
+            .into_iter()
+            .map(|(s, n)| (s.to_string(), n))
+            .collect();
+        let candidate = json!({ "states": ["new", "paid", "shipped"], "initial": "new" });
+        let errors = orphaned_states(&counts, &candidate);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "ORPHANS_EXISTING_INSTANCE");
+        assert!(errors[0].message.contains("'legacy'"));
+        assert!(errors[0].message.contains("2 live"));
+
+        let covering = json!({ "states": ["new", "paid", "legacy"], "initial": "new" });
+        assert!(orphaned_states(&counts, &covering).is_empty());
+    }
+
+    #[test]
+    fn test_events_by_state() {
+        let definition = json!({
+            "states": ["new", "paid", "shipped", "cancelled"],
+            "initial": "new",
+            "transitions": [
+                { "from": "new", "event": "PAY", "to": "paid", "guard": "ctx.amount > 0",
+                  "payloadDefaults": { "amount": 10 } },
+                { "from": ["new", "paid"], "event": "CANCEL", "to": "cancelled" },
+                { "from": "paid", "event": "SHIP", "to": "shipped" }
+            ]
+        });
+        let states = events_by_state(&definition);
+        let summary: Vec<(&str, Vec<&str>)> = states
+            .iter()
+            .map(|s| {
+                (
+                    s.state.as_str(),
+                    s.events.iter().map(|e| e.event.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("new", vec!["PAY", "CANCEL"]),
+                ("paid", vec!["CANCEL", "SHIP"])
+            ]
+        );
+        let pay = &states[0].events[0];
+        assert_eq!(pay.guard.as_deref(), Some("ctx.amount > 0"));
+        assert_eq!(pay.payload_defaults, Some(json!({ "amount": 10 })));
+        assert_eq!(states[1].events[0].transition_index, 1);
+        assert!(states[1].events[0].payload_defaults.is_none());
+    }
+
+    #[test]
+    fn test_search_machines() {
+        let items = vec![
+            json!({ "machine": "order", "versions": [1, 2] }),
+            json!({ "machine": "PurchaseOrder", "versions": [1] }),
+            json!({ "machine": "invoice", "versions": [3] }),
+        ];
+        let names = |search| -> Vec<String> {
+            search_machines(&items, search)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+        assert_eq!(names(Some("ORDER")), vec!["order", "PurchaseOrder"]);
+        assert_eq!(names(Some("voice")), vec!["invoice"]);
+        assert_eq!(names(Some("")).len(), 3);
+        assert_eq!(names(None).len(), 3);
+        assert!(names(Some("shipment")).is_empty());
+        assert_eq!(search_machines(&items, Some("inv"))[0].1, vec![3]);
+    }
+
+    fn publish(name: &str, definition: Value) -> PublishMachine {
+        PublishMachine {
+            name: name.to_string(),
+            definition,
+        }
+    }
+
+    #[test]
+    fn test_validate_publish_is_all_or_nothing() {
+        let valid = json!({
+            "states": ["a", "b"],
+            "initial": "a",
+            "transitions": [{ "from": "a", "event": "GO", "to": "b" }]
+        });
+        let options = ValidationConfig::default();
+        assert!(validate_publish(&[publish("order", valid.clone())], &options).is_ok());
+
+        let machines = [
+            publish("order", valid.clone()),
+            publish("invoice", json!({ "states": [], "initial": "x" })),
+            publish(
+                "refund",
+                json!({ "states": ["a"], "initial": "b", "transitions": [] }),
+            ),
+        ];
+        let err = validate_publish(&machines, &options).unwrap_err();
+        assert_eq!(err.code, "VALIDATION_ERROR");
+        assert!(err.message.starts_with("2 of 3"));
+        let results = &err.details.unwrap()["results"];
+        assert_eq!(results[0]["machine"], "order");
+        assert_eq!(results[0]["valid"], true);
+        assert_eq!(results[1]["valid"], false);
+        assert!(!results[2]["errors"].as_array().unwrap().is_empty());
+
+        let dup = [publish("order", valid.clone()), publish("order", valid)];
+        assert_eq!(
+            validate_publish(&dup, &options).unwrap_err().code,
+            "BAD_REQUEST"
+        );
+        assert_eq!(
+            validate_publish(&[], &options).unwrap_err().code,
+            "BAD_REQUEST"
+        );
+    }
     use serde_json::json;
 
     #[test]
@@ -273,6 +1529,19 @@ mod tests {
         assert!(definitions_equal(&a, &b));
     }
 
+    #[test]
+    fn test_etag_matches() {
+        let mut headers = HeaderMap::new();
+        assert!(!etag_matches(&headers, "\"abc\""));
+
+        headers.insert(header::IF_NONE_MATCH, "\"old\", W/\"abc\"".parse().unwrap());
+        assert!(etag_matches(&headers, "\"abc\""));
+        assert!(!etag_matches(&headers, "\"def\""));
+
+        headers.insert(header::IF_NONE_MATCH, "*".parse().unwrap());
+        assert!(etag_matches(&headers, "\"def\""));
+    }
+
     #[test]
     fn test_definitions_equal_different_states() {
         let a = json!({
@@ -358,6 +1627,92 @@ mod tests {
         assert!(!definitions_equal(&a, &b));
     }
 
+    #[test]
+    fn test_description_only_changes() {
+        let a = json!({
+            "states": ["pending", "done"],
+            "initial": "pending",
+            "transitions": [{"from": "pending", "event": "COMPLETE", "to": "done"}],
+            "meta": {"stateDescriptions": {"pending": "Awaiting review"}}
+        });
+        let mut b = a.clone();
+        b["transitions"][0]["description"] = json!("Reviewer approves");
+        b["meta"]["stateDescriptions"]["pending"] = json!("Awaiting approval");
+        assert!(definitions_equal(&a, &b));
+
+        let mut options = ValidationConfig::default();
+        assert!(definition_unchanged(&b, &a, &options));
+        options.description_changes_create_version = true;
+        assert!(!definition_unchanged(&b, &a, &options));
+        assert!(definition_unchanged(&b, &b.clone(), &options));
+
+        b["transitions"][0]["event"] = json!("APPROVE");
+        assert!(!definitions_equal(&a, &b));
+    }
+
+    #[test]
+    fn test_definitions_equal_compares_context_schema_and_final() {
+        let a = json!({
+            "states": ["pending", "done"],
+            "initial": "pending",
+            "final": ["done"],
+            "transitions": [{"from": "pending", "event": "COMPLETE", "to": "done"}],
+            "contextSchema": {"type": "object"}
+        });
+        let mut schema = a.clone();
+        schema["contextSchema"]["required"] = json!(["orderId"]);
+        assert!(!definitions_equal(&a, &schema));
+
+        let mut finals = a.clone();
+        finals["final"] = json!([]);
+        assert!(!definitions_equal(&a, &finals));
+
+        let options = ValidationConfig::default();
+        assert!(!definition_unchanged(&schema, &a, &options));
+        assert!(!definition_unchanged(&finals, &a, &options));
+    }
+
+    #[tokio::test]
+    async fn test_create_machine_version_saves_context_schema_and_final_changes() {
+        let base = json!({
+            "states": ["pending", "done"],
+            "initial": "pending",
+            "final": ["done"],
+            "transitions": [{ "from": "pending", "event": "COMPLETE", "to": "done" }],
+            "contextSchema": { "type": "object" }
+        });
+        let mut schema = base.clone();
+        schema["contextSchema"]["required"] = json!(["orderId"]);
+        let mut finals = base.clone();
+        finals["final"] = json!([]);
+
+        for (definition, created) in [(base.clone(), false), (schema, true), (finals, true)] {
+            let fake = FakeRstmdb::new().with_machine("orders", 1, base.clone());
+            let state = AppState::for_test(Config::default(), fake.clone());
+            let Json(response) = create_machine_version(
+                State(state),
+                Path("orders".to_string()),
+                Query(CreateMachineVersionQuery {
+                    check_instances: false,
+                }),
+                Json(CreateMachineVersionRequest {
+                    version: None,
+                    definition: definition.clone(),
+                    base_version: Some(1),
+                }),
+            )
+            .await
+            .unwrap();
+            assert_eq!(response.created, created, "{}", definition);
+            if created {
+                assert_eq!(response.version, 2);
+                assert_eq!(fake.machine("orders", 2), Some(definition));
+            } else {
+                assert_eq!(response.version, 1);
+            }
+        }
+    }
+
     #[test]
     fn test_definitions_equal_no_meta() {
         let a = json!({
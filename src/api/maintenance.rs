@@ -0,0 +1,106 @@
+//! Runtime maintenance mode
+
+use crate::api::auth::{require_admin, SessionUser};
+use crate::error::{ApiError, ApiResult};
+use crate::AppState;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+    Extension, Json,
+};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const DEFAULT_MESSAGE: &str = "rstmdb Studio is under maintenance";
+
+/// Maintenance flag toggled at runtime by admins
+#[derive(Default)]
+pub struct MaintenanceMode {
+    enabled: AtomicBool,
+    message: RwLock<Option<String>>,
+}
+
+impl MaintenanceMode {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    pub fn message(&self) -> String {
+        self.message
+            .read()
+            .clone()
+            .unwrap_or_else(|| DEFAULT_MESSAGE.to_string())
+    }
+
+    pub fn set(&self, enabled: bool, message: Option<String>) {
+        *self.message.write() = message;
+        self.enabled.store(enabled, Ordering::Release);
+    }
+
+    pub fn info(&self) -> MaintenanceInfo {
+        let enabled = self.is_enabled();
+        MaintenanceInfo {
+            enabled,
+            message: enabled.then(|| self.message()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaintenanceInfo {
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetMaintenanceRequest {
+    pub enabled: bool,
+    pub message: Option<String>,
+}
+
+/// Paths that stay available during maintenance
+fn is_exempt(path: &str) -> bool {
+    path.ends_with("/server/health")
+}
+
+/// Middleware returning 503 for non-admin requests while maintenance is enabled.
+///
+/// Must run after `require_auth` so the session user is available.
+pub async fn enforce(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> ApiResult<Response> {
+    if state.maintenance.is_enabled() && !is_exempt(req.uri().path()) {
+        let is_admin = req
+            .extensions()
+            .get::<SessionUser>()
+            .is_some_and(|u| u.is_admin());
+        if !is_admin {
+            return Err(ApiError::maintenance(state.maintenance.message()));
+        }
+    }
+    Ok(next.run(req).await)
+}
+
+/// POST /api/v1/server/maintenance
+pub async fn set_maintenance(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<SessionUser>,
+    Json(req): Json<SetMaintenanceRequest>,
+) -> ApiResult<Json<MaintenanceInfo>> {
+    require_admin(&user)?;
+
+    state.maintenance.set(req.enabled, req.message);
+    tracing::warn!(
+        username = %user.username,
+        enabled = req.enabled,
+        "Maintenance mode changed"
+    );
+
+    Ok(Json(state.maintenance.info()))
+}
@@ -1,7 +1,36 @@
 //! API route handlers
 
+pub mod archive;
 pub mod auth;
+pub mod body_limit;
+pub mod compare;
+pub mod concurrency;
+pub mod content_type;
+pub mod drafts;
+pub mod duplicates;
+pub mod envelope;
+pub mod event_dedup;
+pub mod event_stream;
+pub mod fields;
+pub mod import;
+pub mod instance_ttl;
 pub mod instances;
+pub mod latency;
+pub mod machine_counts;
 pub mod machines;
+pub mod maintenance;
+pub mod op_budget;
+pub mod package;
+pub mod pagination;
+pub mod path;
+pub mod pause;
+pub mod priority;
+pub mod search;
 pub mod server;
+pub mod timefmt;
+pub mod timeout;
+pub mod transition_usage;
+pub mod wait;
 pub mod wal;
+pub mod wal_digest;
+pub mod wal_history;
@@ -0,0 +1,26 @@
+//! rstmdb operation budget for API requests
+//!
+//! Each request may issue at most `server.max_rstmdb_ops_per_request` rstmdb
+//! operations; see [`crate::rstmdb::OpBudget`].
+
+use crate::rstmdb::{with_budget, OpBudget};
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+/// Run the request with a fresh budget of `limit` operations; 0 is unlimited
+pub async fn limit(State(limit): State<u64>, req: Request, next: Next) -> Response {
+    if limit == 0 {
+        return next.run(req).await;
+    }
+    let budget = OpBudget::new(limit);
+    let method = req.method().clone();
+    let uri = req.uri().path().to_string();
+    let response = with_budget(Some(budget.clone()), next.run(req)).await;
+    if budget.used() > limit {
+        tracing::warn!(%method, %uri, limit, "Request exceeded its rstmdb operation budget");
+    }
+    response
+}
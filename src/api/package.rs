@@ -0,0 +1,369 @@
+//! Instance audit packages
+//!
+//! Compliance requests usually ask for everything known about one instance.
+//! The package bundles its current state, the definition version it runs,
+//! its WAL history and the states it has been in into one JSON download,
+//! with `redaction.paths` applied throughout.
+//!
+//! The history is streamed page by page as the WAL is scanned, so a long
+//! history is never held in memory. Since the status line is sent before
+//! the scan, the package ends with `complete`: false when the scan failed
+//! part-way (`error` says why) or the instance's creation was not found in
+//! the WAL, and such a package must not be taken as the full history.
+
+use crate::api::archive::Tombstone;
+use crate::api::instances::{InstanceResponse, InstanceWalPages};
+use crate::constants::wal_entry_types;
+use crate::error::{ApiError, ApiResult};
+use crate::json_ext::ValueExt;
+use crate::rstmdb::{current_budget, current_priority, with_budget, with_priority};
+use crate::AppState;
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Package fields ahead of `history`
+#[derive(Debug, Serialize)]
+struct PackageHead {
+    /// Epoch milliseconds
+    exported_at: i64,
+    /// True when `redaction.paths` were applied to contexts and payloads
+    redacted: bool,
+    instance: InstanceResponse,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tombstone: Option<Tombstone>,
+    /// Definition of the version the instance runs
+    definition: Value,
+}
+
+/// Package fields after `history`, known once the scan is over
+#[derive(Debug, Serialize)]
+struct PackageTail {
+    timeline: Vec<StateVisit>,
+    /// True when `history` holds every WAL entry of the instance
+    complete: bool,
+    /// Why the scan stopped early
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ApiError>,
+}
+
+/// One raw WAL entry of the instance; `history` lists them oldest first
+#[derive(Debug, Serialize)]
+pub struct PackageEntry {
+    pub offset: u64,
+    pub entry: Value,
+}
+
+/// A stay in one state
+#[derive(Debug, Serialize, PartialEq)]
+pub struct StateVisit {
+    pub state: String,
+    /// Event that entered the state, `None` for the initial state
+    pub event: Option<String>,
+    /// Epoch milliseconds
+    pub entered_at: i64,
+    /// `None` while the instance is still in the state
+    pub left_at: Option<i64>,
+    pub duration_ms: Option<i64>,
+}
+
+/// Builds the state timeline one WAL entry at a time
+#[derive(Debug, Default)]
+struct Timeline {
+    visits: Vec<StateVisit>,
+}
+
+impl Timeline {
+    fn push(&mut self, entry: &Value) {
+        let timestamp = entry.i64_or("timestamp", 0);
+        let (state, event) = match entry["type"].as_str() {
+            Some(wal_entry_types::CREATE_INSTANCE) => (entry.str_or_empty("initial_state"), None),
+            Some(wal_entry_types::APPLY_EVENT) => (
+                entry.str_or_empty("to_state"),
+                Some(entry.str_or_empty("event")),
+            ),
+            _ => return,
+        };
+        if let Some(previous) = self.visits.last_mut() {
+            previous.left_at = Some(timestamp);
+            previous.duration_ms = Some(timestamp - previous.entered_at);
+        }
+        self.visits.push(StateVisit {
+            state,
+            event,
+            entered_at: timestamp,
+            left_at: None,
+            duration_ms: None,
+        });
+    }
+}
+
+/// States visited, in order, from an instance's WAL entries
+#[cfg(test)]
+fn visited_states(entries: &[(u64, Value)]) -> Vec<StateVisit> {
+    let mut timeline = Timeline::default();
+    for (_, entry) in entries {
+        timeline.push(entry);
+    }
+    timeline.visits
+}
+
+/// Opening of the package document, up to and including `"history":[`
+fn head_chunk(head: &PackageHead) -> String {
+    let mut chunk = serde_json::to_string(head).unwrap_or_else(|_| "{}".to_string());
+    chunk.pop();
+    chunk.push_str(",\"history\":[");
+    chunk
+}
+
+/// Close of the package document, from the `]` ending `history`
+fn tail_chunk(tail: &PackageTail) -> String {
+    let body = serde_json::to_string(tail).unwrap_or_else(|_| "{}".to_string());
+    format!("],{}", &body[1..])
+}
+
+/// Instance ids are caller-chosen, so keep the download name header-safe
+fn package_filename(id: &str) -> String {
+    let safe: String = id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("instance-{}.json", safe)
+}
+
+/// GET /api/v1/instances/:id/package
+///
+/// Failures before the history starts are ordinary error responses. Once
+/// the body is streaming, a failed WAL read (including running out of the
+/// request's rstmdb operation budget) ends the package early with
+/// `complete: false` and the error.
+pub async fn export_instance_package(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> ApiResult<Response> {
+    let result = state.rstmdb.get_instance(&id).await?;
+    let mut machine = state
+        .rstmdb
+        .get_machine(&result.machine, result.version)
+        .await?;
+    let last_wal_offset = result.last_wal_offset;
+
+    let mut instance = InstanceResponse::new(result, &state);
+    state.redactor.redact(&mut instance.ctx);
+    let head = PackageHead {
+        exported_at: chrono::Utc::now().timestamp_millis(),
+        redacted: !state.config.redaction.paths.is_empty(),
+        tombstone: state.archive.get(&id),
+        instance,
+        definition: machine["definition"].take(),
+    };
+    let disposition = format!("attachment; filename=\"{}\"", package_filename(&id));
+
+    // The scan outlives the handler, so carry the request's budget and
+    // queue priority over to it
+    let (tx, rx) = mpsc::channel::<String>(4);
+    let scan = stream_history(state, id, last_wal_offset, head, tx);
+    tokio::spawn(with_budget(
+        current_budget(),
+        with_priority(current_priority(), scan),
+    ));
+    let body = Body::from_stream(futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv()
+            .await
+            .map(|chunk| (Ok::<_, Infallible>(chunk), rx))
+    }));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Write the package to `tx`, one WAL page of history per chunk. Stops
+/// quietly if the client goes away.
+async fn stream_history(
+    state: Arc<AppState>,
+    id: String,
+    last_wal_offset: u64,
+    head: PackageHead,
+    tx: mpsc::Sender<String>,
+) {
+    if tx.send(head_chunk(&head)).await.is_err() {
+        return;
+    }
+
+    let mut pages = InstanceWalPages::new(&id, last_wal_offset);
+    let mut timeline = Timeline::default();
+    let mut created = false;
+    let mut first = true;
+    let mut error = None;
+    loop {
+        let page = match pages.next_page(&state).await {
+            Ok(Some(page)) => page,
+            Ok(None) => break,
+            Err(e) => {
+                tracing::warn!(instance_id = %id, error = %e, "Instance package history cut short");
+                error = Some(e);
+                break;
+            }
+        };
+        let mut chunk = String::new();
+        for (offset, mut entry) in page {
+            created |= entry["type"].as_str() == Some(wal_entry_types::CREATE_INSTANCE);
+            timeline.push(&entry);
+            state.redactor.redact_wal_entry(&mut entry);
+            if !first {
+                chunk.push(',');
+            }
+            first = false;
+            chunk.push_str(
+                &serde_json::to_string(&PackageEntry { offset, entry })
+                    .unwrap_or_else(|_| "null".to_string()),
+            );
+        }
+        if !chunk.is_empty() && tx.send(chunk).await.is_err() {
+            return;
+        }
+    }
+
+    let complete = error.is_none() && created && pages.reached_end();
+    tracing::info!(instance_id = %id, complete, "Instance package exported");
+    let tail = PackageTail {
+        timeline: timeline.visits,
+        complete,
+        error,
+    };
+    let _ = tx.send(tail_chunk(&tail)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_visited_states_timeline() {
+        let entries = vec![
+            (
+                1,
+                json!({"type": "create_instance", "initial_state": "pending", "timestamp": 1000}),
+            ),
+            (2, json!({"type": "put_machine", "timestamp": 1500})),
+            (
+                3,
+                json!({"type": "apply_event", "event": "PAY", "from_state": "pending", "to_state": "paid", "timestamp": 4000}),
+            ),
+            (
+                4,
+                json!({"type": "apply_event", "event": "SHIP", "from_state": "paid", "to_state": "shipped", "timestamp": 9000}),
+            ),
+        ];
+        let timeline = visited_states(&entries);
+        assert_eq!(
+            timeline,
+            vec![
+                StateVisit {
+                    state: "pending".to_string(),
+                    event: None,
+                    entered_at: 1000,
+                    left_at: Some(4000),
+                    duration_ms: Some(3000),
+                },
+                StateVisit {
+                    state: "paid".to_string(),
+                    event: Some("PAY".to_string()),
+                    entered_at: 4000,
+                    left_at: Some(9000),
+                    duration_ms: Some(5000),
+                },
+                StateVisit {
+                    state: "shipped".to_string(),
+                    event: Some("SHIP".to_string()),
+                    entered_at: 9000,
+                    left_at: None,
+                    duration_ms: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_streamed_chunks_form_one_document() {
+        let head = PackageHead {
+            exported_at: 5,
+            redacted: false,
+            instance: InstanceResponse {
+                instance_id: "i-1".to_string(),
+                machine: "order".to_string(),
+                version: 1,
+                state: "paid".to_string(),
+                ctx: json!({}),
+                last_wal_offset: 3,
+                is_archived: false,
+                paused: false,
+            },
+            tombstone: None,
+            definition: json!({"states": ["pending", "paid"]}),
+        };
+        let entries = [
+            PackageEntry {
+                offset: 1,
+                entry: json!({"type": "create_instance"}),
+            },
+            PackageEntry {
+                offset: 3,
+                entry: json!({"type": "apply_event"}),
+            },
+        ];
+        let history: Vec<String> = entries
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap())
+            .collect();
+        let tail = PackageTail {
+            timeline: Vec::new(),
+            complete: false,
+            error: Some(ApiError::resource_exhausted("budget")),
+        };
+        let document = format!(
+            "{}{}{}",
+            head_chunk(&head),
+            history.join(","),
+            tail_chunk(&tail)
+        );
+
+        let package: Value = serde_json::from_str(&document).unwrap();
+        assert_eq!(package["instance"]["instance_id"], "i-1");
+        assert_eq!(package["history"][1]["offset"], 3);
+        assert_eq!(package["complete"], false);
+        assert_eq!(package["error"]["code"], "RESOURCE_EXHAUSTED");
+        assert!(package.get("tombstone").is_none());
+
+        let empty = format!("{}{}", head_chunk(&head), tail_chunk(&tail));
+        let package: Value = serde_json::from_str(&empty).unwrap();
+        assert_eq!(package["history"], json!([]));
+    }
+
+    #[test]
+    fn test_package_filename_is_header_safe() {
+        assert_eq!(package_filename("order-1.a_b"), "instance-order-1.a_b.json");
+        assert_eq!(package_filename("a\"b/c\r\nd"), "instance-a_b_c__d.json");
+    }
+}
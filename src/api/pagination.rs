@@ -0,0 +1,143 @@
+//! Pagination response headers
+//!
+//! List endpoints keep their body fields (`total`, `has_more`, `next_offset`)
+//! and additionally send `X-Total-Count` and an RFC 5988 `Link` header so
+//! generic API clients can page without knowing each endpoint's body shape.
+
+use axum::http::{header, HeaderName, HeaderValue, Uri};
+use axum::response::{IntoResponse, Response};
+
+pub const X_TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+/// Pagination state of one response
+#[derive(Debug, Default)]
+pub struct PageLinks {
+    pub total: Option<u64>,
+    /// Query parameters that select the next page, if there is one
+    pub next: Option<Vec<(&'static str, String)>>,
+    /// Query parameters that select the previous page, if there is one
+    pub prev: Option<Vec<(&'static str, String)>>,
+}
+
+/// The request URI with the given query parameters replaced or added.
+///
+/// Parameters given an empty value are removed; others are kept as sent.
+pub fn page_url(uri: &Uri, params: &[(&str, String)]) -> String {
+    let mut query: Vec<String> = uri
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or("");
+            !params.iter().any(|(name, _)| *name == key)
+        })
+        .map(String::from)
+        .collect();
+    query.extend(
+        params
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(name, value)| format!("{}={}", name, value)),
+    );
+
+    if query.is_empty() {
+        uri.path().to_string()
+    } else {
+        format!("{}?{}", uri.path(), query.join("&"))
+    }
+}
+
+/// `Link` header value for the given pages, or `None` when there are none
+pub fn link_header(uri: &Uri, links: &PageLinks) -> Option<String> {
+    let parts: Vec<String> = [("next", &links.next), ("prev", &links.prev)]
+        .into_iter()
+        .filter_map(|(rel, params)| {
+            params
+                .as_ref()
+                .map(|p| format!("<{}>; rel=\"{}\"", page_url(uri, p), rel))
+        })
+        .collect();
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+/// Add `X-Total-Count` and `Link` headers to a list response
+pub fn with_page_headers(uri: &Uri, links: &PageLinks, body: impl IntoResponse) -> Response {
+    let mut response = body.into_response();
+    let headers = response.headers_mut();
+    if let Some(total) = links.total {
+        headers.insert(X_TOTAL_COUNT, HeaderValue::from(total));
+    }
+    if let Some(value) = link_header(uri, links).and_then(|l| HeaderValue::from_str(&l).ok()) {
+        headers.insert(header::LINK, value);
+    }
+    response
+}
+
+/// Links for offset/limit paging
+pub fn offset_links(total: u64, offset: u64, limit: u64, has_more: bool) -> PageLinks {
+    PageLinks {
+        total: Some(total),
+        next: has_more.then(|| {
+            vec![
+                ("offset", (offset + limit).to_string()),
+                ("limit", limit.to_string()),
+            ]
+        }),
+        prev: (offset > 0).then(|| {
+            vec![
+                ("offset", offset.saturating_sub(limit).to_string()),
+                ("limit", limit.to_string()),
+            ]
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_url_replaces_params() {
+        let uri: Uri = "/api/v1/instances?machine=order&offset=100&limit=50&state=paid"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            page_url(&uri, &[("offset", "150".to_string())]),
+            "/api/v1/instances?machine=order&limit=50&state=paid&offset=150"
+        );
+
+        let timed: Uri = "/api/v1/wal?from_time=2024-01-01T00:00:00Z"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            page_url(
+                &timed,
+                &[("from", "7".to_string()), ("from_time", String::new())]
+            ),
+            "/api/v1/wal?from=7"
+        );
+
+        let bare: Uri = "/api/v1/wal".parse().unwrap();
+        assert_eq!(
+            page_url(&bare, &[("from", "7".to_string())]),
+            "/api/v1/wal?from=7"
+        );
+    }
+
+    #[test]
+    fn test_offset_links() {
+        let uri: Uri = "/api/v1/instances?machine=order&offset=100&limit=50"
+            .parse()
+            .unwrap();
+        let links = offset_links(500, 100, 50, true);
+        assert_eq!(
+            link_header(&uri, &links).unwrap(),
+            "</api/v1/instances?machine=order&offset=150&limit=50>; rel=\"next\", \
+             </api/v1/instances?machine=order&offset=50&limit=50>; rel=\"prev\""
+        );
+
+        let first_and_last = offset_links(10, 0, 50, false);
+        assert_eq!(link_header(&uri, &first_and_last), None);
+    }
+}
@@ -0,0 +1,76 @@
+//! Path parameter extraction
+//!
+//! axum's `Path` rejects a segment that doesn't parse (e.g. `/versions/abc`)
+//! with a plain-text `400`. `ApiPath` extracts the same way but answers with
+//! a JSON `BAD_REQUEST` naming the bad segment.
+
+use crate::error::ApiError;
+use axum::async_trait;
+use axum::extract::path::ErrorKind;
+use axum::extract::rejection::PathRejection;
+use axum::extract::{FromRequestParts, Path, RawPathParams};
+use axum::http::request::Parts;
+use serde::de::DeserializeOwned;
+
+#[derive(Debug)]
+pub struct ApiPath<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiPath<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Path::<T>::from_request_parts(parts, state).await {
+            Ok(Path(value)) => Ok(ApiPath(value)),
+            Err(rejection) => {
+                // Tuple extractors only report the position of the bad segment
+                let names: Vec<String> = RawPathParams::from_request_parts(parts, state)
+                    .await
+                    .map(|params| params.iter().map(|(key, _)| key.to_string()).collect())
+                    .unwrap_or_default();
+                Err(path_error(rejection, &names))
+            }
+        }
+    }
+}
+
+/// JSON error for a path rejection; `names` are the route's parameter names
+fn path_error(rejection: PathRejection, names: &[String]) -> ApiError {
+    let PathRejection::FailedToDeserializePathParams(err) = &rejection else {
+        return ApiError::internal(rejection.body_text());
+    };
+    let (key, value, expected_type) = match err.kind() {
+        ErrorKind::ParseErrorAtKey {
+            key,
+            value,
+            expected_type,
+        } => (Some(key.as_str()), value, expected_type),
+        ErrorKind::ParseErrorAtIndex {
+            index,
+            value,
+            expected_type,
+        } => (names.get(*index).map(String::as_str), value, expected_type),
+        ErrorKind::ParseError {
+            value,
+            expected_type,
+        } => (names.first().map(String::as_str), value, expected_type),
+        ErrorKind::InvalidUtf8InPathParam { key } => {
+            return ApiError::bad_request(format!("Path segment '{}' is not valid UTF-8", key));
+        }
+        _ => return ApiError::internal(err.body_text()),
+    };
+    match key {
+        Some(key) => ApiError::bad_request(format!(
+            "Invalid path segment '{}': '{}' is not a valid {}",
+            key, value, expected_type
+        )),
+        None => ApiError::bad_request(format!(
+            "Invalid path segment: '{}' is not a valid {}",
+            value, expected_type
+        )),
+    }
+}
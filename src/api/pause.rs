@@ -0,0 +1,215 @@
+//! Paused instances
+//!
+//! rstmdb has no notion of a frozen instance, so Studio records paused
+//! instance ids in `<data_dir>/paused.json` and refuses events for them in
+//! the apply-event handler. Operators pause an instance to stop it moving
+//! while they investigate; writers going to rstmdb directly are not blocked.
+
+use crate::api::auth::{require_admin, SessionUser};
+use crate::error::{ApiError, ApiResult};
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use chrono::Utc;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PauseRecord {
+    pub paused_by: String,
+    /// Epoch milliseconds
+    pub paused_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PauseData {
+    instances: HashMap<String, PauseRecord>,
+}
+
+/// Paused instances backed by a JSON file
+pub struct PauseStore {
+    path: PathBuf,
+    data: RwLock<PauseData>,
+}
+
+impl PauseStore {
+    pub fn new(path: &PathBuf) -> Self {
+        let data = if path.exists() {
+            let content = std::fs::read_to_string(path).unwrap_or_default();
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            PauseData::default()
+        };
+
+        Self {
+            path: path.clone(),
+            data: RwLock::new(data),
+        }
+    }
+
+    pub fn is_paused(&self, id: &str) -> bool {
+        self.data.read().instances.contains_key(id)
+    }
+
+    pub fn get(&self, id: &str) -> Option<PauseRecord> {
+        self.data.read().instances.get(id).cloned()
+    }
+
+    /// Record a pause; returns false if the instance was already paused
+    pub fn pause(&self, id: &str, record: PauseRecord) -> anyhow::Result<bool> {
+        {
+            let mut data = self.data.write();
+            if data.instances.contains_key(id) {
+                return Ok(false);
+            }
+            data.instances.insert(id.to_string(), record);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Remove a pause; returns false if the instance was not paused
+    pub fn resume(&self, id: &str) -> anyhow::Result<bool> {
+        if self.data.write().instances.remove(id).is_none() {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let data = self.data.read();
+        let content = serde_json::to_string_pretty(&*data)?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(&self.path, content)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PauseRequest {
+    /// Shown to other operators, e.g. an incident reference
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PauseResponse {
+    pub instance_id: String,
+    pub paused: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pause: Option<PauseRecord>,
+}
+
+/// POST /api/v1/instances/:id/pause
+pub async fn pause_instance(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<SessionUser>,
+    Path(id): Path<String>,
+    body: Option<Json<PauseRequest>>,
+) -> ApiResult<Json<PauseResponse>> {
+    require_admin(&user)?;
+    let Json(req) = body.unwrap_or_default();
+
+    // Only existing instances can be paused
+    state.rstmdb.get_instance(&id).await?;
+    let record = PauseRecord {
+        paused_by: user.username.clone(),
+        paused_at: Utc::now().timestamp_millis(),
+        reason: req.reason.filter(|r| !r.trim().is_empty()),
+    };
+    let paused = state
+        .paused
+        .pause(&id, record)
+        .map_err(|e| ApiError::internal(format!("Failed to save paused instances: {}", e)))?;
+    if !paused {
+        return Err(ApiError::conflict(format!(
+            "Instance '{}' is already paused",
+            id
+        )));
+    }
+    tracing::info!(instance_id = %id, username = %user.username, "Instance paused");
+
+    Ok(Json(PauseResponse {
+        pause: state.paused.get(&id),
+        instance_id: id,
+        paused: true,
+    }))
+}
+
+/// POST /api/v1/instances/:id/resume
+pub async fn resume_instance(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<SessionUser>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<PauseResponse>> {
+    require_admin(&user)?;
+
+    let resumed = state
+        .paused
+        .resume(&id)
+        .map_err(|e| ApiError::internal(format!("Failed to save paused instances: {}", e)))?;
+    if !resumed {
+        return Err(ApiError::conflict(format!(
+            "Instance '{}' is not paused",
+            id
+        )));
+    }
+    tracing::info!(instance_id = %id, username = %user.username, "Instance resumed");
+
+    Ok(Json(PauseResponse {
+        instance_id: id,
+        paused: false,
+        pause: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_pause_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("rstmdb-studio-test-{}", uuid::Uuid::new_v4()))
+            .join("paused.json")
+    }
+
+    fn record(reason: Option<&str>) -> PauseRecord {
+        PauseRecord {
+            paused_by: "admin".to_string(),
+            paused_at: 0,
+            reason: reason.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_pause_and_resume_persist() {
+        let path = temp_pause_path();
+        let store = PauseStore::new(&path);
+        assert!(store.pause("i-1", record(Some("INC-42"))).unwrap());
+        assert!(!store.pause("i-1", record(None)).unwrap());
+
+        let reloaded = PauseStore::new(&path);
+        assert!(reloaded.is_paused("i-1"));
+        assert_eq!(
+            reloaded.get("i-1").unwrap().reason.as_deref(),
+            Some("INC-42")
+        );
+        assert!(!reloaded.is_paused("i-2"));
+
+        assert!(reloaded.resume("i-1").unwrap());
+        assert!(!reloaded.resume("i-1").unwrap());
+        assert!(!PauseStore::new(&path).is_paused("i-1"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,12 @@
+//! rstmdb queue priority for long-running routes
+//!
+//! With `rstmdb.request_queue` enabled, operations issued while handling
+//! these routes queue as bulk work behind interactive requests.
+
+use crate::rstmdb::{with_priority, Priority};
+use axum::{extract::Request, middleware::Next, response::Response};
+
+/// Run the rest of the request at bulk priority
+pub async fn bulk(req: Request, next: Next) -> Response {
+    with_priority(Priority::Bulk, next.run(req)).await
+}
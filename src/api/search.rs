@@ -0,0 +1,298 @@
+//! Global search
+//!
+//! One query over machine names, instance ids and instance contexts for the
+//! UI's search box. rstmdb has no search, so instances are listed machine by
+//! machine up to `search.max_instance_scan`, and the contexts of at most
+//! `search.max_context_scan` of them are fetched and matched. Results are
+//! ranked in memory and paged with an opaque cursor.
+
+use crate::api::instances::fetch_instances;
+use crate::constants::instances::SCAN_PAGE_SIZE;
+use crate::constants::search::{DEFAULT_LIMIT, MAX_LIMIT};
+use crate::error::{ApiError, ApiResult};
+use crate::json_ext::ValueExt;
+use crate::AppState;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<usize>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchHitType {
+    Machine,
+    Instance,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    #[serde(rename = "type")]
+    pub hit_type: SearchHitType,
+    /// Machine name or instance id
+    pub id: String,
+    pub machine: String,
+    /// Current state, for instances
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    /// `name`, `id`, or the context path that matched, e.g. `ctx.customer.email`
+    pub matched: String,
+    /// Higher is more relevant
+    pub score: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    pub items: Vec<SearchHit>,
+    /// Matches found in total, across pages
+    pub total: usize,
+    pub next_cursor: Option<String>,
+    pub instances_scanned: u64,
+    /// True when instances or contexts beyond the scan caps were not searched
+    pub truncated: bool,
+}
+
+/// Relevance of `text` for the lowercased `needle`: exact, prefix or substring
+fn match_score(text: &str, needle: &str) -> Option<u32> {
+    let text = text.to_lowercase();
+    if text == needle {
+        Some(3)
+    } else if text.starts_with(needle) {
+        Some(2)
+    } else if text.contains(needle) {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// Path of the first scalar in `ctx` containing the lowercased `needle`
+fn context_match(ctx: &Value, needle: &str, path: &mut String) -> bool {
+    match ctx {
+        Value::Object(obj) => obj.iter().any(|(key, value)| {
+            let len = path.len();
+            path.push('.');
+            path.push_str(key);
+            let found = context_match(value, needle, path);
+            if !found {
+                path.truncate(len);
+            }
+            found
+        }),
+        Value::Array(items) => items.iter().enumerate().any(|(i, value)| {
+            let len = path.len();
+            path.push_str(&format!("[{}]", i));
+            let found = context_match(value, needle, path);
+            if !found {
+                path.truncate(len);
+            }
+            found
+        }),
+        Value::String(s) => s.to_lowercase().contains(needle),
+        Value::Number(n) => n.to_string().contains(needle),
+        Value::Bool(b) => b.to_string() == needle,
+        Value::Null => false,
+    }
+}
+
+/// Best matches first; machines before instances on ties
+fn rank(hits: &mut [SearchHit]) {
+    hits.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(a.hit_type.cmp(&b.hit_type))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+}
+
+fn parse_cursor(cursor: Option<&str>) -> ApiResult<usize> {
+    match cursor {
+        None => Ok(0),
+        Some(c) => c
+            .parse()
+            .map_err(|_| ApiError::bad_request(format!("Invalid search cursor '{}'", c))),
+    }
+}
+
+/// GET /api/v1/search?q=...
+///
+/// Context matches rank below name and id matches and respect
+/// `redaction.paths`: redacted values are never searched.
+pub async fn search(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SearchQuery>,
+) -> ApiResult<Json<SearchResponse>> {
+    let needle = query.q.trim().to_lowercase();
+    if needle.is_empty() {
+        return Err(ApiError::bad_request("Search query 'q' must not be empty"));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = parse_cursor(query.cursor.as_deref())?;
+    let config = &state.config.search;
+
+    let machines = state.rstmdb.list_machines().await?;
+    let names: Vec<String> = machines["items"]
+        .as_array()
+        .map(|items| items.iter().map(|m| m.str_or_empty("machine")).collect())
+        .unwrap_or_default();
+
+    let mut hits: Vec<SearchHit> = names
+        .iter()
+        .filter_map(|name| {
+            Some(SearchHit {
+                hit_type: SearchHitType::Machine,
+                id: name.clone(),
+                machine: name.clone(),
+                state: None,
+                matched: "name".to_string(),
+                score: match_score(name, &needle)?,
+            })
+        })
+        .collect();
+
+    // Instance ids, machine by machine, up to the scan cap
+    let mut scanned = 0u64;
+    let mut truncated = false;
+    let mut context_candidates = Vec::new();
+    'machines: for machine in &names {
+        let mut offset = 0u32;
+        loop {
+            if scanned >= config.max_instance_scan {
+                truncated = true;
+                break 'machines;
+            }
+            let page = state
+                .rstmdb
+                .list_instances(machine, None, Some(SCAN_PAGE_SIZE), Some(offset))
+                .await?;
+            for instance in page.instances {
+                if scanned >= config.max_instance_scan {
+                    truncated = true;
+                    break 'machines;
+                }
+                scanned += 1;
+                match match_score(&instance.id, &needle) {
+                    Some(score) => hits.push(SearchHit {
+                        hit_type: SearchHitType::Instance,
+                        id: instance.id,
+                        machine: instance.machine,
+                        state: Some(instance.state),
+                        matched: "id".to_string(),
+                        score,
+                    }),
+                    None => context_candidates.push(instance.id),
+                }
+            }
+            if !page.has_more {
+                break;
+            }
+            offset += SCAN_PAGE_SIZE;
+        }
+    }
+
+    // Contexts of instances whose id didn't match
+    if context_candidates.len() > config.max_context_scan {
+        context_candidates.truncate(config.max_context_scan);
+        truncated = true;
+    }
+    for (_, result) in fetch_instances(&state, context_candidates).await? {
+        // An instance deleted since it was listed is simply not a match
+        let Ok(mut instance) = result else {
+            continue;
+        };
+        state.redactor.redact(&mut instance.ctx);
+        let mut path = "ctx".to_string();
+        if context_match(&instance.ctx, &needle, &mut path) {
+            hits.push(SearchHit {
+                hit_type: SearchHitType::Instance,
+                id: instance.instance_id,
+                machine: instance.machine,
+                state: Some(instance.state),
+                matched: path,
+                score: 0,
+            });
+        }
+    }
+
+    rank(&mut hits);
+    let total = hits.len();
+    let items: Vec<SearchHit> = hits.into_iter().skip(offset).take(limit).collect();
+    let next = offset + items.len();
+    Ok(Json(SearchResponse {
+        next_cursor: (next < total).then(|| next.to_string()),
+        items,
+        total,
+        instances_scanned: scanned,
+        truncated,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_match_score() {
+        assert_eq!(match_score("Orders", "orders"), Some(3));
+        assert_eq!(match_score("order-123", "order"), Some(2));
+        assert_eq!(match_score("big-order", "order"), Some(1));
+        assert_eq!(match_score("payments", "order"), None);
+    }
+
+    #[test]
+    fn test_context_match_reports_path() {
+        let ctx = json!({
+            "amount": 120,
+            "customer": { "email": "Ada@example.com", "tags": ["vip", "eu"] }
+        });
+        let find = |needle: &str| {
+            let mut path = "ctx".to_string();
+            context_match(&ctx, needle, &mut path).then_some(path)
+        };
+        assert_eq!(find("ada@").as_deref(), Some("ctx.customer.email"));
+        assert_eq!(find("eu").as_deref(), Some("ctx.customer.tags[1]"));
+        assert_eq!(find("12").as_deref(), Some("ctx.amount"));
+        assert_eq!(find("customer"), None);
+    }
+
+    #[test]
+    fn test_rank_orders_by_relevance() {
+        let hit = |hit_type, id: &str, score| SearchHit {
+            hit_type,
+            id: id.to_string(),
+            machine: "m".to_string(),
+            state: None,
+            matched: "id".to_string(),
+            score,
+        };
+        let mut hits = vec![
+            hit(SearchHitType::Instance, "b", 0),
+            hit(SearchHitType::Instance, "order", 3),
+            hit(SearchHitType::Machine, "order", 3),
+            hit(SearchHitType::Instance, "a", 1),
+        ];
+        rank(&mut hits);
+        let order: Vec<_> = hits.iter().map(|h| (h.hit_type, h.id.as_str())).collect();
+        assert_eq!(
+            order,
+            vec![
+                (SearchHitType::Machine, "order"),
+                (SearchHitType::Instance, "order"),
+                (SearchHitType::Instance, "a"),
+                (SearchHitType::Instance, "b"),
+            ]
+        );
+        assert!(parse_cursor(Some("x")).is_err());
+    }
+}
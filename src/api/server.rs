@@ -1,9 +1,21 @@
 //! Server info and health API handlers
 
-use crate::error::ApiResult;
+use crate::api::auth::{require_admin, session_user, SessionUser};
+use crate::api::maintenance::MaintenanceInfo;
+use crate::config::UiConfig;
+use crate::error::{ApiError, ApiResult};
 use crate::json_ext::ValueExt;
+use crate::metrics::{self, PrometheusText};
+use crate::readiness::{self, Readiness};
+use crate::reload::{self, ReloadOutcome};
+use crate::supervisor::TaskStatus;
 use crate::AppState;
-use axum::{extract::State, http::StatusCode, Json};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
 use serde::Serialize;
 use std::sync::Arc;
 use std::time::Instant;
@@ -12,6 +24,13 @@ use std::time::Instant;
 pub struct ServerInfoResponse {
     pub studio_version: String,
     pub rstmdb: RstmdbInfo,
+    pub maintenance: MaintenanceInfo,
+    /// Login page notice, empty when unset
+    pub login_banner: String,
+    /// Reads are allowed without logging in
+    pub anonymous_read: bool,
+    /// Landing page preferences for the UI
+    pub ui: UiConfig,
 }
 
 #[derive(Debug, Serialize)]
@@ -28,6 +47,13 @@ pub struct HealthResponse {
     pub status: String,
     pub rstmdb_connected: bool,
     pub latency_ms: u64,
+    /// Last successful ping to rstmdb (epoch ms)
+    pub last_ping_at: Option<i64>,
+    /// Keep-alive pings that have failed in a row
+    pub consecutive_ping_failures: u32,
+    /// rstmdb endpoint currently in use, out of `rstmdb.address` and
+    /// `rstmdb.failover_addresses`
+    pub rstmdb_endpoint: String,
 }
 
 /// Health status values
@@ -53,6 +79,10 @@ pub async fn info(State(state): State<Arc<AppState>>) -> ApiResult<Json<ServerIn
             protocol_version: rstmdb_info.u32_or("protocol_version", 1),
             features: rstmdb_info.string_array("features"),
         },
+        maintenance: state.maintenance.info(),
+        login_banner: state.live.read().login_banner.clone().unwrap_or_default(),
+        anonymous_read: state.config.server.allow_anonymous_read,
+        ui: state.config.ui.clone(),
     }))
 }
 
@@ -61,6 +91,11 @@ pub async fn health(State(state): State<Arc<AppState>>) -> ApiResult<Json<Health
     let start = Instant::now();
     let connected = state.rstmdb.ping().await.is_ok();
     let latency_ms = start.elapsed().as_millis() as u64;
+    if connected {
+        state
+            .keepalive
+            .record_success(chrono::Utc::now().timestamp_millis());
+    }
 
     let status = if connected {
         health_status::HEALTHY
@@ -72,6 +107,174 @@ pub async fn health(State(state): State<Arc<AppState>>) -> ApiResult<Json<Health
         status: status.to_string(),
         rstmdb_connected: connected,
         latency_ms,
+        last_ping_at: state.keepalive.last_success(),
+        consecutive_ping_failures: state.keepalive.consecutive_failures(),
+        rstmdb_endpoint: state.rstmdb.active_endpoint(),
+    }))
+}
+
+/// POST /api/v1/server/reload-config
+///
+/// Same as sending SIGHUP: reloads the config file and reports which changes
+/// took effect and which need a restart.
+pub async fn reload_config(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<SessionUser>,
+) -> ApiResult<Json<ReloadOutcome>> {
+    require_admin(&user)?;
+
+    let outcome = reload::reload(&state)
+        .map_err(|e| ApiError::bad_request(format!("Config reload failed: {}", e)))?;
+    tracing::info!(username = %user.username, "Configuration reloaded via API");
+    Ok(Json(outcome))
+}
+
+/// Snapshot of in-process state for diagnosing a misbehaving Studio.
+///
+/// Only counters and timestamps are included; no keys, contexts or
+/// credentials. Studio has no connection pool or circuit breaker: requests
+/// share one rstmdb connection, optionally behind the request queue.
+#[derive(Debug, Serialize)]
+pub struct InternalsResponse {
+    /// Epoch milliseconds
+    pub generated_at: i64,
+    pub http: HttpInternals,
+    /// `None` when `rstmdb.instance_cache.enabled` is off
+    pub instance_cache: Option<CacheInternals>,
+    pub transition_usage_cache_entries: usize,
+    /// Applied `event_id`s remembered for deduplication
+    pub applied_event_ids: usize,
+    pub rstmdb: RstmdbInternals,
+    pub background: BackgroundInternals,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HttpInternals {
+    pub in_flight: usize,
+    pub max_in_flight: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheInternals {
+    pub size: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Hits over lookups, `None` before the first lookup
+    pub hit_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RstmdbInternals {
+    pub address: String,
+    pub active_endpoint: String,
+    /// `None` when `rstmdb.request_queue.enabled` is off
+    pub queue: Option<QueueInternals>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueInternals {
+    pub interactive_waiting: usize,
+    pub bulk_waiting: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackgroundInternals {
+    pub keepalive: KeepaliveInternals,
+    pub wal_sampler: WalSamplerInternals,
+    pub ttl_sweeper: TtlSweeperInternals,
+    /// Supervised tasks, including restarts and the last failure
+    pub tasks: Vec<TaskStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TtlSweeperInternals {
+    pub enabled: bool,
+    /// Epoch ms of the last completed sweep
+    pub last_sweep_at: Option<i64>,
+    /// Instances expired since startup
+    pub expired: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeepaliveInternals {
+    pub enabled: bool,
+    /// Epoch ms of the last successful ping
+    pub last_success_at: Option<i64>,
+    pub consecutive_failures: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalSamplerInternals {
+    pub enabled: bool,
+    /// Epoch ms of the newest retained sample
+    pub last_sample_at: Option<i64>,
+    pub samples: usize,
+}
+
+fn hit_rate(hits: u64, misses: u64) -> Option<f64> {
+    let lookups = hits + misses;
+    (lookups > 0).then(|| hits as f64 / lookups as f64)
+}
+
+/// GET /api/v1/server/internals
+///
+/// Reads only in-memory counters, so it is safe to poll while Studio is
+/// struggling; rstmdb is not contacted.
+pub async fn internals(
+    State(state): State<Arc<AppState>>,
+    user: Option<Extension<SessionUser>>,
+) -> ApiResult<Json<InternalsResponse>> {
+    require_admin(&session_user(user)?)?;
+
+    let config = &state.config;
+    let instance_cache = state
+        .rstmdb
+        .instance_cache_stats()
+        .map(|cache| CacheInternals {
+            size: cache.size,
+            capacity: cache.capacity,
+            hits: cache.hits,
+            misses: cache.misses,
+            hit_rate: hit_rate(cache.hits, cache.misses),
+        });
+    let queue = state.rstmdb.queue_depths().map(|depths| QueueInternals {
+        interactive_waiting: depths.interactive(),
+        bulk_waiting: depths.bulk(),
+    });
+
+    Ok(Json(InternalsResponse {
+        generated_at: chrono::Utc::now().timestamp_millis(),
+        http: HttpInternals {
+            in_flight: state.in_flight.in_flight(),
+            max_in_flight: state.in_flight.max(),
+        },
+        instance_cache,
+        transition_usage_cache_entries: state.transition_usage.entry_count(),
+        applied_event_ids: state.applied_events.entry_count(),
+        rstmdb: RstmdbInternals {
+            address: config.rstmdb.address.clone(),
+            active_endpoint: state.rstmdb.active_endpoint(),
+            queue,
+        },
+        background: BackgroundInternals {
+            keepalive: KeepaliveInternals {
+                enabled: config.rstmdb.keepalive.enabled,
+                last_success_at: state.keepalive.last_success(),
+                consecutive_failures: state.keepalive.consecutive_failures(),
+            },
+            wal_sampler: WalSamplerInternals {
+                enabled: config.wal_history.enabled,
+                last_sample_at: state.wal_history.last_sample().map(|s| s.timestamp),
+                samples: state.wal_history.sample_count(),
+            },
+            ttl_sweeper: TtlSweeperInternals {
+                enabled: config.instance_ttl.enabled,
+                last_sweep_at: state.instance_ttl.last_sweep(),
+                expired: state.instance_ttl.expired_total(),
+            },
+            tasks: state.tasks.statuses(),
+        },
     }))
 }
 
@@ -80,11 +283,116 @@ pub async fn healthz() -> StatusCode {
     StatusCode::OK
 }
 
-/// GET /readyz - Readiness probe
-pub async fn readyz(State(state): State<Arc<AppState>>) -> StatusCode {
-    if state.rstmdb.ping().await.is_ok() {
+fn readiness_status(readiness: &Readiness) -> StatusCode {
+    if readiness.ready {
         StatusCode::OK
     } else {
         StatusCode::SERVICE_UNAVAILABLE
     }
 }
+
+/// GET /readyz - Readiness probe over the configured dependencies
+pub async fn readyz(State(state): State<Arc<AppState>>) -> StatusCode {
+    readiness_status(&readiness::check_all(&state).await)
+}
+
+/// GET /api/v1/server/readiness
+///
+/// Per-dependency breakdown of `/readyz`. Always `200`; see `ready`.
+pub async fn readiness(State(state): State<Arc<AppState>>) -> Json<Readiness> {
+    Json(readiness::check_all(&state).await)
+}
+
+/// GET /metrics - Prometheus metrics
+pub async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let mut text = PrometheusText::new();
+    text.gauge(
+        "studio_http_requests_in_flight",
+        "HTTP requests currently being handled",
+        state.in_flight.in_flight() as f64,
+    )
+    .gauge(
+        "studio_http_requests_in_flight_max",
+        "Requests handled at once before new ones are rejected",
+        state.in_flight.max() as f64,
+    );
+
+    if let Some(cache) = state.rstmdb.instance_cache_stats() {
+        text.counter(
+            "studio_instance_cache_hits_total",
+            "Instance cache hits",
+            cache.hits,
+        )
+        .counter(
+            "studio_instance_cache_misses_total",
+            "Instance cache misses",
+            cache.misses,
+        )
+        .gauge(
+            "studio_instance_cache_entries",
+            "Entries currently held in the instance cache",
+            cache.size as f64,
+        );
+    }
+
+    text.gauge(
+        "studio_machine_counts_cache_entries",
+        "Machine versions with cached state and transition counts",
+        state.machine_counts.entry_count() as f64,
+    );
+    if let Some(refreshed_at) = state.machine_counts.last_refresh() {
+        text.gauge(
+            "studio_machine_counts_last_refresh_timestamp_seconds",
+            "When the machine counts cache was last refreshed",
+            refreshed_at as f64 / 1000.0,
+        );
+    }
+    if state.config.instance_ttl.enabled {
+        text.counter(
+            "studio_instances_expired_total",
+            "Instances expired by the instance TTL sweep",
+            state.instance_ttl.expired_total(),
+        );
+    }
+
+    if let Some(depths) = state.rstmdb.queue_depths() {
+        text.gauge(
+            "studio_rstmdb_queue_interactive_depth",
+            "Interactive rstmdb operations waiting for a slot",
+            depths.interactive() as f64,
+        )
+        .gauge(
+            "studio_rstmdb_queue_bulk_depth",
+            "Bulk rstmdb operations waiting for a slot",
+            depths.bulk() as f64,
+        );
+    }
+
+    text.histogram(
+        "studio_http_request_duration_seconds",
+        "API request duration by method and route",
+        &state.http_latency,
+    )
+    .histogram(
+        "studio_rstmdb_operation_duration_seconds",
+        "rstmdb operation duration; path=\"reconnect\" includes reconnecting and the retry",
+        state.rstmdb.latency(),
+    );
+
+    (
+        [(header::CONTENT_TYPE, metrics::CONTENT_TYPE)],
+        text.finish(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_rate() {
+        assert_eq!(hit_rate(0, 0), None);
+        assert_eq!(hit_rate(3, 1), Some(0.75));
+        assert_eq!(hit_rate(0, 5), Some(0.0));
+    }
+}
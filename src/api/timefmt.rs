@@ -0,0 +1,108 @@
+//! Optional formatted timestamps
+//!
+//! Timestamps are epoch milliseconds. With `?time_format=iso` or `?tz=<zone>`
+//! responses also carry an RFC 3339 string in the requested IANA zone (UTC by
+//! default), so clients without a date library need not convert them.
+
+use crate::error::{ApiError, ApiResult};
+use chrono::{DateTime, SecondsFormat};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+const ISO: &str = "iso";
+const EPOCH: &str = "epoch";
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TimeFormatQuery {
+    /// IANA zone for formatted timestamps, e.g. `Europe/Berlin`
+    pub tz: Option<String>,
+    /// `iso` to include formatted timestamps, `epoch` (default) for raw only
+    pub time_format: Option<String>,
+}
+
+/// Formats epoch-millisecond timestamps in a fixed zone
+#[derive(Debug, Clone, Copy)]
+pub struct TimestampFormatter {
+    tz: Tz,
+}
+
+impl TimeFormatQuery {
+    /// The formatter to apply, or `None` when only raw epochs were asked for
+    pub fn formatter(&self) -> ApiResult<Option<TimestampFormatter>> {
+        let tz = match self.tz.as_deref() {
+            Some(name) => Some(
+                name.parse::<Tz>()
+                    .map_err(|_| ApiError::bad_request(format!("Unknown time zone '{}'", name)))?,
+            ),
+            None => None,
+        };
+        let iso = match self.time_format.as_deref() {
+            Some(ISO) => true,
+            Some(EPOCH) => false,
+            // A zone on its own implies formatted output
+            None => tz.is_some(),
+            Some(other) => {
+                return Err(ApiError::bad_request(format!(
+                    "Unknown time_format '{}', expected '{}' or '{}'",
+                    other, ISO, EPOCH
+                )))
+            }
+        };
+        Ok(iso.then(|| TimestampFormatter {
+            tz: tz.unwrap_or(Tz::UTC),
+        }))
+    }
+}
+
+impl TimestampFormatter {
+    pub fn format(&self, epoch_ms: i64) -> Option<String> {
+        DateTime::from_timestamp_millis(epoch_ms).map(|dt| {
+            dt.with_timezone(&self.tz)
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+        })
+    }
+}
+
+/// Format `epoch_ms` if a formatter was requested
+pub fn format_opt(formatter: Option<TimestampFormatter>, epoch_ms: i64) -> Option<String> {
+    formatter.and_then(|f| f.format(epoch_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(tz: Option<&str>, time_format: Option<&str>) -> TimeFormatQuery {
+        TimeFormatQuery {
+            tz: tz.map(String::from),
+            time_format: time_format.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_formatting_follows_query() {
+        let ts = 1_704_067_200_123; // 2024-01-01T00:00:00.123Z
+
+        assert!(query(None, None).formatter().unwrap().is_none());
+        assert!(query(Some("Asia/Tokyo"), Some("epoch"))
+            .formatter()
+            .unwrap()
+            .is_none());
+
+        let utc = query(None, Some("iso")).formatter().unwrap();
+        assert_eq!(format_opt(utc, ts).unwrap(), "2024-01-01T00:00:00.123Z");
+
+        let tokyo = query(Some("Asia/Tokyo"), None).formatter().unwrap();
+        assert_eq!(
+            format_opt(tokyo, ts).unwrap(),
+            "2024-01-01T09:00:00.123+09:00"
+        );
+    }
+
+    #[test]
+    fn test_unknown_zone_or_format_is_rejected() {
+        let err = query(Some("Mars/Olympus"), None).formatter().unwrap_err();
+        assert_eq!(err.code, "BAD_REQUEST");
+        assert!(query(None, Some("rfc2822")).formatter().is_err());
+    }
+}
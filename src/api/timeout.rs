@@ -0,0 +1,65 @@
+//! Request timeouts
+//!
+//! `tower_http`'s `TimeoutLayer` answers a request that runs too long with an
+//! empty `408`. Requests timing out here are wedged on the server side, so the
+//! response is rewritten into a `TIMEOUT` API error (`504`).
+
+use crate::error::ApiError;
+use axum::{
+    http::{header, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    Router,
+};
+use std::time::Duration;
+use tower_http::timeout::TimeoutLayer;
+
+/// Fail requests to `router`'s routes that run longer than `timeout`
+pub fn with_timeout<S>(router: Router<S>, timeout: Duration) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router
+        .layer(TimeoutLayer::new(timeout))
+        .layer(middleware::map_response(
+            move |response: Response| async move { into_api_error(response, timeout) },
+        ))
+}
+
+/// Replace the timeout layer's bare `408` with a `TIMEOUT` error
+fn into_api_error(response: Response, timeout: Duration) -> Response {
+    let from_layer = response.status() == StatusCode::REQUEST_TIMEOUT
+        && !response.headers().contains_key(header::CONTENT_TYPE);
+    if !from_layer {
+        return response;
+    }
+    tracing::warn!(timeout_ms = timeout.as_millis() as u64, "Request timed out");
+    ApiError::timeout(format!(
+        "Request did not complete within {}",
+        humantime::format_duration(timeout)
+    ))
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer_timeout_becomes_gateway_timeout() {
+        let timed_out = StatusCode::REQUEST_TIMEOUT.into_response();
+        let response = into_api_error(timed_out, Duration::from_secs(30));
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        // Other responses, including JSON 408s, pass through
+        let ok = into_api_error(StatusCode::OK.into_response(), Duration::from_secs(30));
+        assert_eq!(ok.status(), StatusCode::OK);
+        let json_408 = (
+            StatusCode::REQUEST_TIMEOUT,
+            axum::Json(serde_json::json!({})),
+        )
+            .into_response();
+        let passed = into_api_error(json_408, Duration::from_secs(30));
+        assert_eq!(passed.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+}
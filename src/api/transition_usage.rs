@@ -0,0 +1,330 @@
+//! Transition usage counts aggregated from the WAL
+
+use crate::api::machines::latest_version;
+use crate::api::wal::{find_offset_at_time, parse_time};
+use crate::constants::transition_usage::{CACHE_MAX_ENTRIES, CACHE_TTL_SECS, MAX_WAL_SCAN};
+use crate::constants::wal::MAX_PAGE_SIZE;
+use crate::constants::wal_entry_types;
+use crate::error::ApiResult;
+use crate::json_ext::ValueExt;
+use crate::validation::TransitionGraph;
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Deserialize)]
+pub struct TransitionUsageQuery {
+    /// Only count events at or after this time (RFC 3339)
+    pub since: Option<String>,
+    /// Definition version used to list never-fired transitions (default: latest)
+    pub version: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TransitionUsage {
+    pub from: String,
+    pub event: String,
+    pub to: String,
+    pub count: u64,
+    /// False for transitions seen in the WAL but absent from the definition
+    /// (e.g. fired under an older version)
+    pub in_definition: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransitionUsageResponse {
+    pub machine: String,
+    pub version: u32,
+    /// Most used first; transitions that never fired have count 0
+    pub transitions: Vec<TransitionUsage>,
+    /// WAL offset the scan started at: the first entry at or after `since`,
+    /// or 0 without it
+    pub from_offset: u64,
+    /// WAL entries examined
+    pub scanned: u64,
+    /// True when the scan stopped at the limit before reaching the end of the WAL
+    pub truncated: bool,
+    /// When the WAL scan ran (epoch ms); results are cached briefly
+    pub computed_at: i64,
+}
+
+type CacheKey = (String, u32, Option<i64>);
+
+/// Short-lived cache of usage results, since each one is a full WAL scan
+#[derive(Default)]
+pub struct TransitionUsageCache {
+    entries: Mutex<HashMap<CacheKey, (Instant, TransitionUsageResponse)>>,
+}
+
+impl TransitionUsageCache {
+    fn get(&self, key: &CacheKey) -> Option<TransitionUsageResponse> {
+        let entries = self.entries.lock();
+        entries
+            .get(key)
+            .filter(|(stored_at, _)| stored_at.elapsed() < Duration::from_secs(CACHE_TTL_SECS))
+            .map(|(_, response)| response.clone())
+    }
+
+    /// Cached results, including expired ones not yet evicted
+    pub fn entry_count(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    fn insert(&self, key: CacheKey, response: TransitionUsageResponse) {
+        let mut entries = self.entries.lock();
+        entries
+            .retain(|_, (stored_at, _)| stored_at.elapsed() < Duration::from_secs(CACHE_TTL_SECS));
+        if entries.len() >= CACHE_MAX_ENTRIES {
+            entries.clear();
+        }
+        entries.insert(key, (Instant::now(), response));
+    }
+}
+
+/// Counts `(from, event, to)` triples for one machine across WAL records.
+///
+/// `apply_event` entries don't always carry the machine name, so instances
+/// are attributed to the machine via their `create_instance` entries.
+struct UsageCounter<'a> {
+    machine: &'a str,
+    since: Option<i64>,
+    instances: HashSet<String>,
+    counts: HashMap<(String, String, String), u64>,
+}
+
+impl<'a> UsageCounter<'a> {
+    fn new(machine: &'a str, since: Option<i64>) -> Self {
+        Self {
+            machine,
+            since,
+            instances: HashSet::new(),
+            counts: HashMap::new(),
+        }
+    }
+
+    fn record(&mut self, record: &Value) {
+        let entry = &record["entry"];
+        let instance_id = entry.str_or_empty("instance_id");
+        match entry["type"].as_str() {
+            Some(wal_entry_types::CREATE_INSTANCE)
+                if entry["machine"].as_str() == Some(self.machine) =>
+            {
+                self.instances.insert(instance_id);
+            }
+            Some(wal_entry_types::APPLY_EVENT) => {
+                let belongs = match entry["machine"].as_str() {
+                    Some(machine) => machine == self.machine,
+                    None => self.instances.contains(&instance_id),
+                };
+                let in_range = self
+                    .since
+                    .is_none_or(|since| entry.i64_or("timestamp", 0) >= since);
+                if belongs && in_range {
+                    let key = (
+                        entry.str_or_empty("from_state"),
+                        entry.str_or_empty("event"),
+                        entry.str_or_empty("to_state"),
+                    );
+                    *self.counts.entry(key).or_insert(0) += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Merge counts with the definition's transitions, most used first
+    fn finish(self, definition: &Value) -> Vec<TransitionUsage> {
+        let graph = TransitionGraph::from_definition(definition);
+        let mut counts = self.counts;
+        let mut usage: Vec<TransitionUsage> = Vec::new();
+
+        for edge in &graph.edges {
+            let key = (
+                edge.from.to_string(),
+                edge.event.to_string(),
+                edge.to.to_string(),
+            );
+            // Guarded alternatives can share a triple; list it once
+            if usage
+                .iter()
+                .any(|u| (&u.from, &u.event, &u.to) == (&key.0, &key.1, &key.2))
+            {
+                continue;
+            }
+            let count = counts.remove(&key).unwrap_or(0);
+            usage.push(TransitionUsage {
+                from: key.0,
+                event: key.1,
+                to: key.2,
+                count,
+                in_definition: true,
+            });
+        }
+        usage.extend(
+            counts
+                .into_iter()
+                .map(|((from, event, to), count)| TransitionUsage {
+                    from,
+                    event,
+                    to,
+                    count,
+                    in_definition: false,
+                }),
+        );
+
+        // Stable sort keeps definition order among equal counts
+        usage.sort_by_key(|u| Reverse(u.count));
+        usage
+    }
+}
+
+/// GET /api/v1/machines/:name/transition-usage?since=<rfc3339>&version=N
+///
+/// Counts how often each transition fired according to the WAL. Transitions
+/// of the definition that never fired are included with count 0. With
+/// `since`, the scan starts at the first WAL entry at or after that time, so
+/// the `MAX_WAL_SCAN` limit applies to the requested window rather than to
+/// the oldest entries.
+pub async fn get_transition_usage(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<TransitionUsageQuery>,
+) -> ApiResult<Json<TransitionUsageResponse>> {
+    let since = query
+        .since
+        .as_deref()
+        .map(|t| parse_time("since", t))
+        .transpose()?;
+    let version = match query.version {
+        Some(version) => version,
+        None => latest_version(&state, &name).await?,
+    };
+
+    let key = (name.clone(), version, since);
+    if let Some(cached) = state.transition_usage.get(&key) {
+        return Ok(Json(cached));
+    }
+
+    let machine = state.rstmdb.get_machine(&name, version).await?;
+
+    let mut counter = UsageCounter::new(&name, since);
+    let mut scanned = 0u64;
+    let from_offset = match since {
+        Some(since) => find_offset_at_time(&state, since).await?,
+        None => 0,
+    };
+    let mut from = from_offset;
+    let mut truncated = false;
+    loop {
+        let page = state.rstmdb.wal_read(from, Some(MAX_PAGE_SIZE)).await?;
+        let records = page["records"].as_array().cloned().unwrap_or_default();
+        for record in &records {
+            counter.record(record);
+        }
+        scanned += records.len() as u64;
+
+        let next = page.u64_opt("next_offset");
+        match next {
+            Some(next) if records.len() as u64 >= MAX_PAGE_SIZE && next > from => {
+                if scanned >= MAX_WAL_SCAN {
+                    truncated = true;
+                    break;
+                }
+                from = next;
+            }
+            _ => break,
+        }
+    }
+
+    let transitions = counter.finish(&machine["definition"]);
+    let response = TransitionUsageResponse {
+        machine: name,
+        version,
+        transitions,
+        from_offset,
+        scanned,
+        truncated,
+        computed_at: chrono::Utc::now().timestamp_millis(),
+    };
+    state.transition_usage.insert(key, response.clone());
+
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn create(id: &str, machine: &str) -> Value {
+        json!({"entry": {"type": "create_instance", "instance_id": id, "machine": machine, "timestamp": 1}})
+    }
+
+    fn apply(id: &str, from: &str, event: &str, to: &str, ts: i64) -> Value {
+        json!({"entry": {
+            "type": "apply_event", "instance_id": id, "from_state": from,
+            "event": event, "to_state": to, "timestamp": ts
+        }})
+    }
+
+    fn definition() -> Value {
+        json!({
+            "states": ["pending", "paid", "cancelled"],
+            "initial": "pending",
+            "transitions": [
+                { "from": "pending", "event": "PAY", "to": "paid" },
+                { "from": "pending", "event": "CANCEL", "to": "cancelled" }
+            ]
+        })
+    }
+
+    #[test]
+    fn test_counts_only_the_machines_instances() {
+        let mut counter = UsageCounter::new("order", None);
+        for record in [
+            create("o1", "order"),
+            create("o2", "order"),
+            create("x1", "other"),
+            apply("o1", "pending", "PAY", "paid", 10),
+            apply("o2", "pending", "PAY", "paid", 11),
+            apply("x1", "pending", "PAY", "paid", 12),
+        ] {
+            counter.record(&record);
+        }
+
+        let usage = counter.finish(&definition());
+        assert_eq!(usage.len(), 2);
+        assert_eq!((usage[0].event.as_str(), usage[0].count), ("PAY", 2));
+        // Never-fired transition is listed at zero
+        assert_eq!((usage[1].event.as_str(), usage[1].count), ("CANCEL", 0));
+        assert!(usage.iter().all(|u| u.in_definition));
+    }
+
+    #[test]
+    fn test_since_and_unknown_transitions() {
+        let mut counter = UsageCounter::new("order", Some(100));
+        for record in [
+            create("o1", "order"),
+            apply("o1", "pending", "PAY", "paid", 50),
+            apply("o1", "paid", "REFUND", "pending", 150),
+        ] {
+            counter.record(&record);
+        }
+
+        let usage = counter.finish(&definition());
+        let refund = usage.iter().find(|u| u.event == "REFUND").unwrap();
+        assert_eq!(refund.count, 1);
+        assert!(!refund.in_definition);
+        let pay = usage.iter().find(|u| u.event == "PAY").unwrap();
+        assert_eq!(pay.count, 0);
+    }
+}
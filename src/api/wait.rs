@@ -0,0 +1,138 @@
+//! Long-polling for instance state changes
+//!
+//! For clients without SSE or WebSocket support: `GET .../wait` holds the
+//! request until the instance leaves (or enters) a state, or the timeout
+//! passes. The WAL is tailed from the instance's last offset, and the
+//! instance is only re-read when an entry for it appears.
+
+use crate::constants::instances::{DEFAULT_WAIT_TIMEOUT, WAIT_POLL_INTERVAL_MS};
+use crate::constants::wal::MAX_PAGE_SIZE;
+use crate::error::{ApiError, ApiResult};
+use crate::json_ext::ValueExt;
+use crate::rstmdb::InstanceResult;
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WaitUntil {
+    /// Until the instance is no longer in `expected_state`
+    #[default]
+    Leave,
+    /// Until the instance is in `expected_state`
+    Enter,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WaitQuery {
+    pub expected_state: String,
+    #[serde(default)]
+    pub until: WaitUntil,
+    /// e.g. `30s`; capped by `server.timeouts.max_wait`
+    pub timeout: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WaitResponse {
+    pub instance_id: String,
+    pub state: String,
+    pub last_wal_offset: u64,
+    /// False when the timeout passed first
+    pub condition_met: bool,
+    pub waited_ms: u64,
+}
+
+fn condition_met(until: WaitUntil, expected: &str, state: &str) -> bool {
+    match until {
+        WaitUntil::Leave => state != expected,
+        WaitUntil::Enter => state == expected,
+    }
+}
+
+fn wait_timeout(requested: Option<&str>, max: Duration) -> ApiResult<Duration> {
+    let value = requested.unwrap_or(DEFAULT_WAIT_TIMEOUT);
+    let timeout = humantime::parse_duration(value)
+        .map_err(|e| ApiError::bad_request(format!("Invalid timeout '{}': {}", value, e)))?;
+    Ok(timeout.min(max))
+}
+
+/// GET /api/v1/instances/:id/wait?expected_state=S&until=leave|enter&timeout=30s
+///
+/// Answers `200` either way; `condition_met` tells whether the instance got
+/// there before the timeout.
+pub async fn wait_for_instance(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<WaitQuery>,
+) -> ApiResult<Json<WaitResponse>> {
+    let timeout = wait_timeout(query.timeout.as_deref(), state.request_timeouts.max_wait)?;
+    let started = Instant::now();
+    let deadline = started + timeout;
+    let check = |instance: &InstanceResult| {
+        condition_met(query.until, &query.expected_state, &instance.state)
+    };
+
+    let mut instance = state.rstmdb.get_instance_fresh(&id).await?;
+    let mut from = instance.last_wal_offset + 1;
+    while !check(&instance) && Instant::now() < deadline {
+        tokio::time::sleep_until(
+            (Instant::now() + Duration::from_millis(WAIT_POLL_INTERVAL_MS)).min(deadline),
+        )
+        .await;
+
+        let page = state.rstmdb.wal_read(from, Some(MAX_PAGE_SIZE)).await?;
+        let records = page["records"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        let touched = records
+            .iter()
+            .any(|record| record["entry"]["instance_id"].as_str() == Some(id.as_str()));
+        if let Some(last) = records.last() {
+            from = from.max(last.u64_or("offset", 0) + 1);
+        }
+        if touched {
+            instance = state.rstmdb.get_instance_fresh(&id).await?;
+        }
+    }
+
+    Ok(Json(WaitResponse {
+        condition_met: check(&instance),
+        waited_ms: started.elapsed().as_millis() as u64,
+        instance_id: instance.instance_id,
+        state: instance.state,
+        last_wal_offset: instance.last_wal_offset,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition_met() {
+        assert!(condition_met(WaitUntil::Leave, "pending", "paid"));
+        assert!(!condition_met(WaitUntil::Leave, "pending", "pending"));
+        assert!(condition_met(WaitUntil::Enter, "paid", "paid"));
+        assert!(!condition_met(WaitUntil::Enter, "paid", "pending"));
+    }
+
+    #[test]
+    fn test_wait_timeout_is_capped() {
+        let max = Duration::from_secs(60);
+        assert_eq!(wait_timeout(None, max).unwrap(), Duration::from_secs(30));
+        assert_eq!(
+            wait_timeout(Some("5s"), max).unwrap(),
+            Duration::from_secs(5)
+        );
+        assert_eq!(wait_timeout(Some("10m"), max).unwrap(), max);
+        assert_eq!(
+            wait_timeout(Some("soon"), max).unwrap_err().code,
+            "BAD_REQUEST"
+        );
+    }
+}
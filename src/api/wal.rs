@@ -1,21 +1,60 @@
 //! WAL API handlers
 
+use crate::api::instances::fetch_instances;
+use crate::api::machines::latest_versions;
+use crate::api::pagination::{with_page_headers, PageLinks};
+use crate::api::path::ApiPath;
 use crate::constants::wal::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
 use crate::error::{ApiError, ApiResult};
 use crate::json_ext::ValueExt;
+use crate::redact::Redactor;
 use crate::AppState;
 use axum::{
-    extract::{Path, Query, State},
+    extract::{OriginalUri, Query, State},
+    response::Response,
     Json,
 };
+use chrono::DateTime;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
 use std::sync::Arc;
 
 #[derive(Debug, Deserialize)]
 pub struct ListWalQuery {
     pub from: Option<u64>,
     pub limit: Option<u64>,
+    /// Start of a time range (RFC 3339); resolved to an offset by binary search
+    pub from_time: Option<String>,
+    /// End of a time range (RFC 3339, inclusive)
+    pub to_time: Option<String>,
+    /// Include each entry exactly as stored, for debugging
+    #[serde(default)]
+    pub raw: bool,
+    /// Include the machine's latest version and the instance's current state
+    #[serde(default)]
+    pub resolve: bool,
+    /// Replace the configured `redaction.paths` in contexts and payloads
+    #[serde(default)]
+    pub redact: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WalEntryQuery {
+    #[serde(default)]
+    pub resolve: bool,
+    #[serde(default)]
+    pub redact: bool,
+}
+
+/// Current machine/instance context for a WAL entry, with `?resolve=true`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct WalResolution {
+    /// False when the entry's instance or machine no longer exists
+    pub resolved: bool,
+    pub machine_latest_version: Option<u32>,
+    pub instance_state: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -26,13 +65,69 @@ pub struct WalEntry {
     pub instance_id: Option<String>,
     pub machine: Option<String>,
     pub version: Option<u32>,
-    pub details: Value,
+    /// Entry time (epoch ms)
+    pub timestamp: Option<i64>,
+    /// Type-specific fields; `null` for entry types without a typed shape
+    pub payload: Option<WalPayload>,
+    /// The unmodified entry, only with `?raw=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<WalResolution>,
+}
+
+/// Type-specific WAL entry fields, tagged by entry `type`.
+///
+/// Fields already on [`WalEntry`] (instance, machine, version, timestamp) are
+/// not repeated.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WalPayload {
+    CreateInstance(CreateInstancePayload),
+    ApplyEvent(ApplyEventPayload),
+    DeleteInstance(DeleteInstancePayload),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CreateInstancePayload {
+    pub initial_state: String,
+    pub initial_ctx: Option<Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ApplyEventPayload {
+    pub event: String,
+    pub from_state: String,
+    pub to_state: String,
+    /// Event payload as sent by the client
+    pub payload: Option<Value>,
+    /// Context after the transition
+    pub ctx: Option<Value>,
+    pub event_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct DeleteInstancePayload {
+    pub reason: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct WalListResponse {
     pub records: Vec<WalEntry>,
     pub next_offset: Option<u64>,
+    /// Offsets covered by a time-range query, for switching to offset paging
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_range: Option<WalOffsetRange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalOffsetRange {
+    pub from_offset: u64,
+    /// Offset of the last returned entry, if any
+    pub to_offset: Option<u64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -40,56 +135,216 @@ pub struct WalEntryResponse {
     pub sequence: u64,
     pub offset: u64,
     pub entry: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<WalResolution>,
+}
+
+/// Parse an RFC 3339 timestamp into epoch milliseconds (the WAL timestamp unit)
+pub fn parse_time(param: &str, value: &str) -> ApiResult<i64> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.timestamp_millis())
+        .map_err(|e| ApiError::bad_request(format!("Invalid {} '{}': {}", param, value, e)))
+}
+
+/// The record with its entry redacted, when a redactor is given
+fn redacted<'a>(record: &'a Value, redactor: Option<&Redactor>) -> Cow<'a, Value> {
+    match redactor {
+        Some(redactor) => {
+            let mut record = record.clone();
+            redactor.redact_wal_entry(&mut record["entry"]);
+            Cow::Owned(record)
+        }
+        None => Cow::Borrowed(record),
+    }
+}
+
+fn wal_entry_from_record(record: &Value, raw: bool) -> WalEntry {
+    let entry = &record["entry"];
+    WalEntry {
+        sequence: record.u64_or("sequence", 0),
+        offset: record.u64_or("offset", 0),
+        entry_type: entry.str_or_empty("type"),
+        instance_id: entry.str_opt("instance_id"),
+        machine: entry.str_opt("machine"),
+        version: entry.u64_opt("version").map(|v| v as u32),
+        timestamp: entry.get("timestamp").and_then(Value::as_i64),
+        payload: serde_json::from_value(entry.clone()).ok(),
+        raw: raw.then(|| entry.clone()),
+        resolution: None,
+    }
+}
+
+/// Resolve one entry against the latest machine versions and the current
+/// state of each instance (`None` if it could not be fetched)
+fn resolution_for(
+    entry: &Value,
+    latest_versions: &HashMap<String, u32>,
+    instance_states: &HashMap<String, Option<String>>,
+) -> WalResolution {
+    let machine = entry.str_opt("machine");
+    let instance = entry.str_opt("instance_id");
+    let machine_latest_version = machine
+        .as_ref()
+        .and_then(|m| latest_versions.get(m).copied());
+    let instance_state = instance
+        .as_ref()
+        .and_then(|id| instance_states.get(id).cloned().flatten());
+    WalResolution {
+        resolved: (machine.is_none() || machine_latest_version.is_some())
+            && (instance.is_none() || instance_state.is_some()),
+        machine_latest_version,
+        instance_state,
+    }
+}
+
+/// Resolve entries, fetching each distinct instance once
+async fn resolve_entries(
+    state: &Arc<AppState>,
+    entries: &[&Value],
+) -> ApiResult<Vec<WalResolution>> {
+    let latest_versions = latest_versions(state).await?;
+
+    let ids: BTreeSet<String> = entries
+        .iter()
+        .filter_map(|entry| entry.str_opt("instance_id"))
+        .collect();
+    // Deleted instances (and failed lookups) resolve to `None`
+    let instance_states: HashMap<String, Option<String>> = fetch_instances(state, ids)
+        .await?
+        .into_iter()
+        .map(|(id, result)| (id, result.ok().map(|instance| instance.state)))
+        .collect();
+
+    Ok(entries
+        .iter()
+        .map(|entry| resolution_for(entry, &latest_versions, &instance_states))
+        .collect())
+}
+
+/// Find the first WAL offset whose entry timestamp is at or after `ts`.
+///
+/// WAL timestamps are monotonic by offset, so this binary-searches the offset
+/// space with single-entry `wal_read` probes (each returns the first entry at
+/// or after the probed offset).
+pub(crate) async fn find_offset_at_time(state: &AppState, ts: i64) -> ApiResult<u64> {
+    let stats = state.rstmdb.wal_stats().await?;
+    let Some(latest) = stats.u64_opt("latest_offset") else {
+        return Ok(0);
+    };
+
+    let (mut lo, mut hi) = (0u64, latest + 1);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let probe = state.rstmdb.wal_read(mid, Some(1)).await?;
+        match probe["records"].as_array().and_then(|r| r.first()) {
+            Some(record) if record["entry"].i64_or("timestamp", 0) < ts => {
+                lo = record.u64_or("offset", mid).max(mid) + 1;
+            }
+            _ => hi = mid,
+        }
+    }
+    Ok(lo)
 }
 
 /// GET /api/v1/wal
 pub async fn list_wal_entries(
     State(state): State<Arc<AppState>>,
+    OriginalUri(uri): OriginalUri,
     Query(query): Query<ListWalQuery>,
-) -> ApiResult<Json<WalListResponse>> {
-    let from = query.from.unwrap_or(0);
+) -> ApiResult<Response> {
     let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    let to_time = query
+        .to_time
+        .as_deref()
+        .map(|t| parse_time("to_time", t))
+        .transpose()?;
+
+    let from = match query.from_time.as_deref() {
+        Some(t) => {
+            if query.from.is_some() {
+                return Err(ApiError::bad_request(
+                    "Use either 'from' or 'from_time', not both",
+                ));
+            }
+            find_offset_at_time(&state, parse_time("from_time", t)?).await?
+        }
+        None => query.from.unwrap_or(0),
+    };
 
     let result = state.rstmdb.wal_read(from, Some(limit)).await?;
 
-    let records: Vec<WalEntry> = result["records"]
-        .as_array()
-        .map(|arr| {
-            arr.iter()
-                .map(|record| {
-                    let entry = &record["entry"];
-                    WalEntry {
-                        sequence: record.u64_or("sequence", 0),
-                        offset: record.u64_or("offset", 0),
-                        entry_type: entry.str_or_empty("type"),
-                        instance_id: entry.str_opt("instance_id"),
-                        machine: entry.str_opt("machine"),
-                        version: entry.u64_opt("version").map(|v| v as u32),
-                        details: entry.clone(),
-                    }
-                })
-                .collect()
+    let raw_records = result["records"].as_array().cloned().unwrap_or_default();
+    let raw_count = raw_records.len();
+    let mut passed_end = false;
+    let in_range: Vec<&Value> = raw_records
+        .iter()
+        .take_while(|record| {
+            let within = to_time.is_none_or(|end| record["entry"].i64_or("timestamp", 0) <= end);
+            passed_end |= !within;
+            within
         })
-        .unwrap_or_default();
+        .collect();
+    let redactor = query.redact.then_some(&state.redactor);
+    let mut records: Vec<WalEntry> = in_range
+        .iter()
+        .map(|record| wal_entry_from_record(&redacted(record, redactor), query.raw))
+        .collect();
+    if query.resolve {
+        let entries: Vec<&Value> = in_range.iter().map(|record| &record["entry"]).collect();
+        let resolutions = resolve_entries(&state, &entries).await?;
+        for (record, resolution) in records.iter_mut().zip(resolutions) {
+            record.resolution = Some(resolution);
+        }
+    }
 
     // Only return next_offset if we got a full page of results
     // (indicating there might be more entries)
-    let next_offset = if records.len() >= limit as usize {
+    let next_offset = if raw_count >= limit as usize && !passed_end {
         result.u64_opt("next_offset")
     } else {
         None
     };
 
-    Ok(Json(WalListResponse {
-        records,
-        next_offset,
-    }))
+    let resolved_range = (query.from_time.is_some() || to_time.is_some()).then(|| WalOffsetRange {
+        from_offset: from,
+        to_offset: records.last().map(|r| r.offset),
+    });
+
+    // The total only describes plain offset paging, not a time range
+    let total = if resolved_range.is_none() {
+        state
+            .rstmdb
+            .wal_stats()
+            .await
+            .ok()
+            .and_then(|stats| stats.u64_opt("entry_count"))
+    } else {
+        None
+    };
+    // Offsets are opaque cursors, so only a next page can be linked
+    let links = PageLinks {
+        total,
+        next: next_offset
+            .map(|next| vec![("from", next.to_string()), ("from_time", String::new())]),
+        prev: None,
+    };
+
+    Ok(with_page_headers(
+        &uri,
+        &links,
+        Json(WalListResponse {
+            records,
+            next_offset,
+            resolved_range,
+        }),
+    ))
 }
 
 /// GET /api/v1/wal/:offset
 pub async fn get_wal_entry(
     State(state): State<Arc<AppState>>,
-    Path(offset): Path<u64>,
+    ApiPath(offset): ApiPath<u64>,
+    Query(query): Query<WalEntryQuery>,
 ) -> ApiResult<Json<WalEntryResponse>> {
     let result = state.rstmdb.wal_read(offset, Some(1)).await?;
 
@@ -98,10 +353,18 @@ pub async fn get_wal_entry(
         .and_then(|arr| arr.first())
         .ok_or_else(|| ApiError::not_found("WAL entry"))?;
 
+    let resolution = if query.resolve {
+        resolve_entries(&state, &[&record["entry"]]).await?.pop()
+    } else {
+        None
+    };
+
+    let record = redacted(record, query.redact.then_some(&state.redactor));
     Ok(Json(WalEntryResponse {
         sequence: record.u64_or("sequence", 0),
         offset: record.u64_or("offset", offset),
         entry: record["entry"].clone(),
+        resolution,
     }))
 }
 
@@ -144,3 +407,86 @@ pub async fn get_wal_stats(
         },
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(entry: Value) -> Value {
+        json!({"sequence": 3, "offset": 120, "entry": entry})
+    }
+
+    #[test]
+    fn test_typed_payloads() {
+        let apply = wal_entry_from_record(
+            &record(json!({
+                "type": "apply_event", "instance_id": "o1", "event": "PAY",
+                "from_state": "pending", "to_state": "paid",
+                "payload": {"amount": 5}, "timestamp": 1700000000000i64
+            })),
+            false,
+        );
+        assert_eq!(apply.timestamp, Some(1700000000000));
+        assert!(apply.raw.is_none());
+        let Some(WalPayload::ApplyEvent(payload)) = apply.payload else {
+            panic!("expected apply_event payload");
+        };
+        assert_eq!(payload.event, "PAY");
+        assert_eq!(payload.payload, Some(json!({"amount": 5})));
+
+        let create = wal_entry_from_record(
+            &record(json!({
+                "type": "create_instance", "instance_id": "o1", "machine": "order",
+                "version": 1, "initial_state": "pending"
+            })),
+            true,
+        );
+        assert!(matches!(
+            create.payload,
+            Some(WalPayload::CreateInstance(ref p)) if p.initial_state == "pending"
+        ));
+        assert_eq!(create.raw.unwrap()["machine"], "order");
+
+        let serialized = serde_json::to_value(&create.payload).unwrap();
+        assert_eq!(serialized["type"], "create_instance");
+        assert!(serialized.get("machine").is_none());
+    }
+
+    #[test]
+    fn test_untyped_entries_have_no_payload() {
+        let entry = wal_entry_from_record(
+            &record(json!({"type": "put_machine", "machine": "order", "version": 2})),
+            false,
+        );
+        assert_eq!(entry.entry_type, "put_machine");
+        assert!(entry.payload.is_none());
+    }
+
+    #[test]
+    fn test_resolution_marks_deleted_instances() {
+        let latest = HashMap::from([("order".to_string(), 3)]);
+        let states = HashMap::from([
+            ("o1".to_string(), Some("shipped".to_string())),
+            ("gone".to_string(), None),
+        ]);
+
+        let live = json!({"type": "apply_event", "machine": "order", "instance_id": "o1"});
+        assert_eq!(
+            resolution_for(&live, &latest, &states),
+            WalResolution {
+                resolved: true,
+                machine_latest_version: Some(3),
+                instance_state: Some("shipped".to_string()),
+            }
+        );
+
+        let deleted = json!({"type": "apply_event", "machine": "order", "instance_id": "gone"});
+        let resolution = resolution_for(&deleted, &latest, &states);
+        assert!(!resolution.resolved);
+        assert_eq!(resolution.machine_latest_version, Some(3));
+
+        let machine_only = json!({"type": "put_machine", "machine": "order"});
+        assert!(resolution_for(&machine_only, &latest, &states).resolved);
+    }
+}
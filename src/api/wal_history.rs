@@ -0,0 +1,142 @@
+//! WAL growth over time
+//!
+//! `GET /wal/stats` is a point-in-time snapshot. A background sampler records
+//! the WAL entry count and size on an interval and keeps the samples for the
+//! retention window, so the dashboard can chart WAL growth without an
+//! external time-series database.
+
+use crate::error::ApiResult;
+use crate::json_ext::ValueExt;
+use crate::rstmdb::{with_priority, Priority, StudioClient};
+use crate::AppState;
+use axum::{extract::State, Json};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct WalSample {
+    /// Epoch milliseconds
+    pub timestamp: i64,
+    pub entry_count: u64,
+    pub total_size_bytes: u64,
+}
+
+/// Samples recorded within the retention window, oldest first
+pub struct WalHistory {
+    interval: Duration,
+    retention: Duration,
+    samples: Mutex<VecDeque<WalSample>>,
+}
+
+impl WalHistory {
+    pub fn new(interval: Duration, retention: Duration) -> Self {
+        Self {
+            interval,
+            retention,
+            samples: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Append a sample, dropping samples older than the retention window
+    pub fn record(&self, sample: WalSample) {
+        let cutoff = sample.timestamp - self.retention.as_millis() as i64;
+        let mut samples = self.samples.lock();
+        while samples.front().is_some_and(|s| s.timestamp < cutoff) {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    pub fn samples(&self) -> Vec<WalSample> {
+        self.samples.lock().iter().copied().collect()
+    }
+
+    pub fn last_sample(&self) -> Option<WalSample> {
+        self.samples.lock().back().copied()
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.samples.lock().len()
+    }
+}
+
+/// Record WAL stats every `history.interval`. Runs until the process exits.
+pub async fn run_sampler(client: &StudioClient, history: &WalHistory) {
+    let mut ticker = tokio::time::interval(history.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+
+        // A failed sample leaves a gap in the series rather than a zero
+        let stats = match with_priority(Priority::Bulk, client.wal_stats()).await {
+            Ok(stats) => stats,
+            Err(e) => {
+                tracing::debug!(error = %e.message, "WAL stats sample failed");
+                continue;
+            }
+        };
+        history.record(WalSample {
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            entry_count: stats.u64_or("entry_count", 0),
+            total_size_bytes: stats.u64_or("total_size_bytes", 0),
+        });
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalHistoryResponse {
+    /// False when `wal_history.enabled` is off; no samples are recorded
+    pub enabled: bool,
+    pub interval_ms: u64,
+    pub retention_ms: u64,
+    /// Oldest first
+    pub samples: Vec<WalSample>,
+}
+
+/// GET /api/v1/wal/stats/history
+pub async fn get_wal_stats_history(
+    State(state): State<Arc<AppState>>,
+) -> ApiResult<Json<WalHistoryResponse>> {
+    let history = &state.wal_history;
+    Ok(Json(WalHistoryResponse {
+        enabled: state.config.wal_history.enabled,
+        interval_ms: history.interval.as_millis() as u64,
+        retention_ms: history.retention.as_millis() as u64,
+        samples: history.samples(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp: i64, entry_count: u64) -> WalSample {
+        WalSample {
+            timestamp,
+            entry_count,
+            total_size_bytes: entry_count * 100,
+        }
+    }
+
+    #[test]
+    fn test_record_drops_samples_outside_retention() {
+        let history = WalHistory::new(Duration::from_secs(60), Duration::from_secs(180));
+        for (i, timestamp) in [0, 60_000, 120_000, 180_000].into_iter().enumerate() {
+            history.record(sample(timestamp, i as u64));
+        }
+        // The window is inclusive of its start
+        assert_eq!(history.samples().len(), 4);
+
+        history.record(sample(240_000, 4));
+        let samples = history.samples();
+        assert_eq!(
+            samples.iter().map(|s| s.timestamp).collect::<Vec<_>>(),
+            vec![60_000, 120_000, 180_000, 240_000]
+        );
+        assert_eq!(samples.last(), Some(&sample(240_000, 4)));
+    }
+}
@@ -0,0 +1,80 @@
+//! Pluggable authentication backends
+
+use super::{AuthStore, Role};
+use crate::error::ApiResult;
+use async_trait::async_trait;
+
+/// How clients authenticate at `POST /auth/login`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginMethod {
+    /// Username and password form
+    Password,
+    /// Token issued by an external identity provider
+    Token,
+}
+
+impl LoginMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginMethod::Password => "password",
+            LoginMethod::Token => "token",
+        }
+    }
+}
+
+/// Credentials presented at login
+pub enum Credentials {
+    Password { username: String, password: String },
+    Token(String),
+}
+
+/// A user known to a backend
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackendUser {
+    pub username: String,
+    pub role: Role,
+}
+
+/// Source of truth for who may log in and with which role
+#[async_trait]
+pub trait AuthBackend: Send + Sync {
+    fn login_method(&self) -> LoginMethod;
+
+    /// Verify credentials, returning the authenticated username
+    async fn verify(&self, credentials: &Credentials) -> ApiResult<Option<String>>;
+
+    /// Look up a user previously authenticated by this backend
+    async fn get_user(&self, username: &str) -> Option<BackendUser>;
+
+    /// Whether logins can currently be served, for readiness checks
+    async fn check_ready(&self) -> ApiResult<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuthBackend for AuthStore {
+    fn login_method(&self) -> LoginMethod {
+        LoginMethod::Password
+    }
+
+    async fn verify(&self, credentials: &Credentials) -> ApiResult<Option<String>> {
+        match credentials {
+            Credentials::Password { username, password } => {
+                Ok(AuthStore::verify(self, username, password).then(|| username.clone()))
+            }
+            Credentials::Token(_) => Ok(None),
+        }
+    }
+
+    async fn get_user(&self, username: &str) -> Option<BackendUser> {
+        AuthStore::get_user(self, username).map(|u| BackendUser {
+            username: u.username,
+            role: u.role,
+        })
+    }
+
+    async fn check_ready(&self) -> ApiResult<()> {
+        AuthStore::check_readable(self)
+    }
+}
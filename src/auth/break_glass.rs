@@ -0,0 +1,80 @@
+//! Emergency break-glass access
+//!
+//! When enabled, a random token is generated at startup and printed to the
+//! console. Presenting it once grants a short-lived admin session whose only
+//! permitted action is resetting a password.
+
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+
+/// A single-use, time-limited recovery token
+#[derive(Default)]
+pub struct BreakGlass {
+    /// Outstanding token and its expiry; `None` once used, expired or disabled
+    token: Mutex<Option<(String, Instant)>>,
+    ttl: Duration,
+}
+
+impl BreakGlass {
+    /// Generate a new token valid for `ttl`, returning it for display
+    pub fn issue(ttl: Duration) -> (Self, String) {
+        let token = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let store = Self {
+            token: Mutex::new(Some((token.clone(), Instant::now() + ttl))),
+            ttl,
+        };
+        (store, token)
+    }
+
+    /// Lifetime of the token and of the session it grants
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Consume the token if it matches and has not expired.
+    ///
+    /// An expired token is discarded on first use attempt.
+    pub fn redeem(&self, presented: &str) -> bool {
+        let mut slot = self.token.lock();
+        let Some((token, expires_at)) = slot.as_ref() else {
+            return false;
+        };
+        if Instant::now() >= *expires_at {
+            *slot = None;
+            return false;
+        }
+        if !constant_time_eq(token.as_bytes(), presented.as_bytes()) {
+            return false;
+        }
+        *slot = None;
+        true
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_is_single_use() {
+        let (break_glass, token) = BreakGlass::issue(Duration::from_secs(60));
+        assert!(!break_glass.redeem("wrong"));
+        assert!(break_glass.redeem(&token));
+        assert!(!break_glass.redeem(&token));
+    }
+
+    #[test]
+    fn test_expired_and_disabled_tokens_are_rejected() {
+        let (break_glass, token) = BreakGlass::issue(Duration::ZERO);
+        assert!(!break_glass.redeem(&token));
+        assert!(!BreakGlass::default().redeem(""));
+    }
+}
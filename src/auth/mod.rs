@@ -1,6 +1,14 @@
 //! Authentication module
 
+mod backend;
+mod break_glass;
+mod oidc;
 mod password;
+mod sessions;
 mod store;
 
+pub use backend::*;
+pub use break_glass::BreakGlass;
+pub use oidc::OidcBackend;
+pub use sessions::{ClientInfo, SessionInfo, SessionRegistry};
 pub use store::*;
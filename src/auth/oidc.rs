@@ -0,0 +1,308 @@
+//! OpenID Connect backend
+//!
+//! Validates ID/access tokens issued by an external provider against the
+//! issuer's JWKS and maps claims to a Studio user and role.
+
+use super::backend::{AuthBackend, BackendUser, Credentials, LoginMethod};
+use super::Role;
+use crate::config::OidcConfig;
+use crate::constants::auth::{
+    OIDC_JWKS_MIN_REFETCH_SECS, OIDC_JWKS_REFRESH_SECS, OIDC_MAX_KNOWN_USERS,
+};
+use crate::error::{ApiError, ApiResult};
+use async_trait::async_trait;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use lru::LruCache;
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+pub struct OidcBackend {
+    config: OidcConfig,
+    http: reqwest::Client,
+    jwks: tokio::sync::RwLock<Option<(Instant, JwkSet)>>,
+    /// Users recently seen in valid tokens, so roles can be looked up after
+    /// login. Bounded, since anyone the provider issues a token to can log in.
+    users: Mutex<LruCache<String, Role>>,
+}
+
+impl OidcBackend {
+    pub fn new(config: OidcConfig) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::new(),
+            jwks: tokio::sync::RwLock::new(None),
+            users: Mutex::new(LruCache::new(
+                NonZeroUsize::new(OIDC_MAX_KNOWN_USERS).unwrap_or(NonZeroUsize::MIN),
+            )),
+        }
+    }
+
+    async fn jwks_url(&self) -> ApiResult<String> {
+        if let Some(url) = &self.config.jwks_url {
+            return Ok(url.clone());
+        }
+        let discovery = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer.trim_end_matches('/')
+        );
+        let document: Value = self.fetch_json(&discovery).await?;
+        document["jwks_uri"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| ApiError::internal("OIDC discovery document has no jwks_uri"))
+    }
+
+    async fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> ApiResult<T> {
+        let response = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| ApiError::internal(format!("Failed to fetch {}: {}", url, e)))?;
+        response
+            .json()
+            .await
+            .map_err(|e| ApiError::internal(format!("Invalid JSON from {}: {}", url, e)))
+    }
+
+    /// Decoding key for `kid`, refetching the JWKS when stale or the key is
+    /// unknown (at most once per `OIDC_JWKS_MIN_REFETCH_SECS`)
+    async fn decoding_key(&self, kid: Option<&str>) -> ApiResult<Option<DecodingKey>> {
+        if let Some(key) = cached_key(self.jwks.read().await.as_ref(), kid) {
+            return key;
+        }
+
+        let mut cached = self.jwks.write().await;
+        // Another request may have refetched while this one waited
+        if let Some(key) = cached_key(cached.as_ref(), kid) {
+            return key;
+        }
+        let set: JwkSet = self.fetch_json(&self.jwks_url().await?).await?;
+        let key = find_key(&set, kid).transpose();
+        *cached = Some((Instant::now(), set));
+        key
+    }
+}
+
+/// Answer from the cached JWKS, or `None` when it has to be refetched.
+///
+/// An unknown `kid` only causes a refetch once the last fetch is
+/// `OIDC_JWKS_MIN_REFETCH_SECS` old; until then it is rejected, so tokens
+/// with made-up key ids can't make every request call the provider.
+fn cached_key(
+    cached: Option<&(Instant, JwkSet)>,
+    kid: Option<&str>,
+) -> Option<ApiResult<Option<DecodingKey>>> {
+    let (fetched_at, set) = cached?;
+    let age = fetched_at.elapsed();
+    match find_key(set, kid) {
+        Some(key) if age < Duration::from_secs(OIDC_JWKS_REFRESH_SECS) => Some(key.map(Some)),
+        None if age < Duration::from_secs(OIDC_JWKS_MIN_REFETCH_SECS) => Some(Ok(None)),
+        _ => None,
+    }
+}
+
+fn find_key(set: &JwkSet, kid: Option<&str>) -> Option<ApiResult<DecodingKey>> {
+    let jwk = match kid {
+        Some(kid) => set.find(kid)?,
+        None => set.keys.first()?,
+    };
+    Some(
+        DecodingKey::from_jwk(jwk)
+            .map_err(|e| ApiError::internal(format!("Unusable JWKS key: {}", e))),
+    )
+}
+
+/// Username from the configured claim, falling back to `sub`
+fn username_from_claims(claims: &Value, username_claim: &str) -> Option<String> {
+    claims[username_claim]
+        .as_str()
+        .or_else(|| claims["sub"].as_str())
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+}
+
+/// Admin if any value of the roles claim (string or array) is an admin role
+fn role_from_claims(claims: &Value, roles_claim: &str, admin_roles: &[String]) -> Role {
+    let is_admin_role = |v: &Value| {
+        v.as_str()
+            .is_some_and(|r| admin_roles.iter().any(|a| a == r))
+    };
+    let is_admin = match &claims[roles_claim] {
+        Value::Array(values) => values.iter().any(is_admin_role),
+        value => is_admin_role(value),
+    };
+    if is_admin {
+        Role::Admin
+    } else {
+        Role::Viewer
+    }
+}
+
+#[async_trait]
+impl AuthBackend for OidcBackend {
+    fn login_method(&self) -> LoginMethod {
+        LoginMethod::Token
+    }
+
+    async fn verify(&self, credentials: &Credentials) -> ApiResult<Option<String>> {
+        let Credentials::Token(token) = credentials else {
+            return Ok(None);
+        };
+
+        let Ok(header) = decode_header(token) else {
+            return Ok(None);
+        };
+        // Providers sign with published asymmetric keys; never accept HMAC
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            tracing::info!(alg = ?header.alg, "Rejected OIDC token with symmetric algorithm");
+            return Ok(None);
+        }
+        let Some(key) = self.decoding_key(header.kid.as_deref()).await? else {
+            tracing::info!(kid = ?header.kid, "OIDC token signed with unknown key");
+            return Ok(None);
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        match &self.config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let claims = match decode::<Value>(token, &key, &validation) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                tracing::info!(error = %e, "Rejected OIDC token");
+                return Ok(None);
+            }
+        };
+
+        let Some(username) = username_from_claims(&claims, &self.config.username_claim) else {
+            tracing::info!("OIDC token has no usable username claim");
+            return Ok(None);
+        };
+        let role = role_from_claims(&claims, &self.config.roles_claim, &self.config.admin_roles);
+        self.users.lock().put(username.clone(), role);
+
+        Ok(Some(username))
+    }
+
+    async fn get_user(&self, username: &str) -> Option<BackendUser> {
+        self.users.lock().get(username).map(|role| BackendUser {
+            username: username.to_string(),
+            role: *role,
+        })
+    }
+
+    /// The JWKS can be fetched (or is cached and fresh)
+    async fn check_ready(&self) -> ApiResult<()> {
+        self.decoding_key(None).await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_unknown_kid_refetches_at_most_once_per_interval() {
+        let set: JwkSet = serde_json::from_value(json!({"keys": [
+            {"kty": "RSA", "kid": "k1", "alg": "RS256", "n": "sXch", "e": "AQAB"}
+        ]}))
+        .unwrap();
+        let fetched = |ago: u64| (Instant::now() - Duration::from_secs(ago), set.clone());
+
+        let fresh = fetched(0);
+        assert!(matches!(
+            cached_key(Some(&fresh), Some("k1")),
+            Some(Ok(Some(_)))
+        ));
+        // Unknown key right after a fetch: rejected without refetching
+        assert!(matches!(
+            cached_key(Some(&fresh), Some("k2")),
+            Some(Ok(None))
+        ));
+
+        let recent = fetched(OIDC_JWKS_MIN_REFETCH_SECS + 1);
+        assert!(matches!(
+            cached_key(Some(&recent), Some("k1")),
+            Some(Ok(Some(_)))
+        ));
+        assert!(cached_key(Some(&recent), Some("k2")).is_none());
+
+        let stale = fetched(OIDC_JWKS_REFRESH_SECS + 1);
+        assert!(cached_key(Some(&stale), Some("k1")).is_none());
+        assert!(cached_key(None, Some("k1")).is_none());
+    }
+
+    #[test]
+    fn test_username_claim_falls_back_to_sub() {
+        let claims = json!({"sub": "abc123", "preferred_username": "alice"});
+        assert_eq!(
+            username_from_claims(&claims, "preferred_username"),
+            Some("alice".to_string())
+        );
+        assert_eq!(
+            username_from_claims(&claims, "email"),
+            Some("abc123".to_string())
+        );
+        assert_eq!(username_from_claims(&json!({}), "email"), None);
+    }
+
+    #[test]
+    fn test_role_from_claims() {
+        let admin_roles = vec!["studio-admin".to_string()];
+        let array = json!({"groups": ["dev", "studio-admin"]});
+        let single = json!({"groups": "studio-admin"});
+        let other = json!({"groups": ["dev"]});
+
+        assert_eq!(
+            role_from_claims(&array, "groups", &admin_roles),
+            Role::Admin
+        );
+        assert_eq!(
+            role_from_claims(&single, "groups", &admin_roles),
+            Role::Admin
+        );
+        assert_eq!(
+            role_from_claims(&other, "groups", &admin_roles),
+            Role::Viewer
+        );
+        assert_eq!(
+            role_from_claims(&json!({}), "groups", &admin_roles),
+            Role::Viewer
+        );
+    }
+
+    #[tokio::test]
+    async fn test_known_users_are_bounded() {
+        let backend = OidcBackend::new(OidcConfig {
+            issuer: "https://idp.example".to_string(),
+            audience: None,
+            jwks_url: None,
+            username_claim: "preferred_username".to_string(),
+            roles_claim: "roles".to_string(),
+            admin_roles: vec!["admin".to_string()],
+        });
+        for n in 0..=OIDC_MAX_KNOWN_USERS {
+            backend
+                .users
+                .lock()
+                .put(format!("user-{}", n), Role::Viewer);
+        }
+
+        assert_eq!(backend.users.lock().len(), OIDC_MAX_KNOWN_USERS);
+        assert!(backend.get_user("user-0").await.is_none());
+        let latest = format!("user-{}", OIDC_MAX_KNOWN_USERS);
+        assert_eq!(backend.get_user(&latest).await.unwrap().role, Role::Viewer);
+    }
+}
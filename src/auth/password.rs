@@ -15,6 +15,12 @@ pub fn hash_password(password: &str) -> anyhow::Result<String> {
     Ok(password_hash.to_string())
 }
 
+/// Whether `hash` is a PHC-format hash (`$argon2id$...`) rather than, say, a
+/// plaintext password
+pub fn is_password_hash(hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok()
+}
+
 /// Verify a password against a hash
 pub fn verify_password(password: &str, hash: &str) -> bool {
     let parsed_hash = match PasswordHash::new(hash) {
@@ -37,5 +43,7 @@ mod tests {
 
         assert!(verify_password(password, &hash));
         assert!(!verify_password("wrong-password", &hash));
+        assert!(is_password_hash(&hash));
+        assert!(!is_password_hash(password));
     }
 }
@@ -0,0 +1,247 @@
+//! Active login sessions
+//!
+//! The session store can't be enumerated, so every login is also recorded
+//! here under its own id, with the client it came from. Users list and revoke
+//! their sessions through this registry; a session missing from it is
+//! treated as logged out on its next request. Like the session store it is
+//! in memory, so a restart ends every session.
+
+use crate::AppState;
+use axum::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::{header, request::Parts};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Where a login came from
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// The client's address: the peer, unless it is one of `trusted` proxies, in
+/// which case `X-Forwarded-For` is followed back from the right past every
+/// trusted hop. Hops a client added itself sit to the left of the first
+/// untrusted one and are never used.
+fn client_ip(peer: Option<IpAddr>, forwarded: Option<&str>, trusted: &[IpAddr]) -> Option<IpAddr> {
+    let peer = peer?;
+    if !trusted.contains(&peer) {
+        return Some(peer);
+    }
+    let mut client = peer;
+    for hop in forwarded.unwrap_or_default().rsplit(',') {
+        let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = ip;
+        if !trusted.contains(&ip) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let header = |name| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let forwarded = header(header::HeaderName::from_static("x-forwarded-for"));
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        Ok(Self {
+            ip: client_ip(
+                peer,
+                forwarded.as_deref(),
+                &state.config.server.trusted_proxies,
+            )
+            .map(|ip| ip.to_string()),
+            user_agent: header(header::USER_AGENT),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub username: String,
+    /// Unix timestamps
+    pub created_at: i64,
+    pub last_activity: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+/// Login sessions by id
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: Mutex<HashMap<String, SessionInfo>>,
+}
+
+impl SessionRegistry {
+    /// Record a new session, returning its id
+    pub fn register(&self, username: &str, client: ClientInfo, now: i64) -> String {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        self.sessions.lock().insert(
+            id.clone(),
+            SessionInfo {
+                id: id.clone(),
+                username: username.to_string(),
+                created_at: now,
+                last_activity: now,
+                ip: client.ip,
+                user_agent: client.user_agent,
+            },
+        );
+        id
+    }
+
+    /// Mark a session active; false if it was revoked
+    pub fn touch(&self, id: &str, now: i64) -> bool {
+        match self.sessions.lock().get_mut(id) {
+            Some(info) => {
+                info.last_activity = now;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<SessionInfo> {
+        self.sessions.lock().get(id).cloned()
+    }
+
+    /// Sessions of `username`, most recently active first
+    pub fn list(&self, username: &str) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .sessions
+            .lock()
+            .values()
+            .filter(|s| s.username == username)
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_activity));
+        sessions
+    }
+
+    /// Remove a session; false if it didn't exist
+    pub fn revoke(&self, id: &str) -> bool {
+        self.sessions.lock().remove(id).is_some()
+    }
+
+    /// Remove all of `username`'s sessions except `keep`, returning how many
+    pub fn revoke_others(&self, username: &str, keep: &str) -> usize {
+        let mut sessions = self.sessions.lock();
+        let before = sessions.len();
+        sessions.retain(|id, s| s.username != username || id == keep);
+        before - sessions.len()
+    }
+
+    /// Forget sessions that have timed out without logging out
+    pub fn prune(&self, now: i64, idle: Duration, max_lifetime: Duration) {
+        self.sessions.lock().retain(|_, s| {
+            now - s.last_activity <= idle.as_secs() as i64
+                && now - s.created_at <= max_lifetime.as_secs() as i64
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_ip_trusts_forwarded_only_from_proxies() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+
+        // Direct clients can't pick their address
+        assert_eq!(
+            client_ip(Some(ip("203.0.113.9")), Some("1.2.3.4"), &proxies),
+            Some(ip("203.0.113.9"))
+        );
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), Some("1.2.3.4"), &[]),
+            Some(ip("10.0.0.1"))
+        );
+        // Through proxies, the first untrusted hop from the right is the client
+        assert_eq!(
+            client_ip(
+                Some(ip("10.0.0.1")),
+                Some("1.2.3.4, 198.51.100.7, 10.0.0.2"),
+                &proxies
+            ),
+            Some(ip("198.51.100.7"))
+        );
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), None, &proxies),
+            Some(ip("10.0.0.1"))
+        );
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), Some("garbage"), &proxies),
+            Some(ip("10.0.0.1"))
+        );
+        assert_eq!(client_ip(None, Some("1.2.3.4"), &proxies), None);
+    }
+
+    #[test]
+    fn test_register_list_and_revoke() {
+        let registry = SessionRegistry::default();
+        let laptop = registry.register(
+            "ada",
+            ClientInfo {
+                ip: Some("10.0.0.1".to_string()),
+                user_agent: Some("Firefox".to_string()),
+            },
+            100,
+        );
+        let phone = registry.register("ada", ClientInfo::default(), 200);
+        let other = registry.register("bob", ClientInfo::default(), 150);
+
+        let listed: Vec<_> = registry.list("ada").into_iter().map(|s| s.id).collect();
+        assert_eq!(listed, vec![phone.clone(), laptop.clone()]);
+        assert!(registry.touch(&laptop, 300));
+        assert_eq!(registry.list("ada")[0].id, laptop);
+
+        assert_eq!(registry.revoke_others("ada", &laptop), 1);
+        assert!(!registry.touch(&phone, 310));
+        assert!(registry.touch(&other, 310));
+        assert!(registry.revoke(&laptop));
+        assert!(!registry.revoke(&laptop));
+        assert!(registry.list("ada").is_empty());
+    }
+
+    #[test]
+    fn test_prune_drops_timed_out_sessions() {
+        let registry = SessionRegistry::default();
+        let idle = registry.register("ada", ClientInfo::default(), 0);
+        let old = registry.register("ada", ClientInfo::default(), 0);
+        let fresh = registry.register("ada", ClientInfo::default(), 900);
+        registry.touch(&old, 950);
+
+        registry.prune(1000, Duration::from_secs(500), Duration::from_secs(960));
+        assert!(registry.get(&idle).is_none());
+        assert!(registry.get(&old).is_none());
+        assert!(registry.get(&fresh).is_some());
+    }
+}
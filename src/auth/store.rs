@@ -1,51 +1,301 @@
 //! User authentication store
 
-use super::password::{hash_password, verify_password};
+use super::password::{hash_password, is_password_hash, verify_password};
+use crate::error::{ApiError, ApiResult};
+use crate::json_ext::ValueExt;
 use chrono::{DateTime, Utc};
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::path::PathBuf;
 
+/// User role
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Full access. Users created before roles existed were all admins.
+    #[default]
+    Admin,
+    /// Regular access without operator controls
+    Viewer,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub username: String,
     pub password_hash: String,
+    #[serde(default)]
+    pub role: Role,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+/// Current `auth.json` format.
+///
+/// - 0: no version marker; users have no `role`
+/// - 1: explicit `role` on every user
+const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
 struct AuthData {
+    /// Format of the file; absent in files written before versioning
+    #[serde(default)]
+    schema_version: u32,
+    /// Keyed by `User.username`, whatever the username matching mode
     users: HashMap<String, User>,
 }
 
+impl Default for AuthData {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            users: HashMap::new(),
+        }
+    }
+}
+
+/// Users moved between hosts by `rstmdb-studio users export`/`import`.
+/// Carries password hashes only, never plaintext.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserExport {
+    pub schema_version: u32,
+    pub users: Vec<User>,
+}
+
+impl UserExport {
+    /// Parse an export, rejecting anything that could corrupt `auth.json`
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let export: UserExport = serde_json::from_str(content)
+            .map_err(|e| anyhow::anyhow!("Invalid user export: {}", e))?;
+        if export.schema_version > SCHEMA_VERSION {
+            anyhow::bail!(
+                "User export has schema_version {}, this Studio supports up to {}",
+                export.schema_version,
+                SCHEMA_VERSION
+            );
+        }
+        let mut seen = std::collections::HashSet::new();
+        for user in &export.users {
+            if user.username.trim().is_empty() {
+                anyhow::bail!("User export contains a user with an empty username");
+            }
+            if !seen.insert(user.username.as_str()) {
+                anyhow::bail!("User export lists '{}' more than once", user.username);
+            }
+            if !is_password_hash(&user.password_hash) {
+                anyhow::bail!(
+                    "User '{}' has no valid password hash; exports never carry plaintext passwords",
+                    user.username
+                );
+            }
+        }
+        Ok(export)
+    }
+}
+
+/// What an import does with a user that already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum UserCollision {
+    /// Abort the import without writing anything
+    Fail,
+    /// Keep the existing user
+    Skip,
+    /// Replace the existing user
+    Overwrite,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct UserImportSummary {
+    pub created: Vec<String>,
+    pub overwritten: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// Upgrade a raw `auth.json` document to `SCHEMA_VERSION`, one step at a time
+fn migrate(raw: &mut Value) {
+    let from = raw.u64_or("schema_version", 0) as u32;
+    if from < 1 {
+        // Users created before roles existed were all admins
+        if let Some(users) = raw.get_mut("users").and_then(Value::as_object_mut) {
+            for user in users.values_mut().filter_map(Value::as_object_mut) {
+                user.entry("role").or_insert_with(|| json!(Role::Admin));
+            }
+        }
+    }
+    if let Some(obj) = raw.as_object_mut() {
+        obj.insert("schema_version".to_string(), json!(SCHEMA_VERSION));
+    }
+}
+
 /// Authentication store backed by a JSON file
 pub struct AuthStore {
     path: PathBuf,
     data: RwLock<AuthData>,
+    case_insensitive: bool,
 }
 
 impl AuthStore {
     pub fn new(path: &PathBuf) -> Self {
+        let mut migrated_from = None;
         let data = if path.exists() {
             let content = std::fs::read_to_string(path).unwrap_or_default();
-            serde_json::from_str(&content).unwrap_or_default()
+            let mut raw: Value = serde_json::from_str(&content).unwrap_or_default();
+            let version = raw.u64_or("schema_version", 0) as u32;
+            if version < SCHEMA_VERSION && raw.is_object() {
+                migrate(&mut raw);
+                migrated_from = Some(version);
+            } else if version > SCHEMA_VERSION {
+                tracing::warn!(
+                    path = %path.display(),
+                    version,
+                    supported = SCHEMA_VERSION,
+                    "auth.json was written by a newer Studio; unknown fields will be dropped on save"
+                );
+            }
+            let mut data: AuthData = serde_json::from_value(raw).unwrap_or_default();
+            // Older case-insensitive stores keyed users by lowercased name
+            data.users = data
+                .users
+                .into_values()
+                .map(|user| (user.username.clone(), user))
+                .collect();
+            data
         } else {
             AuthData::default()
         };
 
-        Self {
+        let store = Self {
             path: path.clone(),
             data: RwLock::new(data),
+            case_insensitive: false,
+        };
+
+        if let Some(from) = migrated_from {
+            match store.save() {
+                Ok(()) => tracing::info!(
+                    path = %path.display(),
+                    from,
+                    to = SCHEMA_VERSION,
+                    "Migrated auth.json"
+                ),
+                Err(e) => tracing::warn!(
+                    path = %path.display(),
+                    error = %e,
+                    "Migrated auth.json in memory but could not write it back"
+                ),
+            }
+        }
+        store
+    }
+
+    /// Match usernames case-insensitively.
+    ///
+    /// Case is folded only when looking users up; auth.json stays keyed by
+    /// each user's own username, so the mode can be turned off again. Fails
+    /// if stored users differ only by case, since a login could not tell
+    /// them apart.
+    pub fn with_case_insensitive_usernames(mut self, enabled: bool) -> anyhow::Result<Self> {
+        if !enabled {
+            return Ok(self);
+        }
+        let data = self.data.get_mut();
+        let mut by_key: HashMap<String, Vec<&str>> = HashMap::new();
+        for user in data.users.values() {
+            by_key
+                .entry(user.username.to_lowercase())
+                .or_default()
+                .push(&user.username);
+        }
+        let mut collisions: Vec<String> = by_key
+            .into_values()
+            .filter(|names| names.len() > 1)
+            .map(|mut names| {
+                names.sort();
+                names.join(" / ")
+            })
+            .collect();
+        if !collisions.is_empty() {
+            collisions.sort();
+            anyhow::bail!(
+                "auth.case_insensitive_usernames is on, but {} has users that differ only by case: {}. Rename or remove all but one of each before enabling it.",
+                self.path.display(),
+                collisions.join(", ")
+            );
+        }
+
+        self.case_insensitive = true;
+        Ok(self)
+    }
+
+    /// Form of a username that users are matched by
+    fn fold(&self, username: &str) -> String {
+        if self.case_insensitive {
+            username.to_lowercase()
+        } else {
+            username.to_string()
         }
     }
 
+    /// Key of the stored user that `username` refers to
+    fn find_key(&self, users: &HashMap<String, User>, username: &str) -> Option<String> {
+        if users.contains_key(username) {
+            return Some(username.to_string());
+        }
+        if !self.case_insensitive {
+            return None;
+        }
+        let folded = self.fold(username);
+        users.keys().find(|key| self.fold(key) == folded).cloned()
+    }
+
+    /// The backing file, if it exists, can still be read
+    pub fn check_readable(&self) -> ApiResult<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+        std::fs::File::open(&self.path)
+            .map(|_| ())
+            .map_err(|e| ApiError::internal(format!("Cannot read {}: {}", self.path.display(), e)))
+    }
+
     /// Check if any users exist
     pub fn has_users(&self) -> bool {
         !self.data.read().users.is_empty()
     }
 
+    pub fn user_count(&self) -> usize {
+        self.data.read().users.len()
+    }
+
+    /// Create the first admin user; returns false, creating nothing, if any
+    /// user already exists
+    pub fn create_first_user(&self, username: &str, password: &str) -> anyhow::Result<bool> {
+        let password_hash = hash_password(password)?;
+        let now = Utc::now();
+
+        {
+            // Checked under the write lock so concurrent setups can't both win
+            let mut data = self.data.write();
+            if !data.users.is_empty() {
+                return Ok(false);
+            }
+            data.users.insert(
+                username.to_string(),
+                User {
+                    username: username.to_string(),
+                    password_hash,
+                    role: Role::Admin,
+                    created_at: now,
+                    updated_at: now,
+                },
+            );
+        }
+
+        self.save()?;
+        Ok(true)
+    }
+
     /// Create a new user
     pub fn create_user(&self, username: &str, password: &str) -> anyhow::Result<()> {
         let password_hash = hash_password(password)?;
@@ -54,29 +304,140 @@ impl AuthStore {
         let user = User {
             username: username.to_string(),
             password_hash,
+            role: Role::Admin,
             created_at: now,
             updated_at: now,
         };
 
         {
             let mut data = self.data.write();
-            data.users.insert(username.to_string(), user);
+            if let Some(existing) = self.find_key(&data.users, username) {
+                data.users.remove(&existing);
+            }
+            data.users.insert(user.username.clone(), user);
         }
 
         self.save()?;
         Ok(())
     }
 
+    /// Replace a user's password, creating an admin user if none exists
+    pub fn set_password(&self, username: &str, password: &str) -> anyhow::Result<()> {
+        let password_hash = hash_password(password)?;
+        let now = Utc::now();
+
+        {
+            let mut data = self.data.write();
+            match self
+                .find_key(&data.users, username)
+                .and_then(|key| data.users.get_mut(&key))
+            {
+                Some(user) => {
+                    user.password_hash = password_hash;
+                    user.updated_at = now;
+                }
+                None => {
+                    data.users.insert(
+                        username.to_string(),
+                        User {
+                            username: username.to_string(),
+                            password_hash,
+                            role: Role::Admin,
+                            created_at: now,
+                            updated_at: now,
+                        },
+                    );
+                }
+            }
+        }
+
+        self.save()
+    }
+
     /// Verify user credentials
     pub fn verify(&self, username: &str, password: &str) -> bool {
         let data = self.data.read();
-        if let Some(user) = data.users.get(username) {
+        if let Some(user) = self
+            .find_key(&data.users, username)
+            .and_then(|key| data.users.get(&key))
+        {
             verify_password(password, &user.password_hash)
         } else {
             false
         }
     }
 
+    /// Look up a user by username
+    pub fn get_user(&self, username: &str) -> Option<User> {
+        let data = self.data.read();
+        let key = self.find_key(&data.users, username)?;
+        data.users.get(&key).cloned()
+    }
+
+    /// Every user, sorted by username, for `users export`
+    pub fn export_users(&self) -> UserExport {
+        let mut users: Vec<User> = self.data.read().users.values().cloned().collect();
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+        UserExport {
+            schema_version: SCHEMA_VERSION,
+            users,
+        }
+    }
+
+    /// Merge exported users in, resolving existing usernames with `on_collision`.
+    /// Fails without writing if two exported users are the same user
+    /// (e.g. `Ada` and `ada` with case-insensitive usernames).
+    pub fn import_users(
+        &self,
+        export: UserExport,
+        on_collision: UserCollision,
+    ) -> anyhow::Result<UserImportSummary> {
+        let mut keys: HashMap<String, &str> = HashMap::new();
+        for user in &export.users {
+            if let Some(first) = keys.insert(self.fold(&user.username), &user.username) {
+                anyhow::bail!(
+                    "User export lists '{}' and '{}', which are the same user with case-insensitive usernames",
+                    first,
+                    user.username
+                );
+            }
+        }
+
+        let mut summary = UserImportSummary::default();
+        {
+            let mut data = self.data.write();
+            if on_collision == UserCollision::Fail {
+                let existing: Vec<&str> = export
+                    .users
+                    .iter()
+                    .filter(|u| self.find_key(&data.users, &u.username).is_some())
+                    .map(|u| u.username.as_str())
+                    .collect();
+                if !existing.is_empty() {
+                    anyhow::bail!("Users already exist: {}", existing.join(", "));
+                }
+            }
+            for user in export.users {
+                let username = user.username.clone();
+                match self.find_key(&data.users, &username) {
+                    None => summary.created.push(username.clone()),
+                    Some(existing) if on_collision == UserCollision::Overwrite => {
+                        data.users.remove(&existing);
+                        summary.overwritten.push(username.clone());
+                    }
+                    Some(_) => {
+                        summary.skipped.push(username);
+                        continue;
+                    }
+                }
+                data.users.insert(username, user);
+            }
+        }
+
+        self.save()?;
+        Ok(summary)
+    }
+
     /// Save to file
     fn save(&self) -> anyhow::Result<()> {
         let data = self.data.read();
@@ -91,3 +452,285 @@ impl AuthStore {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_auth_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("rstmdb-studio-test-{}", uuid::Uuid::new_v4()))
+            .join("auth.json")
+    }
+
+    #[test]
+    fn test_migrates_unversioned_file() {
+        let path = temp_auth_path();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let v0 = json!({
+            "users": {
+                "admin": {
+                    "username": "admin",
+                    "password_hash": hash_password("secret").unwrap(),
+                    "created_at": "2024-01-01T00:00:00Z",
+                    "updated_at": "2024-01-01T00:00:00Z"
+                }
+            }
+        });
+        std::fs::write(&path, v0.to_string()).unwrap();
+
+        let store = AuthStore::new(&path);
+        assert!(store.verify("admin", "secret"));
+        assert_eq!(store.get_user("admin").unwrap().role, Role::Admin);
+
+        let written: Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["schema_version"], SCHEMA_VERSION);
+        assert_eq!(written["users"]["admin"]["role"], "admin");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_case_sensitive_by_default() {
+        let path = temp_auth_path();
+        let store = AuthStore::new(&path);
+        store.create_user("Admin", "secret").unwrap();
+
+        assert!(store.verify("Admin", "secret"));
+        assert!(!store.verify("admin", "secret"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_case_insensitive_usernames() {
+        let path = temp_auth_path();
+        let store = AuthStore::new(&path)
+            .with_case_insensitive_usernames(true)
+            .unwrap();
+        store.create_user("Admin", "secret").unwrap();
+
+        assert!(store.verify("admin", "secret"));
+        assert!(store.verify("ADMIN", "secret"));
+        assert!(!store.verify("admin", "wrong"));
+
+        // Display casing is preserved
+        assert_eq!(store.get_user("admin").unwrap().username, "Admin");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_create_first_user_only_once() {
+        let path = temp_auth_path();
+        let store = AuthStore::new(&path);
+        assert_eq!(store.user_count(), 0);
+
+        assert!(store.create_first_user("admin", "secret").unwrap());
+        assert!(!store.create_first_user("intruder", "pw").unwrap());
+        assert_eq!(store.user_count(), 1);
+        assert!(store.get_user("intruder").is_none());
+        assert_eq!(store.get_user("admin").unwrap().role, Role::Admin);
+        assert!(AuthStore::new(&path).verify("admin", "secret"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_set_password_keeps_role() {
+        let path = temp_auth_path();
+        let store = AuthStore::new(&path);
+        store.create_user("admin", "old").unwrap();
+        store.data.write().users.get_mut("admin").unwrap().role = Role::Viewer;
+
+        store.set_password("admin", "new").unwrap();
+        assert!(store.verify("admin", "new"));
+        assert!(!store.verify("admin", "old"));
+        assert_eq!(store.get_user("admin").unwrap().role, Role::Viewer);
+
+        store.set_password("rescue", "pw").unwrap();
+        assert_eq!(store.get_user("rescue").unwrap().role, Role::Admin);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_user_export_round_trip() {
+        let source = AuthStore::new(&temp_auth_path());
+        source.create_user("ada", "secret").unwrap();
+        source.create_user("bob", "hunter2").unwrap();
+        let exported = serde_json::to_string(&source.export_users()).unwrap();
+        assert!(!exported.contains("secret"));
+
+        let path = temp_auth_path();
+        let target = AuthStore::new(&path);
+        target.create_user("bob", "other").unwrap();
+
+        let err = target
+            .import_users(UserExport::parse(&exported).unwrap(), UserCollision::Fail)
+            .unwrap_err();
+        assert!(err.to_string().contains("bob"));
+        assert!(target.get_user("ada").is_none());
+
+        let summary = target
+            .import_users(UserExport::parse(&exported).unwrap(), UserCollision::Skip)
+            .unwrap();
+        assert_eq!(summary.created, vec!["ada"]);
+        assert_eq!(summary.skipped, vec!["bob"]);
+        assert!(target.verify("bob", "other"));
+
+        let summary = target
+            .import_users(
+                UserExport::parse(&exported).unwrap(),
+                UserCollision::Overwrite,
+            )
+            .unwrap();
+        assert_eq!(summary.overwritten, vec!["ada", "bob"]);
+        let reloaded = AuthStore::new(&path);
+        assert!(reloaded.verify("ada", "secret"));
+        assert!(reloaded.verify("bob", "hunter2"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_user_export_rejects_plaintext_and_duplicates() {
+        let user = |name: &str, hash: &str| {
+            json!({
+                "username": name,
+                "password_hash": hash,
+                "created_at": "2024-01-01T00:00:00Z",
+                "updated_at": "2024-01-01T00:00:00Z"
+            })
+        };
+        let hash = hash_password("secret").unwrap();
+        let parse = |users: Value| {
+            UserExport::parse(&json!({"schema_version": 1, "users": users}).to_string())
+        };
+
+        assert!(parse(json!([user("ada", &hash)])).is_ok());
+        let err = parse(json!([user("ada", "secret")])).unwrap_err();
+        assert!(err.to_string().contains("no valid password hash"));
+        let err = parse(json!([user("ada", &hash), user("ada", &hash)])).unwrap_err();
+        assert!(err.to_string().contains("more than once"));
+        assert!(parse(json!([user(" ", &hash)])).is_err());
+        assert!(UserExport::parse(r#"{"schema_version": 99, "users": []}"#).is_err());
+        assert!(UserExport::parse(r#"{"users": {}}"#).is_err());
+    }
+
+    #[test]
+    fn test_user_import_rejects_users_sharing_a_key() {
+        let source = AuthStore::new(&temp_auth_path());
+        source.create_user("Ada", "one").unwrap();
+        source.create_user("ada", "two").unwrap();
+        let exported = serde_json::to_string(&source.export_users()).unwrap();
+
+        let path = temp_auth_path();
+        let target = AuthStore::new(&path)
+            .with_case_insensitive_usernames(true)
+            .unwrap();
+        let err = target
+            .import_users(
+                UserExport::parse(&exported).unwrap(),
+                UserCollision::Overwrite,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("'Ada' and 'ada'"));
+        assert!(target.get_user("ada").is_none());
+
+        // Distinct users where usernames are case-sensitive
+        let target = AuthStore::new(&temp_auth_path());
+        let summary = target
+            .import_users(UserExport::parse(&exported).unwrap(), UserCollision::Fail)
+            .unwrap();
+        assert_eq!(summary.created, vec!["Ada", "ada"]);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_case_insensitive_rekeys_existing_users() {
+        let path = temp_auth_path();
+        AuthStore::new(&path)
+            .create_user("Admin", "secret")
+            .unwrap();
+
+        let store = AuthStore::new(&path)
+            .with_case_insensitive_usernames(true)
+            .unwrap();
+        assert!(store.verify("admin", "secret"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_case_insensitive_refuses_colliding_users() {
+        let path = temp_auth_path();
+        let store = AuthStore::new(&path);
+        store.create_user("Admin", "one").unwrap();
+        store.create_user("admin", "two").unwrap();
+        store.create_user("bob", "three").unwrap();
+
+        let err = AuthStore::new(&path)
+            .with_case_insensitive_usernames(true)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Admin / admin"));
+        assert!(!err.to_string().contains("bob"));
+
+        // Nothing was dropped from auth.json
+        let store = AuthStore::new(&path);
+        assert_eq!(store.user_count(), 3);
+        assert!(store.verify("Admin", "one"));
+        assert!(store.verify("admin", "two"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_case_insensitive_mode_round_trips() {
+        let path = temp_auth_path();
+        let written =
+            || -> Value { serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap() };
+        let store = AuthStore::new(&path)
+            .with_case_insensitive_usernames(true)
+            .unwrap();
+        store.create_user("Admin", "secret").unwrap();
+        store.set_password("ADMIN", "changed").unwrap();
+        assert_eq!(written()["users"]["Admin"]["username"], "Admin");
+        assert!(written()["users"].get("admin").is_none());
+
+        // Turning the mode off finds the user by its own name only
+        let store = AuthStore::new(&path);
+        assert!(store.verify("Admin", "changed"));
+        assert!(!store.verify("admin", "changed"));
+        store.create_user("bob", "pw").unwrap();
+
+        let store = AuthStore::new(&path)
+            .with_case_insensitive_usernames(true)
+            .unwrap();
+        assert!(store.verify("admin", "changed"));
+        assert!(store.verify("BOB", "pw"));
+        store.create_user("BOB", "new").unwrap();
+        assert_eq!(store.user_count(), 2);
+        assert_eq!(store.get_user("bob").unwrap().username, "BOB");
+        assert!(written()["users"].get("bob").is_none());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_lowercased_keys_from_older_files_are_rekeyed() {
+        let path = temp_auth_path();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = json!({
+            "schema_version": SCHEMA_VERSION,
+            "users": {
+                "admin": {
+                    "username": "Admin",
+                    "password_hash": hash_password("secret").unwrap(),
+                    "role": "admin",
+                    "created_at": "2024-01-01T00:00:00Z",
+                    "updated_at": "2024-01-01T00:00:00Z"
+                }
+            }
+        });
+        std::fs::write(&path, file.to_string()).unwrap();
+
+        let store = AuthStore::new(&path);
+        assert!(store.verify("Admin", "secret"));
+        assert!(!store.verify("admin", "secret"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
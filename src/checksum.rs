@@ -0,0 +1,167 @@
+//! Definition checksums
+//!
+//! A definition's checksum is computed the way rstmdb computes the checksum
+//! it stores for a version: the lowercase hex SHA-256 of the definition's
+//! canonical JSON (object keys sorted, no whitespace), with
+//! `meta._builderPositions` left out since moving nodes in the builder does
+//! not change the machine. Studio's checksums can therefore be compared
+//! with rstmdb's, e.g. a normalized definition's checksum with a version's
+//! `ETag`.
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Builder layout key excluded from checksums
+const BUILDER_POSITIONS: &str = "_builderPositions";
+
+/// Lowercase hex SHA-256 of the canonical form of `definition`, as rstmdb
+/// stores it
+pub fn definition_checksum(definition: &Value) -> String {
+    sha256(canonical_json(definition).as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The canonical JSON text that [`definition_checksum`] hashes
+pub fn canonical_json(definition: &Value) -> String {
+    let mut canonical = String::new();
+    write_canonical(definition, true, &mut canonical);
+    canonical
+}
+
+/// Canonical JSON of any value, with nothing left out
+pub fn canonical_value_json(value: &Value) -> String {
+    let mut canonical = String::new();
+    write_canonical(value, false, &mut canonical);
+    canonical
+}
+
+/// [`canonical_json`] pretty-printed, one value per line, for line diffs
+pub fn canonical_pretty_json(definition: &Value) -> String {
+    let mut definition = definition.clone();
+    strip_builder_positions(&mut definition);
+    // serde_json maps keep keys sorted, as in the compact form
+    serde_json::to_string_pretty(&definition).unwrap_or_default()
+}
+
+/// Remove `meta._builderPositions` from `definition`
+pub fn strip_builder_positions(definition: &mut Value) {
+    if let Some(meta) = definition.get_mut("meta").and_then(Value::as_object_mut) {
+        meta.remove(BUILDER_POSITIONS);
+    }
+}
+
+fn write_canonical(value: &Value, top_level: bool, out: &mut String) {
+    match value {
+        Value::Object(obj) => {
+            let mut keys: Vec<&String> = obj.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                let value = &obj[key];
+                if top_level && key == "meta" {
+                    let mut meta = value.clone();
+                    if let Some(meta) = meta.as_object_mut() {
+                        meta.remove(BUILDER_POSITIONS);
+                    }
+                    write_canonical(&meta, false, out);
+                } else {
+                    write_canonical(value, false, out);
+                }
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, false, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// SHA-256 of `data`
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn hex(data: &[u8]) -> String {
+        sha256(data).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_sha256_known_vectors() {
+        assert_eq!(
+            hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // Two-block message
+        assert_eq!(
+            hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_definition_checksum_is_canonical() {
+        let a = json!({
+            "states": ["a", "b"],
+            "initial": "a",
+            "transitions": [{"from": "a", "event": "GO", "to": "b"}],
+            "meta": {"owner": "ops", "_builderPositions": {"a": {"x": 1, "y": 2}}}
+        });
+        let b = json!({
+            "meta": {"_builderPositions": {"a": {"x": 9, "y": 9}}, "owner": "ops"},
+            "transitions": [{"to": "b", "event": "GO", "from": "a"}],
+            "initial": "a",
+            "states": ["a", "b"]
+        });
+        assert_eq!(definition_checksum(&a), definition_checksum(&b));
+
+        assert_eq!(
+            canonical_json(&a),
+            r#"{"initial":"a","meta":{"owner":"ops"},"states":["a","b"],"transitions":[{"event":"GO","from":"a","to":"b"}]}"#
+        );
+        assert_eq!(
+            definition_checksum(&a),
+            "b0ed0b4c000d5f1f39848613221a678f49f6989be1c16808631c555b7a6da16c"
+        );
+
+        let mut c = a.clone();
+        c["states"] = json!(["b", "a"]);
+        assert_ne!(definition_checksum(&a), definition_checksum(&c));
+
+        assert_eq!(
+            canonical_pretty_json(&b),
+            serde_json::to_string_pretty(
+                &serde_json::from_str::<Value>(&canonical_json(&a)).unwrap()
+            )
+            .unwrap()
+        );
+
+        let mut stripped = a.clone();
+        strip_builder_positions(&mut stripped);
+        assert_eq!(stripped["meta"], json!({"owner": "ops"}));
+        assert_eq!(definition_checksum(&stripped), definition_checksum(&a));
+    }
+}
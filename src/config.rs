@@ -1,19 +1,522 @@
 //! Configuration management
 
+use crate::auth::Role;
 use crate::constants;
+use crate::validation::{ReservedNames, Severity};
 use figment::{
     providers::{Env, Format, Serialized, Yaml},
     Figment,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub server: ServerConfig,
     pub rstmdb: RstmdbConfig,
     pub auth: AuthConfig,
+    #[serde(default)]
+    pub validation: ValidationConfig,
+    #[serde(default)]
+    pub import: ImportConfig,
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+    #[serde(default)]
+    pub readiness: ReadinessConfig,
+    #[serde(default)]
+    pub wal_history: WalHistoryConfig,
+    #[serde(default)]
+    pub machine_counts: MachineCountsConfig,
+    #[serde(default)]
+    pub instance_ttl: InstanceTtlConfig,
+    #[serde(default)]
+    pub ui: UiConfig,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub features: FeaturesConfig,
+    /// Per-machine restrictions on the events API callers may apply
+    #[serde(default)]
+    pub event_policies: HashMap<String, EventPolicy>,
     pub data_dir: String,
+    /// Log filter (e.g. "info", "rstmdb_studio=debug"); `RUST_LOG` takes precedence
+    #[serde(default)]
+    pub log_level: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidationConfig {
+    /// Always report unknown definition fields, not just with `?strict=true`
+    #[serde(default)]
+    pub strict: bool,
+    /// Whether unknown fields are reported as warnings or errors
+    #[serde(default)]
+    pub unknown_field_severity: Severity,
+    /// Whether `?check_instances=true` reports occupied removed states as
+    /// warnings or rejects the new version
+    #[serde(default)]
+    pub state_in_use_severity: Severity,
+    /// Always search for overlapping guards, not just with
+    /// `?check_guard_overlap=true`
+    #[serde(default)]
+    pub check_guard_overlap: bool,
+    /// Store a new version when only state or transition descriptions changed
+    #[serde(default)]
+    pub description_changes_create_version: bool,
+    /// State and event names rejected with `RESERVED_NAME`
+    #[serde(default)]
+    pub reserved_names: ReservedNames,
+}
+
+/// Context values hidden from responses requested with `?redact=true`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RedactionConfig {
+    /// JSON pointers into instance contexts, e.g. `/customer/email`
+    #[serde(default)]
+    pub paths: Vec<String>,
+}
+
+/// A dependency checked by `/readyz`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadinessDependency {
+    /// rstmdb answers a ping
+    Rstmdb,
+    /// `data_dir` accepts writes
+    DataDir,
+    /// The auth backend can serve logins (auth.json readable, OIDC JWKS reachable)
+    AuthBackend,
+    /// No critical background task (keepalive, TTL sweeper) has been down for
+    /// longer than `max_task_downtime`
+    BackgroundTasks,
+}
+
+impl ReadinessDependency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadinessDependency::Rstmdb => "rstmdb",
+            ReadinessDependency::DataDir => "data_dir",
+            ReadinessDependency::AuthBackend => "auth_backend",
+            ReadinessDependency::BackgroundTasks => "background_tasks",
+        }
+    }
+}
+
+/// Dependencies that must be healthy for Studio to report ready
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadinessConfig {
+    #[serde(default = "default_readiness_dependencies")]
+    pub dependencies: Vec<ReadinessDependency>,
+    /// A check taking longer than this counts as failed
+    #[serde(default = "default_readiness_timeout")]
+    pub timeout: String,
+    /// `background_tasks` fails once a critical task has been down this long
+    #[serde(default = "default_max_task_downtime")]
+    pub max_task_downtime: String,
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        Self {
+            dependencies: default_readiness_dependencies(),
+            timeout: default_readiness_timeout(),
+            max_task_downtime: default_max_task_downtime(),
+        }
+    }
+}
+
+impl ReadinessConfig {
+    /// Parse the humantime task downtime limit
+    pub fn max_task_downtime(&self) -> anyhow::Result<Duration> {
+        humantime::parse_duration(&self.max_task_downtime).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid readiness.max_task_downtime '{}': {}",
+                self.max_task_downtime,
+                e
+            )
+        })
+    }
+}
+
+fn default_readiness_dependencies() -> Vec<ReadinessDependency> {
+    vec![
+        ReadinessDependency::Rstmdb,
+        ReadinessDependency::BackgroundTasks,
+    ]
+}
+
+fn default_readiness_timeout() -> String {
+    constants::readiness::DEFAULT_TIMEOUT.to_string()
+}
+
+fn default_max_task_downtime() -> String {
+    constants::readiness::DEFAULT_MAX_TASK_DOWNTIME.to_string()
+}
+
+/// Events Studio lets callers apply to one machine's instances, on top of
+/// the machine's own transitions. Machines without a policy allow every event.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventPolicy {
+    /// When set, only these events may be applied
+    #[serde(default)]
+    pub allow: Option<Vec<String>>,
+    /// Events that may never be applied through Studio
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Events only users with the admin role may apply
+    #[serde(default)]
+    pub admin_only: Vec<String>,
+}
+
+/// Endpoint groups that can be switched off to reduce attack surface.
+/// Routes of a disabled group are not registered and answer `404`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FeaturesConfig {
+    /// WAL browsing: `/wal`, `/wal/stats`, `/wal/stats/history`, `/wal/:offset`
+    #[serde(default = "default_feature_enabled")]
+    pub wal: bool,
+    /// Instance audit packages: `/instances/:id/package`
+    #[serde(default = "default_feature_enabled")]
+    pub export: bool,
+    /// Event dry runs: `/instances/:id/events/check`
+    #[serde(default = "default_feature_enabled")]
+    pub simulation: bool,
+    /// Account setup and recovery: `/auth/setup`, `/auth/break-glass`,
+    /// `/auth/reset-password`
+    #[serde(default = "default_feature_enabled")]
+    pub user_management: bool,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            wal: true,
+            export: true,
+            simulation: true,
+            user_management: true,
+        }
+    }
+}
+
+fn default_feature_enabled() -> bool {
+    true
+}
+
+/// Web UI landing preferences, returned by `GET /api/v1/server/info`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UiConfig {
+    /// Machine the UI opens on; takes precedence over `default_view`
+    #[serde(default)]
+    pub default_machine: Option<String>,
+    /// Page the UI opens on instead of the dashboard
+    #[serde(default)]
+    pub default_view: Option<UiView>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UiView {
+    Machines,
+    Instances,
+    Wal,
+}
+
+/// Background sampling of WAL size for `GET /api/v1/wal/stats/history`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalHistoryConfig {
+    #[serde(default = "default_wal_history_enabled")]
+    pub enabled: bool,
+    /// Time between samples, e.g. "1m"
+    #[serde(default = "default_wal_history_interval")]
+    pub interval: String,
+    /// How far back samples are kept, e.g. "24h"
+    #[serde(default = "default_wal_history_retention")]
+    pub retention: String,
+}
+
+impl Default for WalHistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_wal_history_enabled(),
+            interval: default_wal_history_interval(),
+            retention: default_wal_history_retention(),
+        }
+    }
+}
+
+impl WalHistoryConfig {
+    /// Parse the humantime sample interval and retention window
+    pub fn durations(&self) -> anyhow::Result<(Duration, Duration)> {
+        let parse = |field: &str, value: &str| {
+            let duration = humantime::parse_duration(value)
+                .map_err(|e| anyhow::anyhow!("Invalid wal_history.{} '{}': {}", field, value, e))?;
+            if duration.is_zero() {
+                anyhow::bail!("wal_history.{} must be greater than zero", field);
+            }
+            Ok(duration)
+        };
+        Ok((
+            parse("interval", &self.interval)?,
+            parse("retention", &self.retention)?,
+        ))
+    }
+}
+
+fn default_wal_history_enabled() -> bool {
+    true
+}
+
+fn default_wal_history_interval() -> String {
+    constants::wal_history::DEFAULT_INTERVAL.to_string()
+}
+
+fn default_wal_history_retention() -> String {
+    constants::wal_history::DEFAULT_RETENTION.to_string()
+}
+
+/// Background refresh of the state and transition counts shown by
+/// `GET /api/v1/machines`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MachineCountsConfig {
+    #[serde(default = "default_machine_counts_enabled")]
+    pub enabled: bool,
+    /// Time between refreshes, e.g. "5m"
+    #[serde(default = "default_machine_counts_interval")]
+    pub interval: String,
+    /// Skip refreshing while fewer machines than this exist; their counts
+    /// are cheap enough to compute on request
+    #[serde(default = "default_machine_counts_min_machines")]
+    pub min_machines: usize,
+}
+
+impl Default for MachineCountsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_machine_counts_enabled(),
+            interval: default_machine_counts_interval(),
+            min_machines: default_machine_counts_min_machines(),
+        }
+    }
+}
+
+impl MachineCountsConfig {
+    /// Parse the humantime refresh interval
+    pub fn interval(&self) -> anyhow::Result<Duration> {
+        let interval = humantime::parse_duration(&self.interval).map_err(|e| {
+            anyhow::anyhow!("Invalid machine_counts.interval '{}': {}", self.interval, e)
+        })?;
+        if interval.is_zero() {
+            anyhow::bail!("machine_counts.interval must be greater than zero");
+        }
+        Ok(interval)
+    }
+}
+
+fn default_machine_counts_enabled() -> bool {
+    true
+}
+
+fn default_machine_counts_interval() -> String {
+    constants::machine_counts::DEFAULT_INTERVAL.to_string()
+}
+
+fn default_machine_counts_min_machines() -> usize {
+    constants::machine_counts::DEFAULT_MIN_MACHINES
+}
+
+/// Expiry of instances left unfinished.
+///
+/// rstmdb has no instance TTL, so a background sweep looks for instances of
+/// the listed machines that are older than their `ttl` and still in a
+/// non-terminal state, and either applies a timeout event or deletes them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceTtlConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Time between sweeps, e.g. "1m"
+    #[serde(default = "default_instance_ttl_interval")]
+    pub interval: String,
+    /// TTL per machine name
+    #[serde(default)]
+    pub machines: HashMap<String, MachineTtlConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MachineTtlConfig {
+    /// Age since creation after which an unfinished instance expires
+    pub ttl: String,
+    #[serde(default)]
+    pub action: InstanceTtlAction,
+    /// Event applied by the `event` action
+    #[serde(default)]
+    pub event: Option<String>,
+}
+
+/// What the sweep does with an expired instance
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstanceTtlAction {
+    /// Apply the machine's configured timeout event
+    #[default]
+    Event,
+    Delete,
+}
+
+/// A validated per-machine TTL
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstanceTtlRule {
+    pub ttl: Duration,
+    pub action: InstanceTtlAction,
+    /// Set for the `event` action
+    pub event: Option<String>,
+}
+
+impl Default for InstanceTtlConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: default_instance_ttl_interval(),
+            machines: HashMap::new(),
+        }
+    }
+}
+
+impl InstanceTtlConfig {
+    /// Parse the humantime sweep interval
+    pub fn interval(&self) -> anyhow::Result<Duration> {
+        let interval = humantime::parse_duration(&self.interval).map_err(|e| {
+            anyhow::anyhow!("Invalid instance_ttl.interval '{}': {}", self.interval, e)
+        })?;
+        if interval.is_zero() {
+            anyhow::bail!("instance_ttl.interval must be greater than zero");
+        }
+        Ok(interval)
+    }
+
+    /// Parse and check each machine's TTL
+    pub fn rules(&self) -> anyhow::Result<HashMap<String, InstanceTtlRule>> {
+        let mut rules = HashMap::new();
+        for (machine, config) in &self.machines {
+            let ttl = humantime::parse_duration(&config.ttl).map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid instance_ttl.machines.{}.ttl '{}': {}",
+                    machine,
+                    config.ttl,
+                    e
+                )
+            })?;
+            if ttl.is_zero() {
+                anyhow::bail!(
+                    "instance_ttl.machines.{}.ttl must be greater than zero",
+                    machine
+                );
+            }
+            let event = config.event.clone().filter(|e| !e.trim().is_empty());
+            if config.action == InstanceTtlAction::Event && event.is_none() {
+                anyhow::bail!(
+                    "instance_ttl.machines.{} uses the event action but sets no event",
+                    machine
+                );
+            }
+            rules.insert(
+                machine.clone(),
+                InstanceTtlRule {
+                    ttl,
+                    action: config.action,
+                    event: event.filter(|_| config.action == InstanceTtlAction::Event),
+                },
+            );
+        }
+        Ok(rules)
+    }
+}
+
+fn default_instance_ttl_interval() -> String {
+    constants::instance_ttl::DEFAULT_INTERVAL.to_string()
+}
+
+/// Machine definition import from URLs.
+///
+/// Only URLs whose scheme and host are allowlisted are fetched, so the
+/// endpoint cannot be used to probe internal services. With no allowed hosts
+/// URL import is disabled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportConfig {
+    #[serde(default = "default_import_allowed_schemes")]
+    pub allowed_schemes: Vec<String>,
+    /// Exact host names; `*.example.com` also matches any subdomain
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    /// Largest definition fetched, in bytes
+    #[serde(default = "default_import_max_bytes")]
+    pub max_bytes: usize,
+    #[serde(default = "default_import_timeout")]
+    pub timeout: String,
+}
+
+impl Default for ImportConfig {
+    fn default() -> Self {
+        Self {
+            allowed_schemes: default_import_allowed_schemes(),
+            allowed_hosts: Vec::new(),
+            max_bytes: default_import_max_bytes(),
+            timeout: default_import_timeout(),
+        }
+    }
+}
+
+impl ImportConfig {
+    /// Parse the humantime fetch timeout
+    pub fn timeout(&self) -> anyhow::Result<Duration> {
+        let timeout = humantime::parse_duration(&self.timeout)
+            .map_err(|e| anyhow::anyhow!("Invalid import.timeout '{}': {}", self.timeout, e))?;
+        if timeout.is_zero() {
+            anyhow::bail!("import.timeout must be greater than zero");
+        }
+        Ok(timeout)
+    }
+}
+
+fn default_import_allowed_schemes() -> Vec<String> {
+    vec!["https".to_string()]
+}
+
+fn default_import_max_bytes() -> usize {
+    constants::import::DEFAULT_MAX_BYTES
+}
+
+fn default_import_timeout() -> String {
+    constants::import::DEFAULT_TIMEOUT.to_string()
+}
+
+/// Bounds on `GET /api/v1/search`, which scans instances itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchConfig {
+    /// Most instances whose ids are matched per search
+    #[serde(default = "default_search_max_instance_scan")]
+    pub max_instance_scan: u64,
+    /// Most instances whose contexts are fetched and matched per search
+    #[serde(default = "default_search_max_context_scan")]
+    pub max_context_scan: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            max_instance_scan: default_search_max_instance_scan(),
+            max_context_scan: default_search_max_context_scan(),
+        }
+    }
+}
+
+fn default_search_max_instance_scan() -> u64 {
+    constants::search::DEFAULT_MAX_INSTANCE_SCAN
+}
+
+fn default_search_max_context_scan() -> usize {
+    constants::search::DEFAULT_MAX_CONTEXT_SCAN
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +525,127 @@ pub struct ServerConfig {
     pub port: u16,
     #[serde(default)]
     pub tls: TlsConfig,
+    /// Notice shown on the login page (markdown allowed)
+    #[serde(default)]
+    pub login_banner: Option<String>,
+    /// Most instance ids accepted by one `POST /instances/batch-get`
+    #[serde(default = "default_batch_get_max_ids")]
+    pub batch_get_max_ids: usize,
+    /// Most events one `POST /instances/:id/fork` re-applies
+    #[serde(default = "default_max_replay_events")]
+    pub max_replay_events: usize,
+    /// How long the result of an apply-event carrying an `event_id` is kept
+    /// for retries ("0s" leaves deduplication to rstmdb alone)
+    #[serde(default = "default_event_dedup_window")]
+    pub event_dedup_window: String,
+    #[serde(default)]
+    pub timeouts: TimeoutsConfig,
+    /// Largest request body accepted by most routes
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Override for routes that upload machine definitions
+    #[serde(default = "default_max_upload_body_bytes")]
+    pub max_upload_body_bytes: usize,
+    /// Serve GET requests without a session; writes still require login
+    #[serde(default)]
+    pub allow_anonymous_read: bool,
+    /// Requests handled at once; more are rejected with `503 RATE_LIMITED`
+    #[serde(default = "default_max_in_flight_requests")]
+    pub max_in_flight_requests: usize,
+    /// rstmdb operations one API request may issue before failing with
+    /// `429 RESOURCE_EXHAUSTED`; 0 disables the limit
+    #[serde(default = "default_max_rstmdb_ops_per_request")]
+    pub max_rstmdb_ops_per_request: u64,
+    /// Wrap successful JSON responses as `{ "data": ... }`; errors stay
+    /// `{ "error": ... }`
+    #[serde(default)]
+    pub response_envelope: bool,
+    /// Reverse proxies whose `X-Forwarded-For` is believed when recording a
+    /// session's client address; with none, the peer address is used
+    #[serde(default)]
+    pub trusted_proxies: Vec<std::net::IpAddr>,
+}
+
+impl ServerConfig {
+    /// Parse the humantime `event_dedup_window`
+    pub fn event_dedup_window(&self) -> anyhow::Result<Duration> {
+        humantime::parse_duration(&self.event_dedup_window).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid server.event_dedup_window '{}': {}",
+                self.event_dedup_window,
+                e
+            )
+        })
+    }
+}
+
+/// Request handling time limits, by route category
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeoutsConfig {
+    /// Most routes
+    #[serde(default = "default_standard_timeout")]
+    pub standard: String,
+    /// Routes that scan the WAL or fan out to many rstmdb calls
+    #[serde(default = "default_long_timeout")]
+    pub long: String,
+    /// Longest `timeout` a long-poll (`GET /instances/:id/wait`) may ask for;
+    /// must be shorter than `long`
+    #[serde(default = "default_max_wait")]
+    pub max_wait: String,
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        Self {
+            standard: default_standard_timeout(),
+            long: default_long_timeout(),
+            max_wait: default_max_wait(),
+        }
+    }
+}
+
+/// Parsed request timeouts from `TimeoutsConfig`
+#[derive(Debug, Clone, Copy)]
+pub struct RequestTimeouts {
+    pub standard: Duration,
+    pub long: Duration,
+    pub max_wait: Duration,
+}
+
+impl TimeoutsConfig {
+    /// Parse the humantime request timeouts
+    pub fn request_timeouts(&self) -> anyhow::Result<RequestTimeouts> {
+        let parse = |name: &str, value: &str| {
+            let timeout = humantime::parse_duration(value).map_err(|e| {
+                anyhow::anyhow!("Invalid server.timeouts.{} '{}': {}", name, value, e)
+            })?;
+            if timeout.is_zero() {
+                anyhow::bail!("server.timeouts.{} must be greater than zero", name);
+            }
+            Ok(timeout)
+        };
+        let timeouts = RequestTimeouts {
+            standard: parse("standard", &self.standard)?,
+            long: parse("long", &self.long)?,
+            max_wait: parse("max_wait", &self.max_wait)?,
+        };
+        if timeouts.max_wait >= timeouts.long {
+            anyhow::bail!("server.timeouts.max_wait must be shorter than server.timeouts.long");
+        }
+        Ok(timeouts)
+    }
+}
+
+fn default_standard_timeout() -> String {
+    constants::server::DEFAULT_STANDARD_TIMEOUT.to_string()
+}
+
+fn default_long_timeout() -> String {
+    constants::server::DEFAULT_LONG_TIMEOUT.to_string()
+}
+
+fn default_max_wait() -> String {
+    constants::server::DEFAULT_MAX_WAIT.to_string()
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -29,14 +653,45 @@ pub struct TlsConfig {
     pub enabled: bool,
     pub cert_path: Option<String>,
     pub key_path: Option<String>,
+    /// CA that client certificates must be signed by; clients are asked for
+    /// one only when this is set
+    #[serde(default)]
+    pub client_ca_path: Option<String>,
+    /// Refuse TLS handshakes without a valid client certificate
+    #[serde(default)]
+    pub require_client_cert: bool,
+    /// Client certificate common name -> role it authenticates as, without a
+    /// password session. Certificates not listed here authenticate no one.
+    #[serde(default)]
+    pub client_cert_roles: HashMap<String, Role>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RstmdbConfig {
+    /// `host:port`, or several separated by commas to fail over between
     pub address: String,
+    /// Further endpoints tried, in order, when `address` can't be reached
+    #[serde(default)]
+    pub failover_addresses: Vec<String>,
     pub token: Option<String>,
+    /// Name this Studio reports to rstmdb, shown in its connection list.
+    /// `{hostname}` is replaced with the local hostname.
+    #[serde(default = "default_client_name")]
+    pub client_name: String,
     #[serde(default)]
     pub tls: RstmdbTlsConfig,
+    #[serde(default)]
+    pub instance_cache: InstanceCacheConfig,
+    #[serde(default)]
+    pub keepalive: KeepaliveConfig,
+    /// Log a warning when a request waits longer than this for the connection
+    #[serde(default = "default_lock_wait_warn")]
+    pub lock_wait_warn: String,
+    /// Abandon a reconnect that takes longer than this
+    #[serde(default = "default_reconnect_timeout")]
+    pub reconnect_timeout: String,
+    #[serde(default)]
+    pub request_queue: RequestQueueConfig,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -46,6 +701,238 @@ pub struct RstmdbTlsConfig {
     pub insecure: bool,
 }
 
+/// Short-TTL cache for `get_instance` responses.
+///
+/// Disabled by default. When enabled, reads may return slightly stale state if
+/// instances are mutated by writers other than this Studio; following the WAL
+/// narrows that to about one `follow_interval`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceCacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_instance_cache_ttl")]
+    pub ttl: String,
+    #[serde(default = "default_instance_cache_capacity")]
+    pub capacity: usize,
+    /// Update cached instances from the WAL as other clients write them
+    #[serde(default = "default_instance_cache_follow_wal")]
+    pub follow_wal: bool,
+    /// Time between WAL polls while following, e.g. "500ms"
+    #[serde(default = "default_instance_cache_follow_interval")]
+    pub follow_interval: String,
+}
+
+impl Default for InstanceCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl: default_instance_cache_ttl(),
+            capacity: default_instance_cache_capacity(),
+            follow_wal: default_instance_cache_follow_wal(),
+            follow_interval: default_instance_cache_follow_interval(),
+        }
+    }
+}
+
+impl InstanceCacheConfig {
+    /// Parse the humantime WAL poll interval
+    pub fn follow_interval(&self) -> anyhow::Result<Duration> {
+        let interval = humantime::parse_duration(&self.follow_interval).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid rstmdb.instance_cache.follow_interval '{}': {}",
+                self.follow_interval,
+                e
+            )
+        })?;
+        if interval.is_zero() {
+            anyhow::bail!("rstmdb.instance_cache.follow_interval must be greater than zero");
+        }
+        Ok(interval)
+    }
+}
+
+/// Background ping that detects half-open rstmdb connections
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeepaliveConfig {
+    #[serde(default = "default_keepalive_enabled")]
+    pub enabled: bool,
+    /// Time between pings, e.g. "30s"
+    #[serde(default = "default_keepalive_interval")]
+    pub interval: String,
+    /// Consecutive failed pings before forcing a reconnect
+    #[serde(default = "default_keepalive_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_keepalive_enabled(),
+            interval: default_keepalive_interval(),
+            failure_threshold: default_keepalive_failure_threshold(),
+        }
+    }
+}
+
+/// Prioritized admission of rstmdb operations.
+///
+/// Disabled by default. When enabled, at most `concurrency` operations run at
+/// once, and interactive requests are served ahead of long-running ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestQueueConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_queue_concurrency")]
+    pub concurrency: usize,
+    /// Interactive operations served per bulk operation under contention
+    #[serde(default = "default_queue_interactive_weight")]
+    pub interactive_weight: u32,
+    #[serde(default = "default_queue_max_queued")]
+    pub max_queued: usize,
+}
+
+impl Default for RequestQueueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            concurrency: default_queue_concurrency(),
+            interactive_weight: default_queue_interactive_weight(),
+            max_queued: default_queue_max_queued(),
+        }
+    }
+}
+
+impl RstmdbConfig {
+    /// `client_name` with `{hostname}` substituted
+    pub fn resolved_client_name(&self) -> String {
+        self.client_name.replace("{hostname}", &hostname())
+    }
+
+    /// Every endpoint to connect to, in failover order: the comma-separated
+    /// entries of `address`, then `failover_addresses`, without duplicates
+    pub fn endpoints(&self) -> Vec<String> {
+        let mut endpoints: Vec<String> = Vec::new();
+        let listed = self
+            .address
+            .split(',')
+            .chain(self.failover_addresses.iter().map(String::as_str));
+        for endpoint in listed.map(str::trim).filter(|e| !e.is_empty()) {
+            if !endpoints.iter().any(|e| e == endpoint) {
+                endpoints.push(endpoint.to_string());
+            }
+        }
+        endpoints
+    }
+}
+
+/// Local hostname from `HOSTNAME` or the kernel, `unknown` if neither is set
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+impl KeepaliveConfig {
+    /// Parse the humantime ping interval
+    pub fn interval(&self) -> anyhow::Result<Duration> {
+        let interval = humantime::parse_duration(&self.interval).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid rstmdb.keepalive.interval '{}': {}",
+                self.interval,
+                e
+            )
+        })?;
+        if interval.is_zero() {
+            anyhow::bail!("rstmdb.keepalive.interval must be greater than zero");
+        }
+        Ok(interval)
+    }
+}
+
+fn default_max_body_bytes() -> usize {
+    constants::server::DEFAULT_MAX_BODY_BYTES
+}
+
+fn default_max_upload_body_bytes() -> usize {
+    constants::server::DEFAULT_MAX_UPLOAD_BODY_BYTES
+}
+
+fn default_max_in_flight_requests() -> usize {
+    constants::server::DEFAULT_MAX_IN_FLIGHT_REQUESTS
+}
+
+fn default_max_rstmdb_ops_per_request() -> u64 {
+    constants::server::DEFAULT_MAX_RSTMDB_OPS_PER_REQUEST
+}
+
+fn default_batch_get_max_ids() -> usize {
+    constants::instances::DEFAULT_BATCH_GET_MAX_IDS
+}
+
+fn default_event_dedup_window() -> String {
+    constants::instances::DEFAULT_EVENT_DEDUP_WINDOW.to_string()
+}
+
+fn default_max_replay_events() -> usize {
+    constants::instances::DEFAULT_MAX_REPLAY_EVENTS
+}
+
+fn default_client_name() -> String {
+    constants::rstmdb::DEFAULT_CLIENT_NAME.to_string()
+}
+
+fn default_lock_wait_warn() -> String {
+    constants::rstmdb::DEFAULT_LOCK_WAIT_WARN.to_string()
+}
+
+fn default_reconnect_timeout() -> String {
+    constants::rstmdb::DEFAULT_RECONNECT_TIMEOUT.to_string()
+}
+
+fn default_keepalive_enabled() -> bool {
+    true
+}
+
+fn default_keepalive_interval() -> String {
+    constants::rstmdb::DEFAULT_KEEPALIVE_INTERVAL.to_string()
+}
+
+fn default_keepalive_failure_threshold() -> u32 {
+    constants::rstmdb::DEFAULT_KEEPALIVE_FAILURE_THRESHOLD
+}
+
+fn default_queue_concurrency() -> usize {
+    constants::rstmdb::DEFAULT_QUEUE_CONCURRENCY
+}
+
+fn default_queue_interactive_weight() -> u32 {
+    constants::rstmdb::DEFAULT_QUEUE_INTERACTIVE_WEIGHT
+}
+
+fn default_queue_max_queued() -> usize {
+    constants::rstmdb::DEFAULT_QUEUE_MAX_QUEUED
+}
+
+fn default_instance_cache_ttl() -> String {
+    constants::rstmdb::DEFAULT_INSTANCE_CACHE_TTL.to_string()
+}
+
+fn default_instance_cache_capacity() -> usize {
+    constants::rstmdb::DEFAULT_INSTANCE_CACHE_CAPACITY
+}
+
+fn default_instance_cache_follow_wal() -> bool {
+    true
+}
+
+fn default_instance_cache_follow_interval() -> String {
+    constants::rstmdb::DEFAULT_INSTANCE_CACHE_FOLLOW_INTERVAL.to_string()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthConfig {
     #[serde(default = "default_session_idle_timeout")]
@@ -56,6 +943,107 @@ pub struct AuthConfig {
     pub lockout_attempts: u32,
     #[serde(default = "default_lockout_duration")]
     pub lockout_duration: String,
+    /// Treat usernames differing only by case as the same account
+    #[serde(default)]
+    pub case_insensitive_usernames: bool,
+    /// Where logins are verified
+    #[serde(default)]
+    pub backend: AuthBackendKind,
+    /// OIDC settings, required when `backend` is `oidc`
+    #[serde(default)]
+    pub oidc: Option<OidcConfig>,
+    /// One-time recovery token for locked-out admins
+    #[serde(default)]
+    pub break_glass: BreakGlassConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakGlassConfig {
+    /// Print a single-use recovery token to the console at startup
+    #[serde(default)]
+    pub enabled: bool,
+    /// How long the token, and the session it grants, stays valid
+    #[serde(default = "default_break_glass_ttl")]
+    pub ttl: String,
+}
+
+impl Default for BreakGlassConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl: default_break_glass_ttl(),
+        }
+    }
+}
+
+impl BreakGlassConfig {
+    /// Parse the humantime token lifetime
+    pub fn ttl(&self) -> anyhow::Result<Duration> {
+        humantime::parse_duration(&self.ttl)
+            .map_err(|e| anyhow::anyhow!("Invalid auth.break_glass.ttl '{}': {}", self.ttl, e))
+    }
+}
+
+/// Authentication backend selection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthBackendKind {
+    /// Users and password hashes in `<data_dir>/auth.json`
+    #[default]
+    Local,
+    /// Bearer tokens issued by an OpenID Connect provider
+    Oidc,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcConfig {
+    /// Expected `iss` claim; also used for discovery of the JWKS endpoint
+    pub issuer: String,
+    /// Expected `aud` claim; not checked when unset
+    #[serde(default)]
+    pub audience: Option<String>,
+    /// JWKS endpoint, overriding discovery via `.well-known/openid-configuration`
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+    /// Claim used as the Studio username (falls back to `sub`)
+    #[serde(default = "default_oidc_username_claim")]
+    pub username_claim: String,
+    /// Claim holding the user's roles or groups (string or array)
+    #[serde(default = "default_oidc_roles_claim")]
+    pub roles_claim: String,
+    /// Values of `roles_claim` that grant the admin role; everyone else is a viewer
+    #[serde(default = "default_oidc_admin_roles")]
+    pub admin_roles: Vec<String>,
+}
+
+/// Parsed session timeouts from `AuthConfig`
+#[derive(Debug, Clone, Copy)]
+pub struct SessionTimeouts {
+    /// Maximum time between requests before a session expires
+    pub idle: Duration,
+    /// Maximum session age regardless of activity
+    pub max_lifetime: Duration,
+}
+
+impl AuthConfig {
+    /// Parse the humantime session timeouts (e.g. "2h", "30m")
+    pub fn session_timeouts(&self) -> anyhow::Result<SessionTimeouts> {
+        let idle = humantime::parse_duration(&self.session_idle_timeout).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid auth.session_idle_timeout '{}': {}",
+                self.session_idle_timeout,
+                e
+            )
+        })?;
+        let max_lifetime = humantime::parse_duration(&self.session_max_lifetime).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid auth.session_max_lifetime '{}': {}",
+                self.session_max_lifetime,
+                e
+            )
+        })?;
+        Ok(SessionTimeouts { idle, max_lifetime })
+    }
 }
 
 fn default_session_idle_timeout() -> String {
@@ -74,6 +1062,22 @@ fn default_lockout_duration() -> String {
     constants::auth::DEFAULT_LOCKOUT_DURATION.to_string()
 }
 
+fn default_break_glass_ttl() -> String {
+    constants::auth::DEFAULT_BREAK_GLASS_TTL.to_string()
+}
+
+fn default_oidc_username_claim() -> String {
+    constants::auth::DEFAULT_OIDC_USERNAME_CLAIM.to_string()
+}
+
+fn default_oidc_roles_claim() -> String {
+    constants::auth::DEFAULT_OIDC_ROLES_CLAIM.to_string()
+}
+
+fn default_oidc_admin_roles() -> Vec<String> {
+    vec![constants::auth::DEFAULT_OIDC_ADMIN_ROLE.to_string()]
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -81,53 +1085,198 @@ impl Default for Config {
                 host: constants::server::DEFAULT_HOST.to_string(),
                 port: constants::server::DEFAULT_PORT,
                 tls: TlsConfig::default(),
+                login_banner: None,
+                batch_get_max_ids: default_batch_get_max_ids(),
+                max_replay_events: default_max_replay_events(),
+                event_dedup_window: default_event_dedup_window(),
+                timeouts: TimeoutsConfig::default(),
+                max_body_bytes: default_max_body_bytes(),
+                max_upload_body_bytes: default_max_upload_body_bytes(),
+                allow_anonymous_read: false,
+                max_in_flight_requests: default_max_in_flight_requests(),
+                max_rstmdb_ops_per_request: default_max_rstmdb_ops_per_request(),
+                response_envelope: false,
+                trusted_proxies: Vec::new(),
             },
             rstmdb: RstmdbConfig {
                 address: constants::rstmdb::DEFAULT_ADDRESS.to_string(),
+                failover_addresses: Vec::new(),
                 token: None,
+                client_name: default_client_name(),
                 tls: RstmdbTlsConfig::default(),
+                instance_cache: InstanceCacheConfig::default(),
+                keepalive: KeepaliveConfig::default(),
+                lock_wait_warn: default_lock_wait_warn(),
+                reconnect_timeout: default_reconnect_timeout(),
+                request_queue: RequestQueueConfig::default(),
             },
             auth: AuthConfig {
                 session_idle_timeout: default_session_idle_timeout(),
                 session_max_lifetime: default_session_max_lifetime(),
                 lockout_attempts: default_lockout_attempts(),
                 lockout_duration: default_lockout_duration(),
+                case_insensitive_usernames: false,
+                backend: AuthBackendKind::default(),
+                oidc: None,
+                break_glass: BreakGlassConfig::default(),
             },
+            validation: ValidationConfig::default(),
+            import: ImportConfig::default(),
+            redaction: RedactionConfig::default(),
+            readiness: ReadinessConfig::default(),
+            wal_history: WalHistoryConfig::default(),
+            machine_counts: MachineCountsConfig::default(),
+            instance_ttl: InstanceTtlConfig::default(),
+            ui: UiConfig::default(),
+            search: SearchConfig::default(),
+            features: FeaturesConfig::default(),
+            event_policies: HashMap::new(),
             data_dir: constants::DEFAULT_DATA_DIR.to_string(),
+            log_level: None,
         }
     }
 }
 
 impl Config {
+    /// Load `config_paths` in order, later files overriding earlier ones, then
+    /// apply CLI flags and `STUDIO_` environment variables. Missing files are
+    /// skipped.
     pub fn load(
-        config_path: &PathBuf,
+        config_paths: &[PathBuf],
         host: &str,
         port: u16,
         rstmdb_addr: &str,
         rstmdb_token: Option<String>,
     ) -> anyhow::Result<Self> {
-        // CLI overrides
-        let cli_overrides = Config {
-            server: ServerConfig {
-                host: host.to_string(),
-                port,
-                tls: TlsConfig::default(),
-            },
-            rstmdb: RstmdbConfig {
-                address: rstmdb_addr.to_string(),
-                token: rstmdb_token,
-                tls: RstmdbTlsConfig::default(),
-            },
-            ..Default::default()
-        };
+        // CLI overrides. Only the keys exposed as flags are set: overriding
+        // with a whole default `server`/`rstmdb` section would reset every
+        // other file setting there, e.g. `rstmdb.instance_cache`.
+        let mut cli_overrides = Figment::new()
+            .merge(Serialized::default("server.host", host))
+            .merge(Serialized::default("server.port", port))
+            .merge(Serialized::default("rstmdb.address", rstmdb_addr));
+        if let Some(token) = rstmdb_token {
+            cli_overrides = cli_overrides.merge(Serialized::default("rstmdb.token", token));
+        }
 
-        let config: Config = Figment::new()
-            .merge(Serialized::defaults(Config::default()))
-            .merge(Yaml::file(config_path))
-            .merge(Serialized::defaults(cli_overrides))
+        let mut figment = Figment::new().merge(Serialized::defaults(Config::default()));
+        for path in config_paths {
+            figment = figment.merge(Yaml::file(path));
+        }
+        let config: Config = figment
+            .merge(cli_overrides)
             .merge(Env::prefixed("STUDIO_").split("__"))
             .extract()?;
 
         Ok(config)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_later_config_files_override_earlier() {
+        let dir = std::env::temp_dir().join(format!("rstmdb-studio-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("base.yaml");
+        let prod = dir.join("prod.yaml");
+        std::fs::write(
+            &base,
+            "server:\n  login_banner: base\n  max_replay_events: 5\nsearch:\n  max_instance_scan: 10\n",
+        )
+        .unwrap();
+        std::fs::write(&prod, "server:\n  login_banner: prod\n").unwrap();
+
+        let load = |paths: &[PathBuf]| {
+            Config::load(paths, "0.0.0.0", 8080, "127.0.0.1:7401", None).unwrap()
+        };
+        let config = load(&[base.clone(), prod.clone()]);
+        assert_eq!(config.server.login_banner.as_deref(), Some("prod"));
+        // Keys the overlay doesn't set keep the base file's values
+        assert_eq!(config.server.max_replay_events, 5);
+        assert_eq!(config.search.max_instance_scan, 10);
+
+        let config = load(&[prod, base]);
+        assert_eq!(config.server.login_banner.as_deref(), Some("base"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_cli_flags_keep_file_settings_in_their_sections() {
+        let dir = std::env::temp_dir().join(format!("rstmdb-studio-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("studio.yaml");
+        std::fs::write(
+            &path,
+            "server:\n  login_banner: file\nrstmdb:\n  address: file:7401\n  instance_cache:\n    enabled: true\n    ttl: 5s\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&[path], "127.0.0.1", 9090, "cli:7401", None).unwrap();
+        assert_eq!(config.server.port, 9090);
+        assert_eq!(config.rstmdb.address, "cli:7401");
+        assert_eq!(config.server.login_banner.as_deref(), Some("file"));
+        assert!(config.rstmdb.instance_cache.enabled);
+        assert_eq!(config.rstmdb.instance_cache.ttl, "5s");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rstmdb_endpoints_in_failover_order() {
+        let mut config = Config::default().rstmdb;
+        assert_eq!(config.endpoints(), vec!["127.0.0.1:7401"]);
+
+        config.address = "db-1:7401, db-2:7401,".to_string();
+        config.failover_addresses = vec!["db-3:7401".to_string(), "db-1:7401".to_string()];
+        assert_eq!(
+            config.endpoints(),
+            vec!["db-1:7401", "db-2:7401", "db-3:7401"]
+        );
+    }
+
+    #[test]
+    fn test_instance_ttl_rules() {
+        let machine = |ttl: &str, action, event: Option<&str>| MachineTtlConfig {
+            ttl: ttl.to_string(),
+            action,
+            event: event.map(String::from),
+        };
+        let mut config = InstanceTtlConfig::default();
+        config.machines.insert(
+            "orders".to_string(),
+            machine("24h", InstanceTtlAction::Event, Some("TIMEOUT")),
+        );
+        config.machines.insert(
+            "carts".to_string(),
+            machine("1h", InstanceTtlAction::Delete, None),
+        );
+        let rules = config.rules().unwrap();
+        assert_eq!(rules["orders"].ttl, Duration::from_secs(86400));
+        assert_eq!(rules["orders"].event.as_deref(), Some("TIMEOUT"));
+        assert_eq!(rules["carts"].action, InstanceTtlAction::Delete);
+
+        for bad in [
+            machine("soon", InstanceTtlAction::Delete, None),
+            machine("0s", InstanceTtlAction::Delete, None),
+            machine("1h", InstanceTtlAction::Event, None),
+        ] {
+            let mut config = InstanceTtlConfig::default();
+            config.machines.insert("orders".to_string(), bad);
+            assert!(config.rules().is_err());
+        }
+    }
+
+    #[test]
+    fn test_readiness_max_task_downtime() {
+        let mut config = ReadinessConfig::default();
+        assert_eq!(
+            config.max_task_downtime().unwrap(),
+            Duration::from_secs(120)
+        );
+        config.max_task_downtime = "soon".to_string();
+        let err = config.max_task_downtime().unwrap_err();
+        assert!(err.to_string().contains("readiness.max_task_downtime"));
+    }
+}
@@ -8,18 +8,157 @@ pub mod wal {
     pub const DEFAULT_PAGE_SIZE: u64 = 50;
     /// Maximum number of WAL entries per request
     pub const MAX_PAGE_SIZE: u64 = 1000;
+    /// Name of the `GET /wal/range-digest` hash chain; bump the version if
+    /// the construction ever changes
+    pub const DIGEST_ALGORITHM: &str = "sha256-chain-v1";
 }
 
 /// Instance API constants
 pub mod instances {
-    /// Maximum WAL entries to scan for instance history
-    pub const HISTORY_MAX_WAL_SCAN: u64 = 10000;
+    /// Page size rstmdb applies when `limit` is omitted
+    pub const DEFAULT_LIST_LIMIT: u32 = 100;
+    /// WAL entries read per page while collecting an instance's history
+    pub const HISTORY_WAL_PAGE_SIZE: u64 = 1000;
+    /// Page size used when scanning all instances of a machine
+    pub const SCAN_PAGE_SIZE: u32 = 500;
+    /// Maximum instances scanned by the stuck detector
+    pub const STUCK_MAX_SCAN: u64 = 10000;
+    /// Maximum instances scanned by validate-against-instances
+    pub const ORPHAN_MAX_SCAN: u64 = 10000;
+    /// Default cap on ids per batch-get request
+    pub const DEFAULT_BATCH_GET_MAX_IDS: usize = 100;
+    /// Default cap on events re-applied by a fork
+    pub const DEFAULT_MAX_REPLAY_EVENTS: usize = 1000;
+    /// Instances fetched from rstmdb at once by a batch-get
+    pub const BATCH_GET_CONCURRENCY: usize = 8;
+    /// Long-poll timeout when `timeout` is omitted (capped by
+    /// `server.timeouts.max_wait`)
+    pub const DEFAULT_WAIT_TIMEOUT: &str = "30s";
+    /// How often a long-poll or instance event stream checks the WAL for new
+    /// entries
+    pub const WAIT_POLL_INTERVAL_MS: u64 = 250;
+    /// How long an applied `event_id` is remembered by default
+    pub const DEFAULT_EVENT_DEDUP_WINDOW: &str = "10m";
+    /// Applied `event_id`s remembered at once; the oldest is dropped beyond this
+    pub const EVENT_DEDUP_MAX_ENTRIES: usize = 100_000;
+}
+
+/// Machine API constants
+pub mod machines {
+    /// Version path segment that resolves to the highest registered version
+    pub const LATEST_VERSION_ALIAS: &str = "latest";
+    /// Numbered versions are immutable, so clients may keep them indefinitely
+    pub const VERSIONED_CACHE_CONTROL: &str = "private, max-age=31536000, immutable";
+    /// The `latest` alias can point at a new version at any time
+    pub const LATEST_CACHE_CONTROL: &str = "no-cache";
+    /// Definitions fetched from rstmdb at once by duplicate detection
+    pub const DUPLICATES_CONCURRENCY: usize = 8;
+    /// Machine version checksums kept in memory
+    pub const CHECKSUM_CACHE_MAX_ENTRIES: usize = 4096;
+    /// Shortest paths returned by the path finder with `?all=true` by default
+    pub const DEFAULT_SHORTEST_PATHS: usize = 10;
+    /// Upper bound on `?limit` for the path finder
+    pub const MAX_SHORTEST_PATHS: usize = 100;
+    /// Unchanged lines around each hunk of a text definition diff
+    pub const DIFF_CONTEXT_LINES: usize = 3;
+    /// Text diffs of longer definitions (either side) skip the line diff
+    /// and show the whole old text replaced by the whole new one
+    pub const DIFF_MAX_LINES: usize = 20_000;
+    /// Edit distance past which the line diff gives up the same way; bounds
+    /// its memory to about `DIFF_MAX_EDITS`² words
+    pub const DIFF_MAX_EDITS: usize = 1_000;
+}
+
+/// Definition validation constants
+pub mod validation {
+    /// Contexts evaluated per guard pair by the overlapping guard check
+    pub const MAX_GUARD_OVERLAP_ASSIGNMENTS: usize = 4096;
+}
+
+/// Readiness probe constants
+pub mod readiness {
+    pub const DEFAULT_TIMEOUT: &str = "2s";
+    /// File written and removed in `data_dir` by the `data_dir` check
+    pub const DATA_DIR_PROBE_FILE: &str = ".readiness-probe";
+    /// How long a critical background task may stay down before
+    /// `background_tasks` fails
+    pub const DEFAULT_MAX_TASK_DOWNTIME: &str = "2m";
+}
+
+/// Background task supervision constants
+pub mod supervisor {
+    use std::time::Duration;
+
+    /// Delay before the first restart; doubles on each further failure
+    pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+    pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+    /// A restarted task running this long is healthy again
+    pub const STABLE_AFTER: Duration = Duration::from_secs(30);
+}
+
+/// WAL growth sampling constants
+pub mod wal_history {
+    pub const DEFAULT_INTERVAL: &str = "1m";
+    pub const DEFAULT_RETENTION: &str = "24h";
+}
+
+/// Machine list count refresh constants
+pub mod machine_counts {
+    pub const DEFAULT_INTERVAL: &str = "5m";
+    pub const DEFAULT_MIN_MACHINES: usize = 50;
+    /// Definitions fetched from rstmdb at once by a refresh
+    pub const REFRESH_CONCURRENCY: usize = 4;
+}
+
+/// Instance expiry sweep constants
+pub mod instance_ttl {
+    pub const DEFAULT_INTERVAL: &str = "1m";
+    /// Instances of one machine examined per sweep
+    pub const MAX_SCAN_PER_MACHINE: u64 = 10000;
+}
+
+/// Machine import by URL constants
+pub mod import {
+    /// Largest machine definition fetched by URL import (1 MiB)
+    pub const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+    pub const DEFAULT_TIMEOUT: &str = "10s";
+    /// Appended to the name of a machine imported with `strategy: rename`,
+    /// followed by `-2`, `-3`, ... if that name is taken too
+    pub const RENAME_SUFFIX: &str = "-imported";
+}
+
+/// Machine definition draft constants
+pub mod drafts {
+    /// Drafts one user can keep at once
+    pub const MAX_DRAFTS_PER_USER: usize = 100;
+}
+
+/// Global search constants
+pub mod search {
+    /// Results per page when `limit` is omitted
+    pub const DEFAULT_LIMIT: usize = 20;
+    pub const MAX_LIMIT: usize = 100;
+    /// Instances listed per search
+    pub const DEFAULT_MAX_INSTANCE_SCAN: u64 = 5000;
+    /// Instance contexts fetched and searched per search
+    pub const DEFAULT_MAX_CONTEXT_SCAN: usize = 200;
+}
+
+/// Transition usage aggregation constants
+pub mod transition_usage {
+    /// Maximum WAL entries scanned per aggregation
+    pub const MAX_WAL_SCAN: u64 = 100_000;
+    /// How long aggregated results are reused
+    pub const CACHE_TTL_SECS: u64 = 60;
+    /// Distinct (machine, version, since) results kept in the cache
+    pub const CACHE_MAX_ENTRIES: usize = 64;
 }
 
 /// WAL entry types (as returned by rstmdb)
 pub mod wal_entry_types {
     pub const CREATE_INSTANCE: &str = "create_instance";
     pub const APPLY_EVENT: &str = "apply_event";
+    pub const DELETE_INSTANCE: &str = "delete_instance";
 }
 
 /// History event types (as returned by Studio API)
@@ -32,11 +171,43 @@ pub mod history_event_types {
 pub mod server {
     pub const DEFAULT_HOST: &str = "0.0.0.0";
     pub const DEFAULT_PORT: u16 = 8080;
+    pub const DEFAULT_STANDARD_TIMEOUT: &str = "30s";
+    pub const DEFAULT_LONG_TIMEOUT: &str = "2m";
+    pub const DEFAULT_MAX_WAIT: &str = "60s";
+    /// Request body limit for most routes (1 MiB)
+    pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+    /// Request body limit for definition uploads (16 MiB)
+    pub const DEFAULT_MAX_UPLOAD_BODY_BYTES: usize = 16 * 1024 * 1024;
+    /// Requests handled at once before new ones are shed
+    pub const DEFAULT_MAX_IN_FLIGHT_REQUESTS: usize = 1024;
+    /// rstmdb operations one API request may issue
+    pub const DEFAULT_MAX_RSTMDB_OPS_PER_REQUEST: u64 = 10_000;
+}
+
+/// Optional rstmdb operations, as named in the server's `info.features`
+pub mod capabilities {
+    /// WAL reads and statistics
+    pub const WAL: &str = "wal";
 }
 
 /// rstmdb connection defaults
 pub mod rstmdb {
     pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7401";
+    /// Client name reported to rstmdb; `{hostname}` is substituted
+    pub const DEFAULT_CLIENT_NAME: &str = "rstmdb-studio@{hostname}";
+    pub const DEFAULT_INSTANCE_CACHE_TTL: &str = "2s";
+    pub const DEFAULT_INSTANCE_CACHE_CAPACITY: usize = 1000;
+    pub const DEFAULT_INSTANCE_CACHE_FOLLOW_INTERVAL: &str = "500ms";
+    pub const DEFAULT_KEEPALIVE_INTERVAL: &str = "30s";
+    pub const DEFAULT_KEEPALIVE_FAILURE_THRESHOLD: u32 = 2;
+    pub const DEFAULT_LOCK_WAIT_WARN: &str = "1s";
+    pub const DEFAULT_RECONNECT_TIMEOUT: &str = "10s";
+    /// rstmdb operations in flight at once when the request queue is enabled
+    pub const DEFAULT_QUEUE_CONCURRENCY: usize = 16;
+    /// Interactive operations served per bulk one while both are waiting
+    pub const DEFAULT_QUEUE_INTERACTIVE_WEIGHT: u32 = 4;
+    /// Waiters per priority tier before callers block on enqueueing
+    pub const DEFAULT_QUEUE_MAX_QUEUED: usize = 1024;
 }
 
 /// Authentication defaults
@@ -45,6 +216,18 @@ pub mod auth {
     pub const DEFAULT_SESSION_MAX_LIFETIME: &str = "24h";
     pub const DEFAULT_LOCKOUT_ATTEMPTS: u32 = 10;
     pub const DEFAULT_LOCKOUT_DURATION: &str = "5m";
+    pub const DEFAULT_BREAK_GLASS_TTL: &str = "15m";
+    pub const DEFAULT_OIDC_USERNAME_CLAIM: &str = "preferred_username";
+    pub const DEFAULT_OIDC_ROLES_CLAIM: &str = "roles";
+    pub const DEFAULT_OIDC_ADMIN_ROLE: &str = "admin";
+    /// How long a fetched JWKS is trusted before it is refetched
+    pub const OIDC_JWKS_REFRESH_SECS: u64 = 3600;
+    /// Shortest time between JWKS fetches; tokens naming an unknown key in
+    /// between are rejected without asking the provider
+    pub const OIDC_JWKS_MIN_REFETCH_SECS: u64 = 60;
+    /// OIDC users whose token role is kept for the lookup right after
+    /// login; sessions carry the role from then on
+    pub const OIDC_MAX_KNOWN_USERS: usize = 1024;
 }
 
 /// Data directory
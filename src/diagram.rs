@@ -0,0 +1,324 @@
+//! SVG rendering of machine definitions
+//!
+//! Produces a static preview of the state graph for the machine catalog.
+//! Layout comes from the builder's `meta._builderPositions` when present,
+//! otherwise from the same grid the builder uses for new definitions.
+
+use crate::validation::TransitionGraph;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// SVG content type
+pub const CONTENT_TYPE: &str = "image/svg+xml";
+
+// Grid layout, matching the builder's defaults
+const NODE_SPACING_X: f64 = 180.0;
+const NODE_SPACING_Y: f64 = 100.0;
+const GRID_ORIGIN: f64 = 50.0;
+
+const NODE_HEIGHT: f64 = 40.0;
+const NODE_MIN_WIDTH: f64 = 100.0;
+const CHAR_WIDTH: f64 = 7.5;
+const PADDING: f64 = 40.0;
+/// Perpendicular offset for edges that have a reverse counterpart
+const CURVE_OFFSET: f64 = 30.0;
+
+#[derive(Debug, Clone, Copy)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    /// Top-left corner, as stored by the builder
+    origin: Point,
+    width: f64,
+}
+
+impl Node {
+    fn center(&self) -> Point {
+        Point {
+            x: self.origin.x + self.width / 2.0,
+            y: self.origin.y + NODE_HEIGHT / 2.0,
+        }
+    }
+
+    /// Point on the node border in the direction of `toward`
+    fn border_toward(&self, toward: Point) -> Point {
+        let c = self.center();
+        let (dx, dy) = (toward.x - c.x, toward.y - c.y);
+        if dx == 0.0 && dy == 0.0 {
+            return c;
+        }
+        let tx = if dx != 0.0 {
+            (self.width / 2.0) / dx.abs()
+        } else {
+            f64::INFINITY
+        };
+        let ty = if dy != 0.0 {
+            (NODE_HEIGHT / 2.0) / dy.abs()
+        } else {
+            f64::INFINITY
+        };
+        let t = tx.min(ty);
+        Point {
+            x: c.x + dx * t,
+            y: c.y + dy * t,
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn node_width(label: &str) -> f64 {
+    (label.chars().count() as f64 * CHAR_WIDTH + 24.0).max(NODE_MIN_WIDTH)
+}
+
+/// Node positions from `meta._builderPositions`, falling back to a grid
+fn layout<'a>(definition: &Value, states: &[&'a str]) -> HashMap<&'a str, Node> {
+    let stored = &definition["meta"]["_builderPositions"];
+    let cols = (states.len() as f64).sqrt().ceil().max(1.0) as usize;
+
+    states
+        .iter()
+        .enumerate()
+        .map(|(index, &state)| {
+            let grid = Point {
+                x: (index % cols) as f64 * NODE_SPACING_X + GRID_ORIGIN,
+                y: (index / cols) as f64 * NODE_SPACING_Y + GRID_ORIGIN,
+            };
+            let position = &stored[state];
+            let origin = match (position["x"].as_f64(), position["y"].as_f64()) {
+                (Some(x), Some(y)) => Point { x, y },
+                _ => grid,
+            };
+            let node = Node {
+                origin,
+                width: node_width(state),
+            };
+            (state, node)
+        })
+        .collect()
+}
+
+/// Render a machine definition as a standalone SVG document
+pub fn render_svg(definition: &Value) -> String {
+    let graph = TransitionGraph::from_definition(definition);
+    let initial = definition["initial"].as_str();
+    let terminal = graph.terminal_states();
+    let nodes = layout(definition, &graph.states);
+
+    // Group edges by (from, to) so parallel transitions share one labeled arrow
+    let mut pairs: Vec<((&str, &str), Vec<&str>)> = Vec::new();
+    for edge in &graph.edges {
+        if !nodes.contains_key(edge.from) || !nodes.contains_key(edge.to) {
+            continue;
+        }
+        match pairs.iter_mut().find(|(k, _)| *k == (edge.from, edge.to)) {
+            Some((_, events)) => {
+                if !events.contains(&edge.event) {
+                    events.push(edge.event);
+                }
+            }
+            None => pairs.push(((edge.from, edge.to), vec![edge.event])),
+        }
+    }
+
+    // Bounds, with headroom above each node for self-loops
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+    for (i, node) in nodes.values().enumerate() {
+        let (x0, y0) = (node.origin.x, node.origin.y - PADDING);
+        let (x1, y1) = (node.origin.x + node.width, node.origin.y + NODE_HEIGHT);
+        if i == 0 {
+            (min_x, min_y, max_x, max_y) = (x0, y0, x1, y1);
+        } else {
+            min_x = min_x.min(x0);
+            min_y = min_y.min(y0);
+            max_x = max_x.max(x1);
+            max_y = max_y.max(y1);
+        }
+    }
+    let (view_x, view_y) = (min_x - PADDING, min_y - PADDING);
+    let width = max_x - min_x + PADDING * 2.0;
+    let height = max_y - min_y + PADDING * 2.0;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{:.0} {:.0} {:.0} {:.0}" width="{:.0}" height="{:.0}" font-family="sans-serif" font-size="12">"#,
+        view_x, view_y, width, height, width, height
+    );
+    svg.push_str(
+        r##"<defs><marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="8" markerHeight="8" orient="auto-start-reverse"><path d="M0,0 L10,5 L0,10 z" fill="#64748b"/></marker></defs>
+"##,
+    );
+
+    for ((from, to), events) in &pairs {
+        let label = escape(&events.join(", "));
+        let (a, b) = (nodes[from], nodes[to]);
+
+        if from == to {
+            let c = a.center();
+            let top = a.origin.y;
+            let _ = writeln!(
+                svg,
+                r##"<path d="M{:.1},{:.1} C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}" fill="none" stroke="#64748b" marker-end="url(#arrow)"/>"##,
+                c.x - 12.0,
+                top,
+                c.x - 30.0,
+                top - 36.0,
+                c.x + 30.0,
+                top - 36.0,
+                c.x + 12.0,
+                top
+            );
+            let _ = writeln!(
+                svg,
+                r##"<text x="{:.1}" y="{:.1}" text-anchor="middle" fill="#334155">{}</text>"##,
+                c.x,
+                top - 30.0,
+                label
+            );
+            continue;
+        }
+
+        let (ca, cb) = (a.center(), b.center());
+        let curved = pairs.iter().any(|((f, t), _)| f == to && t == from);
+        let control = if curved {
+            let (dx, dy) = (cb.x - ca.x, cb.y - ca.y);
+            let len = (dx * dx + dy * dy).sqrt().max(1.0);
+            Point {
+                x: (ca.x + cb.x) / 2.0 - dy / len * CURVE_OFFSET,
+                y: (ca.y + cb.y) / 2.0 + dx / len * CURVE_OFFSET,
+            }
+        } else {
+            Point {
+                x: (ca.x + cb.x) / 2.0,
+                y: (ca.y + cb.y) / 2.0,
+            }
+        };
+        let start = a.border_toward(control);
+        let end = b.border_toward(control);
+        let _ = writeln!(
+            svg,
+            r##"<path d="M{:.1},{:.1} Q{:.1},{:.1} {:.1},{:.1}" fill="none" stroke="#64748b" marker-end="url(#arrow)"/>"##,
+            start.x, start.y, control.x, control.y, end.x, end.y
+        );
+        // Midpoint of the quadratic curve
+        let mid = Point {
+            x: 0.25 * start.x + 0.5 * control.x + 0.25 * end.x,
+            y: 0.25 * start.y + 0.5 * control.y + 0.25 * end.y,
+        };
+        let _ = writeln!(
+            svg,
+            r##"<text x="{:.1}" y="{:.1}" text-anchor="middle" fill="#334155" stroke="#ffffff" stroke-width="3" paint-order="stroke">{}</text>"##,
+            mid.x,
+            mid.y - 4.0,
+            label
+        );
+    }
+
+    for state in &graph.states {
+        let node = nodes[state];
+        let c = node.center();
+        let is_initial = initial == Some(*state);
+        let fill = if terminal.contains(state) {
+            "#f1f5f9"
+        } else {
+            "#ffffff"
+        };
+        let (stroke, stroke_width) = if is_initial {
+            ("#2563eb", 2.5)
+        } else {
+            ("#475569", 1.5)
+        };
+        let _ = writeln!(
+            svg,
+            r##"<g><rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" rx="8" fill="{}" stroke="{}" stroke-width="{}"/><text x="{:.1}" y="{:.1}" text-anchor="middle" dominant-baseline="middle" fill="#0f172a">{}</text></g>"##,
+            node.origin.x,
+            node.origin.y,
+            node.width,
+            NODE_HEIGHT,
+            fill,
+            stroke,
+            stroke_width,
+            c.x,
+            c.y,
+            escape(state)
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_nodes_and_edges() {
+        let def = json!({
+            "states": ["pending", "paid", "shipped"],
+            "initial": "pending",
+            "transitions": [
+                { "from": "pending", "event": "PAY", "to": "paid" },
+                { "from": "paid", "event": "SHIP", "to": "shipped" },
+                { "from": "paid", "event": "REFUND", "to": "pending" }
+            ]
+        });
+        let svg = render_svg(&def);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<rect").count(), 3);
+        assert_eq!(svg.matches("marker-end").count(), 3);
+        assert!(svg.contains(">PAY</text>"));
+    }
+
+    #[test]
+    fn test_builder_positions_used() {
+        let def = json!({
+            "states": ["a", "b"],
+            "initial": "a",
+            "transitions": [{ "from": "a", "event": "GO", "to": "b" }],
+            "meta": { "_builderPositions": { "a": { "x": 400, "y": 300 } } }
+        });
+        let svg = render_svg(&def);
+        assert!(svg.contains(r#"<rect x="400.0" y="300.0""#));
+        // "b" has no stored position and falls back to its grid slot
+        assert!(svg.contains(&format!(
+            r#"<rect x="{:.1}" y="50.0""#,
+            50.0 + NODE_SPACING_X
+        )));
+    }
+
+    #[test]
+    fn test_labels_escaped() {
+        let def = json!({
+            "states": ["<a>", "b&c"],
+            "initial": "<a>",
+            "transitions": [{ "from": "<a>", "event": "X\"Y", "to": "b&c" }]
+        });
+        let svg = render_svg(&def);
+        assert!(svg.contains("&lt;a&gt;"));
+        assert!(svg.contains("b&amp;c"));
+        assert!(svg.contains("X&quot;Y"));
+        assert!(!svg.contains("<a>"));
+    }
+}
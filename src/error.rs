@@ -8,7 +8,7 @@ use axum::{
 use serde::Serialize;
 use serde_json::{json, Value};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ApiError {
     pub code: String,
     pub message: String,
@@ -43,6 +43,10 @@ impl ApiError {
         Self::new("UNAUTHORIZED", "Authentication required")
     }
 
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new("FORBIDDEN", message)
+    }
+
     pub fn not_found(resource: &str) -> Self {
         Self::new("NOT_FOUND", format!("{} not found", resource))
     }
@@ -66,21 +70,84 @@ impl ApiError {
     pub fn rstmdb_error(message: impl Into<String>) -> Self {
         Self::new("RSTMDB_ERROR", message)
     }
-}
 
-impl IntoResponse for ApiError {
-    fn into_response(self) -> Response {
-        let status = match self.code.as_str() {
+    pub fn maintenance(message: impl Into<String>) -> Self {
+        Self::new("MAINTENANCE", message)
+    }
+
+    pub fn timeout(message: impl Into<String>) -> Self {
+        Self::new("TIMEOUT", message)
+    }
+
+    pub fn state_in_use(message: impl Into<String>) -> Self {
+        Self::new("STATE_IN_USE", message)
+    }
+
+    pub fn instance_paused(message: impl Into<String>) -> Self {
+        Self::new("INSTANCE_PAUSED", message)
+    }
+
+    pub fn state_mismatch(message: impl Into<String>) -> Self {
+        Self::new("STATE_MISMATCH", message)
+    }
+
+    pub fn rate_limited(message: impl Into<String>) -> Self {
+        Self::new("RATE_LIMITED", message)
+    }
+
+    pub fn body_too_large(message: impl Into<String>) -> Self {
+        Self::new("BODY_TOO_LARGE", message)
+    }
+
+    pub fn context_schema_violation(message: impl Into<String>) -> Self {
+        Self::new("CONTEXT_SCHEMA_VIOLATION", message)
+    }
+
+    /// The connected rstmdb lacks an optional operation
+    pub fn not_supported(capability: &str) -> Self {
+        Self::new(
+            "NOT_SUPPORTED",
+            format!("The rstmdb server does not support '{}'", capability),
+        )
+        .with_details(json!({ "capability": capability }))
+    }
+
+    /// The request used up its rstmdb operation budget
+    pub fn resource_exhausted(message: impl Into<String>) -> Self {
+        Self::new("RESOURCE_EXHAUSTED", message)
+    }
+
+    pub fn history_too_long(message: impl Into<String>) -> Self {
+        Self::new("HISTORY_TOO_LONG", message)
+    }
+
+    /// HTTP status for this error's code
+    pub fn status_code(&self) -> StatusCode {
+        match self.code.as_str() {
             "UNAUTHORIZED" => StatusCode::UNAUTHORIZED,
             "FORBIDDEN" => StatusCode::FORBIDDEN,
             "NOT_FOUND" => StatusCode::NOT_FOUND,
             "BAD_REQUEST" => StatusCode::BAD_REQUEST,
-            "VALIDATION_ERROR" => StatusCode::UNPROCESSABLE_ENTITY,
-            "CONFLICT" => StatusCode::CONFLICT,
+            "VALIDATION_ERROR" | "CONTEXT_SCHEMA_VIOLATION" | "HISTORY_TOO_LONG" => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            "CONFLICT" | "STATE_IN_USE" | "INSTANCE_PAUSED" | "STATE_MISMATCH" => {
+                StatusCode::CONFLICT
+            }
+            "MAINTENANCE" => StatusCode::SERVICE_UNAVAILABLE,
+            "TIMEOUT" => StatusCode::GATEWAY_TIMEOUT,
+            "BODY_TOO_LARGE" => StatusCode::PAYLOAD_TOO_LARGE,
+            "RATE_LIMITED" => StatusCode::SERVICE_UNAVAILABLE,
+            "NOT_SUPPORTED" => StatusCode::NOT_IMPLEMENTED,
+            "RESOURCE_EXHAUSTED" => StatusCode::TOO_MANY_REQUESTS,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
-        };
+        }
+    }
+}
 
-        (status, Json(json!({ "error": self }))).into_response()
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status_code(), Json(json!({ "error": self }))).into_response()
     }
 }
 
@@ -164,19 +231,25 @@ mod tests {
         assert_eq!(err.message, "Connection failed");
     }
 
+    #[test]
+    fn test_forbidden_error() {
+        let err = ApiError::forbidden("Admin role required");
+        assert_eq!(err.code, "FORBIDDEN");
+        assert_eq!(err.status_code(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_maintenance_error() {
+        let err = ApiError::maintenance("Back soon");
+        assert_eq!(err.code, "MAINTENANCE");
+        assert_eq!(err.message, "Back soon");
+    }
+
     #[test]
     fn test_status_code_mapping() {
         // Test that error codes map to correct HTTP status codes
         fn get_status(code: &str) -> StatusCode {
-            match code {
-                "UNAUTHORIZED" => StatusCode::UNAUTHORIZED,
-                "FORBIDDEN" => StatusCode::FORBIDDEN,
-                "NOT_FOUND" => StatusCode::NOT_FOUND,
-                "BAD_REQUEST" => StatusCode::BAD_REQUEST,
-                "VALIDATION_ERROR" => StatusCode::UNPROCESSABLE_ENTITY,
-                "CONFLICT" => StatusCode::CONFLICT,
-                _ => StatusCode::INTERNAL_SERVER_ERROR,
-            }
+            ApiError::new(code, "").status_code()
         }
 
         assert_eq!(get_status("UNAUTHORIZED"), StatusCode::UNAUTHORIZED);
@@ -187,7 +260,27 @@ mod tests {
             get_status("VALIDATION_ERROR"),
             StatusCode::UNPROCESSABLE_ENTITY
         );
+        assert_eq!(
+            get_status("CONTEXT_SCHEMA_VIOLATION"),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            get_status("HISTORY_TOO_LONG"),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
         assert_eq!(get_status("CONFLICT"), StatusCode::CONFLICT);
+        assert_eq!(get_status("MAINTENANCE"), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(get_status("TIMEOUT"), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(get_status("BODY_TOO_LARGE"), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(get_status("RATE_LIMITED"), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(get_status("STATE_IN_USE"), StatusCode::CONFLICT);
+        assert_eq!(get_status("INSTANCE_PAUSED"), StatusCode::CONFLICT);
+        assert_eq!(get_status("STATE_MISMATCH"), StatusCode::CONFLICT);
+        assert_eq!(get_status("NOT_SUPPORTED"), StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(
+            get_status("RESOURCE_EXHAUSTED"),
+            StatusCode::TOO_MANY_REQUESTS
+        );
         assert_eq!(
             get_status("INTERNAL_ERROR"),
             StatusCode::INTERNAL_SERVER_ERROR
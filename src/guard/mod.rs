@@ -0,0 +1,303 @@
+//! Guard expression engine
+//!
+//! Parses and evaluates the transition guard expressions produced by the guard
+//! builder, e.g. `ctx.amount > 100 && !(ctx.status == "blocked")`.
+//!
+//! Grammar:
+//!
+//! ```text
+//! or      := and ("||" and)*
+//! and     := unary ("&&" unary)*
+//! unary   := "!" unary | primary
+//! primary := "(" or ")" | operand (cmp_op operand)?
+//! operand := "ctx" ("." ident)+ | number | string | true | false | null
+//! ```
+//!
+//! A bare field (`ctx.approved`) is true when the field exists and is neither
+//! `null` nor `false`.
+
+mod parser;
+mod trace;
+
+pub use parser::parse;
+pub use trace::GuardTrace;
+
+use serde_json::Value;
+use std::fmt;
+
+/// Guard parse error
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuardError {
+    pub message: String,
+    /// Byte offset in the source expression
+    pub position: usize,
+}
+
+impl fmt::Display for GuardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for GuardError {}
+
+/// Comparison operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl CompareOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+        }
+    }
+}
+
+/// A comparison operand
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    /// Context field path, without the leading `ctx` segment
+    Field(Vec<String>),
+    Literal(Value),
+}
+
+impl Operand {
+    fn resolve<'a>(&'a self, ctx: &'a Value) -> Option<&'a Value> {
+        match self {
+            Operand::Field(path) => lookup(ctx, path),
+            Operand::Literal(v) => Some(v),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Field(path) => write!(f, "ctx.{}", path.join(".")),
+            Operand::Literal(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Parsed guard expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Or(Vec<Expr>),
+    And(Vec<Expr>),
+    Not(Box<Expr>),
+    /// Bare operand evaluated for truthiness
+    Truthy(Operand),
+    Compare {
+        left: Operand,
+        op: CompareOp,
+        right: Operand,
+    },
+}
+
+impl Expr {
+    /// Evaluate the expression against an instance context
+    pub fn evaluate(&self, ctx: &Value) -> bool {
+        match self {
+            Expr::Or(items) => items.iter().any(|e| e.evaluate(ctx)),
+            Expr::And(items) => items.iter().all(|e| e.evaluate(ctx)),
+            Expr::Not(inner) => !inner.evaluate(ctx),
+            Expr::Truthy(operand) => is_truthy(operand.resolve(ctx)),
+            Expr::Compare { left, op, right } => {
+                compare(left.resolve(ctx), *op, right.resolve(ctx))
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// Context field paths referenced by the expression, in first-use order
+    pub fn fields(&self) -> Vec<&[String]> {
+        let mut fields = Vec::new();
+        self.collect_fields(&mut fields);
+        fields
+    }
+
+    fn collect_fields<'a>(&'a self, out: &mut Vec<&'a [String]>) {
+        let mut push = |operand: &'a Operand| {
+            if let Operand::Field(path) = operand {
+                if !out.contains(&path.as_slice()) {
+                    out.push(path);
+                }
+            }
+        };
+        match self {
+            Expr::Or(items) | Expr::And(items) => {
+                items.iter().for_each(|e| e.collect_fields(out));
+            }
+            Expr::Not(inner) => inner.collect_fields(out),
+            Expr::Truthy(operand) => push(operand),
+            Expr::Compare { left, right, .. } => {
+                push(left);
+                push(right);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Or(items) | Expr::And(items) => {
+                let joiner = if matches!(self, Expr::Or(_)) {
+                    " || "
+                } else {
+                    " && "
+                };
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{}", joiner)?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Not(inner) => write!(f, "!{}", inner),
+            Expr::Truthy(operand) => write!(f, "{}", operand),
+            Expr::Compare { left, op, right } => {
+                write!(f, "{} {} {}", left, op.as_str(), right)
+            }
+        }
+    }
+}
+
+/// Parse and evaluate a guard expression against a context
+pub fn evaluate(source: &str, ctx: &Value) -> Result<bool, GuardError> {
+    Ok(parse(source)?.evaluate(ctx))
+}
+
+/// Look up a field path in a context object
+pub fn lookup<'a>(ctx: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(ctx, |v, segment| v.get(segment))
+}
+
+fn is_truthy(value: Option<&Value>) -> bool {
+    !matches!(value, None | Some(Value::Null) | Some(Value::Bool(false)))
+}
+
+fn compare(left: Option<&Value>, op: CompareOp, right: Option<&Value>) -> bool {
+    let (left, right) = match (left, right) {
+        (Some(l), Some(r)) => (l, r),
+        // Missing fields only satisfy inequality
+        _ => return op == CompareOp::Ne,
+    };
+
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => {
+            let (l, r) = (
+                l.as_f64().unwrap_or(f64::NAN),
+                r.as_f64().unwrap_or(f64::NAN),
+            );
+            match op {
+                CompareOp::Eq => l == r,
+                CompareOp::Ne => l != r,
+                CompareOp::Gt => l > r,
+                CompareOp::Ge => l >= r,
+                CompareOp::Lt => l < r,
+                CompareOp::Le => l <= r,
+            }
+        }
+        (Value::String(l), Value::String(r)) => match op {
+            CompareOp::Eq => l == r,
+            CompareOp::Ne => l != r,
+            CompareOp::Gt => l > r,
+            CompareOp::Ge => l >= r,
+            CompareOp::Lt => l < r,
+            CompareOp::Le => l <= r,
+        },
+        _ => match op {
+            CompareOp::Eq => left == right,
+            CompareOp::Ne => left != right,
+            // Ordering is only defined for numbers and strings
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_fields_deduplicated() {
+        let expr = parse("ctx.a > 1 && (ctx.b.c || ctx.a < 5)").unwrap();
+        let fields: Vec<String> = expr.fields().iter().map(|p| p.join(".")).collect();
+        assert_eq!(fields, vec!["a", "b.c"]);
+    }
+
+    #[test]
+    fn test_numeric_comparison() {
+        let ctx = json!({"score": 60});
+        assert!(evaluate("ctx.score > 50", &ctx).unwrap());
+        assert!(!evaluate("ctx.score <= 50", &ctx).unwrap());
+        assert!(evaluate("ctx.score == 60.0", &ctx).unwrap());
+    }
+
+    #[test]
+    fn test_string_and_bool_equality() {
+        let ctx = json!({"status": "open", "flag": true});
+        assert!(evaluate(r#"ctx.status == "open""#, &ctx).unwrap());
+        assert!(evaluate(r#"ctx.status != "closed""#, &ctx).unwrap());
+        assert!(evaluate("ctx.flag == true", &ctx).unwrap());
+    }
+
+    #[test]
+    fn test_truthiness_and_negation() {
+        let ctx = json!({"approved": true, "rejected": false, "note": null});
+        assert!(evaluate("ctx.approved", &ctx).unwrap());
+        assert!(!evaluate("ctx.rejected", &ctx).unwrap());
+        assert!(!evaluate("ctx.note", &ctx).unwrap());
+        assert!(evaluate("!ctx.missing", &ctx).unwrap());
+    }
+
+    #[test]
+    fn test_logic_and_grouping() {
+        let ctx = json!({"amount": 150, "approved": false, "vip": true});
+        assert!(!evaluate("ctx.amount > 100 && ctx.approved", &ctx).unwrap());
+        assert!(evaluate("ctx.amount > 100 && (ctx.approved || ctx.vip)", &ctx).unwrap());
+        assert!(evaluate("!(ctx.amount < 100)", &ctx).unwrap());
+    }
+
+    #[test]
+    fn test_nested_fields() {
+        let ctx = json!({"customer": {"tier": "gold"}});
+        assert!(evaluate(r#"ctx.customer.tier == "gold""#, &ctx).unwrap());
+        assert!(!evaluate("ctx.customer.missing > 1", &ctx).unwrap());
+    }
+
+    #[test]
+    fn test_missing_field_comparisons() {
+        let ctx = json!({});
+        assert!(!evaluate("ctx.score > 1", &ctx).unwrap());
+        assert!(!evaluate("ctx.score == 1", &ctx).unwrap());
+        assert!(evaluate("ctx.score != 1", &ctx).unwrap());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("ctx.a >").is_err());
+        assert!(parse("score > 1").is_err());
+        assert!(parse("(ctx.a > 1").is_err());
+        assert!(parse(r#"ctx.a == "unterminated"#).is_err());
+        assert!(parse("ctx.a > 1 ctx.b").is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! Guard expression tokenizer and recursive-descent parser
+
+use super::{CompareOp, Expr, GuardError, Operand};
+use serde_json::{Number, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(Number),
+    Str(String),
+    Dot,
+    Not,
+    And,
+    Or,
+    LParen,
+    RParen,
+    Cmp(CompareOp),
+}
+
+fn error(message: impl Into<String>, position: usize) -> GuardError {
+    GuardError {
+        message: message.into(),
+        position,
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, GuardError> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        let start = i;
+
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let two = source.get(i..i + 2).unwrap_or("");
+        let token = match two {
+            "&&" => Some(Token::And),
+            "||" => Some(Token::Or),
+            "==" => Some(Token::Cmp(CompareOp::Eq)),
+            "!=" => Some(Token::Cmp(CompareOp::Ne)),
+            ">=" => Some(Token::Cmp(CompareOp::Ge)),
+            "<=" => Some(Token::Cmp(CompareOp::Le)),
+            _ => None,
+        };
+        if let Some(token) = token {
+            tokens.push((token, start));
+            i += 2;
+            continue;
+        }
+
+        let token = match c {
+            '!' => Token::Not,
+            '>' => Token::Cmp(CompareOp::Gt),
+            '<' => Token::Cmp(CompareOp::Lt),
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '.' => Token::Dot,
+            '"' => {
+                let mut value = String::new();
+                i += 1;
+                let mut closed = false;
+                while i < bytes.len() {
+                    let ch = source[i..].chars().next().unwrap_or('\0');
+                    match ch {
+                        '"' => {
+                            closed = true;
+                            i += 1;
+                            break;
+                        }
+                        '\\' if i + 1 < bytes.len() => {
+                            let escaped = source[i + 1..].chars().next().unwrap_or('\0');
+                            value.push(escaped);
+                            i += 1 + escaped.len_utf8();
+                        }
+                        _ => {
+                            value.push(ch);
+                            i += ch.len_utf8();
+                        }
+                    }
+                }
+                if !closed {
+                    return Err(error("Unterminated string literal", start));
+                }
+                tokens.push((Token::Str(value), start));
+                continue;
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                i += 1;
+                while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') {
+                    i += 1;
+                }
+                let text = &source[start..i];
+                let number = if text.contains('.') {
+                    text.parse::<f64>().ok().and_then(Number::from_f64)
+                } else {
+                    text.parse::<i64>().ok().map(Number::from)
+                };
+                let number =
+                    number.ok_or_else(|| error(format!("Invalid number '{}'", text), start))?;
+                tokens.push((Token::Number(number), start));
+                continue;
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                tokens.push((Token::Ident(source[start..i].to_string()), start));
+                continue;
+            }
+            other => return Err(error(format!("Unexpected character '{}'", other), start)),
+        };
+        tokens.push((token, start));
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map(|(_, p)| *p)
+            .unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(t, _)| t.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, GuardError> {
+        let mut items = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            items.push(self.parse_and()?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Expr::Or(items)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, GuardError> {
+        let mut items = vec![self.parse_unary()?];
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            items.push(self.parse_unary()?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Expr::And(items)
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, GuardError> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, GuardError> {
+        if self.peek() == Some(&Token::LParen) {
+            self.next();
+            let expr = self.parse_or()?;
+            if self.peek() != Some(&Token::RParen) {
+                return Err(error("Expected ')'", self.position()));
+            }
+            self.next();
+            return Ok(expr);
+        }
+
+        let left = self.parse_operand()?;
+        if let Some(Token::Cmp(op)) = self.peek().cloned() {
+            self.next();
+            let right = self.parse_operand()?;
+            return Ok(Expr::Compare { left, op, right });
+        }
+        Ok(Expr::Truthy(left))
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand, GuardError> {
+        let position = self.position();
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Operand::Literal(Value::Number(n))),
+            Some(Token::Str(s)) => Ok(Operand::Literal(Value::String(s))),
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "true" => Ok(Operand::Literal(Value::Bool(true))),
+                "false" => Ok(Operand::Literal(Value::Bool(false))),
+                "null" => Ok(Operand::Literal(Value::Null)),
+                "ctx" => {
+                    let mut path = Vec::new();
+                    while self.peek() == Some(&Token::Dot) {
+                        self.next();
+                        let segment_pos = self.position();
+                        match self.next() {
+                            Some(Token::Ident(segment)) => path.push(segment),
+                            _ => return Err(error("Expected field name after '.'", segment_pos)),
+                        }
+                    }
+                    if path.is_empty() {
+                        return Err(error("Expected 'ctx.<field>'", position));
+                    }
+                    Ok(Operand::Field(path))
+                }
+                other => Err(error(
+                    format!(
+                        "Unknown identifier '{}', fields must start with 'ctx.'",
+                        other
+                    ),
+                    position,
+                )),
+            },
+            Some(_) => Err(error("Expected a field or literal", position)),
+            None => Err(error("Unexpected end of expression", position)),
+        }
+    }
+}
+
+/// Parse a guard expression
+pub fn parse(source: &str) -> Result<Expr, GuardError> {
+    let tokens = tokenize(source)?;
+    if tokens.is_empty() {
+        return Err(error("Empty guard expression", 0));
+    }
+
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: source.len(),
+    };
+    let expr = parser.parse_or()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(error("Unexpected trailing input", parser.position()));
+    }
+    Ok(expr)
+}
@@ -0,0 +1,241 @@
+//! Guard evaluation traces
+//!
+//! A trace mirrors the expression tree with each sub-expression's result and
+//! the context values it read, so a failed guard can be explained as
+//! `ctx.score was 40, needed > 50` instead of just "guard failed". Unlike
+//! [`Expr::evaluate`], every branch is evaluated, so clauses after the first
+//! failing one still show their values.
+
+use super::{compare, is_truthy, Expr, Operand};
+use serde::Serialize;
+use serde_json::Value;
+
+/// An operand and the value it resolved to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TracedOperand {
+    /// `ctx.path` or a literal
+    pub operand: String,
+    /// `None` when the field is missing from the context
+    pub value: Option<Value>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub missing: bool,
+}
+
+/// One evaluated sub-expression
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraceNode {
+    pub expression: String,
+    pub result: bool,
+    /// Operands read by a comparison or truthiness check
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub operands: Vec<TracedOperand>,
+    /// Sub-expressions of `&&`, `||` and `!`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TraceNode>,
+}
+
+/// Evaluation of a whole guard against a context
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GuardTrace {
+    /// The guard as written in the definition
+    pub guard: String,
+    pub result: bool,
+    /// The clause that made the guard fail: the first false operand of an
+    /// `&&`, followed down to a comparison where possible
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_clause: Option<String>,
+    /// Why `failed_clause` was false, e.g. `ctx.score was 40, needed > 50`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+    pub root: TraceNode,
+}
+
+impl Expr {
+    /// Evaluate the expression, recording every sub-expression's result
+    pub fn trace(&self, source: &str, ctx: &Value) -> GuardTrace {
+        let root = self.trace_node(ctx);
+        let blamed = if root.result { None } else { self.blame(&root) };
+        GuardTrace {
+            guard: source.to_string(),
+            result: root.result,
+            failed_clause: blamed.map(|(_, node)| node.expression.clone()),
+            explanation: blamed.map(|(expr, node)| expr.explain(node)),
+            root,
+        }
+    }
+
+    fn trace_node(&self, ctx: &Value) -> TraceNode {
+        let (result, operands, children) = match self {
+            Expr::Or(items) | Expr::And(items) => {
+                let children: Vec<TraceNode> = items.iter().map(|e| e.trace_node(ctx)).collect();
+                let result = if matches!(self, Expr::Or(_)) {
+                    children.iter().any(|c| c.result)
+                } else {
+                    children.iter().all(|c| c.result)
+                };
+                (result, Vec::new(), children)
+            }
+            Expr::Not(inner) => {
+                let child = inner.trace_node(ctx);
+                (!child.result, Vec::new(), vec![child])
+            }
+            Expr::Truthy(operand) => (
+                is_truthy(operand.resolve(ctx)),
+                vec![traced(operand, ctx)],
+                Vec::new(),
+            ),
+            Expr::Compare { left, op, right } => (
+                compare(left.resolve(ctx), *op, right.resolve(ctx)),
+                vec![traced(left, ctx), traced(right, ctx)],
+                Vec::new(),
+            ),
+        };
+        TraceNode {
+            expression: self.to_string(),
+            result,
+            operands,
+            children,
+        }
+    }
+
+    /// The sub-expression responsible for `node` (this expression's trace)
+    /// being false. An `||` or `!` has no single failing operand, so it is
+    /// blamed as a whole.
+    fn blame<'a>(&'a self, node: &'a TraceNode) -> Option<(&'a Expr, &'a TraceNode)> {
+        if node.result {
+            return None;
+        }
+        match self {
+            Expr::And(items) => items
+                .iter()
+                .zip(&node.children)
+                .find(|(_, child)| !child.result)
+                .and_then(|(item, child)| item.blame(child)),
+            _ => Some((self, node)),
+        }
+    }
+
+    /// Why this false sub-expression was false
+    fn explain(&self, node: &TraceNode) -> String {
+        match (self, node.operands.as_slice()) {
+            (
+                Expr::Compare {
+                    op,
+                    right: Operand::Literal(expected),
+                    ..
+                },
+                [left, _],
+            ) => format!(
+                "{} was {}, needed {} {}",
+                left.operand,
+                shown(left),
+                op.as_str(),
+                expected
+            ),
+            (Expr::Compare { op, .. }, [left, right]) => format!(
+                "{} was {} and {} was {}, needed {} {} {}",
+                left.operand,
+                shown(left),
+                right.operand,
+                shown(right),
+                left.operand,
+                op.as_str(),
+                right.operand
+            ),
+            (Expr::Truthy(_), [operand]) => format!(
+                "{} was {}, needed a value other than null or false",
+                operand.operand,
+                shown(operand)
+            ),
+            (Expr::Not(_), _) => format!("{} held, needed it not to", inner_expression(node)),
+            _ => format!("none of {} held", node.expression),
+        }
+    }
+}
+
+fn traced(operand: &Operand, ctx: &Value) -> TracedOperand {
+    let value = operand.resolve(ctx).cloned();
+    TracedOperand {
+        operand: operand.to_string(),
+        missing: value.is_none(),
+        value,
+    }
+}
+
+fn shown(operand: &TracedOperand) -> String {
+    match &operand.value {
+        Some(value) => value.to_string(),
+        None => "missing".to_string(),
+    }
+}
+
+fn inner_expression(node: &TraceNode) -> &str {
+    node.children
+        .first()
+        .map_or(node.expression.as_str(), |c| c.expression.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::guard::parse;
+    use serde_json::json;
+
+    #[test]
+    fn test_trace_blames_first_failing_clause() {
+        let source = r#"ctx.approved && ctx.score > 50 && ctx.region == "eu""#;
+        let ctx = json!({"approved": true, "score": 40});
+        let expr = parse(source).unwrap();
+        let trace = expr.trace(source, &ctx);
+
+        assert!(!trace.result);
+        assert_eq!(trace.result, expr.evaluate(&ctx));
+        assert_eq!(trace.failed_clause.as_deref(), Some("ctx.score > 50"));
+        assert_eq!(
+            trace.explanation.as_deref(),
+            Some("ctx.score was 40, needed > 50")
+        );
+
+        // Clauses after the failing one are still evaluated
+        let children = &trace.root.children;
+        assert_eq!(children.len(), 3);
+        assert!(children[0].result);
+        assert_eq!(children[1].operands[0].value, Some(json!(40)));
+        assert!(children[2].operands[0].missing);
+        assert_eq!(children[2].operands[0].value, None);
+    }
+
+    #[test]
+    fn test_trace_of_passing_and_alternative_guards() {
+        let ctx = json!({"score": 60, "vip": false});
+        let passing = parse("ctx.score > 50")
+            .unwrap()
+            .trace("ctx.score > 50", &ctx);
+        assert!(passing.result);
+        assert_eq!(passing.failed_clause, None);
+        assert_eq!(passing.explanation, None);
+
+        let source = "ctx.vip || ctx.score > 100";
+        let either = parse(source).unwrap().trace(source, &ctx);
+        assert!(!either.result);
+        assert_eq!(
+            either.failed_clause.as_deref(),
+            Some("(ctx.vip || ctx.score > 100)")
+        );
+        assert_eq!(
+            either.explanation.as_deref(),
+            Some("none of (ctx.vip || ctx.score > 100) held")
+        );
+
+        let negated = parse("!ctx.score").unwrap().trace("!ctx.score", &ctx);
+        assert_eq!(
+            negated.explanation.as_deref(),
+            Some("ctx.score held, needed it not to")
+        );
+
+        let missing = parse("ctx.tier").unwrap().trace("ctx.tier", &ctx);
+        assert_eq!(
+            missing.explanation.as_deref(),
+            Some("ctx.tier was missing, needed a value other than null or false")
+        );
+    }
+}
@@ -24,6 +24,12 @@ pub trait ValueExt {
     /// Extract an i64 field with a default value
     fn i64_or(&self, key: &str, default: i64) -> i64;
 
+    /// Extract a numeric field as its exact source text.
+    ///
+    /// With the `lossless-numbers` feature this preserves integers beyond
+    /// 64 bits and long decimals that `u64_opt`/`i64_or` cannot represent.
+    fn number_str(&self, key: &str) -> Option<String>;
+
     /// Extract an array of u32 values from a field
     fn u32_array(&self, key: &str) -> Vec<u32>;
 
@@ -56,6 +62,13 @@ impl ValueExt for Value {
         self[key].as_i64().unwrap_or(default)
     }
 
+    fn number_str(&self, key: &str) -> Option<String> {
+        match &self[key] {
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        }
+    }
+
     fn u32_array(&self, key: &str) -> Vec<u32> {
         self[key]
             .as_array()
@@ -135,6 +148,34 @@ mod tests {
         assert_eq!(v.i64_or("missing", -1), -1);
     }
 
+    #[test]
+    fn test_number_str() {
+        let v = json!({"count": 42, "name": "x"});
+        assert_eq!(v.number_str("count"), Some("42".to_string()));
+        assert_eq!(v.number_str("name"), None);
+        assert_eq!(v.number_str("missing"), None);
+    }
+
+    #[cfg(feature = "lossless-numbers")]
+    #[test]
+    fn test_large_numbers_round_trip_losslessly() {
+        let raw =
+            r#"{"ctx":{"account":123456789012345678901234567890,"rate":0.10000000000000000555}}"#;
+        let v: Value = serde_json::from_str(raw).unwrap();
+        let ctx = &v["ctx"];
+
+        assert_eq!(ctx.u64_opt("account"), None);
+        assert_eq!(
+            ctx.number_str("account"),
+            Some("123456789012345678901234567890".to_string())
+        );
+        assert_eq!(
+            ctx.number_str("rate"),
+            Some("0.10000000000000000555".to_string())
+        );
+        assert_eq!(serde_json::to_string(&v).unwrap(), raw);
+    }
+
     #[test]
     fn test_u32_array() {
         let v = json!({"versions": [1, 2, 3]});
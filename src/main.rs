@@ -2,27 +2,43 @@
 
 mod api;
 mod auth;
+mod checksum;
 mod config;
 mod constants;
+mod diagram;
 mod error;
+mod guard;
 mod json_ext;
+mod metrics;
+mod readiness;
+mod redact;
+mod reload;
 mod rstmdb;
+mod selftest;
 mod static_files;
+mod supervisor;
+mod tls;
+mod unified_diff;
 mod validation;
 
-use crate::config::Config;
+use crate::config::{AuthBackendKind, Config, RequestTimeouts, SessionTimeouts};
+use crate::constants::capabilities;
+use crate::error::ApiError;
 use crate::rstmdb::StudioClient;
-use crate::static_files::static_handler;
 use axum::{
-    routing::{get, post},
-    Router,
+    extract::Request,
+    middleware::{self, Next},
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Router, ServiceExt,
 };
 use clap::{Parser, Subcommand};
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
+use tower_http::normalize_path::NormalizePath;
 use tower_http::trace::TraceLayer;
-use tower_sessions::{MemoryStore, SessionManagerLayer};
+use tower_sessions::{Expiry, MemoryStore, SessionManagerLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 #[derive(Parser)]
@@ -49,13 +65,18 @@ enum Commands {
         /// Data directory
         #[arg(long, default_value = "~/.rstmdb-studio")]
         data_dir: PathBuf,
+
+        /// Configuration file path, for `auth` settings; repeat to layer files
+        #[arg(short, long, default_value = "studio.yaml")]
+        config: Vec<PathBuf>,
     },
 
     /// Start the web server
     Serve {
-        /// Configuration file path
+        /// Configuration file path; repeat to layer files, later ones
+        /// overriding earlier ones
         #[arg(short, long, default_value = "studio.yaml")]
-        config: PathBuf,
+        config: Vec<PathBuf>,
 
         /// Server bind address
         #[arg(long, env = "STUDIO_HOST", default_value = "0.0.0.0")]
@@ -73,20 +94,241 @@ enum Commands {
         #[arg(long, env = "RSTMDB_TOKEN")]
         rstmdb_token: Option<String>,
     },
+
+    /// Back up or migrate the users in auth.json
+    Users {
+        #[command(subcommand)]
+        command: UsersCommand,
+    },
+
+    /// Verify a deployment by running a full create/apply/delete round trip
+    Selftest {
+        /// Configuration file path; repeat to layer files, later ones
+        /// overriding earlier ones
+        #[arg(short, long, default_value = "studio.yaml")]
+        config: Vec<PathBuf>,
+
+        /// rstmdb server address
+        #[arg(long, env = "RSTMDB_ADDR", default_value = "127.0.0.1:7401")]
+        rstmdb_addr: String,
+
+        /// rstmdb auth token
+        #[arg(long, env = "RSTMDB_TOKEN")]
+        rstmdb_token: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum UsersCommand {
+    /// Print all users, with password hashes, as JSON
+    Export {
+        /// Data directory
+        #[arg(long, default_value = "~/.rstmdb-studio")]
+        data_dir: PathBuf,
+
+        /// Configuration file path, for `auth` settings; repeat to layer files
+        #[arg(short, long, default_value = "studio.yaml")]
+        config: Vec<PathBuf>,
+    },
+
+    /// Merge users from a `users export` file into auth.json
+    Import {
+        /// Export file to read; stdin when omitted
+        file: Option<PathBuf>,
+
+        /// Data directory
+        #[arg(long, default_value = "~/.rstmdb-studio")]
+        data_dir: PathBuf,
+
+        /// What to do with users that already exist
+        #[arg(long, value_enum, default_value = "fail")]
+        on_collision: auth::UserCollision,
+
+        /// Configuration file path, for `auth` settings; repeat to layer files
+        #[arg(short, long, default_value = "studio.yaml")]
+        config: Vec<PathBuf>,
+    },
 }
 
 /// Application state shared across handlers
 pub struct AppState {
     pub config: Config,
     pub rstmdb: StudioClient,
-    pub auth_store: auth::AuthStore,
+    pub auth_store: Arc<auth::AuthStore>,
+    pub auth_backend: Arc<dyn auth::AuthBackend>,
+    pub session_timeouts: SessionTimeouts,
+    pub request_timeouts: RequestTimeouts,
+    pub in_flight: Arc<api::concurrency::InFlightLimit>,
+    pub http_latency: Arc<metrics::Histogram>,
+    pub maintenance: api::maintenance::MaintenanceMode,
+    pub transition_usage: api::transition_usage::TransitionUsageCache,
+    pub definition_checksums: api::duplicates::ChecksumCache,
+    pub machine_counts: api::machine_counts::MachineCountsCache,
+    pub applied_events: api::event_dedup::AppliedEvents,
+    pub instance_ttl: api::instance_ttl::InstanceTtl,
+    pub keepalive: rstmdb::KeepaliveStatus,
+    pub wal_history: api::wal_history::WalHistory,
+    pub break_glass: auth::BreakGlass,
+    pub sessions: auth::SessionRegistry,
+    pub archive: api::archive::ArchiveStore,
+    pub paused: api::pause::PauseStore,
+    pub drafts: api::drafts::DraftStore,
+    /// Background tasks and their restarts
+    pub tasks: supervisor::Supervisor,
+    /// Parsed `readiness.max_task_downtime`
+    pub max_task_downtime: std::time::Duration,
+    /// Parsed `import.timeout`
+    pub import_timeout: std::time::Duration,
+    pub redactor: redact::Redactor,
+    /// Settings that can change on config reload
+    pub live: parking_lot::RwLock<reload::LiveSettings>,
+    /// Config currently in effect, including reloaded live settings
+    pub running_config: parking_lot::Mutex<Config>,
+    pub config_source: reload::ConfigSource,
+    pub log_handle: reload::LogHandle,
+}
+
+impl AppState {
+    /// State for serving with `config` over `rstmdb`; fails on settings that
+    /// don't parse
+    pub fn new(
+        config: Config,
+        rstmdb: StudioClient,
+        config_source: reload::ConfigSource,
+        log_handle: reload::LogHandle,
+    ) -> anyhow::Result<Self> {
+        let session_timeouts = config.auth.session_timeouts()?;
+        let request_timeouts = config.server.timeouts.request_timeouts()?;
+        let redactor = redact::Redactor::new(&config.redaction.paths)?;
+        let (wal_history_interval, wal_history_retention) = config.wal_history.durations()?;
+        let event_dedup_window = config.server.event_dedup_window()?;
+        let instance_ttl_rules = config.instance_ttl.rules()?;
+        let max_task_downtime = config.readiness.max_task_downtime()?;
+        let import_timeout = config.import.timeout()?;
+
+        // Load auth store
+        let data_dir = PathBuf::from(shellexpand::tilde(&config.data_dir).to_string());
+        let auth_path = data_dir.join("auth.json");
+        let auth_store = Arc::new(
+            auth::AuthStore::new(&auth_path)
+                .with_case_insensitive_usernames(config.auth.case_insensitive_usernames)?,
+        );
+
+        let auth_backend: Arc<dyn auth::AuthBackend> = match config.auth.backend {
+            AuthBackendKind::Local => {
+                if !auth_store.has_users() {
+                    tracing::warn!(
+                        "No admin user configured. Create one in the web UI or run 'rstmdb-studio init'."
+                    );
+                }
+                auth_store.clone()
+            }
+            AuthBackendKind::Oidc => {
+                let oidc = config.auth.oidc.clone().ok_or_else(|| {
+                    anyhow::anyhow!("auth.backend is 'oidc' but no auth.oidc section is configured")
+                })?;
+                tracing::info!(issuer = %oidc.issuer, "Using OIDC authentication");
+                Arc::new(auth::OidcBackend::new(oidc))
+            }
+        };
+
+        let break_glass = if !config.auth.break_glass.enabled {
+            auth::BreakGlass::default()
+        } else if config.auth.backend != AuthBackendKind::Local {
+            tracing::warn!(
+                "auth.break_glass is only supported with the local auth backend; ignoring"
+            );
+            auth::BreakGlass::default()
+        } else {
+            let ttl = config.auth.break_glass.ttl()?;
+            let (break_glass, token) = auth::BreakGlass::issue(ttl);
+            tracing::warn!(
+                ttl = %humantime::format_duration(ttl),
+                "BREAK-GLASS ACCESS ENABLED: a single-use admin recovery token was printed to the console"
+            );
+            println!("\n  ================= BREAK-GLASS RECOVERY TOKEN =================");
+            println!("  {}", token);
+            println!(
+                "  Single use, valid for {}. POST it with the account to recover to",
+                humantime::format_duration(ttl)
+            );
+            println!("  /api/v1/auth/break-glass, then reset the password.");
+            println!("  Disable auth.break_glass once access is restored.");
+            println!("  ==============================================================\n");
+            break_glass
+        };
+
+        Ok(AppState {
+            config: config.clone(),
+            rstmdb,
+            auth_store,
+            auth_backend,
+            session_timeouts,
+            request_timeouts,
+            in_flight: Arc::new(api::concurrency::InFlightLimit::new(
+                config.server.max_in_flight_requests,
+            )),
+            http_latency: Default::default(),
+            maintenance: Default::default(),
+            transition_usage: Default::default(),
+            definition_checksums: Default::default(),
+            machine_counts: Default::default(),
+            applied_events: api::event_dedup::AppliedEvents::new(event_dedup_window),
+            instance_ttl: api::instance_ttl::InstanceTtl::new(
+                config.instance_ttl.enabled,
+                instance_ttl_rules,
+            ),
+            keepalive: Default::default(),
+            wal_history: api::wal_history::WalHistory::new(
+                wal_history_interval,
+                wal_history_retention,
+            ),
+            break_glass,
+            sessions: Default::default(),
+            archive: api::archive::ArchiveStore::new(&data_dir.join("archived.json")),
+            paused: api::pause::PauseStore::new(&data_dir.join("paused.json")),
+            drafts: api::drafts::DraftStore::new(&data_dir.join("drafts.json")),
+            tasks: supervisor::Supervisor::new(Default::default()),
+            max_task_downtime,
+            import_timeout,
+            redactor,
+            live: parking_lot::RwLock::new(reload::LiveSettings::from_config(&config)),
+            running_config: parking_lot::Mutex::new(config.clone()),
+            config_source,
+            log_handle,
+        })
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    /// State over an in-memory rstmdb, with a fresh data directory
+    pub fn for_test(mut config: Config, rstmdb: rstmdb::FakeRstmdb) -> Arc<Self> {
+        let data_dir =
+            std::env::temp_dir().join(format!("rstmdb-studio-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        config.data_dir = data_dir.to_string_lossy().into_owned();
+        let config_source = reload::ConfigSource {
+            paths: Vec::new(),
+            host: config.server.host.clone(),
+            port: config.server.port,
+            rstmdb_addr: config.rstmdb.address.clone(),
+            rstmdb_token: None,
+        };
+        let (_, log_handle) = tracing_subscriber::reload::Layer::new(EnvFilter::new("info"));
+        let client = StudioClient::with_fake(&config.rstmdb, rstmdb);
+        Arc::new(Self::new(config, client, config_source, log_handle).unwrap())
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // Initialize logging
+    // Initialize logging; the filter can be replaced once config is loaded
+    let (log_filter, log_handle) = tracing_subscriber::reload::Layer::new(
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    );
     tracing_subscriber::registry()
-        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(log_filter)
         .with(tracing_subscriber::fmt::layer())
         .init();
 
@@ -97,8 +339,9 @@ async fn main() -> anyhow::Result<()> {
             admin_user,
             admin_pass,
             data_dir,
+            config,
         } => {
-            init_admin(&admin_user, &admin_pass, &data_dir).await?;
+            init_admin(&admin_user, &admin_pass, &data_dir, &config).await?;
         }
         Commands::Serve {
             config,
@@ -107,7 +350,33 @@ async fn main() -> anyhow::Result<()> {
             rstmdb_addr,
             rstmdb_token,
         } => {
-            serve(config, &host, port, &rstmdb_addr, rstmdb_token).await?;
+            let source = reload::ConfigSource {
+                paths: config,
+                host,
+                port,
+                rstmdb_addr,
+                rstmdb_token,
+            };
+            serve(source, log_handle).await?;
+        }
+        Commands::Users { command } => {
+            manage_users(command)?;
+        }
+        Commands::Selftest {
+            config,
+            rstmdb_addr,
+            rstmdb_token,
+        } => {
+            let config = Config::load(
+                &config,
+                constants::server::DEFAULT_HOST,
+                constants::server::DEFAULT_PORT,
+                &rstmdb_addr,
+                rstmdb_token,
+            )?;
+            if !selftest::run(&config.rstmdb).await {
+                anyhow::bail!("Self-test failed");
+            }
         }
     }
 
@@ -118,6 +387,7 @@ async fn init_admin(
     username: &str,
     password: &str,
     data_dir: &std::path::Path,
+    config_paths: &[PathBuf],
 ) -> anyhow::Result<()> {
     let data_dir = shellexpand::tilde(&data_dir.to_string_lossy()).to_string();
     let data_dir = PathBuf::from(data_dir);
@@ -127,7 +397,7 @@ async fn init_admin(
 
     // Initialize auth store
     let auth_path = data_dir.join("auth.json");
-    let auth_store = auth::AuthStore::new(&auth_path);
+    let auth_store = open_auth_store(&auth_path, config_paths)?;
 
     // Create admin user
     auth_store.create_user(username, password)?;
@@ -144,15 +414,80 @@ async fn init_admin(
     Ok(())
 }
 
+/// The auth store at `auth_path`, matching usernames the way `serve` does
+/// with the same config files
+fn open_auth_store(
+    auth_path: &PathBuf,
+    config_paths: &[PathBuf],
+) -> anyhow::Result<auth::AuthStore> {
+    let config = Config::load(
+        config_paths,
+        constants::server::DEFAULT_HOST,
+        constants::server::DEFAULT_PORT,
+        constants::rstmdb::DEFAULT_ADDRESS,
+        None,
+    )?;
+    auth::AuthStore::new(auth_path)
+        .with_case_insensitive_usernames(config.auth.case_insensitive_usernames)
+}
+
+fn manage_users(command: UsersCommand) -> anyhow::Result<()> {
+    let auth_path = |data_dir: &std::path::Path| {
+        PathBuf::from(shellexpand::tilde(&data_dir.to_string_lossy()).to_string()).join("auth.json")
+    };
+
+    match command {
+        UsersCommand::Export { data_dir, config } => {
+            let auth_path = auth_path(&data_dir);
+            if !auth_path.exists() {
+                anyhow::bail!("No auth data at {}", auth_path.display());
+            }
+            let export = open_auth_store(&auth_path, &config)?.export_users();
+            println!("{}", serde_json::to_string_pretty(&export)?);
+        }
+        UsersCommand::Import {
+            file,
+            data_dir,
+            on_collision,
+            config,
+        } => {
+            let content = match &file {
+                Some(path) => std::fs::read_to_string(path)
+                    .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?,
+                None => std::io::read_to_string(std::io::stdin())?,
+            };
+            let export = auth::UserExport::parse(&content)?;
+
+            let auth_path = auth_path(&data_dir);
+            let summary =
+                open_auth_store(&auth_path, &config)?.import_users(export, on_collision)?;
+            println!(
+                "Imported users into {}: {} created, {} overwritten, {} skipped",
+                auth_path.display(),
+                summary.created.len(),
+                summary.overwritten.len(),
+                summary.skipped.len()
+            );
+            if !summary.skipped.is_empty() {
+                println!("Skipped existing users: {}", summary.skipped.join(", "));
+            }
+        }
+    }
+
+    Ok(())
+}
+
 async fn serve(
-    config_path: PathBuf,
-    host: &str,
-    port: u16,
-    rstmdb_addr: &str,
-    rstmdb_token: Option<String>,
+    config_source: reload::ConfigSource,
+    log_handle: reload::LogHandle,
 ) -> anyhow::Result<()> {
     // Load configuration
-    let config = Config::load(&config_path, host, port, rstmdb_addr, rstmdb_token)?;
+    let config = config_source.load()?;
+    reload::apply_log_level(&log_handle, config.log_level.as_deref())?;
+    let keepalive_interval = config.rstmdb.keepalive.interval()?;
+    let machine_counts_interval = config.machine_counts.interval()?;
+    let instance_ttl_interval = config.instance_ttl.interval()?;
+    let cache_follow_interval = config.rstmdb.instance_cache.follow_interval()?;
 
     tracing::info!(
         rstmdb_addr = %config.rstmdb.address,
@@ -164,89 +499,604 @@ async fn serve(
 
     tracing::info!("Connected to rstmdb server");
 
-    // Load auth store
-    let auth_path =
-        PathBuf::from(shellexpand::tilde(&config.data_dir).to_string()).join("auth.json");
-    let auth_store = auth::AuthStore::new(&auth_path);
+    let state = Arc::new(AppState::new(
+        config.clone(),
+        rstmdb,
+        config_source,
+        log_handle,
+    )?);
+
+    reload::spawn_sighup_handler(state.clone())?;
 
-    if !auth_store.has_users() {
-        tracing::warn!("No admin user configured. Run 'rstmdb-studio init' to create one.");
+    // The UI falls back to the dashboard, so an unknown machine isn't fatal
+    if let Some(machine) = &config.ui.default_machine {
+        if let Err(e) = api::machines::latest_version(&state, machine).await {
+            tracing::warn!(
+                machine = %machine,
+                error = %e.message,
+                "ui.default_machine could not be found; the UI will open on the dashboard"
+            );
+        }
     }
 
-    // Create app state
-    let state = Arc::new(AppState {
-        config: config.clone(),
-        rstmdb,
-        auth_store,
-    });
+    // Keepalive and the TTL sweeper are critical: readiness fails while
+    // either stays down
+    if config.rstmdb.keepalive.enabled {
+        let task_state = state.clone();
+        let failure_threshold = config.rstmdb.keepalive.failure_threshold;
+        state.tasks.spawn("keepalive", true, move || {
+            let state = task_state.clone();
+            async move {
+                rstmdb::run_keepalive(
+                    &state.rstmdb,
+                    &state.keepalive,
+                    keepalive_interval,
+                    failure_threshold,
+                )
+                .await;
+            }
+        });
+    }
+
+    if config.rstmdb.instance_cache.enabled && config.rstmdb.instance_cache.follow_wal {
+        let task_state = state.clone();
+        state.tasks.spawn("instance_cache_wal", false, move || {
+            let state = task_state.clone();
+            async move {
+                rstmdb::run_cache_follower(&state.rstmdb, cache_follow_interval).await;
+            }
+        });
+    }
+
+    if config.wal_history.enabled {
+        let task_state = state.clone();
+        state.tasks.spawn("wal_sampler", false, move || {
+            let state = task_state.clone();
+            async move {
+                api::wal_history::run_sampler(&state.rstmdb, &state.wal_history).await;
+            }
+        });
+    }
+
+    if config.machine_counts.enabled {
+        let task_state = state.clone();
+        let min_machines = config.machine_counts.min_machines;
+        state.tasks.spawn("machine_counts", false, move || {
+            api::machine_counts::run_refresher(
+                task_state.clone(),
+                machine_counts_interval,
+                min_machines,
+            )
+        });
+    }
+
+    if config.instance_ttl.enabled {
+        let task_state = state.clone();
+        state.tasks.spawn("ttl_sweeper", true, move || {
+            api::instance_ttl::run_sweeper(task_state.clone(), instance_ttl_interval)
+        });
+    }
 
     // Build router
-    let app = create_router(state);
+    let app = normalize_trailing_slash(create_router(state));
+    let acceptor = config
+        .server
+        .tls
+        .enabled
+        .then(|| tls::acceptor(&config.server.tls))
+        .transpose()?;
 
     // Start server
     let addr: SocketAddr = format!("{}:{}", config.server.host, config.server.port).parse()?;
     let listener = tokio::net::TcpListener::bind(addr).await?;
 
-    tracing::info!(%addr, "Starting rstmdb Studio");
-    println!("\n  rstmdb Studio running at http://{}\n", addr);
+    let scheme = if acceptor.is_some() { "https" } else { "http" };
+    tracing::info!(%addr, scheme, "Starting rstmdb Studio");
+    println!("\n  rstmdb Studio running at {}://{}\n", scheme, addr);
 
-    axum::serve(listener, app).await?;
+    // Peer addresses are recorded for the session list
+    match acceptor {
+        Some(acceptor) => tls::serve(listener, acceptor, app).await,
+        None => {
+            axum::serve(
+                listener,
+                ServiceExt::<Request>::into_make_service_with_connect_info::<SocketAddr>(app),
+            )
+            .await?
+        }
+    }
 
     Ok(())
 }
 
+/// `routes` if its feature group is enabled, otherwise nothing
+fn gated<S: Clone + Send + Sync + 'static>(enabled: bool, routes: Router<S>) -> Router<S> {
+    if enabled {
+        routes
+    } else {
+        Router::new()
+    }
+}
+
+/// Answer `NOT_SUPPORTED` for `routes` unless the connected rstmdb
+/// advertises `capability`. Checked per request, so a reconnect to a
+/// different server takes effect immediately.
+fn requires(
+    state: &Arc<AppState>,
+    capability: &'static str,
+    routes: Router<Arc<AppState>>,
+) -> Router<Arc<AppState>> {
+    let state = state.clone();
+    routes.route_layer(middleware::from_fn(move |req: Request, next: Next| {
+        let state = state.clone();
+        async move {
+            match state.rstmdb.capabilities().require(capability) {
+                Ok(()) => next.run(req).await,
+                Err(e) => e.into_response(),
+            }
+        }
+    }))
+}
+
+async fn api_not_found() -> ApiError {
+    ApiError::not_found("Endpoint")
+}
+
+/// Strip trailing slashes before routing so `/api/v1/machines/` and
+/// `/api/v1/machines` reach the same handler. Layers added to the router run
+/// after routing, so this must wrap it. Unmatched paths such as `/app/` still
+/// fall through to the SPA.
+fn normalize_trailing_slash(router: Router) -> NormalizePath<Router> {
+    NormalizePath::trim_trailing_slash(router)
+}
+
 fn create_router(state: Arc<AppState>) -> Router {
     // Session store (in-memory for simplicity, use Redis/DB in production)
     let session_store = MemoryStore::default();
+    let idle_timeout =
+        time::Duration::try_from(state.session_timeouts.idle).unwrap_or(time::Duration::MAX);
     let session_layer = SessionManagerLayer::new(session_store)
         .with_secure(false) // Set to true in production with HTTPS
         .with_http_only(true)
-        .with_same_site(tower_sessions::cookie::SameSite::Lax);
+        .with_same_site(tower_sessions::cookie::SameSite::Lax)
+        .with_expiry(Expiry::OnInactivity(idle_timeout));
 
-    // API routes
-    let api = Router::new()
-        // Auth routes
+    let features = state.config.features;
+
+    // Auth routes (no session required)
+    let public_api = Router::new()
         .route("/auth/login", post(api::auth::login))
         .route("/auth/logout", post(api::auth::logout))
         .route("/auth/me", get(api::auth::me))
+        .route("/auth/banner", get(api::auth::banner))
+        .route("/auth/status", get(api::auth::status))
+        .merge(gated(
+            features.user_management,
+            Router::new()
+                .route("/auth/setup", post(api::auth::setup))
+                .route("/auth/break-glass", post(api::auth::break_glass))
+                .route("/auth/reset-password", post(api::auth::reset_password)),
+        ));
+
+    // Routes that scan the WAL or fan out to many rstmdb calls
+    let long_running = Router::new()
+        .route(
+            "/machines/:name/versions/:version/stats",
+            get(api::machines::get_machine_version_stats),
+        )
+        .route(
+            "/machines/:name/transition-usage",
+            get(api::transition_usage::get_transition_usage),
+        )
+        .route(
+            "/machines/duplicates",
+            get(api::duplicates::find_duplicates),
+        )
+        .route(
+            "/machines/:name/stuck",
+            get(api::machines::list_stuck_instances),
+        )
+        .route(
+            "/instances/batch-get",
+            post(api::instances::batch_get_instances),
+        )
+        .route("/instances/compare", get(api::compare::compare_instances))
+        .route("/instances/:id/wait", get(api::wait::wait_for_instance))
+        .route(
+            "/instances/:id/history",
+            get(api::instances::get_instance_history),
+        )
+        .route("/instances/:id/fork", post(api::instances::fork_instance))
+        .merge(gated(
+            features.export,
+            Router::new().route(
+                "/instances/:id/package",
+                get(api::package::export_instance_package),
+            ),
+        ))
+        .route("/search", get(api::search::search))
+        .merge(gated(
+            features.wal,
+            requires(
+                &state,
+                capabilities::WAL,
+                Router::new().route("/wal/range-digest", get(api::wal_digest::get_range_digest)),
+            ),
+        ))
+        .route_layer(middleware::from_fn(api::priority::bulk));
+
+    // Routes that upload machine definitions accept larger bodies
+    let uploads = Router::new()
+        .route(
+            "/machines/:name/versions",
+            post(api::machines::create_machine_version),
+        )
+        .route(
+            "/machines/:name/import/url",
+            post(api::import::import_machine_from_url),
+        )
+        .route("/machines/validate", post(api::machines::validate_machine))
+        .route(
+            "/machines/normalize",
+            post(api::machines::normalize_machine),
+        )
+        .route(
+            "/machines/:name/draft",
+            get(api::drafts::get_draft)
+                .put(api::drafts::save_draft)
+                .delete(api::drafts::discard_draft),
+        );
+    let long_uploads = Router::new()
+        .route("/machines/publish", post(api::machines::publish_machines))
+        .route(
+            "/machines/:name/validate-against-instances",
+            post(api::machines::validate_against_instances),
+        )
+        .route_layer(middleware::from_fn(api::priority::bulk));
+
+    // API routes (session required)
+    let api = Router::new()
+        // Session management
+        .route("/auth/sessions", get(api::auth::list_sessions))
+        .route(
+            "/auth/sessions/revoke-others",
+            post(api::auth::revoke_other_sessions),
+        )
+        .route("/auth/sessions/:id", delete(api::auth::revoke_session))
         // Machine routes
         .route("/machines", get(api::machines::list_machines))
         .route("/machines/:name", get(api::machines::get_machine))
+        .route(
+            "/machines/:name/diff",
+            get(api::machines::diff_machine_versions),
+        )
         .route(
             "/machines/:name/versions/:version",
             get(api::machines::get_machine_version),
         )
         .route(
-            "/machines/:name/versions",
-            post(api::machines::create_machine_version),
+            "/machines/:name/versions/:version/diagram.svg",
+            get(api::machines::get_machine_version_diagram),
+        )
+        .route(
+            "/machines/:name/versions/:version/events",
+            get(api::machines::get_machine_version_events),
+        )
+        .route(
+            "/machines/:name/versions/:version/reachable",
+            get(api::machines::get_reachable_states),
+        )
+        .route(
+            "/machines/:name/versions/:version/path",
+            get(api::machines::find_path),
         )
-        .route("/machines/validate", post(api::machines::validate_machine))
         // Instance routes
-        .route("/instances", get(api::instances::list_instances))
-        .route("/instances/:id", get(api::instances::get_instance))
         .route(
-            "/instances/:id/history",
-            get(api::instances::get_instance_history),
+            "/instances",
+            get(api::instances::list_instances).post(api::instances::create_instance),
+        )
+        .route("/instances/count", get(api::instances::count_instances))
+        .route(
+            "/instances/:id",
+            get(api::instances::get_instance).delete(api::instances::delete_instance),
+        )
+        .route(
+            "/instances/:id/version-status",
+            get(api::instances::get_version_status),
+        )
+        .route("/instances/:id/events", post(api::instances::apply_event))
+        .route(
+            "/instances/:id/events/stream",
+            get(api::event_stream::stream_instance_events),
+        )
+        .route(
+            "/instances/:id/archive",
+            post(api::archive::archive_instance),
+        )
+        .route(
+            "/instances/:id/restore",
+            post(api::archive::restore_instance),
         )
+        .route("/instances/:id/pause", post(api::pause::pause_instance))
+        .route("/instances/:id/resume", post(api::pause::resume_instance))
+        .merge(gated(
+            features.simulation,
+            Router::new().route(
+                "/instances/:id/events/check",
+                post(api::instances::check_event),
+            ),
+        ))
         // WAL routes
-        .route("/wal", get(api::wal::list_wal_entries))
-        .route("/wal/stats", get(api::wal::get_wal_stats))
-        .route("/wal/:offset", get(api::wal::get_wal_entry))
+        .merge(gated(
+            features.wal,
+            requires(
+                &state,
+                capabilities::WAL,
+                Router::new()
+                    .route("/wal", get(api::wal::list_wal_entries))
+                    .route("/wal/stats", get(api::wal::get_wal_stats))
+                    .route(
+                        "/wal/stats/history",
+                        get(api::wal_history::get_wal_stats_history),
+                    )
+                    .route("/wal/:offset", get(api::wal::get_wal_entry)),
+            ),
+        ))
         // Server routes
         .route("/server/info", get(api::server::info))
-        .route("/server/health", get(api::server::health));
+        .route("/server/health", get(api::server::health))
+        .route("/server/readiness", get(api::server::readiness))
+        .route("/server/reload-config", post(api::server::reload_config))
+        .route("/server/internals", get(api::server::internals))
+        .route(
+            "/server/maintenance",
+            post(api::maintenance::set_maintenance),
+        );
+    let RequestTimeouts { standard, long, .. } = state.request_timeouts;
+    let max_body = state.config.server.max_body_bytes;
+    let max_upload = state.config.server.max_upload_body_bytes;
+    let limited = |router, timeout, max_bytes| {
+        api::body_limit::with_body_limit(api::timeout::with_timeout(router, timeout), max_bytes)
+    };
+    let api = limited(api, standard, max_body)
+        .merge(limited(long_running, long, max_body))
+        .merge(limited(uploads, standard, max_upload))
+        .merge(limited(long_uploads, long, max_upload))
+        // Runs after require_auth so the session user is known
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            api::maintenance::enforce,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            api::auth::require_auth,
+        ))
+        .merge(limited(public_api, standard, max_body))
+        .route_layer(middleware::from_fn(api::content_type::require_json))
+        .route_layer(middleware::from_fn_with_state(
+            state.config.server.response_envelope,
+            api::envelope::wrap,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.config.server.max_rstmdb_ops_per_request,
+            api::op_budget::limit,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.http_latency.clone(),
+            api::latency::record,
+        ))
+        // Unknown and disabled API paths must not fall through to the SPA
+        .fallback(api_not_found);
 
-    // Health endpoints (no auth required)
+    // Health and metrics endpoints (no auth required)
     let health = Router::new()
         .route("/healthz", get(api::server::healthz))
-        .route("/readyz", get(api::server::readyz));
+        .route("/readyz", get(api::server::readyz))
+        .route("/metrics", get(api::server::metrics));
 
     Router::new()
         .nest("/api/v1", api)
-        .merge(health)
         // Serve embedded frontend - fallback handles SPA routing
-        .fallback(static_handler)
+        .fallback(api::auth::spa_fallback)
+        .layer(middleware::from_fn_with_state(
+            state.in_flight.clone(),
+            api::concurrency::enforce,
+        ))
+        // Probes stay outside the in-flight limit
+        .merge(health)
         .layer(session_layer)
         .layer(TraceLayer::new_for_http())
         .with_state(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Serve the real router, built as `serve` builds it, on an ephemeral port
+    async fn spawn(config: Config, rstmdb: rstmdb::FakeRstmdb) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = normalize_trailing_slash(create_router(AppState::for_test(config, rstmdb)));
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                ServiceExt::<Request>::into_make_service_with_connect_info::<SocketAddr>(app),
+            )
+            .await
+            .unwrap();
+        });
+        addr
+    }
+
+    async fn get(addr: SocketAddr, path: &str) -> (reqwest::StatusCode, String) {
+        let response = reqwest::get(format!("http://{}{}", addr, path))
+            .await
+            .unwrap();
+        (response.status(), response.text().await.unwrap())
+    }
+
+    /// Reads go through without a session, so routing is all that's tested
+    fn anonymous_reads() -> Config {
+        let mut config = Config::default();
+        config.server.allow_anonymous_read = true;
+        config
+    }
+
+    fn order_machine() -> rstmdb::FakeRstmdb {
+        rstmdb::FakeRstmdb::new().with_machine(
+            "order",
+            3,
+            json!({
+                "states": ["pending", "paid"],
+                "initial": "pending",
+                "transitions": [{ "from": "pending", "event": "PAY", "to": "paid" }]
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_disabled_feature_routes_return_not_found() {
+        let mut config = Config::default();
+        config.features.wal = false;
+        config.features.user_management = false;
+        let addr = spawn(config, rstmdb::FakeRstmdb::new()).await;
+
+        let (status, body) = get(addr, "/api/v1/wal").await;
+        assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+        assert!(body.contains("NOT_FOUND"));
+        let setup = reqwest::Client::new()
+            .post(format!("http://{}/api/v1/auth/setup", addr))
+            .json(&json!({ "username": "admin", "password": "secret" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(setup.status(), reqwest::StatusCode::NOT_FOUND);
+
+        // Enabled groups still exist and ask for a session
+        let (status, _) = get(addr, "/api/v1/instances/i-1/package").await;
+        assert_eq!(status, reqwest::StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            get(addr, "/api/v1/auth/status").await.0,
+            reqwest::StatusCode::OK
+        );
+
+        // Outside the API, disabled paths are left to the SPA
+        assert_eq!(get(addr, "/wal").await, get(addr, "/").await);
+    }
+
+    /// A certificate for `common_name` signed by `ca`, and its key, as PEM
+    fn issue(
+        common_name: &str,
+        names: &[&str],
+        ca: &rcgen::Certificate,
+        ca_key: &rcgen::KeyPair,
+    ) -> (String, String) {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        let mut params = rcgen::CertificateParams::new(names).unwrap();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, common_name);
+        let cert = params.signed_by(&key, ca, ca_key).unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+
+    #[tokio::test]
+    async fn test_tls_client_certificates() {
+        let ca_key = rcgen::KeyPair::generate().unwrap();
+        let mut ca_params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "Studio test CA");
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let (server_cert, server_key) = issue("studio", &["127.0.0.1"], &ca, &ca_key);
+
+        let dir = std::env::temp_dir().join(format!("rstmdb-studio-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, pem: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, pem).unwrap();
+            Some(path.to_string_lossy().into_owned())
+        };
+        let mut config = Config::default();
+        config.server.tls = config::TlsConfig {
+            enabled: true,
+            cert_path: write("server.pem", &server_cert),
+            key_path: write("server.key", &server_key),
+            client_ca_path: write("ca.pem", &ca.pem()),
+            require_client_cert: true,
+            client_cert_roles: [("deploy-bot".to_string(), auth::Role::Viewer)].into(),
+        };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = tls::acceptor(&config.server.tls).unwrap();
+        let app =
+            normalize_trailing_slash(create_router(AppState::for_test(config, order_machine())));
+        tokio::spawn(tls::serve(listener, acceptor, app));
+
+        let get_as = |identity: Option<&str>| {
+            let mut client = reqwest::Client::builder()
+                .add_root_certificate(reqwest::Certificate::from_pem(ca.pem().as_bytes()).unwrap());
+            if let Some(common_name) = identity {
+                let (cert, key) = issue(common_name, &[], &ca, &ca_key);
+                let identity = reqwest::Identity::from_pem(format!("{}{}", cert, key).as_bytes());
+                client = client.identity(identity.unwrap());
+            }
+            client
+                .build()
+                .unwrap()
+                .get(format!("https://{}/api/v1/machines", addr))
+                .send()
+        };
+
+        let response = get_as(Some("deploy-bot")).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert!(response.text().await.unwrap().contains("order"));
+
+        // A valid certificate the config doesn't list still needs a session
+        let response = get_as(Some("stranger")).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        // No certificate, no handshake
+        assert!(get_as(None).await.is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_path_segment_is_json_error() {
+        let addr = spawn(anonymous_reads(), order_machine()).await;
+
+        let (status, body) = get(addr, "/api/v1/machines/order/versions/3/events").await;
+        assert_eq!(status, reqwest::StatusCode::OK, "{}", body);
+
+        let (status, body) = get(addr, "/api/v1/machines/order/versions/abc/events").await;
+        assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["error"]["code"], "BAD_REQUEST");
+        let message = body["error"]["message"].as_str().unwrap();
+        assert!(message.contains("'version'"), "{}", message);
+        assert!(message.contains("'abc'"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_trailing_slash_variants_match() {
+        let addr = spawn(anonymous_reads(), order_machine()).await;
+
+        for (bare, slashed) in [
+            ("/api/v1/machines", "/api/v1/machines/"),
+            ("/api/v1/machines/order", "/api/v1/machines/order/"),
+            ("/api/v1/auth/status", "/api/v1/auth/status/"),
+            ("/app", "/app/"),
+        ] {
+            let (status, body) = get(addr, bare).await;
+            assert_eq!(status, reqwest::StatusCode::OK, "{}: {}", bare, body);
+            assert_eq!(get(addr, slashed).await, (status, body), "{}", slashed);
+        }
+        assert!(get(addr, "/api/v1/machines/").await.1.contains("order"));
+        assert_eq!(get(addr, "/app/").await, get(addr, "/").await);
+    }
+}
@@ -0,0 +1,173 @@
+//! Prometheus text exposition helpers
+
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Prometheus text format content type
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Upper bounds, in seconds, of latency histogram buckets
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+#[derive(Debug, Clone, Default)]
+struct Series {
+    /// Observations per bucket (not cumulative); the last slot is `+Inf`
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// A histogram family over [`LATENCY_BUCKETS`], one series per label set
+#[derive(Default)]
+pub struct Histogram {
+    series: Mutex<BTreeMap<String, Series>>,
+}
+
+impl Histogram {
+    /// Record `duration` under `labels`, e.g. `[("op", "get_instance")]`
+    pub fn observe(&self, labels: &[(&str, &str)], duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|le| seconds <= *le)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        let mut series = self.series.lock();
+        let series = series.entry(format_labels(labels)).or_default();
+        if series.buckets.is_empty() {
+            series.buckets = vec![0; LATENCY_BUCKETS.len() + 1];
+        }
+        series.buckets[bucket] += 1;
+        series.sum += seconds;
+        series.count += 1;
+    }
+}
+
+/// `k1="v1",k2="v2"` with values escaped for the text format
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Builder for a Prometheus text-format metrics page
+#[derive(Default)]
+pub struct PrometheusText {
+    out: String,
+}
+
+impl PrometheusText {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write a counter metric
+    pub fn counter(&mut self, name: &str, help: &str, value: u64) -> &mut Self {
+        self.header(name, help, "counter");
+        let _ = writeln!(self.out, "{} {}", name, value);
+        self
+    }
+
+    /// Write a gauge metric
+    pub fn gauge(&mut self, name: &str, help: &str, value: f64) -> &mut Self {
+        self.header(name, help, "gauge");
+        let _ = writeln!(self.out, "{} {}", name, value);
+        self
+    }
+
+    /// Write a histogram family with cumulative `_bucket`, `_sum` and
+    /// `_count` series; nothing is written before the first observation
+    pub fn histogram(&mut self, name: &str, help: &str, histogram: &Histogram) -> &mut Self {
+        let series = histogram.series.lock().clone();
+        if series.is_empty() {
+            return self;
+        }
+        self.header(name, help, "histogram");
+        for (labels, series) in series {
+            let sep = if labels.is_empty() { "" } else { "," };
+            let mut cumulative = 0;
+            for (i, count) in series.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = LATENCY_BUCKETS
+                    .get(i)
+                    .map_or_else(|| "+Inf".to_string(), |le| le.to_string());
+                let _ = writeln!(
+                    self.out,
+                    "{}_bucket{{{}{}le=\"{}\"}} {}",
+                    name, labels, sep, le, cumulative
+                );
+            }
+            let _ = writeln!(self.out, "{}_sum{{{}}} {}", name, labels, series.sum);
+            let _ = writeln!(self.out, "{}_count{{{}}} {}", name, labels, series.count);
+        }
+        self
+    }
+
+    fn header(&mut self, name: &str, help: &str, kind: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_and_gauge_format() {
+        let mut text = PrometheusText::new();
+        text.counter("studio_hits_total", "Cache hits", 3)
+            .gauge("studio_size", "Cache size", 2.0);
+        let out = text.finish();
+
+        assert!(out.contains("# TYPE studio_hits_total counter\nstudio_hits_total 3\n"));
+        assert!(out.contains("# HELP studio_size Cache size\n"));
+        assert!(out.contains("studio_size 2\n"));
+    }
+
+    #[test]
+    fn test_histogram_format() {
+        let histogram = Histogram::default();
+        let mut text = PrometheusText::new();
+        text.histogram("studio_latency_seconds", "Latency", &histogram);
+        assert_eq!(text.finish(), "");
+
+        let labels = [("op", "get")];
+        histogram.observe(&labels, Duration::from_millis(3));
+        histogram.observe(&labels, Duration::from_millis(40));
+        histogram.observe(&labels, Duration::from_secs(60));
+        let mut text = PrometheusText::new();
+        text.histogram("studio_latency_seconds", "Latency", &histogram);
+        let out = text.finish();
+
+        assert!(out.contains("# TYPE studio_latency_seconds histogram\n"));
+        for (le, count) in [("0.005", 1), ("0.05", 2), ("30", 2), ("+Inf", 3)] {
+            let line = format!(
+                "studio_latency_seconds_bucket{{op=\"get\",le=\"{}\"}} {}\n",
+                le, count
+            );
+            assert!(out.contains(&line), "{}", line);
+        }
+        assert!(out.contains("studio_latency_seconds_count{op=\"get\"} 3\n"));
+
+        assert_eq!(
+            format_labels(&[("route", "a\"b\\"), ("method", "GET")]),
+            r#"route="a\"b\\",method="GET""#
+        );
+    }
+}
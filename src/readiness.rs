@@ -0,0 +1,138 @@
+//! Readiness checks
+//!
+//! `/readyz` reports ready only when every dependency listed in
+//! `readiness.dependencies` passes. Checks run concurrently, each bounded by
+//! `readiness.timeout`.
+
+use crate::config::ReadinessDependency;
+use crate::constants::readiness::{DATA_DIR_PROBE_FILE, DEFAULT_TIMEOUT};
+use crate::error::{ApiError, ApiResult};
+use crate::AppState;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+#[derive(Debug, Serialize)]
+pub struct DependencyStatus {
+    pub name: &'static str,
+    pub ready: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    /// In configured order
+    pub dependencies: Vec<DependencyStatus>,
+}
+
+/// Run every configured check
+pub async fn check_all(state: &Arc<AppState>) -> Readiness {
+    let config = &state.config.readiness;
+    let timeout = humantime::parse_duration(&config.timeout).unwrap_or_else(|e| {
+        tracing::warn!(value = %config.timeout, error = %e, "Invalid readiness.timeout");
+        humantime::parse_duration(DEFAULT_TIMEOUT).expect("valid default")
+    });
+
+    let mut checks = JoinSet::new();
+    for (i, dependency) in config.dependencies.iter().copied().enumerate() {
+        let state = state.clone();
+        checks.spawn(async move { (i, check(&state, dependency, timeout).await) });
+    }
+
+    let mut dependencies = Vec::with_capacity(checks.len());
+    while let Some(joined) = checks.join_next().await {
+        if let Ok(result) = joined {
+            dependencies.push(result);
+        }
+    }
+    dependencies.sort_by_key(|(i, _)| *i);
+    let dependencies: Vec<DependencyStatus> = dependencies.into_iter().map(|(_, s)| s).collect();
+
+    Readiness {
+        ready: dependencies.len() == config.dependencies.len()
+            && dependencies.iter().all(|d| d.ready),
+        dependencies,
+    }
+}
+
+async fn check(
+    state: &AppState,
+    dependency: ReadinessDependency,
+    timeout: Duration,
+) -> DependencyStatus {
+    let start = Instant::now();
+    let result = match tokio::time::timeout(timeout, run(state, dependency)).await {
+        Ok(result) => result,
+        Err(_) => Err(ApiError::timeout(format!(
+            "Check did not complete within {}",
+            humantime::format_duration(timeout)
+        ))),
+    };
+    DependencyStatus {
+        name: dependency.as_str(),
+        ready: result.is_ok(),
+        latency_ms: start.elapsed().as_millis() as u64,
+        error: result.err().map(|e| e.message),
+    }
+}
+
+async fn run(state: &AppState, dependency: ReadinessDependency) -> ApiResult<()> {
+    match dependency {
+        ReadinessDependency::Rstmdb => state.rstmdb.ping().await,
+        ReadinessDependency::DataDir => {
+            let dir = PathBuf::from(shellexpand::tilde(&state.config.data_dir).to_string());
+            check_writable(&dir).await
+        }
+        ReadinessDependency::AuthBackend => state.auth_backend.check_ready().await,
+        ReadinessDependency::BackgroundTasks => check_tasks(state),
+    }
+}
+
+/// Fail while a critical background task has been down too long
+fn check_tasks(state: &AppState) -> ApiResult<()> {
+    let max_downtime = state.max_task_downtime;
+    let failing = state
+        .tasks
+        .failing(max_downtime, chrono::Utc::now().timestamp_millis());
+    if failing.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<&str> = failing.iter().map(|t| t.name).collect();
+    Err(ApiError::internal(format!(
+        "Background tasks down for over {}: {}",
+        humantime::format_duration(max_downtime),
+        names.join(", ")
+    )))
+}
+
+/// Write and remove a probe file in `dir`
+async fn check_writable(dir: &std::path::Path) -> ApiResult<()> {
+    let probe = dir.join(DATA_DIR_PROBE_FILE);
+    let failed =
+        |e: std::io::Error| ApiError::internal(format!("{} is not writable: {}", dir.display(), e));
+    tokio::fs::write(&probe, b"ok").await.map_err(failed)?;
+    tokio::fs::remove_file(&probe).await.map_err(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_writable() {
+        let dir = std::env::temp_dir().join(format!("rstmdb-studio-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(check_writable(&dir).await.is_ok());
+        assert!(!dir.join(DATA_DIR_PROBE_FILE).exists());
+
+        let missing = dir.join("missing");
+        let err = check_writable(&missing).await.unwrap_err();
+        assert!(err.message.contains("not writable"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,101 @@
+//! Context redaction
+//!
+//! Operators list JSON pointers into instance contexts (e.g.
+//! `/customer/email`). With `?redact=true`, history and WAL responses replace
+//! the values at those paths before serialization, so the data can be shared
+//! with support without exposing customer details.
+
+use serde_json::Value;
+
+/// Replacement for redacted values
+pub const REDACTED: &str = "***";
+
+/// WAL entry fields holding a context or event payload
+const CONTEXT_FIELDS: &[&str] = &["initial_ctx", "ctx", "payload"];
+
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    pointers: Vec<String>,
+}
+
+impl Redactor {
+    /// Build from the configured pointers, which must start with `/`
+    pub fn new(pointers: &[String]) -> anyhow::Result<Self> {
+        if let Some(bad) = pointers.iter().find(|p| !p.starts_with('/')) {
+            anyhow::bail!(
+                "Invalid redaction.paths entry '{}': JSON pointers must start with '/'",
+                bad
+            );
+        }
+        Ok(Self {
+            pointers: pointers.to_vec(),
+        })
+    }
+
+    /// Replace each configured path present in `ctx`
+    pub fn redact(&self, ctx: &mut Value) {
+        for pointer in &self.pointers {
+            if let Some(value) = ctx.pointer_mut(pointer) {
+                *value = Value::String(REDACTED.to_string());
+            }
+        }
+    }
+
+    /// Redact the contexts and event payload carried by a raw WAL entry
+    pub fn redact_wal_entry(&self, entry: &mut Value) {
+        for field in CONTEXT_FIELDS {
+            if let Some(ctx) = entry.get_mut(*field) {
+                self.redact(ctx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn redactor(pointers: &[&str]) -> Redactor {
+        Redactor::new(&pointers.iter().map(|p| p.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_redacts_nested_pointers() {
+        let redactor = redactor(&["/customer/email", "/cards/0/number", "/missing/field"]);
+        let mut ctx = json!({
+            "customer": { "email": "a@example.com", "tier": "gold" },
+            "cards": [{ "number": "4111111111111111", "brand": "visa" }],
+            "total": 10
+        });
+        redactor.redact(&mut ctx);
+        assert_eq!(
+            ctx,
+            json!({
+                "customer": { "email": "***", "tier": "gold" },
+                "cards": [{ "number": "***", "brand": "visa" }],
+                "total": 10
+            })
+        );
+    }
+
+    #[test]
+    fn test_redacts_wal_entry_contexts() {
+        let redactor = redactor(&["/customer/email", "/customer"]);
+        let mut entry = json!({
+            "type": "apply_event",
+            "instance_id": "o1",
+            "payload": { "customer": { "email": "a@example.com" } },
+            "ctx": { "customer": { "email": "a@example.com" }, "ok": true }
+        });
+        redactor.redact_wal_entry(&mut entry);
+        assert_eq!(entry["payload"]["customer"], "***");
+        assert_eq!(entry["ctx"], json!({ "customer": "***", "ok": true }));
+        assert_eq!(entry["instance_id"], "o1");
+    }
+
+    #[test]
+    fn test_rejects_relative_pointers() {
+        assert!(Redactor::new(&["customer/email".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Runtime configuration reload
+//!
+//! On SIGHUP (or `POST /api/v1/server/reload-config`) the configuration is
+//! loaded again with the original CLI overrides. Settings listed in
+//! [`LIVE_SETTINGS`] take effect immediately; any other change is logged as
+//! requiring a restart.
+
+use crate::config::{Config, ValidationConfig};
+use crate::AppState;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+use tracing_subscriber::EnvFilter;
+
+/// Handle for swapping the log filter at runtime
+pub type LogHandle = tracing_subscriber::reload::Handle<EnvFilter, tracing_subscriber::Registry>;
+
+/// Setting paths (or path prefixes) that can change without a restart
+const LIVE_SETTINGS: &[&str] = &["log_level", "server.login_banner", "validation"];
+
+/// Settings read through `AppState.live`, swappable at runtime
+#[derive(Debug, Clone)]
+pub struct LiveSettings {
+    pub validation: ValidationConfig,
+    pub login_banner: Option<String>,
+}
+
+impl LiveSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            validation: config.validation.clone(),
+            login_banner: config.server.login_banner.clone(),
+        }
+    }
+}
+
+/// Where the configuration came from, so it can be loaded again
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    /// Config files, merged in order
+    pub paths: Vec<PathBuf>,
+    pub host: String,
+    pub port: u16,
+    pub rstmdb_addr: String,
+    pub rstmdb_token: Option<String>,
+}
+
+impl ConfigSource {
+    pub fn load(&self) -> anyhow::Result<Config> {
+        Config::load(
+            &self.paths,
+            &self.host,
+            self.port,
+            &self.rstmdb_addr,
+            self.rstmdb_token.clone(),
+        )
+    }
+}
+
+/// Setting paths changed by a reload
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct ReloadOutcome {
+    /// Changes now in effect
+    pub applied: Vec<String>,
+    /// Changes that only take effect after a restart
+    pub restart_required: Vec<String>,
+}
+
+/// Dotted paths of leaf values that differ between two configs
+fn changed_paths(old: &Value, new: &Value, prefix: &str, out: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                changed_paths(
+                    a.get(key).unwrap_or(&Value::Null),
+                    b.get(key).unwrap_or(&Value::Null),
+                    &path,
+                    out,
+                );
+            }
+        }
+        (a, b) if a != b => out.push(prefix.to_string()),
+        _ => {}
+    }
+}
+
+fn is_live(path: &str) -> bool {
+    LIVE_SETTINGS
+        .iter()
+        .any(|live| path == *live || path.starts_with(&format!("{}.", live)))
+}
+
+/// Compare configs, splitting changes into live and restart-only settings
+pub fn diff(old: &Config, new: &Config) -> ReloadOutcome {
+    let mut changed = Vec::new();
+    changed_paths(
+        &serde_json::to_value(old).unwrap_or_default(),
+        &serde_json::to_value(new).unwrap_or_default(),
+        "",
+        &mut changed,
+    );
+    let (applied, restart_required) = changed.into_iter().partition(|p| is_live(p));
+    ReloadOutcome {
+        applied,
+        restart_required,
+    }
+}
+
+/// Apply the configured log level, unless `RUST_LOG` takes precedence
+pub fn apply_log_level(handle: &LogHandle, level: Option<&str>) -> anyhow::Result<()> {
+    if std::env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+        return Ok(());
+    }
+    let filter = EnvFilter::try_new(level.unwrap_or("info"))
+        .map_err(|e| anyhow::anyhow!("Invalid log_level: {}", e))?;
+    handle
+        .reload(filter)
+        .map_err(|e| anyhow::anyhow!("Failed to apply log_level: {}", e))
+}
+
+/// Load the configuration again and apply what can change at runtime
+pub fn reload(state: &AppState) -> anyhow::Result<ReloadOutcome> {
+    let new = state.config_source.load()?;
+    // The running config: startup values plus live settings applied since
+    let mut running = state.running_config.lock();
+    let outcome = diff(&running, &new);
+
+    if outcome.applied.iter().any(|p| p == "log_level") {
+        apply_log_level(&state.log_handle, new.log_level.as_deref())?;
+    }
+    *state.live.write() = LiveSettings::from_config(&new);
+    running.log_level = new.log_level;
+    running.server.login_banner = new.server.login_banner;
+    running.validation = new.validation;
+
+    for path in &outcome.applied {
+        tracing::info!(setting = %path, "Config setting reloaded");
+    }
+    for path in &outcome.restart_required {
+        tracing::warn!(setting = %path, "Config setting changed; restart required to apply");
+    }
+
+    Ok(outcome)
+}
+
+/// Reload the configuration whenever the process receives SIGHUP
+#[cfg(unix)]
+pub fn spawn_sighup_handler(state: std::sync::Arc<AppState>) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            match reload(&state) {
+                Ok(outcome) => tracing::info!(
+                    applied = outcome.applied.len(),
+                    restart_required = outcome.restart_required.len(),
+                    "Configuration reloaded on SIGHUP"
+                ),
+                Err(e) => tracing::error!(error = %e, "Configuration reload failed"),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn spawn_sighup_handler(_state: std::sync::Arc<AppState>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::Severity;
+
+    #[test]
+    fn test_diff_splits_live_and_restart_settings() {
+        let old = Config::default();
+        let mut new = Config::default();
+        new.validation.unknown_field_severity = Severity::Error;
+        new.server.login_banner = Some("Maintenance tonight".to_string());
+        new.server.port = 9090;
+        new.rstmdb.keepalive.interval = "10s".to_string();
+
+        let outcome = diff(&old, &new);
+        assert_eq!(
+            outcome.applied,
+            vec!["server.login_banner", "validation.unknown_field_severity"]
+        );
+        assert_eq!(
+            outcome.restart_required,
+            vec!["rstmdb.keepalive.interval", "server.port"]
+        );
+        assert_eq!(diff(&old, &old), ReloadOutcome::default());
+    }
+}
@@ -0,0 +1,196 @@
+//! The rstmdb connection behind [`StudioClient`](super::StudioClient)
+//!
+//! `StudioClient` adds reconnects, caching, queueing and metrics around one
+//! connection, which is a [`Backend`]: rstmdb-client's `Client` when
+//! serving, an in-memory fake in tests. Results are already Studio's types,
+//! so nothing outside this module depends on rstmdb-client's.
+
+use super::client::{
+    ApplyEventResult, CreateInstanceResult, DeleteInstanceResult, InstanceResult, InstanceSummary,
+    ListInstancesResult, PutMachineResult,
+};
+use async_trait::async_trait;
+use rstmdb_client::Client;
+use serde_json::Value;
+
+/// Errors are only inspected through their message (connection loss, not
+/// found), so any error will do
+pub type BackendError = Box<dyn std::error::Error + Send + Sync>;
+pub type BackendResult<T> = Result<T, BackendError>;
+
+#[async_trait]
+pub trait Backend: Send + Sync {
+    async fn close(&self) -> BackendResult<()>;
+    async fn ping(&self) -> BackendResult<()>;
+    async fn info(&self) -> BackendResult<Value>;
+    /// `{ "items": [{ "machine", "versions" }] }`
+    async fn list_machines(&self) -> BackendResult<Value>;
+    /// `{ "definition", "checksum" }` of one version
+    async fn get_machine(&self, name: &str, version: u32) -> BackendResult<Value>;
+    async fn put_machine(
+        &self,
+        name: &str,
+        version: u32,
+        definition: Value,
+    ) -> BackendResult<PutMachineResult>;
+    async fn get_instance(&self, id: &str) -> BackendResult<InstanceResult>;
+    async fn create_instance(
+        &self,
+        machine: &str,
+        version: u32,
+        instance_id: Option<&str>,
+        initial_ctx: Option<Value>,
+    ) -> BackendResult<CreateInstanceResult>;
+    /// `event_id` is rstmdb's idempotency key
+    async fn apply_event(
+        &self,
+        instance_id: &str,
+        event: &str,
+        payload: Option<Value>,
+        expected_state: Option<&str>,
+        event_id: Option<&str>,
+    ) -> BackendResult<ApplyEventResult>;
+    async fn delete_instance(&self, instance_id: &str) -> BackendResult<DeleteInstanceResult>;
+    /// `{ "records": [{ "offset", "entry" }], "next_offset" }`
+    async fn wal_read(&self, from: u64, limit: Option<u64>) -> BackendResult<Value>;
+    async fn wal_stats(&self) -> BackendResult<Value>;
+    async fn list_instances(
+        &self,
+        machine: Option<&str>,
+        state: Option<&str>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> BackendResult<ListInstancesResult>;
+}
+
+#[async_trait]
+impl Backend for Client {
+    async fn close(&self) -> BackendResult<()> {
+        Ok(Client::close(self).await?)
+    }
+
+    async fn ping(&self) -> BackendResult<()> {
+        Ok(Client::ping(self).await?)
+    }
+
+    async fn info(&self) -> BackendResult<Value> {
+        Ok(Client::info(self).await?)
+    }
+
+    async fn list_machines(&self) -> BackendResult<Value> {
+        Ok(Client::list_machines(self).await?)
+    }
+
+    async fn get_machine(&self, name: &str, version: u32) -> BackendResult<Value> {
+        let result = Client::get_machine(self, name, version).await?;
+        Ok(serde_json::to_value(result).unwrap_or(Value::Null))
+    }
+
+    async fn put_machine(
+        &self,
+        name: &str,
+        version: u32,
+        definition: Value,
+    ) -> BackendResult<PutMachineResult> {
+        let result = Client::put_machine(self, name, version, definition).await?;
+        Ok(PutMachineResult {
+            machine: result.machine,
+            version: result.version,
+            checksum: result.stored_checksum,
+            created: result.created,
+        })
+    }
+
+    async fn get_instance(&self, id: &str) -> BackendResult<InstanceResult> {
+        let result = Client::get_instance(self, id).await?;
+        Ok(InstanceResult {
+            instance_id: id.to_string(),
+            machine: result.machine,
+            version: result.version,
+            state: result.state,
+            ctx: result.ctx,
+            last_wal_offset: result.last_wal_offset,
+        })
+    }
+
+    async fn create_instance(
+        &self,
+        machine: &str,
+        version: u32,
+        instance_id: Option<&str>,
+        initial_ctx: Option<Value>,
+    ) -> BackendResult<CreateInstanceResult> {
+        let result =
+            Client::create_instance(self, machine, version, instance_id, initial_ctx, None).await?;
+        Ok(CreateInstanceResult {
+            instance_id: result.instance_id,
+            state: result.state,
+            wal_offset: result.wal_offset,
+        })
+    }
+
+    async fn apply_event(
+        &self,
+        instance_id: &str,
+        event: &str,
+        payload: Option<Value>,
+        expected_state: Option<&str>,
+        event_id: Option<&str>,
+    ) -> BackendResult<ApplyEventResult> {
+        let result =
+            Client::apply_event(self, instance_id, event, payload, expected_state, event_id)
+                .await?;
+        Ok(ApplyEventResult {
+            from_state: result.from_state,
+            to_state: result.to_state,
+            ctx: result.ctx,
+            wal_offset: result.wal_offset,
+            applied: result.applied,
+            event_id: result.event_id,
+        })
+    }
+
+    async fn delete_instance(&self, instance_id: &str) -> BackendResult<DeleteInstanceResult> {
+        let result = Client::delete_instance(self, instance_id, None).await?;
+        Ok(DeleteInstanceResult {
+            instance_id: result.instance_id,
+            deleted: result.deleted,
+            wal_offset: result.wal_offset,
+        })
+    }
+
+    async fn wal_read(&self, from: u64, limit: Option<u64>) -> BackendResult<Value> {
+        Ok(Client::wal_read(self, from, limit).await?)
+    }
+
+    async fn wal_stats(&self) -> BackendResult<Value> {
+        Ok(Client::wal_stats(self).await?)
+    }
+
+    async fn list_instances(
+        &self,
+        machine: Option<&str>,
+        state: Option<&str>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> BackendResult<ListInstancesResult> {
+        let result = Client::list_instances(self, machine, state, limit, offset).await?;
+        Ok(ListInstancesResult {
+            instances: result
+                .instances
+                .into_iter()
+                .map(|i| InstanceSummary {
+                    id: i.id,
+                    machine: i.machine,
+                    version: i.version,
+                    state: i.state,
+                    created_at: i.created_at,
+                    updated_at: i.updated_at,
+                    last_wal_offset: i.last_wal_offset,
+                })
+                .collect(),
+            total: result.total,
+            has_more: result.has_more,
+        })
+    }
+}
@@ -0,0 +1,108 @@
+//! Per-request rstmdb operation budget
+//!
+//! API requests run inside [`with_budget`], and every rstmdb operation they
+//! issue is charged to it. Once a request has used its allowance, further
+//! operations fail with `RESOURCE_EXHAUSTED` instead of reaching rstmdb, so a
+//! pathological request (a machine with thousands of states, a full WAL scan)
+//! can't issue unbounded backend calls. Like the queue priority, the budget
+//! is task-local: handlers that spawn tasks carry it over with
+//! [`current_budget`]. Background jobs run without one.
+
+use crate::error::ApiError;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// rstmdb operations one request may issue
+#[derive(Debug)]
+pub struct OpBudget {
+    limit: u64,
+    used: AtomicU64,
+}
+
+impl OpBudget {
+    pub fn new(limit: u64) -> Arc<Self> {
+        Arc::new(Self {
+            limit,
+            used: AtomicU64::new(0),
+        })
+    }
+
+    /// Operations charged so far, including a refused one
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    fn charge(&self, op_name: &str) -> Result<(), ApiError> {
+        let used = self.used.fetch_add(1, Ordering::Relaxed) + 1;
+        if used <= self.limit {
+            return Ok(());
+        }
+        Err(ApiError::resource_exhausted(format!(
+            "Request exceeded its budget of {} rstmdb operations at '{}'",
+            self.limit, op_name
+        ))
+        .with_details(serde_json::json!({
+            "limit": self.limit,
+            "operation": op_name,
+        })))
+    }
+}
+
+tokio::task_local! {
+    static BUDGET: Arc<OpBudget>;
+}
+
+/// Run `fut` with its rstmdb operations charged to `budget`; `None` leaves
+/// them unlimited
+pub async fn with_budget<F: Future>(budget: Option<Arc<OpBudget>>, fut: F) -> F::Output {
+    match budget {
+        Some(budget) => BUDGET.scope(budget, fut).await,
+        None => fut.await,
+    }
+}
+
+/// Budget of the current task, to pass on to tasks it spawns
+pub fn current_budget() -> Option<Arc<OpBudget>> {
+    BUDGET.try_with(Arc::clone).ok()
+}
+
+/// Charge one operation to the current task's budget, if it has one
+pub(super) fn charge(op_name: &str) -> Result<(), ApiError> {
+    BUDGET
+        .try_with(|budget| budget.charge(op_name))
+        .unwrap_or(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_budget_refuses_operations_past_limit() {
+        assert!(charge("Get instance").is_ok());
+
+        let budget = OpBudget::new(3);
+        with_budget(Some(budget.clone()), async {
+            assert!(charge("List machines").is_ok());
+            // Spawned tasks share the budget they are given
+            let inherited = current_budget();
+            tokio::spawn(with_budget(inherited, async {
+                assert!(charge("Get machine").is_ok());
+                assert!(charge("Get machine").is_ok());
+            }))
+            .await
+            .unwrap();
+
+            let err = charge("Count instances").unwrap_err();
+            assert_eq!(err.code, "RESOURCE_EXHAUSTED");
+            assert!(err.message.contains("'Count instances'"));
+        })
+        .await;
+        assert_eq!(budget.used(), 4);
+
+        // Unscoped work stays unlimited
+        assert!(charge("Get instance").is_ok());
+        assert!(current_budget().is_none());
+    }
+}
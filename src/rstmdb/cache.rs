@@ -0,0 +1,229 @@
+//! Bounded TTL + LRU cache for instance reads
+
+use super::InstanceResult;
+use crate::constants::wal_entry_types;
+use lru::LruCache;
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Snapshot of cache counters
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub size: usize,
+    pub capacity: usize,
+}
+
+/// LRU cache of `get_instance` results keyed by instance id.
+///
+/// Entries older than the TTL are treated as misses.
+pub struct InstanceCache {
+    entries: Mutex<LruCache<String, (Instant, InstanceResult)>>,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl InstanceCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Get a fresh cached instance, recording a hit or miss
+    pub fn get(&self, id: &str) -> Option<InstanceResult> {
+        let mut entries = self.entries.lock();
+        let fresh = match entries.get(id) {
+            Some((stored_at, instance)) if stored_at.elapsed() < self.ttl => Some(instance.clone()),
+            Some(_) => {
+                entries.pop(id);
+                None
+            }
+            None => None,
+        };
+
+        let counter = if fresh.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        fresh
+    }
+
+    /// Cache an instance unless a newer copy (by WAL offset) is already
+    /// cached, e.g. a read that raced a WAL update
+    pub fn insert(&self, id: &str, instance: InstanceResult) {
+        let mut entries = self.entries.lock();
+        if let Some((_, cached)) = entries.peek(id) {
+            if cached.last_wal_offset > instance.last_wal_offset {
+                return;
+            }
+        }
+        entries.put(id.to_string(), (Instant::now(), instance));
+    }
+
+    /// Remove an entry, e.g. after the instance was written
+    pub fn invalidate(&self, id: &str) {
+        self.entries.lock().pop(id);
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().clear();
+    }
+
+    /// Bring a cached instance up to date with the WAL entry at `offset`.
+    /// An event carrying the resulting context updates it in place; anything
+    /// else written to the instance drops it. Entries the cached copy
+    /// already reflects are ignored.
+    pub fn apply_wal_entry(&self, offset: u64, entry: &Value) {
+        let Some(id) = entry["instance_id"].as_str() else {
+            return;
+        };
+        let mut entries = self.entries.lock();
+        let Some((stored_at, instance)) = entries.peek_mut(id) else {
+            return;
+        };
+        if instance.last_wal_offset >= offset {
+            return;
+        }
+        let transition = (
+            entry["type"].as_str(),
+            entry["to_state"].as_str(),
+            &entry["ctx"],
+        );
+        match transition {
+            (Some(wal_entry_types::APPLY_EVENT), Some(to_state), ctx) if ctx.is_object() => {
+                instance.state = to_state.to_string();
+                instance.ctx = ctx.clone();
+                instance.last_wal_offset = offset;
+                *stored_at = Instant::now();
+            }
+            _ => {
+                entries.pop(id);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            size: entries.len(),
+            capacity: entries.cap().get(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn instance(id: &str, state: &str) -> InstanceResult {
+        InstanceResult {
+            instance_id: id.to_string(),
+            machine: "order".to_string(),
+            version: 1,
+            state: state.to_string(),
+            ctx: json!({}),
+            last_wal_offset: 0,
+        }
+    }
+
+    #[test]
+    fn test_hit_and_miss() {
+        let cache = InstanceCache::new(10, Duration::from_secs(60));
+        assert!(cache.get("a").is_none());
+        cache.insert("a", instance("a", "pending"));
+        assert_eq!(cache.get("a").unwrap().state, "pending");
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.size, 1);
+    }
+
+    #[test]
+    fn test_wal_entries_update_or_drop_cached_instances() {
+        let cache = InstanceCache::new(10, Duration::from_secs(60));
+        cache.insert("a", instance("a", "pending"));
+        cache.insert("b", instance("b", "pending"));
+
+        let paid = json!({"type": "apply_event", "instance_id": "a", "from_state": "pending",
+            "to_state": "paid", "ctx": {"n": 1}});
+        cache.apply_wal_entry(5, &paid);
+        let a = cache.get("a").unwrap();
+        assert_eq!((a.state.as_str(), a.last_wal_offset), ("paid", 5));
+        assert_eq!(a.ctx, json!({"n": 1}));
+
+        // Already reflected
+        let older = json!({"type": "apply_event", "instance_id": "a", "to_state": "pending",
+            "ctx": {}});
+        cache.apply_wal_entry(4, &older);
+        assert_eq!(cache.get("a").unwrap().state, "paid");
+
+        // Without the resulting context the cached copy can't be updated
+        let no_ctx = json!({"type": "apply_event", "instance_id": "a", "to_state": "shipped"});
+        cache.apply_wal_entry(6, &no_ctx);
+        assert!(cache.get("a").is_none());
+
+        cache.apply_wal_entry(7, &json!({"type": "delete_instance", "instance_id": "b"}));
+        assert!(cache.get("b").is_none());
+
+        // Uncached instances stay uncached
+        cache.apply_wal_entry(8, &paid);
+        assert_eq!(cache.stats().size, 0);
+    }
+
+    #[test]
+    fn test_insert_keeps_newer_cached_copy() {
+        let cache = InstanceCache::new(10, Duration::from_secs(60));
+        let mut newer = instance("a", "paid");
+        newer.last_wal_offset = 9;
+        cache.insert("a", newer);
+
+        let mut older = instance("a", "pending");
+        older.last_wal_offset = 3;
+        cache.insert("a", older);
+        assert_eq!(cache.get("a").unwrap().state, "paid");
+    }
+
+    #[test]
+    fn test_expired_entry_is_miss() {
+        let cache = InstanceCache::new(10, Duration::ZERO);
+        cache.insert("a", instance("a", "pending"));
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.stats().size, 0);
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let cache = InstanceCache::new(10, Duration::from_secs(60));
+        cache.insert("a", instance("a", "pending"));
+        cache.invalidate("a");
+        assert!(cache.get("a").is_none());
+    }
+
+    #[test]
+    fn test_capacity_evicts_least_recently_used() {
+        let cache = InstanceCache::new(2, Duration::from_secs(60));
+        cache.insert("a", instance("a", "s"));
+        cache.insert("b", instance("b", "s"));
+        cache.get("a");
+        cache.insert("c", instance("c", "s"));
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+    }
+}
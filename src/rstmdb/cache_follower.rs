@@ -0,0 +1,227 @@
+//! Keep the instance cache current from the WAL
+//!
+//! Studio drops a cached instance when it writes it, but other rstmdb clients
+//! write too. Following the WAL applies their writes to whatever is cached,
+//! so those show up within one poll interval instead of after the TTL. The
+//! TTL stays as a backstop for anything the follower misses while it is down.
+//!
+//! Offsets can be sparse, so the follower moves by the server's
+//! `next_offset` and checks each record's `sequence` instead: a sequence that
+//! skips ahead means entries were compacted away unseen, and the cache is
+//! cleared then rather than trusted.
+
+use super::cache::InstanceCache;
+use super::{with_priority, Priority, StudioClient};
+use crate::constants::wal::MAX_PAGE_SIZE;
+use crate::json_ext::ValueExt;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Where the follower reads next, and the sequence it expects there
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cursor {
+    from: u64,
+    /// Unknown until the first record is read
+    next_sequence: Option<u64>,
+}
+
+/// Apply one `wal_read` page to the cache and move the cursor past it,
+/// returning the number of records read
+fn apply_page(cache: &InstanceCache, cursor: &mut Cursor, page: &Value) -> usize {
+    let records = page["records"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+    let mut last_offset = None;
+    for record in records {
+        let offset = record.u64_or("offset", 0);
+        if let Some(sequence) = record.u64_opt("sequence") {
+            if cursor
+                .next_sequence
+                .is_some_and(|expected| sequence > expected)
+            {
+                tracing::info!(
+                    expected = cursor.next_sequence,
+                    sequence,
+                    "Gap in the WAL; clearing the instance cache"
+                );
+                cache.clear();
+            }
+            cursor.next_sequence = Some(sequence + 1);
+        }
+        cache.apply_wal_entry(offset, &record["entry"]);
+        last_offset = Some(offset);
+    }
+
+    if let Some(last) = last_offset {
+        // Without a cursor from the server, resume just past what was read
+        cursor.from = page
+            .u64_opt("next_offset")
+            .filter(|&next| next > last)
+            .unwrap_or(last + 1);
+    }
+    records.len()
+}
+
+/// Follow the WAL from its current end, applying entries to the client's
+/// instance cache every `interval`. Returns when a read fails, with the cache
+/// cleared, so the supervisor restarts it from the new end of the WAL.
+pub async fn run_cache_follower(client: &StudioClient, interval: Duration) {
+    let Some(cache) = client.instance_cache() else {
+        return;
+    };
+
+    // Start at the latest entry itself: re-applying it is harmless, and it
+    // gives the sequence the next entry must follow. Anything cached before
+    // then may already be stale.
+    let mut cursor = match with_priority(Priority::Bulk, client.wal_stats()).await {
+        Ok(stats) => Cursor {
+            from: stats.u64_opt("latest_offset").unwrap_or(0),
+            next_sequence: None,
+        },
+        Err(e) => {
+            tracing::warn!(error = %e.message, "Instance cache could not find the end of the WAL");
+            return;
+        }
+    };
+    cache.clear();
+
+    loop {
+        let page = match with_priority(
+            Priority::Bulk,
+            client.wal_read(cursor.from, Some(MAX_PAGE_SIZE)),
+        )
+        .await
+        {
+            Ok(page) => page,
+            Err(e) => {
+                tracing::warn!(error = %e.message, "Instance cache stopped following the WAL");
+                cache.clear();
+                return;
+            }
+        };
+        let read = apply_page(cache, &mut cursor, &page);
+
+        // Catch up without waiting while there is a backlog
+        if (read as u64) < MAX_PAGE_SIZE {
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::rstmdb::backend::Backend;
+    use crate::rstmdb::{FakeRstmdb, InstanceResult};
+    use serde_json::json;
+    use std::time::Instant;
+
+    fn cached(id: &str) -> InstanceResult {
+        InstanceResult {
+            instance_id: id.to_string(),
+            machine: "order".to_string(),
+            version: 1,
+            state: "pending".to_string(),
+            ctx: json!({}),
+            last_wal_offset: 1,
+        }
+    }
+
+    #[test]
+    fn test_apply_page_follows_the_server_cursor() {
+        let cache = InstanceCache::new(10, Duration::from_secs(60));
+        cache.insert("b", cached("b"));
+        let mut cursor = Cursor {
+            from: 4,
+            next_sequence: None,
+        };
+        // Sparse offsets with consecutive sequences are not a gap
+        let page = json!({"records": [
+            {"sequence": 7, "offset": 4, "entry": {"type": "create_instance", "instance_id": "a"}},
+            {"sequence": 8, "offset": 40, "entry": {"type": "delete_instance", "instance_id": "a"}},
+        ], "next_offset": 64});
+        assert_eq!(apply_page(&cache, &mut cursor, &page), 2);
+        assert_eq!(
+            cursor,
+            Cursor {
+                from: 64,
+                next_sequence: Some(9)
+            }
+        );
+        assert!(cache.get("b").is_some());
+
+        let empty = json!({"records": [], "next_offset": null});
+        assert_eq!(apply_page(&cache, &mut cursor, &empty), 0);
+        assert_eq!(cursor.from, 64);
+
+        let page = json!({"records": [
+            {"sequence": 9, "offset": 90, "entry": {"type": "create_instance", "instance_id": "c"}},
+        ], "next_offset": 128});
+        apply_page(&cache, &mut cursor, &page);
+        assert_eq!(cursor.from, 128);
+        assert!(cache.get("b").is_some());
+    }
+
+    #[test]
+    fn test_skipped_sequence_clears_the_cache() {
+        let cache = InstanceCache::new(10, Duration::from_secs(60));
+        cache.insert("b", cached("b"));
+        let mut cursor = Cursor {
+            from: 4,
+            next_sequence: Some(3),
+        };
+        let page = json!({"records": [
+            {"sequence": 5, "offset": 9, "entry": {"type": "create_instance", "instance_id": "a"}},
+        ], "next_offset": 10});
+        apply_page(&cache, &mut cursor, &page);
+        assert!(cache.get("b").is_none());
+        assert_eq!(cursor.next_sequence, Some(6));
+    }
+
+    #[tokio::test]
+    async fn test_follower_picks_up_writes_by_other_clients() {
+        let fake = FakeRstmdb::new().with_machine(
+            "order",
+            1,
+            json!({
+                "states": ["pending", "paid"],
+                "initial": "pending",
+                "transitions": [{"from": "pending", "event": "PAY", "to": "paid"}]
+            }),
+        );
+        fake.create_instance("order", 1, Some("o-1"), None)
+            .await
+            .unwrap();
+
+        let mut config = Config::default().rstmdb;
+        config.instance_cache.enabled = true;
+        config.instance_cache.ttl = "1h".to_string();
+        let client = std::sync::Arc::new(StudioClient::with_fake(&config, fake.clone()));
+        assert_eq!(client.get_instance("o-1").await.unwrap().state, "pending");
+
+        let follower = client.clone();
+        let task = tokio::spawn(async move {
+            run_cache_follower(&follower, Duration::from_millis(10)).await;
+        });
+
+        // Cache the instance again once the follower has started from the end
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while fake.calls("wal_read") == 0 {
+            assert!(Instant::now() < deadline, "follower did not start");
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(client.get_instance("o-1").await.unwrap().state, "pending");
+
+        // Another client's write, straight to rstmdb
+        fake.apply_event("o-1", "PAY", None, None, None)
+            .await
+            .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while client.get_instance("o-1").await.unwrap().state != "paid" {
+            assert!(Instant::now() < deadline, "cached instance never caught up");
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(fake.calls("get_instance"), 2);
+        task.abort();
+    }
+}
@@ -0,0 +1,62 @@
+//! Optional rstmdb capabilities
+//!
+//! rstmdb lists the optional operations it supports in the `features` of its
+//! `info` response. Studio records them on every (re)connect so endpoints
+//! built on an optional operation can answer `NOT_SUPPORTED` up front instead
+//! of passing through whatever error the server gives for an unknown op.
+
+use crate::error::{ApiError, ApiResult};
+use parking_lot::RwLock;
+use std::collections::HashSet;
+
+/// Features advertised by the connected rstmdb
+#[derive(Debug, Default)]
+pub struct Capabilities {
+    /// `None` until known, or when the server reports no features at all;
+    /// everything is allowed then
+    features: RwLock<Option<HashSet<String>>>,
+}
+
+impl Capabilities {
+    /// Record the server's `features`
+    pub fn set(&self, features: Vec<String>) {
+        *self.features.write() = (!features.is_empty()).then(|| features.into_iter().collect());
+    }
+
+    pub fn supports(&self, capability: &str) -> bool {
+        self.features
+            .read()
+            .as_ref()
+            .is_none_or(|features| features.contains(capability))
+    }
+
+    /// `NOT_SUPPORTED` unless the server supports `capability`
+    pub fn require(&self, capability: &str) -> ApiResult<()> {
+        if self.supports(capability) {
+            Ok(())
+        } else {
+            Err(ApiError::not_supported(capability))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_require_capability() {
+        let capabilities = Capabilities::default();
+        assert!(capabilities.require("wal").is_ok());
+
+        capabilities.set(vec!["auth".to_string(), "wal".to_string()]);
+        assert!(capabilities.require("wal").is_ok());
+        let err = capabilities.require("truncate").unwrap_err();
+        assert_eq!(err.code, "NOT_SUPPORTED");
+        assert_eq!(err.details.unwrap()["capability"], "truncate");
+
+        // A server that reports nothing is not assumed to lack everything
+        capabilities.set(Vec::new());
+        assert!(capabilities.require("truncate").is_ok());
+    }
+}
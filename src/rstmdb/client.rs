@@ -1,44 +1,202 @@
 //! rstmdb client wrapper for Studio
 
+use super::backend::{Backend, BackendResult};
+use super::budget;
+use super::cache::{CacheStats, InstanceCache};
+use super::capabilities::Capabilities;
+use super::lock::{ConnectionLock, ReplaceError};
+use super::queue::{current_priority, QueueDepths, RequestQueue};
+use super::singleflight::SingleFlight;
 use crate::config::RstmdbConfig;
 use crate::error::ApiError;
+use crate::json_ext::ValueExt;
+use crate::metrics::Histogram;
+use parking_lot::Mutex;
 use rstmdb_client::{Client, ConnectionConfig};
 use serde_json::Value;
 use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::OwnedRwLockReadGuard;
+
+fn parse_duration(setting: &str, value: &str) -> Result<Duration, ApiError> {
+    humantime::parse_duration(value)
+        .map_err(|e| ApiError::bad_request(format!("Invalid {} '{}': {}", setting, value, e)))
+}
 
 /// Studio client wrapping rstmdb-client with auto-reconnect
 pub struct StudioClient {
-    client: Arc<RwLock<Client>>,
+    client: ConnectionLock<Box<dyn Backend>>,
     config: RstmdbConfig,
+    instance_cache: Option<InstanceCache>,
+    /// Shares in-flight results of identical parameterless reads
+    reads: SingleFlight<Value>,
+    /// Set once the server rejects or ignores `limit=0` on list_instances
+    count_needs_page: AtomicBool,
+    /// Prioritized admission, if enabled
+    queue: Option<RequestQueue>,
+    capabilities: Capabilities,
+    /// Operation durations by name, split by whether a reconnect was needed
+    latency: Histogram,
+    /// Endpoint of the current connection, tried first on reconnect
+    active_endpoint: Arc<Mutex<String>>,
+    /// Reconnects go back to this instead of the network
+    #[cfg(test)]
+    fake: Option<super::FakeRstmdb>,
 }
 
 impl StudioClient {
     /// Connect to rstmdb server
     pub async fn connect(config: &RstmdbConfig) -> Result<Self, ApiError> {
-        let client = Self::create_client(config).await?;
+        let instance_cache = if config.instance_cache.enabled {
+            let ttl = parse_duration("rstmdb.instance_cache.ttl", &config.instance_cache.ttl)?;
+            Some(InstanceCache::new(config.instance_cache.capacity, ttl))
+        } else {
+            None
+        };
+
+        let lock_wait_warn = parse_duration("rstmdb.lock_wait_warn", &config.lock_wait_warn)?;
+        let reconnect_timeout =
+            parse_duration("rstmdb.reconnect_timeout", &config.reconnect_timeout)?;
+
+        let queue = config.request_queue.enabled.then(|| {
+            let q = &config.request_queue;
+            RequestQueue::new(q.concurrency, q.max_queued, q.interactive_weight)
+        });
+
+        let (client, endpoint) = Self::create_client(config, None).await?;
 
-        Ok(Self {
-            client: Arc::new(RwLock::new(client)),
+        let studio = Self {
+            client: ConnectionLock::new(client, lock_wait_warn, reconnect_timeout),
             config: config.clone(),
-        })
+            instance_cache,
+            reads: SingleFlight::default(),
+            count_needs_page: AtomicBool::new(false),
+            queue,
+            capabilities: Capabilities::default(),
+            latency: Histogram::default(),
+            active_endpoint: Arc::new(Mutex::new(endpoint)),
+            #[cfg(test)]
+            fake: None,
+        };
+        studio.load_capabilities().await;
+        Ok(studio)
+    }
+
+    /// A client over an in-memory rstmdb instead of a connection, for tests
+    #[cfg(test)]
+    pub fn with_fake(config: &RstmdbConfig, fake: super::FakeRstmdb) -> Self {
+        let instance_cache = config.instance_cache.enabled.then(|| {
+            let ttl = parse_duration("rstmdb.instance_cache.ttl", &config.instance_cache.ttl)
+                .expect("valid instance cache ttl");
+            InstanceCache::new(config.instance_cache.capacity, ttl)
+        });
+        Self {
+            client: ConnectionLock::new(
+                Box::new(fake.clone()),
+                Duration::from_secs(1),
+                Duration::from_secs(1),
+            ),
+            config: config.clone(),
+            instance_cache,
+            reads: SingleFlight::default(),
+            count_needs_page: AtomicBool::new(false),
+            queue: None,
+            capabilities: Capabilities::default(),
+            latency: Histogram::default(),
+            active_endpoint: Arc::new(Mutex::new("fake".to_string())),
+            fake: Some(fake),
+        }
     }
 
-    /// Create a new client connection
-    async fn create_client(config: &RstmdbConfig) -> Result<Client, ApiError> {
-        let addr = tokio::net::lookup_host(&config.address)
+    /// Address of the rstmdb endpoint Studio is connected to
+    pub fn active_endpoint(&self) -> String {
+        self.active_endpoint.lock().clone()
+    }
+
+    /// Durations of rstmdb operations
+    pub fn latency(&self) -> &Histogram {
+        &self.latency
+    }
+
+    /// Optional operations the connected server supports
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Record the server's advertised features. Reads over the raw
+    /// connection, bypassing the request queue, since it runs mid-reconnect.
+    async fn load_capabilities(&self) {
+        let result = self.client.read().await.info().await;
+        match result {
+            Ok(info) => self.capabilities.set(info.string_array("features")),
+            Err(e) => {
+                tracing::warn!(error = %e, "Could not read rstmdb features; assuming all are supported");
+                self.capabilities.set(Vec::new());
+            }
+        }
+    }
+
+    pub(super) fn instance_cache(&self) -> Option<&InstanceCache> {
+        self.instance_cache.as_ref()
+    }
+
+    /// Instance cache counters, if caching is enabled
+    pub fn instance_cache_stats(&self) -> Option<CacheStats> {
+        self.instance_cache.as_ref().map(|c| c.stats())
+    }
+
+    /// Request queue depths, if the queue is enabled
+    pub fn queue_depths(&self) -> Option<&QueueDepths> {
+        self.queue.as_ref().map(|q| q.depths())
+    }
+
+    /// Connect to the first reachable endpoint, starting with `preferred`
+    /// (the last one that worked) and then in configured order. Returns the
+    /// client and the endpoint it is connected to.
+    async fn create_client(
+        config: &RstmdbConfig,
+        preferred: Option<&str>,
+    ) -> Result<(Box<dyn Backend>, String), ApiError> {
+        let mut endpoints = config.endpoints();
+        if let Some(pos) = preferred.and_then(|p| endpoints.iter().position(|e| e == p)) {
+            let endpoint = endpoints.remove(pos);
+            endpoints.insert(0, endpoint);
+        }
+
+        let mut last_error = ApiError::bad_request("No rstmdb address configured");
+        for (i, endpoint) in endpoints.iter().enumerate() {
+            match Self::connect_endpoint(config, endpoint).await {
+                Ok(client) => {
+                    if i > 0 {
+                        tracing::warn!(endpoint = %endpoint, "Failed over to rstmdb endpoint");
+                    }
+                    return Ok((Box::new(client), endpoint.clone()));
+                }
+                Err(e) => {
+                    if endpoints.len() > 1 {
+                        tracing::warn!(endpoint = %endpoint, error = %e.message, "rstmdb endpoint unavailable");
+                    }
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Open a connection to one endpoint
+    async fn connect_endpoint(config: &RstmdbConfig, endpoint: &str) -> Result<Client, ApiError> {
+        let addr = tokio::net::lookup_host(endpoint)
             .await
             .map_err(|e| ApiError::bad_request(format!("Invalid rstmdb address: {}", e)))?
             .next()
             .ok_or_else(|| {
-                ApiError::bad_request(format!(
-                    "Could not resolve rstmdb address: {}",
-                    config.address
-                ))
+                ApiError::bad_request(format!("Could not resolve rstmdb address: {}", endpoint))
             })?;
 
-        let mut conn_config = ConnectionConfig::new(addr).with_client_name("rstmdb-studio");
+        let mut conn_config =
+            ConnectionConfig::new(addr).with_client_name(config.resolved_client_name());
 
         if let Some(ref token) = config.token {
             conn_config = conn_config.with_auth_token(token);
@@ -63,17 +221,84 @@ impl StudioClient {
         Ok(client)
     }
 
+    /// Replace the current connection with a fresh one.
+    ///
+    /// Gives up after `rstmdb.reconnect_timeout` so a hung connect can't hold
+    /// the connection lock indefinitely.
+    pub async fn reconnect(&self) -> Result<(), ApiError> {
+        let config = self.config.clone();
+        let active = self.active_endpoint.clone();
+        #[cfg(test)]
+        let fake = self.fake.clone();
+        self.client
+            .replace(move |client| {
+                Box::pin(async move {
+                    let _ = client.close().await;
+                    #[cfg(test)]
+                    if let Some(fake) = fake {
+                        return Ok(Box::new(fake) as Box<dyn Backend>);
+                    }
+                    let preferred = active.lock().clone();
+                    let (client, endpoint) = Self::create_client(&config, Some(&preferred)).await?;
+                    *active.lock() = endpoint;
+                    Ok(client)
+                })
+            })
+            .await
+            .map_err(|e| match e {
+                ReplaceError::Connect(e) => e,
+                ReplaceError::TimedOut(after) => ApiError::rstmdb_error(format!(
+                    "Reconnect to rstmdb timed out after {}",
+                    humantime::format_duration(after)
+                )),
+            })?;
+
+        tracing::info!(endpoint = %self.active_endpoint(), "Reconnected to rstmdb server");
+        // The server may have been upgraded or replaced
+        self.load_capabilities().await;
+        Ok(())
+    }
+
     /// Execute an operation with auto-reconnect on connection failure
     async fn with_reconnect<T, F, Fut>(&self, op_name: &str, op: F) -> Result<T, ApiError>
     where
-        F: Fn(Arc<RwLock<Client>>) -> Fut,
-        Fut: Future<Output = Result<T, rstmdb_client::ClientError>>,
+        F: Fn(OwnedRwLockReadGuard<Box<dyn Backend>>) -> Fut,
+        Fut: Future<Output = BackendResult<T>>,
+    {
+        self.with_reconnect_retrying(op_name, true, op).await
+    }
+
+    /// Execute an operation, reconnecting on connection failure and, if
+    /// `retry`, running it again. A write that is not safe to repeat may
+    /// have been applied before the connection dropped, so it is not retried
+    /// and fails saying so.
+    async fn with_reconnect_retrying<T, F, Fut>(
+        &self,
+        op_name: &str,
+        retry: bool,
+        op: F,
+    ) -> Result<T, ApiError>
+    where
+        F: Fn(OwnedRwLockReadGuard<Box<dyn Backend>>) -> Fut,
+        Fut: Future<Output = BackendResult<T>>,
     {
+        // Charged once, before queueing; a reconnect retry is the same operation
+        budget::charge(op_name)?;
+
+        // Held for the whole operation, including a reconnect and retry
+        let _slot = match &self.queue {
+            Some(queue) => Some(queue.acquire(current_priority()).await?),
+            None => None,
+        };
+
+        // Timed from admission, so queueing shows up in the queue depths instead
+        let started = Instant::now();
+
         // First attempt
-        let result = op(self.client.clone()).await;
+        let result = op(self.client.read().await).await;
 
-        match result {
-            Ok(v) => Ok(v),
+        let (path, result) = match result {
+            Ok(v) => ("direct", Ok(v)),
             Err(e) => {
                 let err_str = e.to_string();
                 // Check if it's a connection error
@@ -82,66 +307,69 @@ impl StudioClient {
                     || err_str.contains("connection")
                 {
                     tracing::info!("Connection lost, reconnecting to rstmdb...");
-
-                    // Reconnect
-                    let mut client = self.client.write().await;
-                    let _ = client.close().await;
-                    *client = Self::create_client(&self.config).await?;
-                    drop(client);
-
-                    tracing::info!("Reconnected to rstmdb server");
-
-                    // Retry the operation
-                    op(self.client.clone())
-                        .await
-                        .map_err(|e| ApiError::rstmdb_error(format!("{} failed: {}", op_name, e)))
+                    let retried = match (self.reconnect().await, retry) {
+                        // Retry the operation
+                        (Ok(()), true) => op(self.client.read().await).await.map_err(|e| {
+                            ApiError::rstmdb_error(format!("{} failed: {}", op_name, e))
+                        }),
+                        (Err(e), true) => Err(e),
+                        (_, false) => Err(ApiError::rstmdb_error(format!(
+                            "{} failed: the connection to rstmdb was lost before it answered, \
+                             so it may or may not have been applied ({})",
+                            op_name, err_str
+                        ))),
+                    };
+                    ("reconnect", retried)
                 } else {
-                    Err(ApiError::rstmdb_error(format!("{} failed: {}", op_name, e)))
+                    (
+                        "direct",
+                        Err(ApiError::rstmdb_error(format!("{} failed: {}", op_name, e))),
+                    )
                 }
             }
-        }
+        };
+        self.latency
+            .observe(&[("op", op_name), ("path", path)], started.elapsed());
+        result
     }
 
     /// Ping the server
     pub async fn ping(&self) -> Result<(), ApiError> {
-        self.with_reconnect("Ping", |client| async move {
-            let c = client.read().await;
-            c.ping().await
-        })
-        .await
+        self.with_reconnect("Ping", |c| async move { c.ping().await })
+            .await
+    }
+
+    /// Ping once over the current connection, without reconnecting on failure
+    pub async fn probe(&self) -> Result<(), ApiError> {
+        let c = self.client.read().await;
+        c.ping()
+            .await
+            .map_err(|e| ApiError::rstmdb_error(format!("Ping failed: {}", e)))
     }
 
     /// Get server info
     pub async fn info(&self) -> Result<Value, ApiError> {
-        self.with_reconnect("Info", |client| async move {
-            let c = client.read().await;
-            c.info().await
-        })
-        .await
+        self.with_reconnect("Info", |c| async move { c.info().await })
+            .await
     }
 
     /// List all machines
     pub async fn list_machines(&self) -> Result<Value, ApiError> {
-        self.with_reconnect("List machines", |client| async move {
-            let c = client.read().await;
-            c.list_machines().await
-        })
-        .await
+        self.reads
+            .run("list_machines", || {
+                self.with_reconnect("List machines", |c| async move { c.list_machines().await })
+            })
+            .await
     }
 
     /// Get machine definition
     pub async fn get_machine(&self, name: &str, version: u32) -> Result<Value, ApiError> {
         let name = name.to_string();
-        let result = self
-            .with_reconnect("Get machine", |client| {
-                let name = name.clone();
-                async move {
-                    let c = client.read().await;
-                    c.get_machine(&name, version).await
-                }
-            })
-            .await?;
-        Ok(serde_json::to_value(result).unwrap_or(Value::Null))
+        self.with_reconnect("Get machine", |c| {
+            let name = name.clone();
+            async move { c.get_machine(&name, version).await }
+        })
+        .await
     }
 
     /// Create or update machine definition
@@ -152,34 +380,29 @@ impl StudioClient {
         definition: Value,
     ) -> Result<PutMachineResult, ApiError> {
         let name = name.to_string();
-        let result = self
-            .with_reconnect("Put machine", |client| {
-                let name = name.clone();
-                let definition = definition.clone();
-                async move {
-                    let c = client.read().await;
-                    c.put_machine(&name, version, definition).await
-                }
-            })
-            .await?;
-        Ok(PutMachineResult {
-            machine: result.machine,
-            version: result.version,
-            checksum: result.stored_checksum,
-            created: result.created,
+        self.with_reconnect("Put machine", |c| {
+            let name = name.clone();
+            let definition = definition.clone();
+            async move { c.put_machine(&name, version, definition).await }
         })
+        .await
     }
 
-    /// Get instance
+    /// Get instance, served from the instance cache when enabled
     pub async fn get_instance(&self, id: &str) -> Result<InstanceResult, ApiError> {
+        if let Some(cached) = self.instance_cache.as_ref().and_then(|c| c.get(id)) {
+            return Ok(cached);
+        }
+        self.get_instance_fresh(id).await
+    }
+
+    /// Get instance from rstmdb, bypassing (but refreshing) the instance cache
+    pub async fn get_instance_fresh(&self, id: &str) -> Result<InstanceResult, ApiError> {
         let id = id.to_string();
-        let result = self
-            .with_reconnect("Get instance", |client| {
+        let instance = self
+            .with_reconnect("Get instance", |c| {
                 let id = id.clone();
-                async move {
-                    let c = client.read().await;
-                    c.get_instance(&id).await
-                }
+                async move { c.get_instance(&id).await }
             })
             .await
             .map_err(|e| {
@@ -189,32 +412,118 @@ impl StudioClient {
                     e
                 }
             })?;
-        Ok(InstanceResult {
-            instance_id: id,
-            machine: result.machine,
-            version: result.version,
-            state: result.state,
-            ctx: result.ctx,
-            last_wal_offset: result.last_wal_offset,
+
+        if let Some(cache) = &self.instance_cache {
+            cache.insert(&instance.instance_id, instance.clone());
+        }
+
+        Ok(instance)
+    }
+
+    /// Create an instance of a machine version
+    pub async fn create_instance(
+        &self,
+        machine: &str,
+        version: u32,
+        instance_id: Option<&str>,
+        initial_ctx: Option<Value>,
+    ) -> Result<CreateInstanceResult, ApiError> {
+        let machine = machine.to_string();
+        let instance_id = instance_id.map(|s| s.to_string());
+        // A repeated create with the same id is refused, never duplicated
+        let retry = instance_id.is_some();
+        self.with_reconnect_retrying("Create instance", retry, |c| {
+            let machine = machine.clone();
+            let instance_id = instance_id.clone();
+            let initial_ctx = initial_ctx.clone();
+            async move {
+                c.create_instance(&machine, version, instance_id.as_deref(), initial_ctx)
+                    .await
+            }
         })
+        .await
+    }
+
+    /// Apply an event to an instance. `event_id` is sent as the idempotency
+    /// key, so rstmdb applies an id at most once; only then is the event
+    /// retried after a reconnect.
+    pub async fn apply_event(
+        &self,
+        instance_id: &str,
+        event: &str,
+        payload: Option<Value>,
+        expected_state: Option<&str>,
+        event_id: Option<&str>,
+    ) -> Result<ApplyEventResult, ApiError> {
+        let instance_id = instance_id.to_string();
+        let event = event.to_string();
+        let expected_state = expected_state.map(|s| s.to_string());
+        let event_id = event_id.map(|s| s.to_string());
+        let result = self
+            .with_reconnect_retrying("Apply event", event_id.is_some(), |c| {
+                let instance_id = instance_id.clone();
+                let event = event.clone();
+                let payload = payload.clone();
+                let expected_state = expected_state.clone();
+                let event_id = event_id.clone();
+                async move {
+                    c.apply_event(
+                        &instance_id,
+                        &event,
+                        payload,
+                        expected_state.as_deref(),
+                        event_id.as_deref(),
+                    )
+                    .await
+                }
+            })
+            .await;
+        self.invalidate_instance(&instance_id);
+        result
+    }
+
+    /// Delete an instance
+    pub async fn delete_instance(
+        &self,
+        instance_id: &str,
+    ) -> Result<DeleteInstanceResult, ApiError> {
+        let instance_id = instance_id.to_string();
+        let result = self
+            .with_reconnect("Delete instance", |c| {
+                let instance_id = instance_id.clone();
+                async move { c.delete_instance(&instance_id).await }
+            })
+            .await;
+        self.invalidate_instance(&instance_id);
+        result.map_err(|e| {
+            if e.to_string().contains("not found") {
+                ApiError::not_found("Instance")
+            } else {
+                e
+            }
+        })
+    }
+
+    /// Drop a cached instance after a write, whether or not the write succeeded
+    fn invalidate_instance(&self, id: &str) {
+        if let Some(cache) = &self.instance_cache {
+            cache.invalidate(id);
+        }
     }
 
     /// Read WAL entries
     pub async fn wal_read(&self, from: u64, limit: Option<u64>) -> Result<Value, ApiError> {
-        self.with_reconnect("WAL read", |client| async move {
-            let c = client.read().await;
-            c.wal_read(from, limit).await
-        })
-        .await
+        self.with_reconnect("WAL read", |c| async move { c.wal_read(from, limit).await })
+            .await
     }
 
     /// Get WAL statistics
     pub async fn wal_stats(&self) -> Result<Value, ApiError> {
-        self.with_reconnect("WAL stats", |client| async move {
-            let c = client.read().await;
-            c.wal_stats().await
-        })
-        .await
+        self.reads
+            .run("wal_stats", || {
+                self.with_reconnect("WAL stats", |c| async move { c.wal_stats().await })
+            })
+            .await
     }
 
     /// List instances for a specific machine with optional state filter and pagination
@@ -227,34 +536,44 @@ impl StudioClient {
     ) -> Result<ListInstancesResult, ApiError> {
         let machine = machine.to_string();
         let state = state.map(|s| s.to_string());
-        let result = self
-            .with_reconnect("List instances", |client| {
-                let machine = machine.clone();
-                let state = state.clone();
-                async move {
-                    let c = client.read().await;
-                    c.list_instances(Some(&machine), state.as_deref(), limit, offset)
-                        .await
-                }
-            })
-            .await?;
-        Ok(ListInstancesResult {
-            instances: result
-                .instances
-                .into_iter()
-                .map(|i| InstanceSummary {
-                    id: i.id,
-                    machine: i.machine,
-                    version: i.version,
-                    state: i.state,
-                    created_at: i.created_at,
-                    updated_at: i.updated_at,
-                    last_wal_offset: i.last_wal_offset,
-                })
-                .collect(),
-            total: result.total,
-            has_more: result.has_more,
+        self.with_reconnect("List instances", |c| {
+            let machine = machine.clone();
+            let state = state.clone();
+            async move {
+                c.list_instances(Some(&machine), state.as_deref(), limit, offset)
+                    .await
+            }
         })
+        .await
+    }
+
+    /// Number of instances of a machine, optionally in one state.
+    ///
+    /// rstmdb has no count operation, so this lists with `limit=0` to get the
+    /// total without items. If the server rejects that or returns items anyway,
+    /// this and later counts use a one-item page instead.
+    pub async fn count_instances(
+        &self,
+        machine: &str,
+        state: Option<&str>,
+    ) -> Result<u64, ApiError> {
+        if !self.count_needs_page.load(Ordering::Relaxed) {
+            match self.list_instances(machine, state, Some(0), None).await {
+                Ok(result) if result.instances.is_empty() => return Ok(result.total),
+                Ok(result) => {
+                    self.count_needs_page.store(true, Ordering::Relaxed);
+                    return Ok(result.total);
+                }
+                Err(e) => {
+                    tracing::debug!(error = %e, "list_instances with limit=0 failed; counting with a one-item page");
+                    self.count_needs_page.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        Ok(self
+            .list_instances(machine, state, Some(1), None)
+            .await?
+            .total)
     }
 }
 
@@ -268,7 +587,7 @@ pub struct PutMachineResult {
     pub created: bool,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct InstanceResult {
     pub instance_id: String,
     pub machine: String,
@@ -278,6 +597,30 @@ pub struct InstanceResult {
     pub last_wal_offset: u64,
 }
 
+#[derive(Debug, serde::Serialize)]
+pub struct CreateInstanceResult {
+    pub instance_id: String,
+    pub state: String,
+    pub wal_offset: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ApplyEventResult {
+    pub from_state: String,
+    pub to_state: String,
+    pub ctx: Option<Value>,
+    pub wal_offset: u64,
+    pub applied: bool,
+    pub event_id: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DeleteInstanceResult {
+    pub instance_id: String,
+    pub deleted: bool,
+    pub wal_offset: u64,
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct ListInstancesResult {
     pub instances: Vec<InstanceSummary>,
@@ -295,3 +638,110 @@ pub struct InstanceSummary {
     pub updated_at: i64,
     pub last_wal_offset: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::rstmdb::FakeRstmdb;
+    use serde_json::json;
+
+    fn client() -> (StudioClient, FakeRstmdb) {
+        let fake = FakeRstmdb::new().with_machine(
+            "counter",
+            1,
+            json!({
+                "states": ["on"],
+                "initial": "on",
+                "transitions": [{ "from": "on", "event": "TICK", "to": "on" }]
+            }),
+        );
+        let client = StudioClient::with_fake(&Config::default().rstmdb, fake.clone());
+        (client, fake)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_list_machines_share_one_call() {
+        let (client, fake) = client();
+        let client = std::sync::Arc::new(client);
+        fake.hold("list_machines");
+
+        let tasks: Vec<_> = (0..50)
+            .map(|_| {
+                let client = client.clone();
+                tokio::spawn(async move { client.list_machines().await })
+            })
+            .collect();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while fake.calls("list_machines") == 0 {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "list_machines never reached rstmdb"
+            );
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        fake.release("list_machines");
+
+        for task in tasks {
+            let machines = task.await.unwrap().unwrap();
+            assert_eq!(machines["items"][0]["machine"], "counter");
+        }
+        assert_eq!(fake.calls("list_machines"), 1);
+
+        // Once answered, the next call goes to rstmdb again
+        client.list_machines().await.unwrap();
+        assert_eq!(fake.calls("list_machines"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_writes_without_idempotency_key_are_not_retried() {
+        let (client, fake) = client();
+        client
+            .create_instance("counter", 1, Some("c-1"), None)
+            .await
+            .unwrap();
+        let wal_len = || fake.instance("c-1").unwrap().last_wal_offset;
+        let before = wal_len();
+
+        fake.drop_connection_after("apply_event");
+        let err = client
+            .apply_event("c-1", "TICK", None, None, None)
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("may or may not have been applied"));
+        assert_eq!(fake.calls("apply_event"), 1);
+        assert_eq!(wal_len(), before + 1);
+
+        // With an event_id the retry is answered from rstmdb's idempotency check
+        fake.drop_connection_after("apply_event");
+        let result = client
+            .apply_event("c-1", "TICK", None, None, Some("e-1"))
+            .await
+            .unwrap();
+        assert!(!result.applied);
+        assert_eq!(fake.calls("apply_event"), 3);
+        assert_eq!(wal_len(), before + 2);
+    }
+
+    #[tokio::test]
+    async fn test_create_is_retried_only_with_an_instance_id() {
+        let (client, fake) = client();
+        fake.drop_connection_after("create_instance");
+        let err = client
+            .create_instance("counter", 1, None, None)
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("may or may not have been applied"));
+        assert_eq!(fake.calls("create_instance"), 1);
+
+        // The retry is refused as a duplicate rather than creating another
+        fake.drop_connection_after("create_instance");
+        let err = client
+            .create_instance("counter", 1, Some("c-2"), None)
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("already exists"));
+        assert_eq!(fake.calls("create_instance"), 3);
+        assert!(fake.instance("c-2").is_some());
+    }
+}
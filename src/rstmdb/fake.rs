@@ -0,0 +1,462 @@
+//! In-memory rstmdb for handler tests
+//!
+//! Keeps machines, instances and a WAL the way rstmdb reports them, so a
+//! test can run handlers against [`StudioClient::with_fake`] and check
+//! both the response and what ended up stored. Versions are immutable as
+//! in rstmdb: storing a different definition at an existing version fails,
+//! storing the same one answers `created: false`.
+//!
+//! [`StudioClient::with_fake`]: super::StudioClient::with_fake
+
+use super::backend::{Backend, BackendResult};
+use super::client::{
+    ApplyEventResult, CreateInstanceResult, DeleteInstanceResult, InstanceResult, InstanceSummary,
+    ListInstancesResult, PutMachineResult,
+};
+use crate::checksum::definition_checksum;
+use crate::constants::wal_entry_types;
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Cloning shares the store, so a test keeps a handle to inspect it
+#[derive(Clone, Default)]
+pub struct FakeRstmdb {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    machines: BTreeMap<String, BTreeMap<u32, Value>>,
+    instances: BTreeMap<String, InstanceResult>,
+    wal: Vec<(u64, Value)>,
+    /// `(instance, event_id)` pairs already applied
+    applied: HashSet<(String, String)>,
+    /// Operations whose next call succeeds but loses the answer
+    drop_after: HashSet<&'static str>,
+    calls: HashMap<&'static str, usize>,
+    /// Operations whose calls wait until released; closing the semaphore
+    /// releases them
+    held: HashMap<&'static str, Arc<Semaphore>>,
+    /// Most records one `wal_read` page returns, whatever the limit asked
+    wal_page_limit: Option<u64>,
+}
+
+impl Inner {
+    fn call(&mut self, op: &'static str) {
+        *self.calls.entry(op).or_default() += 1;
+    }
+
+    /// The connection error a dropped answer to `op` surfaces as, if it
+    /// was set to drop
+    fn dropped<T>(&mut self, op: &'static str, result: T) -> BackendResult<T> {
+        if self.drop_after.remove(op) {
+            return Err("connection reset by peer".into());
+        }
+        Ok(result)
+    }
+
+    fn append(&mut self, mut entry: Value) -> u64 {
+        let offset = self.wal.last().map_or(1, |(offset, _)| offset + 1);
+        entry["timestamp"] = json!(chrono::Utc::now().timestamp_millis());
+        self.wal.push((offset, entry));
+        offset
+    }
+}
+
+impl FakeRstmdb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store a machine version up front
+    pub fn with_machine(self, name: &str, version: u32, definition: Value) -> Self {
+        self.inner
+            .lock()
+            .machines
+            .entry(name.to_string())
+            .or_default()
+            .insert(version, definition);
+        self
+    }
+
+    /// Return at most `limit` records per `wal_read` page, like a server
+    /// with a smaller page size than Studio asks for
+    pub fn with_wal_page_limit(self, limit: u64) -> Self {
+        self.inner.lock().wal_page_limit = Some(limit);
+        self
+    }
+
+    pub fn machine(&self, name: &str, version: u32) -> Option<Value> {
+        self.inner.lock().machines.get(name)?.get(&version).cloned()
+    }
+
+    pub fn instance(&self, id: &str) -> Option<InstanceResult> {
+        self.inner.lock().instances.get(id).cloned()
+    }
+
+    /// Make the next `op` take effect but fail as if the connection
+    /// dropped before the answer arrived
+    pub fn drop_connection_after(&self, op: &'static str) {
+        self.inner.lock().drop_after.insert(op);
+    }
+
+    /// How often `op` (a [`Backend`] method name) was called
+    pub fn calls(&self, op: &str) -> usize {
+        self.inner.lock().calls.get(op).copied().unwrap_or(0)
+    }
+
+    /// Make calls to `op` wait until [`release`](Self::release), so a test
+    /// can pile up concurrent callers. Only the coalesced reads
+    /// (`list_machines`, `wal_stats`) wait.
+    pub fn hold(&self, op: &'static str) {
+        self.inner
+            .lock()
+            .held
+            .insert(op, Arc::new(Semaphore::new(0)));
+    }
+
+    pub fn release(&self, op: &'static str) {
+        if let Some(gate) = self.inner.lock().held.remove(op) {
+            gate.close();
+        }
+    }
+
+    /// Count a call to `op`, then wait while it is held
+    async fn enter(&self, op: &'static str) {
+        let gate = {
+            let mut inner = self.inner.lock();
+            inner.call(op);
+            inner.held.get(op).cloned()
+        };
+        if let Some(gate) = gate {
+            let _ = gate.acquire().await;
+        }
+    }
+}
+
+fn not_found(what: &str) -> BackendResult<Value> {
+    Err(format!("{} not found", what).into())
+}
+
+#[async_trait]
+impl Backend for FakeRstmdb {
+    async fn close(&self) -> BackendResult<()> {
+        Ok(())
+    }
+
+    async fn ping(&self) -> BackendResult<()> {
+        self.inner.lock().call("ping");
+        Ok(())
+    }
+
+    async fn info(&self) -> BackendResult<Value> {
+        self.inner.lock().call("info");
+        Ok(json!({ "features": [] }))
+    }
+
+    async fn list_machines(&self) -> BackendResult<Value> {
+        self.enter("list_machines").await;
+        let inner = self.inner.lock();
+        let items: Vec<Value> = inner
+            .machines
+            .iter()
+            .map(|(machine, versions)| {
+                json!({ "machine": machine, "versions": versions.keys().collect::<Vec<_>>() })
+            })
+            .collect();
+        Ok(json!({ "items": items }))
+    }
+
+    async fn get_machine(&self, name: &str, version: u32) -> BackendResult<Value> {
+        let mut inner = self.inner.lock();
+        inner.call("get_machine");
+        match inner.machines.get(name).and_then(|v| v.get(&version)) {
+            Some(definition) => Ok(json!({
+                "definition": definition,
+                "checksum": definition_checksum(definition),
+            })),
+            None => not_found("Machine version"),
+        }
+    }
+
+    async fn put_machine(
+        &self,
+        name: &str,
+        version: u32,
+        definition: Value,
+    ) -> BackendResult<PutMachineResult> {
+        let mut inner = self.inner.lock();
+        inner.call("put_machine");
+        let checksum = definition_checksum(&definition);
+        let versions = inner.machines.entry(name.to_string()).or_default();
+        let created = match versions.get(&version) {
+            Some(stored) if definition_checksum(stored) == checksum => false,
+            Some(_) => {
+                return Err(format!(
+                    "Machine '{}' version {} already exists with a different definition",
+                    name, version
+                )
+                .into())
+            }
+            None => {
+                versions.insert(version, definition);
+                true
+            }
+        };
+        Ok(PutMachineResult {
+            machine: name.to_string(),
+            version,
+            checksum,
+            created,
+        })
+    }
+
+    async fn get_instance(&self, id: &str) -> BackendResult<InstanceResult> {
+        let mut inner = self.inner.lock();
+        inner.call("get_instance");
+        inner
+            .instances
+            .get(id)
+            .cloned()
+            .ok_or_else(|| "Instance not found".into())
+    }
+
+    async fn create_instance(
+        &self,
+        machine: &str,
+        version: u32,
+        instance_id: Option<&str>,
+        initial_ctx: Option<Value>,
+    ) -> BackendResult<CreateInstanceResult> {
+        let mut inner = self.inner.lock();
+        inner.call("create_instance");
+        let Some(definition) = inner.machines.get(machine).and_then(|v| v.get(&version)) else {
+            return Err("Machine version not found".into());
+        };
+        let state = definition["initial"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let id = instance_id
+            .map(String::from)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        if inner.instances.contains_key(&id) {
+            return Err(format!("Instance '{}' already exists", id).into());
+        }
+        let ctx = initial_ctx.unwrap_or_else(|| json!({}));
+        let offset = inner.append(json!({
+            "type": wal_entry_types::CREATE_INSTANCE,
+            "instance_id": id,
+            "machine": machine,
+            "version": version,
+            "initial_state": state,
+            "initial_ctx": ctx,
+        }));
+        inner.instances.insert(
+            id.clone(),
+            InstanceResult {
+                instance_id: id.clone(),
+                machine: machine.to_string(),
+                version,
+                state: state.clone(),
+                ctx,
+                last_wal_offset: offset,
+            },
+        );
+        inner.dropped(
+            "create_instance",
+            CreateInstanceResult {
+                instance_id: id,
+                state,
+                wal_offset: offset,
+            },
+        )
+    }
+
+    async fn apply_event(
+        &self,
+        instance_id: &str,
+        event: &str,
+        payload: Option<Value>,
+        expected_state: Option<&str>,
+        event_id: Option<&str>,
+    ) -> BackendResult<ApplyEventResult> {
+        let mut inner = self.inner.lock();
+        inner.call("apply_event");
+        let Some(instance) = inner.instances.get(instance_id).cloned() else {
+            return Err("Instance not found".into());
+        };
+        if let Some(event_id) = event_id {
+            if inner
+                .applied
+                .contains(&(instance_id.to_string(), event_id.to_string()))
+            {
+                return Ok(ApplyEventResult {
+                    from_state: instance.state.clone(),
+                    to_state: instance.state,
+                    ctx: Some(instance.ctx),
+                    wal_offset: instance.last_wal_offset,
+                    applied: false,
+                    event_id: Some(event_id.to_string()),
+                });
+            }
+        }
+        if let Some(expected) = expected_state {
+            if expected != instance.state {
+                return Err(format!(
+                    "State mismatch: expected '{}', instance is in '{}'",
+                    expected, instance.state
+                )
+                .into());
+            }
+        }
+
+        let definition = &inner.machines[&instance.machine][&instance.version];
+        let from_matches = |from: &Value| match from {
+            Value::Array(states) => states.iter().any(|s| s == instance.state.as_str()),
+            state => state == instance.state.as_str(),
+        };
+        let Some(to_state) = definition["transitions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|t| t["event"] == event && from_matches(&t["from"]))
+            .and_then(|t| t["to"].as_str())
+            .map(String::from)
+        else {
+            return Err(format!(
+                "No transition for event '{}' from state '{}'",
+                event, instance.state
+            )
+            .into());
+        };
+
+        // Top-level payload keys replace those of the context
+        let mut ctx = instance.ctx.clone();
+        if let (Some(ctx), Some(Value::Object(fields))) = (ctx.as_object_mut(), &payload) {
+            ctx.extend(fields.clone());
+        }
+        let offset = inner.append(json!({
+            "type": wal_entry_types::APPLY_EVENT,
+            "instance_id": instance_id,
+            "event": event,
+            "from_state": instance.state,
+            "to_state": to_state,
+            "payload": payload,
+            "ctx": ctx,
+            "event_id": event_id,
+        }));
+        if let Some(event_id) = event_id {
+            inner
+                .applied
+                .insert((instance_id.to_string(), event_id.to_string()));
+        }
+        let stored = inner.instances.get_mut(instance_id).unwrap();
+        stored.state = to_state.clone();
+        stored.ctx = ctx.clone();
+        stored.last_wal_offset = offset;
+        inner.dropped(
+            "apply_event",
+            ApplyEventResult {
+                from_state: instance.state,
+                to_state,
+                ctx: Some(ctx),
+                wal_offset: offset,
+                applied: true,
+                event_id: event_id.map(String::from),
+            },
+        )
+    }
+
+    async fn delete_instance(&self, instance_id: &str) -> BackendResult<DeleteInstanceResult> {
+        let mut inner = self.inner.lock();
+        inner.call("delete_instance");
+        if inner.instances.remove(instance_id).is_none() {
+            return Err("Instance not found".into());
+        }
+        let offset = inner.append(json!({
+            "type": wal_entry_types::DELETE_INSTANCE,
+            "instance_id": instance_id,
+        }));
+        Ok(DeleteInstanceResult {
+            instance_id: instance_id.to_string(),
+            deleted: true,
+            wal_offset: offset,
+        })
+    }
+
+    async fn wal_read(&self, from: u64, limit: Option<u64>) -> BackendResult<Value> {
+        let mut inner = self.inner.lock();
+        inner.call("wal_read");
+        let limit = limit
+            .unwrap_or(100)
+            .min(inner.wal_page_limit.unwrap_or(u64::MAX));
+        let records: Vec<Value> = inner
+            .wal
+            .iter()
+            .zip(1u64..)
+            .filter(|((offset, _), _)| *offset >= from)
+            .take(limit as usize)
+            .map(|((offset, entry), sequence)| {
+                json!({ "sequence": sequence, "offset": offset, "entry": entry })
+            })
+            .collect();
+        let next_offset = records
+            .last()
+            .and_then(|r| r["offset"].as_u64())
+            .map(|last| last + 1);
+        Ok(json!({ "records": records, "next_offset": next_offset }))
+    }
+
+    async fn wal_stats(&self) -> BackendResult<Value> {
+        self.enter("wal_stats").await;
+        let inner = self.inner.lock();
+        Ok(json!({
+            "entry_count": inner.wal.len(),
+            "latest_offset": inner.wal.last().map(|(offset, _)| offset),
+        }))
+    }
+
+    async fn list_instances(
+        &self,
+        machine: Option<&str>,
+        state: Option<&str>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> BackendResult<ListInstancesResult> {
+        let mut inner = self.inner.lock();
+        inner.call("list_instances");
+        let matching: Vec<&InstanceResult> = inner
+            .instances
+            .values()
+            .filter(|i| machine.is_none_or(|m| i.machine == m))
+            .filter(|i| state.is_none_or(|s| i.state == s))
+            .collect();
+        let total = matching.len() as u64;
+        let offset = offset.unwrap_or(0) as usize;
+        let limit = limit.unwrap_or(100) as usize;
+        let instances: Vec<InstanceSummary> = matching
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|i| InstanceSummary {
+                id: i.instance_id.clone(),
+                machine: i.machine.clone(),
+                version: i.version,
+                state: i.state.clone(),
+                created_at: 0,
+                updated_at: 0,
+                last_wal_offset: i.last_wal_offset,
+            })
+            .collect();
+        Ok(ListInstancesResult {
+            has_more: (offset + instances.len()) < total as usize,
+            instances,
+            total,
+        })
+    }
+}
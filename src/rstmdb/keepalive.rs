@@ -0,0 +1,110 @@
+//! Background keep-alive pings
+//!
+//! A half-open TCP connection is otherwise only noticed by the next request,
+//! which on a quiet Studio can be minutes later. Pinging on an interval keeps
+//! the connection warm and reconnects once pings keep failing.
+
+use super::StudioClient;
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Outcome of recent keep-alive pings, shared with the health endpoint
+#[derive(Debug, Default)]
+pub struct KeepaliveStatus {
+    /// Epoch ms of the last successful ping, 0 if none yet
+    last_success_ms: AtomicI64,
+    consecutive_failures: AtomicU32,
+}
+
+impl KeepaliveStatus {
+    pub fn record_success(&self, now_ms: i64) {
+        self.last_success_ms.store(now_ms, Ordering::Relaxed);
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+
+    /// Record a failed ping, returning true once `threshold` failures in a row
+    /// have been seen and a reconnect is due
+    pub fn record_failure(&self, threshold: u32) -> bool {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        failures >= threshold.max(1)
+    }
+
+    pub fn reset_failures(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+
+    /// Epoch ms of the last successful ping
+    pub fn last_success(&self) -> Option<i64> {
+        match self.last_success_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(ms),
+        }
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures.load(Ordering::Relaxed)
+    }
+}
+
+/// Ping rstmdb every `interval`, reconnecting after `failure_threshold`
+/// consecutive failures. Runs until the process exits.
+pub async fn run_keepalive(
+    client: &StudioClient,
+    status: &KeepaliveStatus,
+    interval: Duration,
+    failure_threshold: u32,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+
+        // A half-open connection may never answer, so bound the ping
+        let error = match tokio::time::timeout(interval, client.probe()).await {
+            Ok(Ok(())) => {
+                status.record_success(chrono::Utc::now().timestamp_millis());
+                continue;
+            }
+            Ok(Err(e)) => e.message,
+            Err(_) => format!("no response within {:?}", interval),
+        };
+
+        if !status.record_failure(failure_threshold) {
+            tracing::debug!(error = %error, "Keep-alive ping failed");
+            continue;
+        }
+
+        tracing::warn!(
+            error = %error,
+            failures = status.consecutive_failures(),
+            "Keep-alive pings failing, reconnecting to rstmdb"
+        );
+        match client.reconnect().await {
+            Ok(()) => status.reset_failures(),
+            Err(e) => tracing::warn!(error = %e.message, "Keep-alive reconnect failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_threshold() {
+        let status = KeepaliveStatus::default();
+        assert_eq!(status.last_success(), None);
+
+        assert!(!status.record_failure(3));
+        assert!(!status.record_failure(3));
+        assert!(status.record_failure(3));
+
+        status.record_success(1_000);
+        assert_eq!(status.last_success(), Some(1_000));
+        assert_eq!(status.consecutive_failures(), 0);
+
+        // A threshold of 0 behaves like 1
+        assert!(status.record_failure(0));
+    }
+}
@@ -0,0 +1,181 @@
+//! Instrumented connection lock
+//!
+//! Requests share the connection through a read lock while a reconnect takes
+//! the write lock. tokio's `RwLock` is fair, so once a reconnect is queued new
+//! readers wait behind it; a reconnect that hangs would stall every request.
+//! Read waits above a threshold are logged, and a reconnect that runs past its
+//! timeout is abandoned so the write lock is released.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedRwLockReadGuard, RwLock};
+
+/// Future returned by a reconnect, borrowing the current connection
+pub type ConnectFuture<'a, C, E> = Pin<Box<dyn Future<Output = Result<C, E>> + Send + 'a>>;
+
+/// Why replacing the connection failed
+#[derive(Debug)]
+pub enum ReplaceError<E> {
+    /// The connect future failed; the old connection is kept
+    Connect(E),
+    /// The connect future did not finish in time and was dropped
+    TimedOut(Duration),
+}
+
+pub struct ConnectionLock<C> {
+    inner: Arc<RwLock<C>>,
+    /// Read lock waits longer than this are logged
+    wait_warn: Duration,
+    /// Longest a reconnect may hold the write lock
+    reconnect_timeout: Duration,
+}
+
+impl<C> ConnectionLock<C> {
+    pub fn new(conn: C, wait_warn: Duration, reconnect_timeout: Duration) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(conn)),
+            wait_warn,
+            reconnect_timeout,
+        }
+    }
+
+    /// Shared access to the connection, warning when the wait was long
+    pub async fn read(&self) -> OwnedRwLockReadGuard<C> {
+        let started = Instant::now();
+        let guard = self.inner.clone().read_owned().await;
+        let waited = started.elapsed();
+        if waited >= self.wait_warn {
+            tracing::warn!(
+                waited_ms = waited.as_millis() as u64,
+                threshold_ms = self.wait_warn.as_millis() as u64,
+                "Slow rstmdb connection lock; a reconnect may be stuck"
+            );
+        }
+        guard
+    }
+
+    /// Replace the connection under the write lock.
+    ///
+    /// `connect` receives the current connection (e.g. to close it) and returns
+    /// its replacement. It is dropped if it runs past the reconnect timeout.
+    pub async fn replace<F, E>(&self, connect: F) -> Result<(), ReplaceError<E>>
+    where
+        F: for<'a> FnOnce(&'a mut C) -> ConnectFuture<'a, C, E>,
+    {
+        let mut conn = self.inner.write().await;
+        match tokio::time::timeout(self.reconnect_timeout, connect(&mut *conn)).await {
+            Ok(Ok(new)) => {
+                *conn = new;
+                Ok(())
+            }
+            Ok(Err(e)) => Err(ReplaceError::Connect(e)),
+            Err(_) => {
+                tracing::error!(
+                    timeout_ms = self.reconnect_timeout.as_millis() as u64,
+                    "rstmdb reconnect timed out; releasing connection lock"
+                );
+                Err(ReplaceError::TimedOut(self.reconnect_timeout))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use tokio::sync::Notify;
+
+    /// Marks a reconnect as holding the write lock until its future is dropped
+    struct Holding(Arc<AtomicBool>);
+
+    impl Drop for Holding {
+        fn drop(&mut self) {
+            self.0.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Many concurrent "creates" while a reconnect hangs: the watchdog must
+    /// release the write lock so every reader completes.
+    #[tokio::test]
+    async fn test_stuck_reconnect_does_not_block_readers() {
+        let lock = Arc::new(ConnectionLock::new(
+            1u32,
+            Duration::from_millis(20),
+            Duration::from_millis(100),
+        ));
+        let completed = Arc::new(AtomicUsize::new(0));
+        let holding = Arc::new(AtomicBool::new(false));
+        let started = Arc::new(Notify::new());
+
+        // Hold the write lock with a reconnect that never finishes
+        let reconnect = {
+            let lock = lock.clone();
+            let holding = holding.clone();
+            let started = started.clone();
+            tokio::spawn(async move {
+                lock.replace(|_| {
+                    Box::pin(async move {
+                        holding.store(true, Ordering::SeqCst);
+                        let _held = Holding(holding);
+                        started.notify_one();
+                        std::future::pending::<Result<u32, ()>>().await
+                    })
+                })
+                .await
+            })
+        };
+        started.notified().await;
+
+        // Every reader queues behind the reconnect and only gets the
+        // connection once the watchdog has dropped it
+        let mut creates = Vec::new();
+        for _ in 0..200 {
+            let lock = lock.clone();
+            let completed = completed.clone();
+            let holding = holding.clone();
+            creates.push(tokio::spawn(async move {
+                let conn = lock.read().await;
+                assert!(!holding.load(Ordering::SeqCst));
+                tokio::task::yield_now().await;
+                completed.fetch_add(1, Ordering::SeqCst);
+                *conn
+            }));
+        }
+
+        let all = async {
+            for create in creates {
+                assert_eq!(create.await.unwrap(), 1);
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), all)
+            .await
+            .expect("readers deadlocked behind the reconnect");
+        assert_eq!(completed.load(Ordering::SeqCst), 200);
+        assert!(matches!(
+            reconnect.await.unwrap(),
+            Err(ReplaceError::TimedOut(_))
+        ));
+
+        // The lock is usable again and a successful reconnect swaps the value
+        lock.replace(|old| {
+            let next = *old + 1;
+            Box::pin(async move { Ok::<_, ()>(next) })
+        })
+        .await
+        .unwrap();
+        assert_eq!(*lock.read().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_connect_keeps_old_connection() {
+        let lock = ConnectionLock::new(7u32, Duration::from_secs(1), Duration::from_secs(1));
+        let result = lock
+            .replace(|_| Box::pin(async { Err::<u32, _>("refused") }))
+            .await;
+        assert!(matches!(result, Err(ReplaceError::Connect("refused"))));
+        assert_eq!(*lock.read().await, 7);
+    }
+}
@@ -1,5 +1,22 @@
 //! rstmdb client wrapper
 
+mod backend;
+mod budget;
+mod cache;
+mod cache_follower;
+mod capabilities;
 mod client;
+#[cfg(test)]
+mod fake;
+mod keepalive;
+mod lock;
+mod queue;
+mod singleflight;
 
+pub use budget::{current_budget, with_budget, OpBudget};
+pub use cache_follower::run_cache_follower;
 pub use client::*;
+#[cfg(test)]
+pub use fake::FakeRstmdb;
+pub use keepalive::*;
+pub use queue::{current_priority, with_priority, Priority};
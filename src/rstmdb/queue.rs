@@ -0,0 +1,240 @@
+//! Prioritized admission to rstmdb
+//!
+//! When enabled, each rstmdb operation first waits for one of a fixed number
+//! of slots. Waiters queue in one of two bounded channels by priority and a
+//! dispatcher hands out free slots, serving up to `interactive_weight`
+//! interactive requests for every bulk one while both are waiting. Quick UI
+//! reads stay responsive while WAL scans and batch jobs still make progress.
+//!
+//! The priority is a task-local hint: handlers run bulk work inside
+//! [`with_priority`], and everything else is interactive.
+
+use crate::error::ApiError;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Single reads and writes behind UI interactions
+    Interactive,
+    /// Scans, batch fetches and other long-running work
+    Bulk,
+}
+
+tokio::task_local! {
+    static PRIORITY: Priority;
+}
+
+/// Run `fut` with rstmdb operations queued at `priority`
+pub async fn with_priority<F: Future>(priority: Priority, fut: F) -> F::Output {
+    PRIORITY.scope(priority, fut).await
+}
+
+/// Priority of the current task, `Interactive` unless set by [`with_priority`]
+pub fn current_priority() -> Priority {
+    PRIORITY.try_with(|p| *p).unwrap_or(Priority::Interactive)
+}
+
+type Waiter = oneshot::Sender<OwnedSemaphorePermit>;
+
+/// Waiting request counts, for metrics
+#[derive(Debug, Default)]
+pub struct QueueDepths {
+    interactive: AtomicUsize,
+    bulk: AtomicUsize,
+}
+
+impl QueueDepths {
+    fn counter(&self, priority: Priority) -> &AtomicUsize {
+        match priority {
+            Priority::Interactive => &self.interactive,
+            Priority::Bulk => &self.bulk,
+        }
+    }
+
+    pub fn interactive(&self) -> usize {
+        self.interactive.load(Ordering::Relaxed)
+    }
+
+    pub fn bulk(&self) -> usize {
+        self.bulk.load(Ordering::Relaxed)
+    }
+}
+
+pub struct RequestQueue {
+    interactive: mpsc::Sender<Waiter>,
+    bulk: mpsc::Sender<Waiter>,
+    depths: Arc<QueueDepths>,
+}
+
+impl RequestQueue {
+    /// Start the dispatcher.
+    ///
+    /// `concurrency` operations run at once; each tier holds at most
+    /// `max_queued` waiters before callers wait to enqueue.
+    pub fn new(concurrency: usize, max_queued: usize, interactive_weight: u32) -> Self {
+        let (interactive, interactive_rx) = mpsc::channel(max_queued.max(1));
+        let (bulk, bulk_rx) = mpsc::channel(max_queued.max(1));
+        let depths = Arc::new(QueueDepths::default());
+        let dispatcher = Dispatcher {
+            slots: Arc::new(Semaphore::new(concurrency.max(1))),
+            interactive: interactive_rx,
+            bulk: bulk_rx,
+            next_interactive: None,
+            next_bulk: None,
+            weight: interactive_weight.max(1),
+            depths: depths.clone(),
+        };
+        tokio::spawn(dispatcher.run());
+        Self {
+            interactive,
+            bulk,
+            depths,
+        }
+    }
+
+    pub fn depths(&self) -> &QueueDepths {
+        &self.depths
+    }
+
+    /// Wait for a slot; the operation may run while the permit is held
+    pub async fn acquire(&self, priority: Priority) -> Result<OwnedSemaphorePermit, ApiError> {
+        let (tx, rx) = oneshot::channel();
+        let channel = match priority {
+            Priority::Interactive => &self.interactive,
+            Priority::Bulk => &self.bulk,
+        };
+        let counter = self.depths.counter(priority);
+        counter.fetch_add(1, Ordering::Relaxed);
+        if channel.send(tx).await.is_err() {
+            counter.fetch_sub(1, Ordering::Relaxed);
+            return Err(ApiError::internal("rstmdb request queue stopped"));
+        }
+        rx.await
+            .map_err(|_| ApiError::internal("rstmdb request queue stopped"))
+    }
+}
+
+struct Dispatcher {
+    slots: Arc<Semaphore>,
+    interactive: mpsc::Receiver<Waiter>,
+    bulk: mpsc::Receiver<Waiter>,
+    /// Waiters taken off a channel but not yet served
+    next_interactive: Option<Waiter>,
+    next_bulk: Option<Waiter>,
+    weight: u32,
+    depths: Arc<QueueDepths>,
+}
+
+impl Dispatcher {
+    async fn run(mut self) {
+        let mut interactive_streak = 0;
+        loop {
+            let Ok(slot) = self.slots.clone().acquire_owned().await else {
+                return;
+            };
+            if !self.fill().await {
+                return;
+            }
+
+            let serve_bulk = self.next_bulk.is_some()
+                && (self.next_interactive.is_none() || interactive_streak >= self.weight);
+            let (waiter, priority) = if serve_bulk {
+                interactive_streak = 0;
+                (self.next_bulk.take(), Priority::Bulk)
+            } else {
+                // Only interactive requests served while bulk work waits count
+                interactive_streak = if self.next_bulk.is_some() {
+                    interactive_streak + 1
+                } else {
+                    0
+                };
+                (self.next_interactive.take(), Priority::Interactive)
+            };
+            self.depths
+                .counter(priority)
+                .fetch_sub(1, Ordering::Relaxed);
+            if let Some(waiter) = waiter {
+                // A cancelled caller drops the receiver; the slot is released
+                let _ = waiter.send(slot);
+            }
+        }
+    }
+
+    /// Peek at both channels, waiting until at least one waiter is known.
+    /// Returns false once both channels are closed and drained.
+    async fn fill(&mut self) -> bool {
+        if self.next_interactive.is_none() {
+            self.next_interactive = self.interactive.try_recv().ok();
+        }
+        if self.next_bulk.is_none() {
+            self.next_bulk = self.bulk.try_recv().ok();
+        }
+        if self.next_interactive.is_some() || self.next_bulk.is_some() {
+            return true;
+        }
+        tokio::select! {
+            biased;
+            Some(waiter) = self.interactive.recv() => self.next_interactive = Some(waiter),
+            Some(waiter) = self.bulk.recv() => self.next_bulk = Some(waiter),
+            else => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_interactive_requests_overtake_bulk_backlog() {
+        let queue = Arc::new(RequestQueue::new(1, 64, 3));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        // Occupy the only slot so everything else queues up
+        let blocker = queue.acquire(Priority::Interactive).await.unwrap();
+
+        let mut tasks = Vec::new();
+        for (i, priority) in [
+            (0, Priority::Bulk),
+            (1, Priority::Bulk),
+            (2, Priority::Bulk),
+        ]
+        .into_iter()
+        .chain((10..15).map(|i| (i, Priority::Interactive)))
+        {
+            let queue = queue.clone();
+            let order = order.clone();
+            tasks.push(tokio::spawn(async move {
+                let _slot = queue.acquire(priority).await.unwrap();
+                order.lock().push(i);
+            }));
+            // Enqueue in a deterministic order
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        assert_eq!(queue.depths().bulk(), 3);
+        assert_eq!(queue.depths().interactive(), 5);
+
+        drop(blocker);
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // Bulk work was first in line, but three interactive requests are
+        // served per bulk one while both tiers are waiting
+        assert_eq!(*order.lock(), vec![10, 11, 12, 0, 13, 14, 1, 2]);
+        assert_eq!(queue.depths().bulk() + queue.depths().interactive(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_priority_is_task_local() {
+        assert_eq!(current_priority(), Priority::Interactive);
+        let inner = with_priority(Priority::Bulk, async { current_priority() }).await;
+        assert_eq!(inner, Priority::Bulk);
+    }
+}
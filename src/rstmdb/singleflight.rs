@@ -0,0 +1,108 @@
+//! Coalescing of identical concurrent reads
+//!
+//! When many dashboard clients refresh at once, each would otherwise issue
+//! the same rstmdb call. Callers sharing a key while an operation is in
+//! flight wait for that operation and receive a clone of its result.
+
+use crate::error::ApiError;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+type Slot<T> = Arc<OnceCell<Result<T, ApiError>>>;
+
+pub struct SingleFlight<T> {
+    inflight: Mutex<HashMap<String, Slot<T>>>,
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self {
+            inflight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    /// Run `op` unless an operation with the same key is already in flight,
+    /// in which case wait for and share its result
+    pub async fn run<F, Fut>(&self, key: &str, op: F) -> Result<T, ApiError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        let slot = self
+            .inflight
+            .lock()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+
+        // If the running caller is cancelled, a waiter picks the operation up
+        let result = slot.get_or_init(op).await.clone();
+
+        // The first caller to finish retires the slot so later reads go to rstmdb
+        let mut inflight = self.inflight.lock();
+        if inflight
+            .get(key)
+            .is_some_and(|current| Arc::ptr_eq(current, &slot))
+        {
+            inflight.remove(key);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_share_one_operation() {
+        let flight = Arc::new(SingleFlight::<u32>::default());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..50)
+            .map(|_| {
+                let flight = flight.clone();
+                let calls = calls.clone();
+                tokio::spawn(async move {
+                    flight
+                        .run("op", || async {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Ok(7)
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap(), 7);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Once finished, the next call runs again
+        let next = flight.run("op", || async { Ok(8) }).await;
+        assert_eq!(next.unwrap(), 8);
+        assert!(flight.inflight.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_shared_and_keys_are_independent() {
+        let flight = SingleFlight::<u32>::default();
+        let err = flight
+            .run("wal_stats", || async {
+                Err(ApiError::rstmdb_error("down"))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.message, "down");
+        assert_eq!(flight.run("other", || async { Ok(1) }).await.unwrap(), 1);
+    }
+}
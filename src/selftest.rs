@@ -0,0 +1,181 @@
+//! End-to-end self-test against a live rstmdb server
+//!
+//! Runs the `selftest` subcommand: creates a throwaway machine and instance,
+//! drives it through a transition, reads its history back from the WAL, and
+//! deletes the instance again. Each step is reported as it completes.
+
+use crate::config::RstmdbConfig;
+use crate::constants::wal_entry_types;
+use crate::error::ApiError;
+use crate::json_ext::ValueExt;
+use crate::rstmdb::StudioClient;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::time::Instant;
+
+/// WAL entries read after the instance's creation offset when checking history
+const HISTORY_SCAN: u64 = 1000;
+
+fn definition() -> Value {
+    json!({
+        "states": ["created", "done"],
+        "initial": "created",
+        "transitions": [
+            { "from": "created", "event": "FINISH", "to": "done" }
+        ],
+        "meta": { "description": "Temporary machine created by rstmdb-studio selftest" }
+    })
+}
+
+#[derive(Default)]
+struct Report {
+    passed: usize,
+    failed: usize,
+}
+
+impl Report {
+    /// Run a step, print its outcome, and return its value if it passed
+    async fn step<T, F>(&mut self, name: &str, fut: F) -> Option<T>
+    where
+        F: Future<Output = Result<T, ApiError>>,
+    {
+        let started = Instant::now();
+        let result = fut.await;
+        let elapsed = started.elapsed().as_millis();
+        match result {
+            Ok(value) => {
+                self.passed += 1;
+                println!("  [PASS] {} ({}ms)", name, elapsed);
+                Some(value)
+            }
+            Err(e) => {
+                self.failed += 1;
+                println!("  [FAIL] {} ({}ms): {}", name, elapsed, e.message);
+                None
+            }
+        }
+    }
+
+    fn skip(&self, name: &str, reason: &str) {
+        println!("  [SKIP] {}: {}", name, reason);
+    }
+}
+
+/// Check that the WAL holds the instance's create and transition entries
+async fn read_history(
+    client: &StudioClient,
+    instance_id: &str,
+    from_offset: u64,
+) -> Result<usize, ApiError> {
+    let result = client.wal_read(from_offset, Some(HISTORY_SCAN)).await?;
+    let entries: Vec<String> = result["records"]
+        .as_array()
+        .map(|records| {
+            records
+                .iter()
+                .map(|r| &r["entry"])
+                .filter(|e| e["instance_id"].as_str() == Some(instance_id))
+                .map(|e| e.str_or_empty("type"))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    for expected in [
+        wal_entry_types::CREATE_INSTANCE,
+        wal_entry_types::APPLY_EVENT,
+    ] {
+        if !entries.iter().any(|t| t == expected) {
+            return Err(ApiError::internal(format!(
+                "No '{}' WAL entry found for instance",
+                expected
+            )));
+        }
+    }
+    Ok(entries.len())
+}
+
+/// Run the self-test, returning true if every step passed.
+///
+/// The instance is deleted even if earlier steps fail. The machine itself is
+/// left behind under a unique `studio-selftest-*` name, since rstmdb has no
+/// operation for deleting machine definitions.
+pub async fn run(config: &RstmdbConfig) -> bool {
+    let mut report = Report::default();
+    println!("Running self-test against {}\n", config.address);
+
+    let Some(client) = report
+        .step("Connect to rstmdb", StudioClient::connect(config))
+        .await
+    else {
+        return false;
+    };
+    report.step("Ping", client.ping()).await;
+
+    let machine = format!(
+        "studio-selftest-{}",
+        &uuid::Uuid::new_v4().simple().to_string()[..8]
+    );
+    let machine_created = report
+        .step(
+            &format!("Create machine '{}'", machine),
+            client.put_machine(&machine, 1, definition()),
+        )
+        .await
+        .is_some();
+
+    let instance = if machine_created {
+        report
+            .step(
+                "Create instance",
+                client.create_instance(&machine, 1, None, Some(json!({ "selftest": true }))),
+            )
+            .await
+    } else {
+        report.skip("Create instance", "machine was not created");
+        None
+    };
+
+    match &instance {
+        Some(instance) => {
+            let id = instance.instance_id.as_str();
+            report
+                .step("Apply event", async {
+                    let result = client
+                        .apply_event(id, "FINISH", None, Some("created"), None)
+                        .await?;
+                    if result.to_state != "done" {
+                        return Err(ApiError::internal(format!(
+                            "Expected state 'done', got '{}'",
+                            result.to_state
+                        )));
+                    }
+                    Ok(result)
+                })
+                .await;
+            report
+                .step(
+                    "Read history",
+                    read_history(&client, id, instance.wal_offset),
+                )
+                .await;
+            report
+                .step("Delete instance", client.delete_instance(id))
+                .await;
+        }
+        None => {
+            for name in ["Apply event", "Read history", "Delete instance"] {
+                report.skip(name, "no instance was created");
+            }
+        }
+    }
+
+    if machine_created {
+        println!(
+            "\n  Note: machine '{}' is kept; rstmdb cannot delete machine definitions",
+            machine
+        );
+    }
+
+    println!("\n{} passed, {} failed", report.passed, report.failed);
+    report.failed == 0 && instance.is_some()
+}
@@ -0,0 +1,208 @@
+//! Background task supervision
+//!
+//! Background jobs (keepalive, WAL sampler, TTL sweeper, ...) loop forever,
+//! so one that panics or returns has stopped doing its work. The supervisor
+//! runs each job in its own task, restarts it with exponential backoff when
+//! it ends, and records starts, restarts and the last error for
+//! `/server/internals`. A job counts as down from the moment it ends until a
+//! restart has stayed up for a while; the `background_tasks` readiness check
+//! fails while a critical job has been down longer than
+//! `readiness.max_task_downtime`.
+
+use crate::constants::supervisor::{INITIAL_BACKOFF, MAX_BACKOFF, STABLE_AFTER};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStatus {
+    pub name: &'static str,
+    /// Readiness fails while a critical task stays down
+    pub critical: bool,
+    pub running: bool,
+    /// Epoch ms of the latest (re)start
+    pub last_started_at: Option<i64>,
+    pub restarts: u32,
+    /// Why the task last ended
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Epoch ms since which the task has not run stably, if it hasn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub down_since: Option<i64>,
+}
+
+/// Restart timing
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    /// A run lasting this long counts as recovered and resets the backoff
+    pub stable_after: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: INITIAL_BACKOFF,
+            max: MAX_BACKOFF,
+            stable_after: STABLE_AFTER,
+        }
+    }
+}
+
+pub struct Supervisor {
+    backoff: Backoff,
+    tasks: Mutex<Vec<Arc<Mutex<TaskStatus>>>>,
+}
+
+impl Supervisor {
+    pub fn new(backoff: Backoff) -> Self {
+        Self {
+            backoff,
+            tasks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Run `make()` as a background task, restarting it whenever it ends
+    pub fn spawn<F, Fut>(&self, name: &'static str, critical: bool, make: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let status = Arc::new(Mutex::new(TaskStatus {
+            name,
+            critical,
+            running: false,
+            last_started_at: None,
+            restarts: 0,
+            last_error: None,
+            down_since: None,
+        }));
+        self.tasks.lock().push(status.clone());
+        tokio::spawn(supervise(name, make, status, self.backoff));
+    }
+
+    /// Every supervised task, in spawn order
+    pub fn statuses(&self) -> Vec<TaskStatus> {
+        self.tasks.lock().iter().map(|s| s.lock().clone()).collect()
+    }
+
+    /// Critical tasks down for longer than `max_downtime` at `now` (epoch ms)
+    pub fn failing(&self, max_downtime: Duration, now: i64) -> Vec<TaskStatus> {
+        let max_ms = max_downtime.as_millis() as i64;
+        self.statuses()
+            .into_iter()
+            .filter(|t| t.critical && t.down_since.is_some_and(|since| now - since > max_ms))
+            .collect()
+    }
+}
+
+async fn supervise<F, Fut>(
+    name: &'static str,
+    make: F,
+    status: Arc<Mutex<TaskStatus>>,
+    backoff: Backoff,
+) where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut delay = backoff.initial;
+    loop {
+        {
+            let mut status = status.lock();
+            status.running = true;
+            status.last_started_at = Some(chrono::Utc::now().timestamp_millis());
+        }
+        let mut handle = tokio::spawn(make());
+
+        let result = match tokio::time::timeout(backoff.stable_after, &mut handle).await {
+            Ok(result) => result,
+            Err(_) => {
+                // Up long enough to count as healthy again
+                status.lock().down_since = None;
+                delay = backoff.initial;
+                handle.await
+            }
+        };
+
+        let error = match result {
+            Ok(()) => "exited".to_string(),
+            Err(e) if e.is_panic() => {
+                let panic = e.into_panic();
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                format!("panicked: {}", message)
+            }
+            Err(e) => format!("cancelled: {}", e),
+        };
+        tracing::error!(task = name, error = %error, retry_in = ?delay, "Background task stopped; restarting");
+        {
+            let mut status = status.lock();
+            status.running = false;
+            status.restarts += 1;
+            status.last_error = Some(error);
+            status
+                .down_since
+                .get_or_insert_with(|| chrono::Utc::now().timestamp_millis());
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(backoff.max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    async fn wait_for(supervisor: &Supervisor, done: impl Fn(&TaskStatus) -> bool) -> TaskStatus {
+        for _ in 0..500 {
+            let status = supervisor.statuses().remove(0);
+            if done(&status) {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("task status never matched: {:?}", supervisor.statuses());
+    }
+
+    #[tokio::test]
+    async fn test_panicking_task_is_restarted_and_recovers() {
+        let supervisor = Supervisor::new(Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(20),
+            stable_after: Duration::from_millis(500),
+        });
+        let runs = Arc::new(AtomicU32::new(0));
+        let counter = runs.clone();
+        supervisor.spawn("flaky", true, move || {
+            let run = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if run < 2 {
+                    panic!("boom {}", run);
+                }
+                std::future::pending::<()>().await
+            }
+        });
+
+        let status = wait_for(&supervisor, |s| s.restarts == 2 && s.running).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(status.last_error.as_deref(), Some("panicked: boom 1"));
+        let down_since = status.down_since.expect("not yet stable");
+        assert_eq!(supervisor.failing(Duration::ZERO, down_since + 1).len(), 1);
+        assert!(supervisor
+            .failing(Duration::from_secs(60), down_since + 1)
+            .is_empty());
+
+        // Once the restart has stayed up, the task is healthy again
+        let status = wait_for(&supervisor, |s| s.down_since.is_none()).await;
+        assert_eq!(status.restarts, 2);
+        assert!(supervisor.failing(Duration::ZERO, i64::MAX).is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+//! HTTPS listener
+//!
+//! With `server.tls.enabled`, Studio terminates TLS itself. Setting
+//! `client_ca_path` asks clients for a certificate signed by that CA, and
+//! `require_client_cert` refuses handshakes without one. The common name of
+//! a verified client certificate is attached to every request on its
+//! connection as [`ClientCert`]; `client_cert_roles` decides whether it
+//! authenticates anyone.
+
+use crate::config::TlsConfig;
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request},
+    response::Response,
+};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::convert::Infallible;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower::{Service, ServiceExt};
+
+/// Verified client certificate of the connection a request arrived on
+#[derive(Debug, Clone)]
+pub struct ClientCert {
+    pub common_name: String,
+}
+
+/// Build the TLS acceptor for `server.tls`, failing on missing or
+/// unreadable certificates
+pub fn acceptor(tls: &TlsConfig) -> anyhow::Result<TlsAcceptor> {
+    let cert_path = tls.cert_path.as_deref().ok_or_else(|| {
+        anyhow::anyhow!("server.tls.cert_path is required when server.tls.enabled is set")
+    })?;
+    let key_path = tls.key_path.as_deref().ok_or_else(|| {
+        anyhow::anyhow!("server.tls.key_path is required when server.tls.enabled is set")
+    })?;
+    let certs = read_certs(cert_path)?;
+    let key = read_key(key_path)?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match tls.client_ca_path.as_deref() {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(ca_path)? {
+                roots.add(cert)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if tls.require_client_cert {
+                verifier.build()?
+            } else {
+                verifier.allow_unauthenticated().build()?
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+        None if tls.require_client_cert => {
+            anyhow::bail!("server.tls.require_client_cert needs server.tls.client_ca_path")
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder.with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn read_certs(path: &str) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path, e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("Invalid certificate in {}: {}", path, e))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificates in {}", path);
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &str) -> anyhow::Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path, e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| anyhow::anyhow!("Invalid private key in {}: {}", path, e))?
+        .ok_or_else(|| anyhow::anyhow!("No private key in {}", path))
+}
+
+fn common_name(cert: &CertificateDer) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+    let name = cert.subject().iter_common_name().next()?.as_str().ok()?;
+    Some(name.to_string())
+}
+
+/// Serve `app` over TLS. Like `axum::serve`, requests carry the peer address
+/// as `ConnectInfo<SocketAddr>`. Runs until the process exits.
+pub async fn serve<S>(listener: TcpListener, acceptor: TlsAcceptor, app: S)
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                // e.g. out of file descriptors; retrying at once would spin
+                tracing::warn!(error = %e, "Failed to accept connection");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!(%peer, error = %e, "TLS handshake failed");
+                    return;
+                }
+            };
+            let client_cert = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(common_name)
+                .map(|common_name| ClientCert { common_name });
+
+            let service = hyper::service::service_fn(move |req: Request<hyper::body::Incoming>| {
+                let mut req = req.map(Body::new);
+                req.extensions_mut().insert(ConnectInfo(peer));
+                if let Some(cert) = &client_cert {
+                    req.extensions_mut().insert(cert.clone());
+                }
+                app.clone().oneshot(req)
+            });
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!(%peer, error = %e, "Connection closed with an error");
+            }
+        });
+    }
+}
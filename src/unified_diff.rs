@@ -0,0 +1,230 @@
+//! Unified text diffs
+//!
+//! Line diff (Myers' O((N+M)D) algorithm) rendered in the `diff -u` format
+//! with `---`/`+++` headers and `@@` hunks, for pasting definition changes
+//! into code review. No diff crate is a dependency. Inputs longer than
+//! `DIFF_MAX_LINES`, or further apart than `DIFF_MAX_EDITS`, are shown as
+//! one hunk replacing the whole old text, so a request can't make the diff
+//! take unbounded time or memory.
+
+use crate::constants::machines::{DIFF_MAX_EDITS, DIFF_MAX_LINES};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Shortest edit script turning `a` into `b`, as one op per line; `None` if
+/// it takes more than `max_edits` edits
+fn diff_lines<'a>(a: &[&'a str], b: &[&'a str], max_edits: usize) -> Option<Vec<(Op, &'a str)>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    // Diagonals -max-1..=max+1, shifted to be non-negative
+    let offset = max + 1;
+    let idx = |k: isize| (k + offset) as usize;
+    let mut v = vec![0isize; (2 * max + 3) as usize];
+    // Frontier before each step d, only diagonals -d-1..=d+1 (all that the
+    // walk back reads), so memory grows with the edit distance squared
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let mut found = false;
+
+    'search: for d in 0..=max.min(max_edits as isize) {
+        trace.push(v[idx(-d - 1)..=idx(d + 1)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) {
+                v[idx(k + 1)]
+            } else {
+                v[idx(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx(k)] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'search;
+            }
+        }
+    }
+    if !found {
+        return None;
+    }
+
+    // Walk the recorded frontiers back from the end
+    let mut ops = Vec::with_capacity(a.len().max(b.len()));
+    let (mut x, mut y) = (n, m);
+    for (d, frontier) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| frontier[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            ops.push((Op::Equal, a[(x - 1) as usize]));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                ops.push((Op::Insert, b[(y - 1) as usize]));
+            } else {
+                ops.push((Op::Delete, a[(x - 1) as usize]));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    ops.reverse();
+    Some(ops)
+}
+
+/// Edit script deleting all of `a` and inserting all of `b`
+fn replace_all<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(Op, &'a str)> {
+    a.iter()
+        .map(|line| (Op::Delete, *line))
+        .chain(b.iter().map(|line| (Op::Insert, *line)))
+        .collect()
+}
+
+/// `start,len` of a hunk side; `diff -u` omits a length of 1 and, for an
+/// empty side, gives the line before it
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// Unified diff from `old` to `new` with `context` unchanged lines around
+/// each change. Empty when the texts have the same lines.
+pub fn unified_diff(
+    old_label: &str,
+    new_label: &str,
+    old: &str,
+    new: &str,
+    context: usize,
+) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let ops = if a.len().max(b.len()) > DIFF_MAX_LINES {
+        None
+    } else {
+        diff_lines(&a, &b, DIFF_MAX_EDITS)
+    }
+    .unwrap_or_else(|| replace_all(&a, &b));
+
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, _))| *op != Op::Equal)
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Changes closer than two contexts apart share a hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changes {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    for (start, end) in hunks {
+        let before = &ops[..start];
+        let old_start = before.iter().filter(|(op, _)| *op != Op::Insert).count();
+        let new_start = before.iter().filter(|(op, _)| *op != Op::Delete).count();
+        let hunk = &ops[start..end];
+        let old_len = hunk.iter().filter(|(op, _)| *op != Op::Insert).count();
+        let new_len = hunk.iter().filter(|(op, _)| *op != Op::Delete).count();
+        let _ = writeln!(
+            out,
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_len),
+            hunk_range(new_start, new_len)
+        );
+        for (op, line) in hunk {
+            let prefix = match op {
+                Op::Equal => ' ',
+                Op::Delete => '-',
+                Op::Insert => '+',
+            };
+            let _ = writeln!(out, "{}{}", prefix, line);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff_hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+        assert_eq!(
+            unified_diff("v1", "v2", old, new, 1),
+            "--- v1\n+++ v2\n\
+             @@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n\
+             @@ -10 +10,2 @@\n j\n+k\n"
+        );
+        // With more context the two changes merge into one hunk
+        let merged = unified_diff("v1", "v2", old, new, 5);
+        assert_eq!(merged.matches("@@ -").count(), 1);
+        assert!(merged.contains("@@ -1,10 +1,11 @@\n"));
+
+        assert_eq!(unified_diff("v1", "v2", old, old, 3), "");
+        assert_eq!(
+            unified_diff("v1", "v2", "", "x\n", 3),
+            "--- v1\n+++ v2\n@@ -0,0 +1 @@\n+x\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_lines_is_minimal() {
+        let a = ["x", "a", "b", "c", "y"];
+        let b = ["a", "b", "z", "c"];
+        let ops = diff_lines(&a, &b, 10).unwrap();
+        let edits = ops.iter().filter(|(op, _)| *op != Op::Equal).count();
+        assert_eq!(edits, 3);
+        let rebuilt: Vec<&str> = ops
+            .iter()
+            .filter(|(op, _)| *op != Op::Delete)
+            .map(|(_, line)| *line)
+            .collect();
+        assert_eq!(rebuilt, b);
+        assert!(diff_lines(&a, &b, 2).is_none());
+    }
+
+    #[test]
+    fn test_distant_texts_fall_back_to_whole_replace() {
+        let old: String = (0..DIFF_MAX_EDITS)
+            .map(|i| format!("old {}\n", i))
+            .collect();
+        let new: String = (0..DIFF_MAX_EDITS)
+            .map(|i| format!("new {}\n", i))
+            .collect();
+        let diff = unified_diff("v1", "v2", &old, &new, 3);
+        let header = format!("@@ -1,{0} +1,{0} @@\n", DIFF_MAX_EDITS);
+        assert!(diff.starts_with(&format!("--- v1\n+++ v2\n{}-old 0\n", header)));
+        assert_eq!(diff.matches("\n-old ").count(), DIFF_MAX_EDITS);
+        assert!(diff.ends_with(&format!("+new {}\n", DIFF_MAX_EDITS - 1)));
+    }
+}
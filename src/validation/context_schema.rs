@@ -0,0 +1,480 @@
+//! Instance context schemas
+//!
+//! A machine may declare `contextSchema`, a JSON Schema for instance `ctx`.
+//! Studio supports the commonly used subset: `type`, `properties`,
+//! `required`, `additionalProperties`, `items`, `enum`, `const`,
+//! `minimum`/`maximum`, `minLength`/`maxLength` and `minItems`/`maxItems`.
+//! Other keywords are ignored, as JSON Schema does for unknown keywords.
+
+use super::{ValidationError, ValidationWarning};
+use crate::guard::{self, Expr, Operand};
+use serde::Serialize;
+use serde_json::Value;
+
+const TYPES: &[&str] = &[
+    "object", "array", "string", "number", "integer", "boolean", "null",
+];
+
+/// A context value that does not match the schema
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SchemaViolation {
+    /// Location in the context, e.g. `$.customer.tier`
+    pub path: String,
+    pub message: String,
+}
+
+fn schema_error(path: &str, message: String) -> ValidationError {
+    ValidationError {
+        code: "INVALID_CONTEXT_SCHEMA".to_string(),
+        message,
+        path: Some(path.to_string()),
+    }
+}
+
+/// Check that a schema uses the supported keywords with well-formed values
+pub fn check_schema(schema: &Value, path: &str, errors: &mut Vec<ValidationError>) {
+    let Some(obj) = schema.as_object() else {
+        // `true`/`false` are valid schemas
+        if !schema.is_boolean() {
+            errors.push(schema_error(
+                path,
+                "Schema must be an object or boolean".to_string(),
+            ));
+        }
+        return;
+    };
+
+    if let Some(ty) = obj.get("type") {
+        let names: Vec<&Value> = match ty {
+            Value::Array(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        for name in names {
+            if !name.as_str().is_some_and(|n| TYPES.contains(&n)) {
+                errors.push(schema_error(
+                    &format!("{}.type", path),
+                    format!("Unknown schema type {}", name),
+                ));
+            }
+        }
+    }
+
+    if let Some(properties) = obj.get("properties") {
+        match properties.as_object() {
+            Some(props) => {
+                for (name, sub) in props {
+                    check_schema(sub, &format!("{}.properties.{}", path, name), errors);
+                }
+            }
+            None => errors.push(schema_error(
+                &format!("{}.properties", path),
+                "'properties' must be an object".to_string(),
+            )),
+        }
+    }
+
+    if let Some(required) = obj.get("required") {
+        let valid = required
+            .as_array()
+            .is_some_and(|r| r.iter().all(Value::is_string));
+        if !valid {
+            errors.push(schema_error(
+                &format!("{}.required", path),
+                "'required' must be an array of strings".to_string(),
+            ));
+        }
+    }
+
+    for key in ["items", "additionalProperties"] {
+        if let Some(sub) = obj.get(key) {
+            check_schema(sub, &format!("{}.{}", path, key), errors);
+        }
+    }
+
+    if obj.get("enum").is_some_and(|e| !e.is_array()) {
+        errors.push(schema_error(
+            &format!("{}.enum", path),
+            "'enum' must be an array".to_string(),
+        ));
+    }
+
+    for key in ["minimum", "maximum"] {
+        if obj.get(key).is_some_and(|v| !v.is_number()) {
+            errors.push(schema_error(
+                &format!("{}.{}", path, key),
+                format!("'{}' must be a number", key),
+            ));
+        }
+    }
+    for key in ["minLength", "maxLength", "minItems", "maxItems"] {
+        if obj.get(key).is_some_and(|v| v.as_u64().is_none()) {
+            errors.push(schema_error(
+                &format!("{}.{}", path, key),
+                format!("'{}' must be a non-negative integer", key),
+            ));
+        }
+    }
+}
+
+fn type_matches(name: &str, value: &Value) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => false,
+    }
+}
+
+/// Validate a context against a schema, returning every violation found
+pub fn validate_context(schema: &Value, ctx: &Value) -> Vec<SchemaViolation> {
+    let mut violations = Vec::new();
+    validate_value(schema, ctx, "$", &mut violations);
+    violations
+}
+
+fn validate_value(schema: &Value, value: &Value, path: &str, out: &mut Vec<SchemaViolation>) {
+    let mut violation = |message: String| {
+        out.push(SchemaViolation {
+            path: path.to_string(),
+            message,
+        })
+    };
+
+    let obj = match schema {
+        Value::Bool(false) => {
+            violation("No value is allowed here".to_string());
+            return;
+        }
+        Value::Object(obj) => obj,
+        _ => return,
+    };
+
+    if let Some(ty) = obj.get("type") {
+        let names: Vec<&str> = match ty {
+            Value::Array(items) => items.iter().filter_map(Value::as_str).collect(),
+            other => other.as_str().into_iter().collect(),
+        };
+        if !names.iter().any(|n| type_matches(n, value)) {
+            violation(format!("Expected {}, got {}", names.join(" or "), value));
+            return;
+        }
+    }
+
+    if let Some(allowed) = obj.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            violation(format!("Value {} is not one of the allowed values", value));
+        }
+    }
+    if let Some(expected) = obj.get("const") {
+        if expected != value {
+            violation(format!("Expected {}, got {}", expected, value));
+        }
+    }
+
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = obj.get("minimum").and_then(Value::as_f64) {
+            if n < min {
+                violation(format!("{} is less than the minimum {}", value, min));
+            }
+        }
+        if let Some(max) = obj.get("maximum").and_then(Value::as_f64) {
+            if n > max {
+                violation(format!("{} is greater than the maximum {}", value, max));
+            }
+        }
+    }
+
+    if let Some(s) = value.as_str() {
+        let len = s.chars().count() as u64;
+        if let Some(min) = obj.get("minLength").and_then(Value::as_u64) {
+            if len < min {
+                violation(format!("String shorter than {} characters", min));
+            }
+        }
+        if let Some(max) = obj.get("maxLength").and_then(Value::as_u64) {
+            if len > max {
+                violation(format!("String longer than {} characters", max));
+            }
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        let len = items.len() as u64;
+        if let Some(min) = obj.get("minItems").and_then(Value::as_u64) {
+            if len < min {
+                violation(format!("Array has fewer than {} items", min));
+            }
+        }
+        if let Some(max) = obj.get("maxItems").and_then(Value::as_u64) {
+            if len > max {
+                violation(format!("Array has more than {} items", max));
+            }
+        }
+        if let Some(item_schema) = obj.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                validate_value(item_schema, item, &format!("{}[{}]", path, i), out);
+            }
+        }
+    }
+
+    if let Some(fields) = value.as_object() {
+        if let Some(required) = obj.get("required").and_then(Value::as_array) {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !fields.contains_key(name) {
+                    out.push(SchemaViolation {
+                        path: format!("{}.{}", path, name),
+                        message: "Required field is missing".to_string(),
+                    });
+                }
+            }
+        }
+
+        let properties = obj.get("properties").and_then(Value::as_object);
+        for (name, field) in fields {
+            let field_path = format!("{}.{}", path, name);
+            match properties.and_then(|p| p.get(name)) {
+                Some(field_schema) => validate_value(field_schema, field, &field_path, out),
+                None => {
+                    if let Some(additional) = obj.get("additionalProperties") {
+                        if additional == &Value::Bool(false) {
+                            out.push(SchemaViolation {
+                                path: field_path,
+                                message: "Field is not allowed by the schema".to_string(),
+                            });
+                        } else {
+                            validate_value(additional, field, &field_path, out);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What the schema says about a context field path
+pub(super) enum FieldSchema<'a> {
+    /// Declared, with this schema
+    Declared(&'a Value),
+    /// Inside a free-form object whose contents the schema doesn't describe
+    Unconstrained,
+    /// Not allowed by a schema that lists its properties
+    Undeclared,
+}
+
+pub(super) fn field_schema<'a>(schema: &'a Value, path: &[String]) -> FieldSchema<'a> {
+    let mut current = schema;
+    for segment in path {
+        let Some(obj) = current.as_object() else {
+            return FieldSchema::Unconstrained;
+        };
+        let properties = obj.get("properties").and_then(Value::as_object);
+        current = match properties.and_then(|p| p.get(segment)) {
+            Some(sub) => sub,
+            None => match obj.get("additionalProperties") {
+                Some(Value::Bool(false)) => return FieldSchema::Undeclared,
+                Some(additional @ Value::Object(_)) => additional,
+                _ if properties.is_some() => return FieldSchema::Undeclared,
+                _ => return FieldSchema::Unconstrained,
+            },
+        };
+    }
+    FieldSchema::Declared(current)
+}
+
+fn literal_type(value: &Value) -> Option<&'static str> {
+    match value {
+        Value::String(_) => Some("string"),
+        Value::Number(_) => Some("number"),
+        Value::Bool(_) => Some("boolean"),
+        // Comparing with null checks presence, which suits any type
+        _ => None,
+    }
+}
+
+pub(super) fn declared_types(schema: &Value) -> Vec<&str> {
+    match &schema["type"] {
+        Value::String(t) => vec![t.as_str()],
+        Value::Array(items) => items.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Field/literal pairs compared by the expression
+pub(super) fn comparisons(expr: &Expr) -> Vec<(&[String], &Value)> {
+    match expr {
+        Expr::Or(items) | Expr::And(items) => items.iter().flat_map(comparisons).collect(),
+        Expr::Not(inner) => comparisons(inner),
+        Expr::Truthy(_) => Vec::new(),
+        Expr::Compare { left, right, .. } => match (left, right) {
+            (Operand::Field(path), Operand::Literal(value))
+            | (Operand::Literal(value), Operand::Field(path)) => vec![(path.as_slice(), value)],
+            _ => Vec::new(),
+        },
+    }
+}
+
+/// Cross-check transition guards against `contextSchema`.
+///
+/// Fields the schema doesn't declare are errors (`GUARD_UNKNOWN_FIELD`);
+/// comparing a field with a literal of another type is a warning
+/// (`GUARD_TYPE_MISMATCH`). Guards that don't parse are skipped.
+pub fn check_guards(
+    definition: &Value,
+    schema: &Value,
+    errors: &mut Vec<ValidationError>,
+    warnings: &mut Vec<ValidationWarning>,
+) {
+    let Some(transitions) = definition["transitions"].as_array() else {
+        return;
+    };
+    for (i, transition) in transitions.iter().enumerate() {
+        let Some(source) = transition["guard"].as_str() else {
+            continue;
+        };
+        let Ok(expr) = guard::parse(source) else {
+            continue;
+        };
+        let path = format!("$.transitions[{}].guard", i);
+
+        for field in expr.fields() {
+            if let FieldSchema::Undeclared = field_schema(schema, field) {
+                errors.push(ValidationError {
+                    code: "GUARD_UNKNOWN_FIELD".to_string(),
+                    message: format!(
+                        "Guard references 'ctx.{}', which is not declared in contextSchema",
+                        field.join(".")
+                    ),
+                    path: Some(path.clone()),
+                });
+            }
+        }
+
+        for (field, literal) in comparisons(&expr) {
+            let (FieldSchema::Declared(field_schema), Some(literal_type)) =
+                (field_schema(schema, field), literal_type(literal))
+            else {
+                continue;
+            };
+            let types = declared_types(field_schema);
+            let compatible = types.is_empty()
+                || types
+                    .iter()
+                    .any(|t| *t == literal_type || (*t == "integer" && literal_type == "number"));
+            if !compatible {
+                warnings.push(ValidationWarning {
+                    code: "GUARD_TYPE_MISMATCH".to_string(),
+                    message: format!(
+                        "Guard compares 'ctx.{}' ({}) with {} literal {}",
+                        field.join("."),
+                        types.join(" or "),
+                        literal_type,
+                        literal
+                    ),
+                    path: Some(path.clone()),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["amount"],
+            "properties": {
+                "amount": { "type": "number", "minimum": 0 },
+                "customer": {
+                    "type": "object",
+                    "properties": { "tier": { "enum": ["gold", "silver"] } }
+                },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            },
+            "additionalProperties": false
+        })
+    }
+
+    #[test]
+    fn test_valid_context() {
+        let ctx = json!({"amount": 10, "customer": {"tier": "gold"}, "tags": ["a"]});
+        assert!(validate_context(&schema(), &ctx).is_empty());
+    }
+
+    #[test]
+    fn test_violation_paths() {
+        let ctx = json!({"customer": {"tier": "bronze"}, "tags": ["a", 1], "extra": true});
+        let paths: Vec<String> = validate_context(&schema(), &ctx)
+            .into_iter()
+            .map(|v| v.path)
+            .collect();
+        assert!(paths.contains(&"$.amount".to_string()));
+        assert!(paths.contains(&"$.customer.tier".to_string()));
+        assert!(paths.contains(&"$.tags[1]".to_string()));
+        assert!(paths.contains(&"$.extra".to_string()));
+    }
+
+    #[test]
+    fn test_check_guards_against_schema() {
+        let definition = json!({
+            "transitions": [
+                { "from": "a", "event": "E", "to": "b", "guard": "ctx.amount > 10 && ctx.customer.tier == \"gold\"" },
+                { "from": "a", "event": "F", "to": "b", "guard": "ctx.score > 5 || ctx.customer.tier > 3" },
+                { "from": "a", "event": "G", "to": "b", "guard": "ctx.tags != null" }
+            ]
+        });
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        check_guards(&definition, &schema(), &mut errors, &mut warnings);
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "GUARD_UNKNOWN_FIELD");
+        assert!(errors[0].message.contains("ctx.score"));
+        assert_eq!(errors[0].path.as_deref(), Some("$.transitions[1].guard"));
+
+        // `tier` has no declared type, so only string-typed fields can mismatch
+        assert!(warnings.is_empty());
+        let typed = json!({
+            "type": "object",
+            "properties": { "tier": { "type": "string" }, "amount": { "type": "integer" } }
+        });
+        let definition = json!({
+            "transitions": [
+                { "from": "a", "event": "E", "to": "b", "guard": "ctx.tier > 3 && ctx.amount >= 1.5" },
+                { "from": "a", "event": "F", "to": "b", "guard": "ctx.meta.anything" }
+            ]
+        });
+        check_guards(&definition, &typed, &mut errors, &mut warnings);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "GUARD_TYPE_MISMATCH");
+        assert!(warnings[0].message.contains("ctx.tier"));
+        // `ctx.meta` isn't declared by a schema that lists its properties
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn test_check_schema_structure() {
+        let mut errors = Vec::new();
+        check_schema(&schema(), "$.contextSchema", &mut errors);
+        assert!(errors.is_empty());
+
+        let bad = json!({"type": "text", "required": "amount", "properties": {"a": 5}});
+        check_schema(&bad, "$.contextSchema", &mut errors);
+        let paths: Vec<_> = errors.iter().filter_map(|e| e.path.as_deref()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "$.contextSchema.type",
+                "$.contextSchema.properties.a",
+                "$.contextSchema.required"
+            ]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! Differences between two versions of a definition
+
+use super::TransitionGraph;
+use serde::Serialize;
+use serde_json::Value;
+
+/// A transition edge as it appears in a diff
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DiffTransition {
+    pub from: String,
+    pub event: String,
+    pub to: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guard: Option<String>,
+}
+
+/// States and transitions added or removed going from one version to another
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct DefinitionDiff {
+    pub added_states: Vec<String>,
+    pub removed_states: Vec<String>,
+    pub added_transitions: Vec<DiffTransition>,
+    pub removed_transitions: Vec<DiffTransition>,
+}
+
+fn transitions(graph: &TransitionGraph) -> Vec<DiffTransition> {
+    let mut out: Vec<DiffTransition> = Vec::new();
+    for edge in &graph.edges {
+        let transition = DiffTransition {
+            from: edge.from.to_string(),
+            event: edge.event.to_string(),
+            to: edge.to.to_string(),
+            guard: edge.guard.map(String::from),
+        };
+        if !out.contains(&transition) {
+            out.push(transition);
+        }
+    }
+    out
+}
+
+/// Compare two definitions. Transitions are matched on `(from, event, to, guard)`,
+/// so a changed guard shows up as one removal and one addition.
+pub fn diff_definitions(old: &Value, new: &Value) -> DefinitionDiff {
+    let old_graph = TransitionGraph::from_definition(old);
+    let new_graph = TransitionGraph::from_definition(new);
+
+    let old_transitions = transitions(&old_graph);
+    let new_transitions = transitions(&new_graph);
+
+    DefinitionDiff {
+        added_states: new_graph
+            .states
+            .iter()
+            .filter(|s| !old_graph.states.contains(s))
+            .map(|s| s.to_string())
+            .collect(),
+        removed_states: old_graph
+            .states
+            .iter()
+            .filter(|s| !new_graph.states.contains(s))
+            .map(|s| s.to_string())
+            .collect(),
+        added_transitions: new_transitions
+            .iter()
+            .filter(|t| !old_transitions.contains(t))
+            .cloned()
+            .collect(),
+        removed_transitions: old_transitions
+            .into_iter()
+            .filter(|t| !new_transitions.contains(t))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_definitions() {
+        let v1 = json!({
+            "states": ["pending", "paid", "cancelled"],
+            "initial": "pending",
+            "transitions": [
+                { "from": "pending", "event": "PAY", "to": "paid" },
+                { "from": "pending", "event": "CANCEL", "to": "cancelled" }
+            ]
+        });
+        let v2 = json!({
+            "states": ["pending", "paid", "shipped"],
+            "initial": "pending",
+            "transitions": [
+                { "from": "pending", "event": "PAY", "to": "paid", "guard": "ctx.amount > 0" },
+                { "from": "paid", "event": "SHIP", "to": "shipped" }
+            ]
+        });
+
+        let diff = diff_definitions(&v1, &v2);
+        assert_eq!(diff.added_states, vec!["shipped"]);
+        assert_eq!(diff.removed_states, vec!["cancelled"]);
+        let added: Vec<_> = diff
+            .added_transitions
+            .iter()
+            .map(|t| t.event.as_str())
+            .collect();
+        let removed: Vec<_> = diff
+            .removed_transitions
+            .iter()
+            .map(|t| t.event.as_str())
+            .collect();
+        assert_eq!(added, vec!["PAY", "SHIP"]);
+        assert_eq!(removed, vec!["PAY", "CANCEL"]);
+
+        assert_eq!(diff_definitions(&v1, &v1), DefinitionDiff::default());
+    }
+}
@@ -0,0 +1,266 @@
+//! Transition graph built from a machine definition
+
+use super::transition_from_states;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// A single `from -> to` edge. Transitions with an array `from` expand into
+/// one edge per source state, all sharing the transition's index.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge<'a> {
+    /// Index of the transition in `$.transitions`
+    pub index: usize,
+    pub from: &'a str,
+    pub event: &'a str,
+    pub to: &'a str,
+    pub guard: Option<&'a str>,
+}
+
+/// Directed graph of states and transitions
+#[derive(Debug, Clone)]
+pub struct TransitionGraph<'a> {
+    /// Declared states, in definition order
+    pub states: Vec<&'a str>,
+    /// States listed in `$.final`
+    pub finals: Vec<&'a str>,
+    pub edges: Vec<Edge<'a>>,
+}
+
+impl<'a> TransitionGraph<'a> {
+    /// Build the graph from a (possibly invalid) definition, skipping malformed entries
+    pub fn from_definition(definition: &'a Value) -> Self {
+        let states = definition["states"]
+            .as_array()
+            .map(|arr| arr.iter().filter_map(|s| s.as_str()).collect())
+            .unwrap_or_default();
+        let finals = definition["final"]
+            .as_array()
+            .map(|arr| arr.iter().filter_map(|s| s.as_str()).collect())
+            .unwrap_or_default();
+
+        let mut edges = Vec::new();
+        if let Some(transitions) = definition["transitions"].as_array() {
+            for (index, transition) in transitions.iter().enumerate() {
+                let (Some(event), Some(to)) =
+                    (transition["event"].as_str(), transition["to"].as_str())
+                else {
+                    continue;
+                };
+                let guard = transition["guard"].as_str();
+                for from in transition_from_states(transition) {
+                    edges.push(Edge {
+                        index,
+                        from,
+                        event,
+                        to,
+                        guard,
+                    });
+                }
+            }
+        }
+
+        Self {
+            states,
+            finals,
+            edges,
+        }
+    }
+
+    /// Edges leaving a state
+    pub fn outgoing<'s>(&'s self, state: &'s str) -> impl Iterator<Item = &'s Edge<'a>> {
+        self.edges.iter().filter(move |e| e.from == state)
+    }
+
+    /// Edges entering a state
+    pub fn incoming<'s>(&'s self, state: &'s str) -> impl Iterator<Item = &'s Edge<'a>> {
+        self.edges.iter().filter(move |e| e.to == state)
+    }
+
+    pub fn fan_out(&self, state: &str) -> usize {
+        self.outgoing(state).count()
+    }
+
+    pub fn fan_in(&self, state: &str) -> usize {
+        self.incoming(state).count()
+    }
+
+    /// States reachable from `start`, including `start`, in definition order
+    pub fn reachable_from(&self, start: &str) -> Vec<&'a str> {
+        let mut seen: HashSet<&str> = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(state) = queue.pop_front() {
+            for edge in self.outgoing(state) {
+                if seen.insert(edge.to) {
+                    queue.push_back(edge.to);
+                }
+            }
+        }
+        self.states
+            .iter()
+            .copied()
+            .filter(|s| seen.contains(s))
+            .collect()
+    }
+
+    /// Shortest edge sequences from `from` to `to`, at most `limit` of them,
+    /// in definition order. Empty when `to` is unreachable; a single empty
+    /// path when `from == to`.
+    pub fn shortest_paths(&self, from: &str, to: &str, limit: usize) -> Vec<Vec<Edge<'a>>> {
+        // Distance of every state to `to`, walking edges backwards
+        let mut remaining: HashMap<&str, usize> = HashMap::from([(to, 0)]);
+        let mut queue = VecDeque::from([to]);
+        while let Some(state) = queue.pop_front() {
+            let distance = remaining[state];
+            for edge in self.incoming(state) {
+                if !remaining.contains_key(edge.from) {
+                    remaining.insert(edge.from, distance + 1);
+                    queue.push_back(edge.from);
+                }
+            }
+        }
+        if !remaining.contains_key(from) || limit == 0 {
+            return Vec::new();
+        }
+
+        // Every edge one step closer to `to` lies on a shortest path
+        let mut paths = Vec::new();
+        let mut stack = vec![(from, Vec::new())];
+        while let Some((state, path)) = stack.pop() {
+            if state == to {
+                paths.push(path);
+                if paths.len() >= limit {
+                    break;
+                }
+                continue;
+            }
+            let closer: Vec<&Edge<'a>> = self
+                .outgoing(state)
+                .filter(|e| remaining.get(e.to) == Some(&(remaining[state] - 1)))
+                .collect();
+            // Reversed so the first edge is explored first
+            for edge in closer.into_iter().rev() {
+                let mut next = path.clone();
+                next.push(*edge);
+                stack.push((edge.to, next));
+            }
+        }
+        paths
+    }
+
+    /// States declared final or with no outgoing transitions, in
+    /// definition order
+    pub fn terminal_states(&self) -> Vec<&'a str> {
+        self.states
+            .iter()
+            .copied()
+            .filter(|s| self.finals.contains(s) || self.fan_out(s) == 0)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_array_from_expands_edges() {
+        let def = json!({
+            "states": ["a", "b", "c"],
+            "initial": "a",
+            "transitions": [
+                { "from": ["a", "b"], "event": "GO", "to": "c", "guard": "ctx.ok" }
+            ]
+        });
+        let graph = TransitionGraph::from_definition(&def);
+        assert_eq!(graph.edges.len(), 2);
+        assert!(graph.edges.iter().all(|e| e.index == 0));
+        assert_eq!(graph.fan_in("c"), 2);
+        assert_eq!(graph.fan_out("a"), 1);
+        assert_eq!(graph.edges[0].guard, Some("ctx.ok"));
+        assert_eq!(graph.terminal_states(), vec!["c"]);
+    }
+
+    #[test]
+    fn test_declared_final_states_are_terminal() {
+        let def = json!({
+            "states": ["open", "closed", "archived"],
+            "initial": "open",
+            "final": ["closed", "missing", 3],
+            "transitions": [
+                { "from": "open", "event": "CLOSE", "to": "closed" },
+                { "from": "closed", "event": "ARCHIVE", "to": "archived" }
+            ]
+        });
+        let graph = TransitionGraph::from_definition(&def);
+        assert_eq!(graph.terminal_states(), vec!["closed", "archived"]);
+    }
+
+    #[test]
+    fn test_reachable_from() {
+        let def = json!({
+            "states": ["a", "b", "c", "d", "e"],
+            "initial": "a",
+            "transitions": [
+                { "from": "a", "event": "GO", "to": "b" },
+                { "from": ["b", "d"], "event": "NEXT", "to": "c" },
+                { "from": "c", "event": "BACK", "to": "b" },
+                { "from": "e", "event": "GO", "to": "a" }
+            ]
+        });
+        let graph = TransitionGraph::from_definition(&def);
+        assert_eq!(graph.reachable_from("b"), vec!["b", "c"]);
+        assert_eq!(graph.reachable_from("e"), vec!["a", "b", "c", "e"]);
+        assert_eq!(graph.reachable_from("d"), vec!["b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_shortest_paths() {
+        let def = json!({
+            "states": ["a", "b", "c", "d", "e"],
+            "initial": "a",
+            "transitions": [
+                { "from": "a", "event": "GO", "to": "b" },
+                { "from": "a", "event": "SKIP", "to": "c" },
+                { "from": "b", "event": "NEXT", "to": "d" },
+                { "from": "c", "event": "NEXT", "to": "d", "guard": "ctx.ok" },
+                { "from": "a", "event": "LONG", "to": "e" },
+                { "from": "e", "event": "ON", "to": "b" }
+            ]
+        });
+        let graph = TransitionGraph::from_definition(&def);
+        fn events<'a>(paths: Vec<Vec<Edge<'a>>>) -> Vec<Vec<&'a str>> {
+            paths
+                .iter()
+                .map(|p| p.iter().map(|e| e.event).collect())
+                .collect()
+        }
+        assert_eq!(
+            events(graph.shortest_paths("a", "d", 10)),
+            vec![vec!["GO", "NEXT"], vec!["SKIP", "NEXT"]]
+        );
+        assert_eq!(
+            events(graph.shortest_paths("a", "d", 1)),
+            vec![vec!["GO", "NEXT"]]
+        );
+        assert_eq!(
+            graph.shortest_paths("a", "d", 10)[1][1].guard,
+            Some("ctx.ok")
+        );
+        assert_eq!(
+            events(graph.shortest_paths("b", "b", 10)),
+            vec![Vec::<&str>::new()]
+        );
+        assert!(graph.shortest_paths("d", "a", 10).is_empty());
+    }
+
+    #[test]
+    fn test_malformed_transitions_skipped() {
+        let def = json!({
+            "states": ["a"],
+            "transitions": [{ "from": "a" }, "oops"]
+        });
+        let graph = TransitionGraph::from_definition(&def);
+        assert!(graph.edges.is_empty());
+    }
+}
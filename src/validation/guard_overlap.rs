@@ -0,0 +1,311 @@
+//! Overlapping guard detection
+//!
+//! Two guarded transitions with the same source state and event are only
+//! deterministic if their guards can never both be true. With a
+//! `contextSchema`, this check searches for a context that satisfies the
+//! schema and both guards: candidate values for each referenced field are
+//! derived from the guards' literals and the field's schema, and combinations
+//! are evaluated with the guard engine. The search is bounded, so overlaps
+//! needing values outside the candidates may go unreported.
+
+use super::context_schema::{
+    comparisons, declared_types, field_schema, validate_context, FieldSchema,
+};
+use super::{transition_from_states, ValidationWarning};
+use crate::constants::validation::MAX_GUARD_OVERLAP_ASSIGNMENTS;
+use crate::guard::{self, Expr};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Warn `POTENTIALLY_OVERLAPPING_GUARDS` for each pair of guarded transitions
+/// sharing a source state and event whose guards can both hold. Guards that
+/// don't parse are skipped.
+pub fn check_guard_overlap(
+    definition: &Value,
+    schema: &Value,
+    warnings: &mut Vec<ValidationWarning>,
+) {
+    let Some(transitions) = definition["transitions"].as_array() else {
+        return;
+    };
+
+    // (from, event) -> guarded transitions, in definition order
+    let mut groups: BTreeMap<(&str, &str), Vec<(usize, Expr)>> = BTreeMap::new();
+    for (i, transition) in transitions.iter().enumerate() {
+        let (Some(event), Some(source)) =
+            (transition["event"].as_str(), transition["guard"].as_str())
+        else {
+            continue;
+        };
+        let Ok(expr) = guard::parse(source) else {
+            continue;
+        };
+        for from in transition_from_states(transition) {
+            groups
+                .entry((from, event))
+                .or_default()
+                .push((i, expr.clone()));
+        }
+    }
+
+    // A pair sharing several source states is reported once
+    let mut reported = BTreeSet::new();
+    for ((from, event), guarded) in &groups {
+        for (n, (first, a)) in guarded.iter().enumerate() {
+            for (second, b) in &guarded[n + 1..] {
+                if first == second || reported.contains(&(*first, *second)) {
+                    continue;
+                }
+                let Some(witness) = find_overlap(schema, a, b) else {
+                    continue;
+                };
+                reported.insert((*first, *second));
+                warnings.push(ValidationWarning {
+                    code: "POTENTIALLY_OVERLAPPING_GUARDS".to_string(),
+                    message: format!(
+                        "Guard can be true together with the guard of transitions[{}] for event '{}' from '{}', e.g. with ctx {}",
+                        first, event, from, witness
+                    ),
+                    path: Some(format!("$.transitions[{}].guard", second)),
+                });
+            }
+        }
+    }
+}
+
+/// A context valid under `schema` for which both guards hold, if one is found
+/// within the assignment budget
+fn find_overlap(schema: &Value, a: &Expr, b: &Expr) -> Option<Value> {
+    let mut fields: Vec<&[String]> = a.fields();
+    for field in b.fields() {
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+    }
+    let literals: Vec<(&[String], &Value)> =
+        comparisons(a).into_iter().chain(comparisons(b)).collect();
+    let domains: Vec<Vec<Option<Value>>> = fields
+        .iter()
+        .map(|field| {
+            let field_literals: Vec<&Value> = literals
+                .iter()
+                .filter(|(path, _)| path == field)
+                .map(|(_, value)| *value)
+                .collect();
+            let declared = match field_schema(schema, field) {
+                FieldSchema::Declared(declared) => Some(declared),
+                _ => None,
+            };
+            candidates(declared, &field_literals)
+        })
+        .collect();
+
+    // Odometer over the candidate domains
+    let mut choice = vec![0; fields.len()];
+    for _ in 0..MAX_GUARD_OVERLAP_ASSIGNMENTS {
+        let ctx = build_context(&fields, &domains, &choice);
+        if a.evaluate(&ctx) && b.evaluate(&ctx) && satisfies(schema, &fields, &ctx) {
+            return Some(ctx);
+        }
+        let mut position = 0;
+        loop {
+            if position == choice.len() {
+                return None;
+            }
+            choice[position] += 1;
+            if choice[position] < domains[position].len() {
+                break;
+            }
+            choice[position] = 0;
+            position += 1;
+        }
+    }
+    None
+}
+
+/// Values worth trying for a field: those the guards compare it with and
+/// their neighbours, values the schema allows, and leaving it out
+fn candidates(declared: Option<&Value>, literals: &[&Value]) -> Vec<Option<Value>> {
+    let mut values: Vec<Value> = Vec::new();
+    let fixed = declared.and_then(|d| match (d.get("enum"), d.get("const")) {
+        (Some(Value::Array(allowed)), _) => Some(allowed.clone()),
+        (_, Some(value)) => Some(vec![value.clone()]),
+        _ => None,
+    });
+
+    if let Some(fixed) = fixed {
+        values = fixed;
+    } else {
+        let bounds = declared
+            .into_iter()
+            .flat_map(|d| [d.get("minimum"), d.get("maximum")])
+            .flatten();
+        for literal in literals.iter().copied().chain(bounds) {
+            match literal {
+                Value::Number(n) => {
+                    let n = n.as_f64().unwrap_or(0.0);
+                    for candidate in [n, n - 1.0, n + 1.0, n.floor(), n.ceil()] {
+                        values.push(number(candidate));
+                    }
+                }
+                Value::String(s) => {
+                    // Equal, ordered before and ordered after the literal
+                    values.push(json!(s));
+                    values.push(json!(""));
+                    values.push(json!(format!("{}~", s)));
+                }
+                Value::Bool(_) => values.extend([json!(true), json!(false)]),
+                other => values.push(other.clone()),
+            }
+        }
+        let types = declared.map(declared_types).unwrap_or_default();
+        for ty in &types {
+            match *ty {
+                "boolean" => values.extend([json!(true), json!(false)]),
+                "number" | "integer" => values.push(json!(0)),
+                "string" => values.push(json!("")),
+                "null" => values.push(Value::Null),
+                "object" => values.push(json!({})),
+                "array" => values.push(json!([])),
+                _ => {}
+            }
+        }
+        if types.is_empty() {
+            values.extend([json!(true), json!(false), Value::Null]);
+        }
+    }
+
+    let mut out: Vec<Option<Value>> = Vec::new();
+    for value in values {
+        if !out.contains(&Some(value.clone())) {
+            out.push(Some(value));
+        }
+    }
+    out.push(None);
+    out
+}
+
+/// Integral values become JSON integers so they satisfy `"type": "integer"`
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        json!(n as i64)
+    } else {
+        json!(n)
+    }
+}
+
+fn build_context(fields: &[&[String]], domains: &[Vec<Option<Value>>], choice: &[usize]) -> Value {
+    let mut ctx = Value::Object(Map::new());
+    for ((path, domain), &i) in fields.iter().zip(domains).zip(choice) {
+        if let Some(value) = &domain[i] {
+            insert(&mut ctx, path, value.clone());
+        }
+    }
+    ctx
+}
+
+/// Set `path` in `ctx`, creating intermediate objects. A path running
+/// through a non-object value set by another field is left out.
+fn insert(ctx: &mut Value, path: &[String], value: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut current = ctx;
+    for segment in parents {
+        let Some(obj) = current.as_object_mut() else {
+            return;
+        };
+        current = obj
+            .entry(segment.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    if let Some(obj) = current.as_object_mut() {
+        obj.insert(last.clone(), value);
+    }
+}
+
+/// The schema holds for the assigned fields. Violations elsewhere, such as
+/// required fields the guards don't mention, are ignored.
+fn satisfies(schema: &Value, fields: &[&[String]], ctx: &Value) -> bool {
+    let prefixes: Vec<String> = fields
+        .iter()
+        .map(|field| format!("$.{}", field.join(".")))
+        .collect();
+    validate_context(schema, ctx).iter().all(|violation| {
+        !prefixes.iter().any(|prefix| {
+            violation
+                .path
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warnings_for(schema: Value, guards: &[&str]) -> Vec<ValidationWarning> {
+        let transitions: Vec<Value> = guards
+            .iter()
+            .map(|g| json!({ "from": "review", "event": "DECIDE", "to": "done", "guard": g }))
+            .collect();
+        let definition = json!({ "transitions": transitions });
+        let mut warnings = Vec::new();
+        check_guard_overlap(&definition, &schema, &mut warnings);
+        warnings
+    }
+
+    #[test]
+    fn test_overlapping_numeric_ranges() {
+        let schema = json!({
+            "type": "object",
+            "required": ["amount"],
+            "properties": { "amount": { "type": "integer" } }
+        });
+        let warnings = warnings_for(schema.clone(), &["ctx.amount > 100", "ctx.amount >= 50"]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "POTENTIALLY_OVERLAPPING_GUARDS");
+        assert_eq!(warnings[0].path.as_deref(), Some("$.transitions[1].guard"));
+
+        // Disjoint ranges, including at the boundary
+        assert!(warnings_for(schema, &["ctx.amount > 100", "ctx.amount <= 100"]).is_empty());
+    }
+
+    #[test]
+    fn test_schema_rules_out_overlap() {
+        // Both guards hold only for an amount below the schema's minimum
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "amount": { "type": "number", "minimum": 0 },
+                "tier": { "enum": ["gold", "silver"] }
+            }
+        });
+        assert!(warnings_for(schema.clone(), &["ctx.amount < 0", "ctx.amount < 5"]).is_empty());
+        assert!(warnings_for(
+            schema.clone(),
+            &[
+                r#"ctx.tier == "gold""#,
+                r#"ctx.tier != "gold" && ctx.tier != "silver""#
+            ]
+        )
+        .is_empty());
+        assert_eq!(
+            warnings_for(schema, &[r#"ctx.tier == "gold""#, "ctx.amount > 10"]).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_complementary_boolean_guards() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "approved": { "type": "boolean" }, "vip": { "type": "boolean" } }
+        });
+        assert!(warnings_for(schema.clone(), &["ctx.approved", "!ctx.approved"]).is_empty());
+        let warnings = warnings_for(schema, &["ctx.approved", "ctx.vip || !ctx.approved"]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.contains(r#""approved":true"#));
+    }
+}
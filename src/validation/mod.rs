@@ -1,8 +1,20 @@
 //! State machine definition validation
 
+pub mod context_schema;
+mod diff;
+mod graph;
+mod guard_overlap;
+mod sarif;
+mod stats;
+
+pub use diff::{diff_definitions, DefinitionDiff};
+pub use graph::TransitionGraph;
+pub use sarif::to_sarif;
+pub use stats::{definition_stats, DefinitionStats};
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ValidationResult {
@@ -27,6 +39,107 @@ pub struct ValidationWarning {
     pub path: Option<String>,
 }
 
+/// Severity for optional checks that can be reported as warnings or errors
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Warning,
+    Error,
+}
+
+/// State and event names rstmdb reserves for itself. A trailing `*` makes a
+/// pattern match by prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReservedNames {
+    #[serde(default = "default_reserved_states")]
+    pub states: Vec<String>,
+    #[serde(default = "default_reserved_events")]
+    pub events: Vec<String>,
+}
+
+impl Default for ReservedNames {
+    fn default() -> Self {
+        Self {
+            states: default_reserved_states(),
+            events: default_reserved_events(),
+        }
+    }
+}
+
+fn default_reserved_states() -> Vec<String> {
+    vec!["$*".to_string()]
+}
+
+fn default_reserved_events() -> Vec<String> {
+    vec!["$*".to_string(), "__init__".to_string()]
+}
+
+impl ReservedNames {
+    fn matches(patterns: &[String], name: &str) -> bool {
+        patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+
+    pub fn is_reserved_state(&self, name: &str) -> bool {
+        Self::matches(&self.states, name)
+    }
+
+    pub fn is_reserved_event(&self, name: &str) -> bool {
+        Self::matches(&self.events, name)
+    }
+}
+
+/// Definition key holding the JSON Schema for instance contexts
+pub const CONTEXT_SCHEMA_FIELD: &str = "contextSchema";
+
+/// Keys recognized at the top level of a definition
+const KNOWN_FIELDS: &[&str] = &[
+    "states",
+    "initial",
+    "transitions",
+    "final",
+    "events",
+    "meta",
+    CONTEXT_SCHEMA_FIELD,
+];
+
+/// Transition key holding the payload an event form is pre-filled with
+pub const PAYLOAD_DEFAULTS_FIELD: &str = "payloadDefaults";
+
+/// Transition key holding an author's note; it has no effect on behavior
+pub const DESCRIPTION_FIELD: &str = "description";
+
+/// `meta` key mapping state names to authors' notes. States are plain
+/// strings, so their notes live beside them rather than on them.
+pub const STATE_DESCRIPTIONS_FIELD: &str = "stateDescriptions";
+
+/// Keys recognized on a transition
+const KNOWN_TRANSITION_FIELDS: &[&str] = &[
+    "from",
+    "event",
+    "to",
+    "guard",
+    "meta",
+    PAYLOAD_DEFAULTS_FIELD,
+    DESCRIPTION_FIELD,
+];
+
+/// Source states of a transition (`from` may be a string or an array of strings)
+pub fn transition_from_states(transition: &Value) -> Vec<&str> {
+    if let Some(from) = transition["from"].as_str() {
+        vec![from]
+    } else if let Some(arr) = transition["from"].as_array() {
+        arr.iter().filter_map(|s| s.as_str()).collect()
+    } else {
+        vec![]
+    }
+}
+
 /// Validate a state machine definition
 pub fn validate_definition(definition: &Value) -> ValidationResult {
     let mut errors = Vec::new();
@@ -47,6 +160,83 @@ pub fn validate_definition(definition: &Value) -> ValidationResult {
     }
 }
 
+/// Strict mode: report top-level and transition keys outside the known schema.
+///
+/// Catches typos such as `transtions` that would otherwise be ignored.
+pub fn validate_unknown_fields(
+    definition: &Value,
+    severity: Severity,
+    result: &mut ValidationResult,
+) {
+    let mut unknown = Vec::new();
+    if let Some(obj) = definition.as_object() {
+        for key in obj.keys() {
+            if !KNOWN_FIELDS.contains(&key.as_str()) {
+                unknown.push((format!("$.{}", key), key, KNOWN_FIELDS));
+            }
+        }
+    }
+    if let Some(transitions) = definition["transitions"].as_array() {
+        for (i, transition) in transitions.iter().enumerate() {
+            for key in transition.as_object().into_iter().flat_map(|o| o.keys()) {
+                if !KNOWN_TRANSITION_FIELDS.contains(&key.as_str()) {
+                    unknown.push((
+                        format!("$.transitions[{}].{}", i, key),
+                        key,
+                        KNOWN_TRANSITION_FIELDS,
+                    ));
+                }
+            }
+        }
+    }
+
+    for (path, key, known) in unknown {
+        let mut message = format!("Unknown field '{}'", key);
+        if let Some(suggestion) = closest_match(key, known) {
+            message.push_str(&format!(", did you mean '{}'?", suggestion));
+        }
+        let path = Some(path);
+        let code = "UNKNOWN_FIELD".to_string();
+        match severity {
+            Severity::Warning => result.warnings.push(ValidationWarning {
+                code,
+                message,
+                path,
+            }),
+            Severity::Error => result.errors.push(ValidationError {
+                code,
+                message,
+                path,
+            }),
+        }
+    }
+    result.valid = result.errors.is_empty();
+}
+
+/// Known key within edit distance 2 of `key`, if any
+fn closest_match<'a>(key: &str, known: &[&'a str]) -> Option<&'a str> {
+    known
+        .iter()
+        .map(|k| (edit_distance(key, k), *k))
+        .filter(|(d, _)| *d <= 2)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, k)| k)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
 fn validate_schema(definition: &Value, errors: &mut Vec<ValidationError>) {
     // Check required fields
     if !definition.is_object() {
@@ -138,6 +328,56 @@ fn validate_schema(definition: &Value, errors: &mut Vec<ValidationError>) {
             }
         }
     }
+
+    // final (optional)
+    if let Some(finals) = definition.get("final") {
+        match finals.as_array() {
+            None => errors.push(ValidationError {
+                code: "INVALID_TYPE".to_string(),
+                message: "'final' must be an array".to_string(),
+                path: Some("$.final".to_string()),
+            }),
+            Some(arr) => {
+                for (i, state) in arr.iter().enumerate() {
+                    if !state.is_string() {
+                        errors.push(ValidationError {
+                            code: "INVALID_TYPE".to_string(),
+                            message: format!("Final state at index {} must be a string", i),
+                            path: Some(format!("$.final[{}]", i)),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // contextSchema (optional)
+    if let Some(schema) = definition.get(CONTEXT_SCHEMA_FIELD) {
+        context_schema::check_schema(schema, "$.contextSchema", errors);
+    }
+
+    // meta.stateDescriptions (optional)
+    if let Some(descriptions) = definition["meta"].get(STATE_DESCRIPTIONS_FIELD) {
+        let path = format!("$.meta.{}", STATE_DESCRIPTIONS_FIELD);
+        match descriptions.as_object() {
+            None => errors.push(ValidationError {
+                code: "INVALID_TYPE".to_string(),
+                message: format!("'meta.{}' must be an object", STATE_DESCRIPTIONS_FIELD),
+                path: Some(path),
+            }),
+            Some(descriptions) => {
+                for (state, description) in descriptions {
+                    if !description.is_string() {
+                        errors.push(ValidationError {
+                            code: "INVALID_TYPE".to_string(),
+                            message: format!("Description of state '{}' must be a string", state),
+                            path: Some(format!("{}.{}", path, state)),
+                        });
+                    }
+                }
+            }
+        }
+    }
 }
 
 fn validate_transition(transition: &Value, index: usize, errors: &mut Vec<ValidationError>) {
@@ -222,8 +462,81 @@ fn validate_transition(transition: &Value, index: usize, errors: &mut Vec<Valida
             });
         }
     }
+
+    // payloadDefaults (optional)
+    if let Some(defaults) = transition.get(PAYLOAD_DEFAULTS_FIELD) {
+        if !defaults.is_object() {
+            errors.push(ValidationError {
+                code: "INVALID_TYPE".to_string(),
+                message: format!("'{}' must be an object", PAYLOAD_DEFAULTS_FIELD),
+                path: Some(format!("{}.{}", path_prefix, PAYLOAD_DEFAULTS_FIELD)),
+            });
+        }
+    }
+
+    // description (optional)
+    if let Some(description) = transition.get(DESCRIPTION_FIELD) {
+        if !description.is_string() {
+            errors.push(ValidationError {
+                code: "INVALID_TYPE".to_string(),
+                message: format!("'{}' must be a string", DESCRIPTION_FIELD),
+                path: Some(format!("{}.{}", path_prefix, DESCRIPTION_FIELD)),
+            });
+        }
+    }
 }
 
+/// Reject state and event names rstmdb keeps for itself, per `reserved`
+pub fn validate_reserved_names(
+    definition: &Value,
+    reserved: &ReservedNames,
+    result: &mut ValidationResult,
+) {
+    if let Some(states) = definition["states"].as_array() {
+        for (i, state) in states.iter().enumerate() {
+            if let Some(s) = state.as_str().filter(|s| reserved.is_reserved_state(s)) {
+                result.errors.push(ValidationError {
+                    code: "RESERVED_NAME".to_string(),
+                    message: format!("State name '{}' is reserved by rstmdb", s),
+                    path: Some(format!("$.states[{}]", i)),
+                });
+            }
+        }
+    }
+    if let Some(transitions) = definition["transitions"].as_array() {
+        for (i, transition) in transitions.iter().enumerate() {
+            if let Some(event) = transition["event"]
+                .as_str()
+                .filter(|e| reserved.is_reserved_event(e))
+            {
+                result.errors.push(ValidationError {
+                    code: "RESERVED_NAME".to_string(),
+                    message: format!("Event name '{}' is reserved by rstmdb", event),
+                    path: Some(format!("$.transitions[{}].event", i)),
+                });
+            }
+        }
+    }
+    result.valid = result.errors.is_empty();
+}
+
+/// Deeper determinism check: warn about guarded transitions sharing a source
+/// state and event whose guards can both be true. Needs a `contextSchema`;
+/// without one nothing is reported.
+pub fn validate_guard_overlap(definition: &Value, result: &mut ValidationResult) {
+    if let Some(schema) = definition.get(CONTEXT_SCHEMA_FIELD) {
+        guard_overlap::check_guard_overlap(definition, schema, &mut result.warnings);
+    }
+}
+
+/// Sorted from states, event, to and guard of a transition
+type TransitionKey<'a> = (
+    Vec<&'a str>,
+    Option<&'a str>,
+    Option<&'a str>,
+    Option<&'a str>,
+);
+
 fn validate_semantics(
     definition: &Value,
     errors: &mut Vec<ValidationError>,
@@ -251,20 +564,10 @@ fn validate_semantics(
     }
 
     // Check transitions reference valid states
-    let mut referenced_states: HashSet<String> = HashSet::new();
-    let mut incoming_transitions: HashSet<String> = HashSet::new();
-    let mut outgoing_transitions: HashSet<String> = HashSet::new();
-
     if let Some(transitions) = definition["transitions"].as_array() {
         for (i, transition) in transitions.iter().enumerate() {
             // Check 'from' states
-            let from_states: Vec<&str> = if let Some(from) = transition["from"].as_str() {
-                vec![from]
-            } else if let Some(arr) = transition["from"].as_array() {
-                arr.iter().filter_map(|s| s.as_str()).collect()
-            } else {
-                vec![]
-            };
+            let from_states = transition_from_states(transition);
 
             for from in &from_states {
                 if !states.contains(*from) {
@@ -274,7 +577,6 @@ fn validate_semantics(
                         path: Some(format!("$.transitions[{}].from", i)),
                     });
                 }
-                outgoing_transitions.insert(from.to_string());
             }
 
             // Check 'to' state
@@ -286,12 +588,32 @@ fn validate_semantics(
                         path: Some(format!("$.transitions[{}].to", i)),
                     });
                 }
-                incoming_transitions.insert(to.to_string());
-                referenced_states.insert(to.to_string());
             }
+        }
+    }
 
-            for from in from_states {
-                referenced_states.insert(from.to_string());
+    // Check final states exist
+    if let Some(finals) = definition["final"].as_array() {
+        for (i, state) in finals.iter().enumerate() {
+            if let Some(s) = state.as_str().filter(|s| !states.contains(*s)) {
+                errors.push(ValidationError {
+                    code: "INVALID_STATE".to_string(),
+                    message: format!("Final state '{}' not in states list", s),
+                    path: Some(format!("$.final[{}]", i)),
+                });
+            }
+        }
+    }
+
+    // Warnings: notes on states the definition doesn't declare
+    if let Some(descriptions) = definition["meta"][STATE_DESCRIPTIONS_FIELD].as_object() {
+        for state in descriptions.keys() {
+            if !states.contains(state) {
+                warnings.push(ValidationWarning {
+                    code: "UNKNOWN_STATE_DESCRIPTION".to_string(),
+                    message: format!("Description given for undeclared state '{}'", state),
+                    path: Some(format!("$.meta.{}.{}", STATE_DESCRIPTIONS_FIELD, state)),
+                });
             }
         }
     }
@@ -313,10 +635,58 @@ fn validate_semantics(
         }
     }
 
-    // Warnings: unreachable states (no incoming transitions except initial)
+    // Warnings: identical transitions (usually a copy-paste mistake)
+    if let Some(transitions) = definition["transitions"].as_array() {
+        let mut seen: HashMap<TransitionKey, usize> = HashMap::new();
+        for (i, transition) in transitions.iter().enumerate() {
+            let mut from = transition_from_states(transition);
+            from.sort_unstable();
+            let key = (
+                from,
+                transition["event"].as_str(),
+                transition["to"].as_str(),
+                transition["guard"].as_str(),
+            );
+            if let Some(first) = seen.get(&key) {
+                warnings.push(ValidationWarning {
+                    code: "DUPLICATE_TRANSITION".to_string(),
+                    message: format!("Transition duplicates transitions[{}]", first),
+                    path: Some(format!("$.transitions[{}]", i)),
+                });
+            } else {
+                seen.insert(key, i);
+            }
+        }
+    }
+
+    // Guards must only use fields the context schema declares
+    if let Some(schema) = definition.get(CONTEXT_SCHEMA_FIELD) {
+        context_schema::check_guards(definition, schema, errors, warnings);
+    }
+
+    let graph = TransitionGraph::from_definition(definition);
     let initial = definition["initial"].as_str().unwrap_or("");
+
+    // An initial state that is also terminal leaves instances stuck at creation
+    if let Some(finals) = definition["final"].as_array() {
+        let position = finals.iter().position(|s| s.as_str() == Some(initial));
+        if let Some(i) = position {
+            if graph.fan_out(initial) == 0 {
+                errors.push(ValidationError {
+                    code: "INITIAL_IS_TERMINAL".to_string(),
+                    message: format!(
+                        "Initial state '{}' is declared final and has no outgoing transitions; instances could never transition",
+                        initial
+                    ),
+                    path: Some(format!("$.final[{}]", i)),
+                });
+            }
+        }
+    }
+
+    // Warnings: unreachable states (no incoming transitions except initial)
     for state in &states {
-        if state != initial && !incoming_transitions.contains(state) {
+        if state != initial && graph.fan_in(state) == 0 {
             warnings.push(ValidationWarning {
                 code: "UNREACHABLE_STATE".to_string(),
                 message: format!("State '{}' has no incoming transitions", state),
@@ -327,7 +697,7 @@ fn validate_semantics(
 
     // Warnings: dead-end states (no outgoing transitions)
     for state in &states {
-        if !outgoing_transitions.contains(state) {
+        if graph.fan_out(state) == 0 {
             warnings.push(ValidationWarning {
                 code: "DEAD_END_STATE".to_string(),
                 message: format!(
@@ -361,6 +731,242 @@ mod tests {
         assert!(result.errors.is_empty());
     }
 
+    #[test]
+    fn test_initial_state_declared_final() {
+        let def = json!({
+            "states": ["only"],
+            "initial": "only",
+            "final": ["only"],
+            "transitions": []
+        });
+        let result = validate_definition(&def);
+        assert!(!result.valid);
+        assert_eq!(result.errors[0].code, "INITIAL_IS_TERMINAL");
+        assert_eq!(result.errors[0].path.as_deref(), Some("$.final[0]"));
+
+        // Allowed while the initial state can still transition
+        let def = json!({
+            "states": ["a", "b"],
+            "initial": "a",
+            "final": ["b", "a"],
+            "transitions": [{ "from": "a", "event": "GO", "to": "b" }]
+        });
+        assert!(validate_definition(&def).valid);
+    }
+
+    #[test]
+    fn test_final_states_checked() {
+        let def = json!({
+            "states": ["a", "b"],
+            "initial": "a",
+            "final": "b",
+            "transitions": [{ "from": "a", "event": "GO", "to": "b" }]
+        });
+        let result = validate_definition(&def);
+        assert!(!result.valid);
+        assert_eq!(result.errors[0].code, "INVALID_TYPE");
+        assert_eq!(result.errors[0].path.as_deref(), Some("$.final"));
+
+        let mut def = def;
+        def["final"] = json!(["b", 1]);
+        let result = validate_definition(&def);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].path.as_deref(), Some("$.final[1]"));
+
+        def["final"] = json!(["b", "c"]);
+        let result = validate_definition(&def);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].code, "INVALID_STATE");
+        assert_eq!(result.errors[0].path.as_deref(), Some("$.final[1]"));
+
+        def["final"] = json!(["b"]);
+        assert!(validate_definition(&def).valid);
+    }
+
+    #[test]
+    fn test_reserved_state_names() {
+        let def = json!({
+            "states": ["new", "$system", "done"],
+            "initial": "new",
+            "transitions": [
+                { "from": "new", "event": "GO", "to": "$system" },
+                { "from": "$system", "event": "FINISH", "to": "done" }
+            ]
+        });
+        let mut result = validate_definition(&def);
+        assert!(result.valid);
+        validate_reserved_names(&def, &ReservedNames::default(), &mut result);
+        assert!(!result.valid);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].code, "RESERVED_NAME");
+        assert_eq!(result.errors[0].path.as_deref(), Some("$.states[1]"));
+
+        let allow_all = ReservedNames {
+            states: vec![],
+            events: vec![],
+        };
+        let mut result = validate_definition(&def);
+        validate_reserved_names(&def, &allow_all, &mut result);
+        assert!(result.valid);
+    }
+
+    #[test]
+    fn test_reserved_event_names() {
+        let def = json!({
+            "states": ["a", "b"],
+            "initial": "a",
+            "transitions": [
+                { "from": "a", "event": "__init__", "to": "b" },
+                { "from": "b", "event": "__init__x", "to": "a" },
+                { "from": "b", "event": "RESET", "to": "a" }
+            ]
+        });
+        let mut result = validate_definition(&def);
+        validate_reserved_names(&def, &ReservedNames::default(), &mut result);
+        let paths: Vec<_> = result
+            .errors
+            .iter()
+            .filter(|e| e.code == "RESERVED_NAME")
+            .filter_map(|e| e.path.as_deref())
+            .collect();
+        assert_eq!(paths, vec!["$.transitions[0].event"]);
+
+        let custom = ReservedNames {
+            states: vec![],
+            events: vec!["RESET".to_string(), "__*".to_string()],
+        };
+        let mut result = validate_definition(&def);
+        validate_reserved_names(&def, &custom, &mut result);
+        assert_eq!(result.errors.len(), 3);
+    }
+
+    #[test]
+    fn test_payload_defaults_must_be_object() {
+        let def = json!({
+            "states": ["a", "b"],
+            "initial": "a",
+            "transitions": [
+                { "from": "a", "event": "GO", "to": "b", "payloadDefaults": { "amount": 0 } },
+                { "from": "b", "event": "BACK", "to": "a", "payloadDefaults": [1] }
+            ]
+        });
+        let result = validate_definition(&def);
+        assert!(!result.valid);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(
+            result.errors[0].path.as_deref(),
+            Some("$.transitions[1].payloadDefaults")
+        );
+    }
+
+    #[test]
+    fn test_descriptions_must_be_strings() {
+        let def = json!({
+            "states": ["a", "b"],
+            "initial": "a",
+            "transitions": [
+                { "from": "a", "event": "GO", "to": "b", "description": "Starts work" },
+                { "from": "b", "event": "BACK", "to": "a", "description": 1 }
+            ],
+            "meta": { "stateDescriptions": { "a": "Waiting", "b": false, "c": "Gone" } }
+        });
+        let result = validate_definition(&def);
+        let paths: Vec<_> = result
+            .errors
+            .iter()
+            .filter_map(|e| e.path.as_deref())
+            .collect();
+        assert_eq!(
+            paths,
+            vec!["$.transitions[1].description", "$.meta.stateDescriptions.b"]
+        );
+
+        let mut def = def;
+        def["transitions"][1]["description"] = json!("Rework");
+        def["meta"]["stateDescriptions"]["b"] = json!("In progress");
+        let result = validate_definition(&def);
+        assert!(result.valid);
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.warnings[0].code, "UNKNOWN_STATE_DESCRIPTION");
+    }
+
+    #[test]
+    fn test_duplicate_transition_warning() {
+        let def = json!({
+            "states": ["a", "b", "c"],
+            "initial": "a",
+            "transitions": [
+                { "from": "a", "event": "GO", "to": "b", "guard": "ctx.ok" },
+                { "from": "b", "event": "GO", "to": "c" },
+                { "from": "a", "event": "GO", "to": "b", "guard": "ctx.ok" },
+                { "from": "a", "event": "GO", "to": "b" }
+            ]
+        });
+        let result = validate_definition(&def);
+        assert!(result.valid);
+        let duplicates: Vec<_> = result
+            .warnings
+            .iter()
+            .filter(|w| w.code == "DUPLICATE_TRANSITION")
+            .collect();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].path.as_deref(), Some("$.transitions[2]"));
+    }
+
+    #[test]
+    fn test_duplicate_transition_reordered_from() {
+        let def = json!({
+            "states": ["a", "b", "c"],
+            "initial": "a",
+            "transitions": [
+                { "from": ["a", "b"], "event": "GO", "to": "c" },
+                { "from": ["b", "a"], "event": "GO", "to": "c" }
+            ]
+        });
+        let result = validate_definition(&def);
+        assert!(result.valid);
+        let warning = result
+            .warnings
+            .iter()
+            .find(|w| w.code == "DUPLICATE_TRANSITION")
+            .unwrap();
+        assert_eq!(warning.path.as_deref(), Some("$.transitions[1]"));
+        assert!(warning.message.contains("transitions[0]"));
+    }
+
+    #[test]
+    fn test_unknown_fields_strict() {
+        let def = json!({
+            "states": ["a", "b"],
+            "initial": "a",
+            "transitions": [{ "from": "a", "event": "GO", "to": "b", "gaurd": "ctx.ok" }],
+            "transtions": []
+        });
+
+        let mut result = validate_definition(&def);
+        assert!(result.valid);
+        validate_unknown_fields(&def, Severity::Warning, &mut result);
+        assert!(result.valid);
+        let unknown: Vec<_> = result
+            .warnings
+            .iter()
+            .filter(|w| w.code == "UNKNOWN_FIELD")
+            .collect();
+        assert_eq!(unknown.len(), 2);
+        assert!(unknown
+            .iter()
+            .any(|w| w.path.as_deref() == Some("$.transtions")
+                && w.message.contains("did you mean 'transitions'")));
+        assert!(unknown
+            .iter()
+            .any(|w| w.path.as_deref() == Some("$.transitions[0].gaurd")));
+
+        let mut result = validate_definition(&def);
+        validate_unknown_fields(&def, Severity::Error, &mut result);
+        assert!(!result.valid);
+        assert_eq!(result.errors.len(), 2);
+    }
+
     #[test]
     fn test_missing_fields() {
         let def = json!({});
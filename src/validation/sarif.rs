@@ -0,0 +1,141 @@
+//! SARIF 2.1.0 export of validation results
+//!
+//! Lets CI tooling (e.g. GitHub code scanning) surface definition problems inline.
+
+use super::ValidationResult;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &str = "2.1.0";
+const TOOL_NAME: &str = "rstmdb-studio";
+
+/// Convert a validation result into a SARIF 2.1.0 log.
+///
+/// Each error/warning becomes a result whose `ruleId` is the validation code and
+/// whose logical location is the JSON path. When `artifact_uri` is given, a
+/// physical location pointing at that file is attached as well.
+pub fn to_sarif(result: &ValidationResult, artifact_uri: Option<&str>) -> Value {
+    let mut rule_ids: BTreeSet<&str> = BTreeSet::new();
+    let mut results = Vec::new();
+
+    for e in &result.errors {
+        rule_ids.insert(&e.code);
+        results.push(sarif_result(
+            &e.code,
+            "error",
+            &e.message,
+            e.path.as_deref(),
+            artifact_uri,
+        ));
+    }
+    for w in &result.warnings {
+        rule_ids.insert(&w.code);
+        results.push(sarif_result(
+            &w.code,
+            "warning",
+            &w.message,
+            w.path.as_deref(),
+            artifact_uri,
+        ));
+    }
+
+    let rules: Vec<Value> = rule_ids.into_iter().map(|id| json!({ "id": id })).collect();
+
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": TOOL_NAME,
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+fn sarif_result(
+    code: &str,
+    level: &str,
+    message: &str,
+    path: Option<&str>,
+    artifact_uri: Option<&str>,
+) -> Value {
+    let mut location = serde_json::Map::new();
+    if let Some(path) = path {
+        location.insert(
+            "logicalLocations".to_string(),
+            json!([{ "fullyQualifiedName": path, "kind": "member" }]),
+        );
+    }
+    if let Some(uri) = artifact_uri {
+        location.insert(
+            "physicalLocation".to_string(),
+            json!({ "artifactLocation": { "uri": uri } }),
+        );
+    }
+
+    let mut result = json!({
+        "ruleId": code,
+        "level": level,
+        "message": { "text": message },
+    });
+    if !location.is_empty() {
+        result["locations"] = json!([Value::Object(location)]);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::validate_definition;
+
+    #[test]
+    fn test_sarif_maps_levels_and_rules() {
+        let def = json!({
+            "states": ["pending", "done"],
+            "initial": "unknown",
+            "transitions": [
+                { "from": "pending", "event": "COMPLETE", "to": "done" }
+            ]
+        });
+        let result = validate_definition(&def);
+        let sarif = to_sarif(&result, None);
+
+        assert_eq!(sarif["version"], "2.1.0");
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        let err = results
+            .iter()
+            .find(|r| r["ruleId"] == "INVALID_INITIAL_STATE")
+            .unwrap();
+        assert_eq!(err["level"], "error");
+        assert_eq!(
+            err["locations"][0]["logicalLocations"][0]["fullyQualifiedName"],
+            "$.initial"
+        );
+        assert!(results
+            .iter()
+            .any(|r| r["ruleId"] == "DEAD_END_STATE" && r["level"] == "warning"));
+
+        let rules = sarif["runs"][0]["tool"]["driver"]["rules"]
+            .as_array()
+            .unwrap();
+        assert!(rules.iter().any(|r| r["id"] == "INVALID_INITIAL_STATE"));
+    }
+
+    #[test]
+    fn test_sarif_physical_location() {
+        let result = validate_definition(&json!({}));
+        let sarif = to_sarif(&result, Some("machines/order.json"));
+        let first = &sarif["runs"][0]["results"][0];
+        assert_eq!(
+            first["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "machines/order.json"
+        );
+    }
+}
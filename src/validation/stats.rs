@@ -0,0 +1,125 @@
+//! Structural statistics for a machine definition
+
+use super::graph::TransitionGraph;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StateDegree {
+    pub state: String,
+    pub fan_in: usize,
+    pub fan_out: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DefinitionStats {
+    pub states: usize,
+    pub transitions: usize,
+    /// Distinct event names
+    pub events: usize,
+    pub guarded_transitions: usize,
+    pub unguarded_transitions: usize,
+    /// States declared final or with no outgoing transitions
+    pub terminal_states: Vec<String>,
+    /// State with the most outgoing edges
+    pub max_fan_out: Option<StateDegree>,
+    /// State with the most incoming edges
+    pub max_fan_in: Option<StateDegree>,
+    /// Per-state in/out degree, in definition order
+    pub degrees: Vec<StateDegree>,
+}
+
+/// Compute structural statistics from a definition's transition graph
+pub fn definition_stats(definition: &Value) -> DefinitionStats {
+    let graph = TransitionGraph::from_definition(definition);
+    let transitions = definition["transitions"].as_array();
+
+    let transitions_count = transitions.map(|t| t.len()).unwrap_or(0);
+    let guarded_transitions = transitions
+        .map(|t| t.iter().filter(|t| t["guard"].is_string()).count())
+        .unwrap_or(0);
+    let events: HashSet<&str> = graph.edges.iter().map(|e| e.event).collect();
+
+    let degrees: Vec<StateDegree> = graph
+        .states
+        .iter()
+        .map(|state| StateDegree {
+            state: state.to_string(),
+            fan_in: graph.fan_in(state),
+            fan_out: graph.fan_out(state),
+        })
+        .collect();
+
+    let terminal_states = graph
+        .terminal_states()
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+    // Ties resolve to the first state in definition order
+    let max_by = |key: fn(&StateDegree) -> usize| {
+        degrees
+            .iter()
+            .rev()
+            .max_by_key(|d| key(d))
+            .filter(|d| key(d) > 0)
+            .cloned()
+    };
+    let max_fan_out = max_by(|d| d.fan_out);
+    let max_fan_in = max_by(|d| d.fan_in);
+
+    DefinitionStats {
+        states: graph.states.len(),
+        transitions: transitions_count,
+        events: events.len(),
+        guarded_transitions,
+        unguarded_transitions: transitions_count - guarded_transitions,
+        terminal_states,
+        max_fan_out,
+        max_fan_in,
+        degrees,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_definition_stats() {
+        let def = json!({
+            "states": ["pending", "approved", "rejected", "done"],
+            "initial": "pending",
+            "transitions": [
+                { "from": "pending", "event": "REVIEW", "to": "approved", "guard": "ctx.score > 50" },
+                { "from": "pending", "event": "REVIEW", "to": "rejected", "guard": "ctx.score <= 50" },
+                { "from": ["approved", "rejected"], "event": "CLOSE", "to": "done" }
+            ]
+        });
+
+        let stats = definition_stats(&def);
+        assert_eq!(stats.states, 4);
+        assert_eq!(stats.transitions, 3);
+        assert_eq!(stats.events, 2);
+        assert_eq!(stats.guarded_transitions, 2);
+        assert_eq!(stats.unguarded_transitions, 1);
+        assert_eq!(stats.terminal_states, vec!["done".to_string()]);
+
+        let fan_out = stats.max_fan_out.unwrap();
+        assert_eq!((fan_out.state.as_str(), fan_out.fan_out), ("pending", 2));
+        let fan_in = stats.max_fan_in.unwrap();
+        assert_eq!((fan_in.state.as_str(), fan_in.fan_in), ("done", 2));
+    }
+
+    #[test]
+    fn test_definition_stats_no_transitions() {
+        let def = json!({ "states": ["only"], "initial": "only", "transitions": [] });
+        let stats = definition_stats(&def);
+        assert_eq!(stats.transitions, 0);
+        assert!(stats.max_fan_out.is_none());
+        assert!(stats.max_fan_in.is_none());
+        assert_eq!(stats.terminal_states, vec!["only".to_string()]);
+    }
+}